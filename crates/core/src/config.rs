@@ -1,9 +1,51 @@
+use gcodekit_device_adapters::discovery::DiscoveryOptions;
+use gcodekit_device_adapters::tls::TlsOptions;
+use tracing::warn;
 use serde::Deserialize;
+use std::path::PathBuf;
 use std::time::Duration;
 
 #[derive(Deserialize, Debug)]
 struct ConfigFile {
     network_timeout_secs: Option<u64>,
+    #[serde(default)]
+    tls: Option<TlsConfig>,
+    #[serde(default)]
+    discovery: Option<DiscoveryConfig>,
+}
+
+/// `tls` section of `config.json`, used for wss:// connections.
+#[derive(Deserialize, Debug, Default)]
+struct TlsConfig {
+    ca_bundle: Option<PathBuf>,
+    #[serde(default)]
+    ca_bundle_only: bool,
+    client_cert: Option<PathBuf>,
+    client_key: Option<PathBuf>,
+}
+
+/// `discovery` section of `config.json`, used for network device discovery.
+#[derive(Deserialize, Debug, Default)]
+struct DiscoveryConfig {
+    /// `false` turns off the mDNS browse, which is on by default.
+    mdns: Option<bool>,
+    mdns_timeout_ms: Option<u64>,
+    /// CIDR subnet to port-scan, or `auto` for the local /24.
+    scan_subnet: Option<String>,
+    scan_ports: Option<Vec<u16>>,
+    connect_timeout_ms: Option<u64>,
+}
+
+/// Directory holding `config.json`: `gcodekit6` in the platform data dir.
+fn config_dir() -> Option<PathBuf> {
+    Some(dirs_next::data_local_dir()?.join("gcodekit6"))
+}
+
+/// Read `gcodekit6/config.json` from the platform data dir, if present and valid.
+fn load_config_file() -> Option<ConfigFile> {
+    let cfg_path = config_dir()?.join("config.json");
+    let contents = std::fs::read_to_string(cfg_path).ok()?;
+    serde_json::from_str::<ConfigFile>(&contents).ok()
 }
 
 /// Determine network timeout using precedence:
@@ -21,21 +63,76 @@ pub fn network_timeout() -> Duration {
     }
 
     // 2. config file
-    if let Some(proj_dirs) = dirs_next::data_local_dir().map(|p| p.join("gcodekit6")) {
-        let cfg_path = proj_dirs.join("config.json");
-        if cfg_path.exists() {
-            if let Ok(contents) = std::fs::read_to_string(&cfg_path) {
-                if let Ok(cf) = serde_json::from_str::<ConfigFile>(&contents) {
-                    if let Some(secs) = cf.network_timeout_secs {
-                        if secs > 0 {
-                            return Duration::from_secs(secs);
-                        }
-                    }
-                }
-            }
+    if let Some(secs) = load_config_file().and_then(|cf| cf.network_timeout_secs) {
+        if secs > 0 {
+            return Duration::from_secs(secs);
         }
     }
 
     // 3. fallback
     gcodekit_utils::settings::network_timeout()
 }
+
+/// TLS settings for secure transports from the `tls` section of
+/// `gcodekit6/config.json`, e.g.:
+///
+/// ```json
+/// { "tls": { "ca_bundle": "/etc/gcodekit6/shop-ca.pem",
+///            "client_cert": "client.pem", "client_key": "client.key" } }
+/// ```
+///
+/// Relative paths are resolved against the directory holding `config.json`,
+/// not the working directory. Returns defaults (platform trust store, no
+/// client auth) when unset.
+pub fn tls_options() -> TlsOptions {
+    let tls = load_config_file().and_then(|cf| cf.tls).unwrap_or_default();
+    let dir = config_dir();
+    let resolve = |path: Option<PathBuf>| {
+        path.map(|p| match &dir {
+            Some(dir) if p.is_relative() => dir.join(p),
+            _ => p,
+        })
+    };
+    TlsOptions {
+        ca_bundle: resolve(tls.ca_bundle),
+        ca_bundle_only: tls.ca_bundle_only,
+        client_cert: resolve(tls.client_cert),
+        client_key: resolve(tls.client_key),
+    }
+}
+
+/// Network discovery settings from the `discovery` section of
+/// `gcodekit6/config.json`, e.g.:
+///
+/// ```json
+/// { "discovery": { "mdns": true, "mdns_timeout_ms": 1000,
+///                  "scan_subnet": "auto", "scan_ports": [23, 80, 81] } }
+/// ```
+///
+/// Both methods block `discover_devices` for their timeout. The mDNS browse
+/// is on by default with a short timeout and `"mdns": false` turns it off;
+/// the subnet scan is off unless configured.
+pub fn discovery_options() -> DiscoveryOptions {
+    let mut opts = DiscoveryOptions::default();
+    let Some(cfg) = load_config_file().and_then(|cf| cf.discovery) else {
+        return opts;
+    };
+    if cfg.mdns == Some(false) {
+        opts.mdns = None;
+    } else if let (Some(query), Some(ms)) = (opts.mdns.as_mut(), cfg.mdns_timeout_ms) {
+        query.timeout = Duration::from_millis(ms);
+    }
+    if let Some(subnet) = cfg.scan_subnet {
+        match gcodekit_device_adapters::discovery::parse_subnet(&subnet) {
+            Ok(net) => opts.scan_subnet = Some(net),
+            Err(e) => warn!(error = %e, "config::discovery_options: ignoring scan_subnet"),
+        }
+    }
+    if let Some(ports) = cfg.scan_ports {
+        opts.scan_ports = ports;
+    }
+    if let Some(ms) = cfg.connect_timeout_ms {
+        opts.connect_timeout = Duration::from_millis(ms);
+    }
+    opts
+}
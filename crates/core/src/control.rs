@@ -0,0 +1,167 @@
+//! Machine control operations: homing, unlock, soft reset, check mode and
+//! sleep.
+//!
+//! Each operation sends the controller's command, waits for its reply and
+//! for the status reports to show the expected transition (Home to Idle,
+//! Alarm to Idle, ...), and fails with a clear error when the controller
+//! refuses or the transition does not happen in time. Every operation
+//! publishes a `SessionEvent::Operation` with its outcome.
+
+use crate::machine_state::{Axis, MachineStatus};
+use crate::protocol::Protocol;
+use crate::session::{Session, SessionEvent};
+use anyhow::{bail, Result};
+use serde::Serialize;
+use tracing::{info, warn};
+
+/// GRBL realtime soft reset (Ctrl-X).
+pub const SOFT_RESET: u8 = 0x18;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Operation {
+    Home,
+    Unlock,
+    SoftReset,
+    /// Entering (`true`) or leaving check mode.
+    CheckMode(bool),
+    Sleep,
+}
+
+/// Homing command for `axes`; all axes when empty.
+fn home_command(protocol: Protocol, axes: &[Axis]) -> String {
+    match protocol {
+        // grblHAL and FluidNC take the axes after `$H`; plain GRBL homes all
+        Protocol::Grbl => axes.iter().fold("$H".to_string(), |mut s, a| {
+            s.push(a.letter());
+            s
+        }),
+        Protocol::Marlin => axes.iter().fold("G28".to_string(), |mut s, a| {
+            s.push(' ');
+            s.push(a.letter());
+            s
+        }),
+    }
+}
+
+impl Session {
+    /// Run `f` as `operation` and publish its outcome.
+    fn operation(&self, operation: Operation, f: impl FnOnce() -> Result<()>) -> Result<()> {
+        let result = f();
+        match &result {
+            Ok(()) => info!(id = %self.id(), operation = ?operation, "control::operation: done"),
+            Err(e) => warn!(id = %self.id(), operation = ?operation, err = %e, "control::operation: failed"),
+        }
+        self.publish(SessionEvent::Operation {
+            id: self.id().to_string(),
+            operation,
+            error: result.as_ref().err().map(ToString::to_string),
+        });
+        result
+    }
+
+    fn check_no_job(&self, operation: Operation) -> Result<()> {
+        if self.progress().status.is_active() {
+            bail!("session {}: cannot run {:?} while a job is running", self.id(), operation);
+        }
+        Ok(())
+    }
+
+    fn unsupported(&self, operation: Operation) -> Result<()> {
+        bail!("session {}: {:?} is not supported by {:?} controllers", self.id(), operation, self.protocol())
+    }
+
+    /// Home `axes`, or every axis when empty, and wait for the cycle to end
+    /// in Idle.
+    pub fn home(&self, axes: &[Axis]) -> Result<()> {
+        self.operation(Operation::Home, || {
+            self.check_no_job(Operation::Home)?;
+            let timeout = self.options().homing_timeout;
+            // Both firmwares only reply once the cycle has finished
+            self.send_command(&home_command(self.protocol(), axes), timeout)?;
+            if self.protocol() == Protocol::Grbl {
+                let state = self.wait_for_report(self.options().command_timeout, |s| {
+                    matches!(s.status, MachineStatus::Idle | MachineStatus::Alarm)
+                })?;
+                if state.status == MachineStatus::Alarm {
+                    bail!("session {}: homing ended in alarm", self.id());
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Clear an alarm lock (`$X`; `M999` restarts a halted Marlin).
+    pub fn unlock(&self) -> Result<()> {
+        self.operation(Operation::Unlock, || {
+            let timeout = self.options().command_timeout;
+            match self.protocol() {
+                Protocol::Grbl => {
+                    self.send_command("$X", timeout)?;
+                    self.wait_for_report(timeout, |s| s.status != MachineStatus::Alarm)?;
+                }
+                Protocol::Marlin => self.send_command("M999", timeout)?,
+            }
+            Ok(())
+        })
+    }
+
+    /// Reset the controller, dropping anything queued or streaming, and
+    /// wait for it to restart.
+    pub fn soft_reset(&self) -> Result<()> {
+        self.operation(Operation::SoftReset, || {
+            if self.protocol() != Protocol::Grbl {
+                return self.unsupported(Operation::SoftReset);
+            }
+            let since = self.signals().resets;
+            self.send_realtime(SOFT_RESET)?;
+            self.wait_for("the controller to restart", self.options().command_timeout, |s, _| {
+                s.resets > since
+            })?;
+            Ok(())
+        })
+    }
+
+    /// Enter or leave check mode, in which the controller parses G-code
+    /// without moving. Leaving check mode resets the controller.
+    pub fn check_mode(&self, on: bool) -> Result<()> {
+        let operation = Operation::CheckMode(on);
+        self.operation(operation, || {
+            if self.protocol() != Protocol::Grbl {
+                return self.unsupported(operation);
+            }
+            self.check_no_job(operation)?;
+            // `$C` toggles, so only send it when the mode has to change
+            if (self.state().status == MachineStatus::Check) == on {
+                return Ok(());
+            }
+            let timeout = self.options().command_timeout;
+            if on {
+                self.send_command("$C", timeout)?;
+                self.wait_for_report(timeout, |s| s.status == MachineStatus::Check)?;
+            } else {
+                // The reset may swallow the `ok`, so wait for the banner
+                let since = self.signals().resets;
+                self.send_line("$C")?;
+                self.wait_for("the controller to restart", timeout, |s, _| s.resets > since)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Put the controller to sleep (`$SLP`; Marlin disables its steppers
+    /// with `M84`). A reset wakes it.
+    pub fn sleep(&self) -> Result<()> {
+        self.operation(Operation::Sleep, || {
+            self.check_no_job(Operation::Sleep)?;
+            let timeout = self.options().command_timeout;
+            match self.protocol() {
+                Protocol::Grbl => {
+                    self.send_command("$SLP", timeout)?;
+                    self.wait_for_report(timeout, |s| s.status == MachineStatus::Sleep)?;
+                }
+                Protocol::Marlin => self.send_command("M84", timeout)?,
+            }
+            Ok(())
+        })
+    }
+}
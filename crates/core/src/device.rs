@@ -35,3 +35,35 @@ pub fn load_devices() -> io::Result<Vec<Device>> {
         }
     }
 }
+
+/// Insert or update one device in `devices.json`, keyed by `id`, keeping
+/// every other record. Firmware and capabilities already known for the
+/// device are kept when the new record leaves them empty.
+pub fn upsert_device(device: Device) -> io::Result<()> {
+    debug!(id = %device.id, "device::upsert_device: merging device");
+    gcodekit_utils::storage::update_json("devices.json", |devices: &mut Vec<Device>| {
+        match devices.iter_mut().find(|d| d.id == device.id) {
+            Some(existing) => {
+                let mut device = device;
+                if device.firmware.is_none() {
+                    device.firmware = existing.firmware.take();
+                }
+                if device.capabilities.is_empty() {
+                    device.capabilities = std::mem::take(&mut existing.capabilities);
+                }
+                *existing = device;
+            }
+            None => devices.push(device),
+        }
+    })
+}
+
+/// Remove a device from `devices.json`. Returns whether a record was removed.
+pub fn remove_device(id: &str) -> io::Result<bool> {
+    debug!(id = %id, "device::remove_device: removing device");
+    gcodekit_utils::storage::update_json("devices.json", |devices: &mut Vec<Device>| {
+        let before = devices.len();
+        devices.retain(|d| d.id != id);
+        devices.len() != before
+    })
+}
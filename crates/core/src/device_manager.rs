@@ -1,10 +1,48 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use crate::endpoint::Endpoint;
+use crate::machine_state::MachineStatus;
+use crate::profile::MachineProfile;
+use crate::protocol::Protocol;
+use crate::session::{Session, SessionEvent, SessionOptions, StreamProgress};
+use gcodekit_device_adapters::discovery::{DiscoveryOptions, NetworkPeer, PeerSource};
 use gcodekit_device_adapters::Transport;
+use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::broadcast;
 use tracing::{debug, info};
 
-/// High-level device manager that orchestrates adapter connections.
-pub struct DeviceManager {}
+/// Capacity of the session event channel; slow subscribers see `Lagged`.
+const EVENT_CAPACITY: usize = 1024;
+
+/// High-level device manager that orchestrates adapter connections and owns
+/// the live `Session` of each connected machine, keyed by device id.
+pub struct DeviceManager {
+    sessions: Mutex<HashMap<String, Arc<Session>>>,
+    events: broadcast::Sender<SessionEvent>,
+}
+
+/// Summary of a session for listings.
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+    pub id: String,
+    pub endpoint: String,
+    pub connected: bool,
+    pub status: MachineStatus,
+    pub alarm: Option<String>,
+    pub progress: StreamProgress,
+    pub queued_jobs: usize,
+}
+
+/// Aggregated view over all sessions.
+#[derive(Debug, Clone, Default)]
+pub struct FleetStatus {
+    pub sessions: Vec<SessionInfo>,
+    pub connected: usize,
+    /// Sessions with a running or paused job.
+    pub streaming: usize,
+    pub alarmed: usize,
+}
 
 /// Rich device information returned to UIs and callers that need metadata.
 #[derive(Debug, Clone)]
@@ -18,15 +56,161 @@ pub struct DiscoveredDevice {
     pub pid: Option<u16>,
 }
 
+impl Default for DeviceManager {
+    fn default() -> Self {
+        DeviceManager::new()
+    }
+}
+
 impl DeviceManager {
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        DeviceManager {
+            sessions: Mutex::new(HashMap::new()),
+            events,
+        }
+    }
+
+    /// Process-wide manager used by the UI.
+    pub fn global() -> &'static DeviceManager {
+        static GLOBAL: OnceLock<DeviceManager> = OnceLock::new();
+        GLOBAL.get_or_init(DeviceManager::new)
+    }
+
+    /// Subscribe to lifecycle, line, state and stream events of all sessions.
+    pub fn subscribe(&self) -> broadcast::Receiver<SessionEvent> {
+        self.events.subscribe()
+    }
+
+    /// Start a session over an existing transport, replacing any previous
+    /// session with the same id.
+    pub fn open_session(
+        &self,
+        id: &str,
+        endpoint: Endpoint,
+        transport: Box<dyn Transport>,
+        protocol: Protocol,
+        options: SessionOptions,
+    ) -> Arc<Session> {
+        let session = Arc::new(Session::start(id, endpoint, transport, protocol, options, self.events.clone()));
+        let previous = self.sessions.lock().unwrap().insert(id.to_string(), session.clone());
+        if let Some(old) = previous {
+            debug!(id = %id, "device_manager::open_session: replacing previous session");
+            let _ = old.disconnect();
+        }
+        session
+    }
+
+    /// Connect to `endpoint` and start a session keyed by its URL. Returns
+    /// the existing session if that endpoint is already connected.
+    pub fn connect_session(&self, endpoint: &Endpoint) -> Result<Arc<Session>> {
+        let id = endpoint.to_string();
+        if let Some(existing) = self.session(&id).filter(|s| s.is_connected()) {
+            return Ok(existing);
+        }
+        let transport = Self::connect(endpoint)?;
+        let dev = crate::models::Device::from_endpoint(endpoint, id.clone());
+        let _ = crate::device::upsert_device(dev);
+        let protocol = Protocol::for_endpoint(endpoint, None);
+        Ok(self.open_session(&id, endpoint.clone(), transport, protocol, SessionOptions::default()))
+    }
+
+    /// Connect to a saved profile's machine, using its firmware and
+    /// streaming settings. The session is keyed by the endpoint URL.
+    pub fn connect_profile_session(&self, profile_id: &str) -> Result<Arc<Session>> {
+        let profile = Self::get_profile(profile_id)?.ok_or_else(|| anyhow!("profile {} not found", profile_id))?;
+        let id = profile.endpoint.to_string();
+        if let Some(existing) = self.session(&id).filter(|s| s.is_connected()) {
+            return Ok(existing);
+        }
+        let transport = Self::connect_profile(profile_id)?;
+        let protocol = Protocol::for_endpoint(&profile.endpoint, profile.firmware);
+        let options = SessionOptions::from_profile(&profile);
+        Ok(self.open_session(&id, profile.endpoint.clone(), transport, protocol, options))
+    }
+
+    /// Look up a session by device id.
+    pub fn session(&self, id: &str) -> Option<Arc<Session>> {
+        self.sessions.lock().unwrap().get(id).cloned()
+    }
+
+    /// Snapshot of the session map, so callers never hold its lock while
+    /// talking to a machine.
+    fn all_sessions(&self) -> Vec<Arc<Session>> {
+        self.sessions.lock().unwrap().values().cloned().collect()
+    }
+
+    /// All sessions, including ones whose transport has dropped.
+    pub fn list_sessions(&self) -> Vec<SessionInfo> {
+        let mut out: Vec<SessionInfo> = self
+            .all_sessions()
+            .iter()
+            .map(|s| {
+                let state = s.state();
+                SessionInfo {
+                    id: s.id().to_string(),
+                    endpoint: s.endpoint().to_string(),
+                    connected: s.is_connected(),
+                    status: state.status,
+                    alarm: state.alarm,
+                    progress: s.progress(),
+                    queued_jobs: s.queued_jobs().len(),
+                }
+            })
+            .collect();
+        out.sort_by(|a, b| a.id.cmp(&b.id));
+        out
+    }
+
+    /// Aggregated status of every machine.
+    pub fn fleet_status(&self) -> FleetStatus {
+        let sessions = self.list_sessions();
+        FleetStatus {
+            connected: sessions.iter().filter(|s| s.connected).count(),
+            streaming: sessions.iter().filter(|s| s.progress.status.is_active()).count(),
+            alarmed: sessions
+                .iter()
+                .filter(|s| s.status == MachineStatus::Alarm || s.alarm.is_some())
+                .count(),
+            sessions,
+        }
+    }
+
+    /// Emergency-stop every connected machine. Each stop is issued
+    /// independently; returns the ids that could not be signalled.
+    pub fn emergency_stop_all(&self) -> Vec<(String, anyhow::Error)> {
+        let mut failed = Vec::new();
+        for session in self.all_sessions() {
+            if let Err(e) = session.emergency_stop() {
+                failed.push((session.id().to_string(), e));
+            }
+        }
+        info!(failed = failed.len(), "device_manager::emergency_stop_all: issued");
+        failed
+    }
+
+    /// Disconnect and forget a session. Returns whether it existed.
+    pub fn disconnect(&self, id: &str) -> Result<bool> {
+        let removed = self.sessions.lock().unwrap().remove(id);
+        match removed {
+            Some(session) => {
+                info!(id = %id, "device_manager::disconnect: closing session");
+                session.disconnect()?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
     /// Discover available devices on the system. Returns tuples of (id, display_name, transport_hint)
-    /// For now this includes serial ports and a placeholder for network discovery.
+    /// covering persisted devices, serial ports and network controllers.
     pub fn discover_devices() -> Result<Vec<(String, String, String)>> {
         let mut devices = Vec::new();
         // Pre-load any persisted devices so discovery can include known devices
         if let Ok(persisted) = crate::device::load_devices() {
             for d in persisted.iter() {
-                devices.push((d.id.clone(), d.name.clone(), format!("{:?}", d.transport)));
+                let id = d.endpoint.as_ref().map(|e| e.to_string()).unwrap_or_else(|| d.id.clone());
+                devices.push((id, d.name.clone(), format!("{:?}", d.transport)));
             }
         }
         // Serial ports (structured info)
@@ -41,12 +225,20 @@ impl DeviceManager {
                 } else {
                     p.path.clone()
                 };
-                let id = format!("serial:{}", p.path);
+                let id = Endpoint::serial(p.path.as_str()).to_string();
                 devices.push((id.clone(), display, "serial".to_string()));
             }
         }
 
-        // Network discovery placeholder: return empty for now
+        // Network controllers (mDNS, plus subnet scan when configured)
+        if let Ok(found) = Self::discover_network(&crate::config::discovery_options()) {
+            for d in found {
+                if !devices.iter().any(|(id, _, _)| *id == d.id) {
+                    devices.push((d.id, d.display, d.transport));
+                }
+            }
+        }
+
         Ok(devices)
     }
 
@@ -57,7 +249,7 @@ impl DeviceManager {
         if let Ok(persisted) = crate::device::load_devices() {
             for d in persisted.into_iter() {
                 out.push(DiscoveredDevice {
-                    id: d.id.clone(),
+                    id: d.endpoint.as_ref().map(|e| e.to_string()).unwrap_or_else(|| d.id.clone()),
                     display: d.name.clone(),
                     transport: format!("{:?}", d.transport),
                     product: None,
@@ -81,7 +273,7 @@ impl DeviceManager {
                     p.path.clone()
                 };
                 out.push(DiscoveredDevice {
-                    id: format!("serial:{}", p.path),
+                    id: Endpoint::serial(p.path.as_str()).to_string(),
                     display,
                     transport: "serial".to_string(),
                     product: p.product.clone(),
@@ -92,9 +284,44 @@ impl DeviceManager {
             }
         }
 
+        // Network controllers
+        if let Ok(found) = Self::discover_network(&crate::config::discovery_options()) {
+            for d in found {
+                if !out.iter().any(|o| o.id == d.id) {
+                    out.push(d);
+                }
+            }
+        }
+
         Ok(out)
     }
 
+    /// Discover FluidNC/ESP3D/grblHAL controllers on the network using mDNS
+    /// and, if `opts.scan_subnet` is set, a TCP port scan. Device ids are
+    /// endpoint URLs accepted by `connect_endpoint` (e.g. `telnet://10.0.0.5:23`).
+    pub fn discover_network(opts: &DiscoveryOptions) -> Result<Vec<DiscoveredDevice>> {
+        let peers = gcodekit_device_adapters::discovery::discover(opts);
+        debug!(count = peers.len(), "device_manager::discover_network: peers found");
+        Ok(peers
+            .into_iter()
+            .map(|p| {
+                let display = match p.source {
+                    PeerSource::Mdns => format!("{} ({}, {})", p.name, p.addr, p.transport),
+                    PeerSource::PortScan => format!("{} ({})", p.addr, p.transport),
+                };
+                DiscoveredDevice {
+                    id: endpoint_for_peer(&p).to_string(),
+                    display,
+                    transport: p.transport,
+                    product: None,
+                    manufacturer: None,
+                    vid: None,
+                    pid: None,
+                }
+            })
+            .collect())
+    }
+
     /// Attempt to discover a TCP peer at the provided address string using a short timeout.
     /// Returns the peer string on success.
     pub fn discover_tcp_peer(addr: &str, timeout: std::time::Duration) -> Result<String> {
@@ -109,104 +336,202 @@ impl DeviceManager {
         let transport = gcodekit_device_adapters::create_tcp_transport(addr)?;
         debug!(addr = %addr, "device_manager::connect_network: transport created");
         // Persist a simple Device record for this network connection
-        let dev = crate::models::Device {
-            id: format!("tcp:{}", addr),
-            name: format!("TCP Device {}", addr),
-            port: addr.to_string(),
-            baud: None,
-            firmware: None,
-            capabilities: vec![],
-            status: crate::models::DeviceStatus::Connected,
-            transport: crate::models::Transport::Tcp,
+        let endpoint = Endpoint::Tcp {
+            host: addr.ip().to_string(),
+            port: addr.port(),
         };
-        // Save best-effort; ignore errors so connect still returns success
-        let _ = crate::device::save_devices(vec![dev]);
+        let dev = crate::models::Device::from_endpoint(&endpoint, format!("TCP Device {}", addr));
+        // Merge best-effort; ignore errors so connect still returns success
+        let _ = crate::device::upsert_device(dev);
         Ok(transport)
     }
 
-    /// Connect to a device by endpoint string. Supports:
-    /// - tcp://host:port or host:port -> TCP
-    /// - serial:///dev/ttyXXX or a path starting with '/' -> serial device
-    ///   Returns a boxed `Transport` for the selected adapter.
+    /// Connect to a device by endpoint URL (see `crate::endpoint` for the
+    /// accepted forms, e.g. `serial:///dev/ttyUSB0?baud=115200`,
+    /// `telnet://fluidnc.local`, `ws://10.0.0.5:81`, `sim:grbl`).
+    /// Returns a boxed `Transport` for the selected adapter.
     pub fn connect_endpoint(endpoint: &str) -> Result<Box<dyn Transport>> {
-        // Quick heuristic: ws:// or wss:// indicates websocket, tcp:// or presence of ':' after a hostname indicates TCP
-        if endpoint.starts_with("ws://") || endpoint.starts_with("wss://") {
-            // The websocket transport factory is only available when the device-adapters
-            // crate is compiled with the `websocket` feature. If it's not enabled,
-            // return an error with a suggestion.
-            #[cfg(feature = "websocket")]
-            {
-                info!(endpoint = %endpoint, "device_manager::connect_endpoint: websocket requested");
-                let transport = gcodekit_device_adapters::create_websocket_transport(endpoint)?;
-                debug!(endpoint = %endpoint, "device_manager::connect_endpoint: websocket transport created");
-                return Ok(transport);
-            }
-
-            #[cfg(not(feature = "websocket"))]
-            {
-                return Err(anyhow::anyhow!("websocket transport requested but device-adapters not built with 'websocket' feature"));
-            }
-        }
+        let ep: Endpoint = endpoint.parse()?;
+        Self::connect(&ep)
+    }
 
-        if endpoint.starts_with("tcp://") || (endpoint.contains(":") && !endpoint.starts_with('/'))
-        {
-            // Strip optional tcp://
-            let ep = endpoint.trim_start_matches("tcp://");
-            let sock: std::net::SocketAddr = ep.parse()?;
-            info!(endpoint = %ep, sock = %sock, "device_manager::connect_endpoint: tcp requested");
-            let transport = gcodekit_device_adapters::create_tcp_transport(sock)?;
-            debug!(endpoint = %ep, sock = %sock, "device_manager::connect_endpoint: tcp transport created");
-            Ok(transport)
-        } else {
-            // Assume a serial device path. Support optional serial:// prefix and
-            // simple query params: baud and timeout_ms, e.g. serial:///dev/ttyUSB0?baud=115200&timeout_ms=500
-            let mut baud = 115200u32;
-            let mut timeout = std::time::Duration::from_millis(200);
-            // We'll optionally own a path string if parsing a URL; otherwise
-            // we reuse the borrowed endpoint string.
-            let mut owned_path: Option<String> = None;
-            if endpoint.starts_with("serial://") || endpoint.contains('?') {
-                // Use the url crate to parse query params safely.
-                let url = url::Url::parse(endpoint)
-                    .or_else(|_| url::Url::parse(&format!("serial://{}", endpoint)))?;
-                let path_owned = url.path().to_string();
-                let p = path_owned
-                    .strip_prefix('/')
-                    .unwrap_or(&path_owned)
-                    .to_string();
-                owned_path = Some(p);
-                for (k, v) in url.query_pairs() {
-                    match k.as_ref() {
-                        "baud" => {
-                            if let Ok(b) = v.parse::<u32>() {
-                                baud = b
-                            }
-                        }
-                        "timeout_ms" => {
-                            if let Ok(ms) = v.parse::<u64>() {
-                                timeout = std::time::Duration::from_millis(ms)
-                            }
+    /// Connect to a typed endpoint. Network hosts are resolved and each
+    /// resolved address is tried in turn.
+    pub fn connect(endpoint: &Endpoint) -> Result<Box<dyn Transport>> {
+        info!(endpoint = %endpoint, "device_manager::connect: attempting");
+        let transport: Box<dyn Transport> = match endpoint {
+            Endpoint::Serial {
+                path,
+                baud,
+                timeout_ms,
+                parity,
+                flow_control,
+            } => {
+                let opts = gcodekit_device_adapters::SerialOptions {
+                    baud: baud.unwrap_or(crate::endpoint::DEFAULT_BAUD),
+                    timeout: timeout_ms
+                        .map(std::time::Duration::from_millis)
+                        .unwrap_or(crate::endpoint::DEFAULT_SERIAL_TIMEOUT),
+                    parity: parity.clone(),
+                    flow_control: flow_control.clone(),
+                };
+                debug!(path = %path, baud = %opts.baud, "device_manager::connect: serial requested");
+                gcodekit_device_adapters::create_serial_transport_with_options(path, opts)?
+            }
+            Endpoint::Tcp { .. } => {
+                let mut last_err = anyhow::anyhow!("tcp connect failed");
+                let mut connected = None;
+                for addr in endpoint.resolve()? {
+                    match gcodekit_device_adapters::create_tcp_transport(addr) {
+                        Ok(t) => {
+                            connected = Some(t);
+                            break;
                         }
-                        _ => {}
+                        Err(e) => last_err = e.into(),
                     }
                 }
-            } else if endpoint.starts_with('/') {
-                owned_path = Some(endpoint.to_string());
+                connected.ok_or(last_err)?
+            }
+            Endpoint::Udp { bind, .. } => {
+                let peer = endpoint.resolve()?[0];
+                let bind = bind.clone().unwrap_or_else(|| {
+                    if peer.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }.to_string()
+                });
+                gcodekit_device_adapters::create_udp_transport(&bind, &peer.to_string())?
             }
+            Endpoint::WebSocket { .. } => {
+                // The websocket transport factory is only available when the device-adapters
+                // crate is compiled with the `websocket` feature. If it's not enabled,
+                // return an error with a suggestion.
+                #[cfg(feature = "websocket")]
+                {
+                    let tls = crate::config::tls_options();
+                    gcodekit_device_adapters::create_websocket_transport_with_tls(&endpoint.to_string(), &tls)?
+                }
 
-            let path_str = owned_path.as_deref().unwrap_or(endpoint);
+                #[cfg(not(feature = "websocket"))]
+                {
+                    return Err(anyhow::anyhow!("websocket transport requested but device-adapters not built with 'websocket' feature"));
+                }
+            }
+            Endpoint::Telnet {
+                binary, keepalive_ms, ..
+            } => {
+                // Telnet boards (FluidNC, grblHAL WiFi) are often addressed by
+                // mDNS hostname, so resolve rather than parse.
+                let defaults = gcodekit_device_adapters::telnet::TelnetOptions::default();
+                let opts = gcodekit_device_adapters::telnet::TelnetOptions {
+                    binary: binary.unwrap_or(defaults.binary),
+                    keepalive: match keepalive_ms {
+                        Some(0) => None,
+                        Some(ms) => Some(std::time::Duration::from_millis(*ms)),
+                        None => defaults.keepalive,
+                    },
+                    timeout: crate::config::network_timeout(),
+                };
+                let addrs = endpoint.resolve()?;
+                gcodekit_device_adapters::create_telnet_transport_with_options(&addrs[..], opts)?
+            }
+            Endpoint::Sim { firmware, latency_ms } => {
+                gcodekit_device_adapters::create_sim_transport(gcodekit_device_adapters::sim::SimOptions {
+                    firmware: *firmware,
+                    latency: std::time::Duration::from_millis(latency_ms.unwrap_or(0)),
+                    ..Default::default()
+                })?
+            }
+            Endpoint::Replay { path, delay_ms } => gcodekit_device_adapters::create_replay_transport(
+                path,
+                gcodekit_device_adapters::replay::ReplayOptions {
+                    delay: std::time::Duration::from_millis(delay_ms.unwrap_or(0)),
+                },
+            )?,
+        };
+        debug!(endpoint = %endpoint, "device_manager::connect: transport created");
+        Ok(transport)
+    }
 
-            let opts = gcodekit_device_adapters::SerialOptions {
-                baud,
-                timeout,
-                parity: None,
-                flow_control: None,
-            };
-            debug!(path = %path_str, baud = %opts.baud, "device_manager::connect_endpoint: serial requested");
-            let transport = gcodekit_device_adapters::create_serial_transport_with_options(path_str, opts)?;
-            debug!(path = %path_str, "device_manager::connect_endpoint: serial transport created");
-            Ok(transport)
-        }
+    /// All saved machine profiles.
+    pub fn list_profiles() -> Result<Vec<MachineProfile>> {
+        Ok(crate::profile::load_profiles()?)
+    }
+
+    /// Look up a saved profile by id.
+    pub fn get_profile(id: &str) -> Result<Option<MachineProfile>> {
+        Ok(crate::profile::load_profiles()?.into_iter().find(|p| p.id == id))
+    }
+
+    /// Save a new profile. Fails if a profile with the same id exists.
+    pub fn create_profile(profile: MachineProfile) -> Result<()> {
+        profile.validate()?;
+        let id = profile.id.clone();
+        crate::profile::update_profiles(|profiles| {
+            if profiles.iter().any(|p| p.id == profile.id) {
+                return Err(anyhow!("profile {} already exists", profile.id));
+            }
+            profiles.push(profile);
+            Ok(())
+        })??;
+        info!(id = %id, "device_manager::create_profile: profile created");
+        Ok(())
+    }
+
+    /// Replace an existing profile, matched by id.
+    pub fn update_profile(profile: MachineProfile) -> Result<()> {
+        profile.validate()?;
+        let id = profile.id.clone();
+        crate::profile::update_profiles(|profiles| {
+            let slot = profiles
+                .iter_mut()
+                .find(|p| p.id == profile.id)
+                .ok_or_else(|| anyhow!("profile {} not found", profile.id))?;
+            *slot = profile;
+            Ok::<_, anyhow::Error>(())
+        })??;
+        info!(id = %id, "device_manager::update_profile: profile updated");
+        Ok(())
+    }
+
+    /// Delete a profile. Returns whether it existed.
+    pub fn delete_profile(id: &str) -> Result<bool> {
+        let removed = crate::profile::update_profiles(|profiles| {
+            let before = profiles.len();
+            profiles.retain(|p| p.id != id);
+            profiles.len() != before
+        })?;
+        debug!(id = %id, removed, "device_manager::delete_profile: done");
+        Ok(removed)
+    }
+
+    /// Connect to the endpoint of a saved profile and record the machine
+    /// in `devices.json` under the profile name.
+    pub fn connect_profile(id: &str) -> Result<Box<dyn Transport>> {
+        let profile = Self::get_profile(id)?.ok_or_else(|| anyhow!("profile {} not found", id))?;
+        let transport = Self::connect(&profile.endpoint)?;
+        let mut dev = crate::models::Device::from_endpoint(&profile.endpoint, profile.name.clone());
+        dev.firmware = profile.firmware.map(|f| format!("{:?}", f));
+        let _ = crate::device::upsert_device(dev);
+        Ok(transport)
+    }
+}
+
+/// Endpoint for a controller found by network discovery.
+fn endpoint_for_peer(peer: &NetworkPeer) -> Endpoint {
+    let host = peer.addr.ip().to_string();
+    let port = peer.addr.port();
+    match peer.transport.as_str() {
+        "telnet" => Endpoint::Telnet {
+            host,
+            port,
+            binary: None,
+            keepalive_ms: None,
+        },
+        "websocket" => Endpoint::WebSocket {
+            secure: false,
+            host,
+            port: Some(port),
+            path: String::new(),
+        },
+        _ => Endpoint::Tcp { host, port },
     }
 }
 
@@ -233,6 +558,28 @@ mod tests {
         let _ = conn.send_line("M115");
     }
 
+    #[test]
+    fn test_connect_telnet_endpoint_strips_negotiation() {
+        use std::io::{Read, Write};
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        let addr = listener.local_addr().unwrap();
+
+        std::thread::spawn(move || {
+            if let Ok((mut s, _)) = listener.accept() {
+                // IAC WILL ECHO followed by a response split around it
+                let _ = s.write_all(&[255, 251, 1, b'o']);
+                let _ = s.write_all(b"k\r\n");
+                let mut buf = [0u8; 64];
+                let _ = s.read(&mut buf);
+                std::thread::sleep(std::time::Duration::from_millis(50));
+            }
+        });
+
+        let endpoint = format!("telnet://127.0.0.1:{}", addr.port());
+        let mut conn = DeviceManager::connect_endpoint(&endpoint).expect("connect telnet");
+        assert_eq!(conn.read_line().expect("read"), "ok");
+    }
+
     #[test]
     #[ignore]
     fn test_connect_serial_endpoint_ignored() {
@@ -0,0 +1,465 @@
+//! Typed connection endpoints and their URL form.
+//!
+//! Every transport is addressed by a URL whose options are query params:
+//!
+//! - `serial:///dev/ttyUSB0?baud=115200&timeout_ms=200` (`serial://COM3` on Windows)
+//! - `tcp://host:port`, `udp://host:port?bind=0.0.0.0:0`
+//! - `ws://host[:port]/path`, `wss://...`
+//! - `telnet://host[:port]?binary=false&keepalive_ms=5000`
+//! - `sim:grbl?latency_ms=5`, `replay:///path/to/session.log?delay_ms=10`
+//!
+//! Serial and replay paths are percent-encoded where they would clash with
+//! URL syntax (`?`, `#`, `%`, spaces).
+//!
+//! Older shapes are still accepted when parsing (`serial:/dev/x`,
+//! `tcp:host:port`, bare `host:port`, bare device paths) so persisted ids
+//! keep working; `Display` always produces the canonical URL.
+
+use crate::error::CoreError;
+use crate::models::Transport as TransportKind;
+use gcodekit_device_adapters::sim::SimFirmware;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Default baud rate for serial endpoints without a `baud` param.
+pub const DEFAULT_BAUD: u32 = 115200;
+/// Default serial read timeout when `timeout_ms` is not given.
+pub const DEFAULT_SERIAL_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// A parsed connection endpoint.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Endpoint {
+    Serial {
+        path: String,
+        baud: Option<u32>,
+        timeout_ms: Option<u64>,
+        parity: Option<String>,
+        flow_control: Option<String>,
+    },
+    Tcp {
+        host: String,
+        port: u16,
+    },
+    Udp {
+        host: String,
+        port: u16,
+        /// Local bind address; defaults to an ephemeral port.
+        bind: Option<String>,
+    },
+    WebSocket {
+        secure: bool,
+        host: String,
+        port: Option<u16>,
+        /// Request path including any server query string, e.g. `/ws`.
+        path: String,
+    },
+    Telnet {
+        host: String,
+        port: u16,
+        binary: Option<bool>,
+        keepalive_ms: Option<u64>,
+    },
+    Sim {
+        firmware: SimFirmware,
+        latency_ms: Option<u64>,
+    },
+    Replay {
+        path: PathBuf,
+        delay_ms: Option<u64>,
+    },
+}
+
+fn invalid(msg: impl Into<String>) -> CoreError {
+    CoreError::Endpoint(msg.into())
+}
+
+/// Characters escaped in serial and replay paths: those that would end the
+/// path or be read as an escape, plus what URLs never carry raw.
+const PATH: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+fn decode_path(s: &str) -> Result<String, CoreError> {
+    percent_decode_str(s)
+        .decode_utf8()
+        .map(|p| p.into_owned())
+        .map_err(|_| invalid(format!("invalid path encoding: {}", s)))
+}
+
+/// Split `host[:port]`, accepting bracketed IPv6 literals.
+fn split_host_port(s: &str) -> Result<(String, Option<u16>), CoreError> {
+    if let Some(rest) = s.strip_prefix('[') {
+        let (host, after) = rest.split_once(']').ok_or_else(|| invalid(format!("unterminated IPv6 host: {}", s)))?;
+        let port = match after.strip_prefix(':') {
+            Some(p) => Some(p.parse().map_err(|_| invalid(format!("invalid port: {}", p)))?),
+            None if after.is_empty() => None,
+            None => return Err(invalid(format!("invalid host: {}", s))),
+        };
+        return Ok((host.to_string(), port));
+    }
+    match s.rsplit_once(':') {
+        // A bare IPv6 address has several colons and no port
+        Some((host, _)) if host.contains(':') => Ok((s.to_string(), None)),
+        Some((host, port)) => {
+            let port = port.parse().map_err(|_| invalid(format!("invalid port: {}", port)))?;
+            Ok((host.to_string(), Some(port)))
+        }
+        None => Ok((s.to_string(), None)),
+    }
+}
+
+fn fmt_host(host: &str) -> String {
+    if host.contains(':') {
+        format!("[{}]", host)
+    } else {
+        host.to_string()
+    }
+}
+
+fn require_host(host: &str, scheme: &str) -> Result<(), CoreError> {
+    if host.is_empty() {
+        return Err(invalid(format!("{} endpoint requires a host", scheme)));
+    }
+    Ok(())
+}
+
+fn parse_param<T: FromStr>(key: &str, value: &str) -> Result<T, CoreError> {
+    value
+        .parse()
+        .map_err(|_| invalid(format!("invalid value for {}: {}", key, value)))
+}
+
+fn is_serial_path(s: &str) -> bool {
+    s.starts_with('/')
+        || s.strip_prefix("COM")
+            .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+}
+
+impl Endpoint {
+    /// Serial endpoint for `path` with default options.
+    pub fn serial(path: impl Into<String>) -> Self {
+        Endpoint::Serial {
+            path: path.into(),
+            baud: None,
+            timeout_ms: None,
+            parity: None,
+            flow_control: None,
+        }
+    }
+
+    /// URL scheme of this endpoint.
+    pub fn scheme(&self) -> &'static str {
+        match self {
+            Endpoint::Serial { .. } => "serial",
+            Endpoint::Tcp { .. } => "tcp",
+            Endpoint::Udp { .. } => "udp",
+            Endpoint::WebSocket { secure: false, .. } => "ws",
+            Endpoint::WebSocket { secure: true, .. } => "wss",
+            Endpoint::Telnet { .. } => "telnet",
+            Endpoint::Sim { .. } => "sim",
+            Endpoint::Replay { .. } => "replay",
+        }
+    }
+
+    /// Transport kind recorded for persisted devices.
+    pub fn transport(&self) -> TransportKind {
+        match self {
+            Endpoint::Serial { .. } => TransportKind::Serial,
+            Endpoint::Tcp { .. } => TransportKind::Tcp,
+            Endpoint::Udp { .. } => TransportKind::Udp,
+            Endpoint::WebSocket { .. } => TransportKind::WebSocket,
+            Endpoint::Telnet { .. } => TransportKind::Telnet,
+            Endpoint::Sim { .. } => TransportKind::Sim,
+            Endpoint::Replay { .. } => TransportKind::Replay,
+        }
+    }
+
+    /// Host and port for network endpoints (WebSocket ports default to
+    /// 80/443). `None` for serial, sim and replay.
+    pub fn host_port(&self) -> Option<(&str, u16)> {
+        match self {
+            Endpoint::Tcp { host, port } | Endpoint::Udp { host, port, .. } | Endpoint::Telnet { host, port, .. } => {
+                Some((host, *port))
+            }
+            Endpoint::WebSocket { secure, host, port, .. } => {
+                Some((host, port.unwrap_or(if *secure { 443 } else { 80 })))
+            }
+            _ => None,
+        }
+    }
+
+    /// Resolve the host of a network endpoint (DNS, mDNS via the system
+    /// resolver, or IP literals).
+    pub fn resolve(&self) -> Result<Vec<SocketAddr>, CoreError> {
+        let (host, port) = self
+            .host_port()
+            .ok_or_else(|| invalid(format!("{} endpoint has no network address", self.scheme())))?;
+        let addrs: Vec<SocketAddr> = (host, port).to_socket_addrs()?.collect();
+        if addrs.is_empty() {
+            return Err(invalid(format!("{} did not resolve to any address", host)));
+        }
+        Ok(addrs)
+    }
+
+    fn parse_url(scheme: &str, rest: &str) -> Result<Self, CoreError> {
+        let target = rest.strip_prefix("//").unwrap_or(rest);
+        let (target, query) = match target.split_once('?') {
+            Some((t, q)) => (t, Some(q)),
+            None => (target, None),
+        };
+        let params: Vec<(String, String)> = query
+            .map(|q| url::form_urlencoded::parse(q.as_bytes()).into_owned().collect())
+            .unwrap_or_default();
+        let unknown = |k: &str| invalid(format!("unknown {} option: {}", scheme, k));
+
+        match scheme {
+            "serial" => {
+                if target.is_empty() {
+                    return Err(invalid("serial endpoint requires a device path"));
+                }
+                let mut ep = Endpoint::serial(decode_path(target)?);
+                if let Endpoint::Serial {
+                    baud,
+                    timeout_ms,
+                    parity,
+                    flow_control,
+                    ..
+                } = &mut ep
+                {
+                    for (k, v) in &params {
+                        match k.as_str() {
+                            "baud" => *baud = Some(parse_param(k, v)?),
+                            "timeout_ms" => *timeout_ms = Some(parse_param(k, v)?),
+                            "parity" => *parity = Some(v.clone()),
+                            "flow_control" => *flow_control = Some(v.clone()),
+                            _ => return Err(unknown(k)),
+                        }
+                    }
+                }
+                Ok(ep)
+            }
+            "tcp" | "udp" | "telnet" => {
+                let target = target.trim_end_matches('/');
+                let (host, port) = split_host_port(target)?;
+                require_host(&host, scheme)?;
+                match scheme {
+                    "tcp" => {
+                        if let Some((k, _)) = params.first() {
+                            return Err(unknown(k));
+                        }
+                        let port = port.ok_or_else(|| invalid("tcp endpoint requires a port"))?;
+                        Ok(Endpoint::Tcp { host, port })
+                    }
+                    "udp" => {
+                        let port = port.ok_or_else(|| invalid("udp endpoint requires a port"))?;
+                        let mut bind = None;
+                        for (k, v) in &params {
+                            match k.as_str() {
+                                "bind" => bind = Some(v.clone()),
+                                _ => return Err(unknown(k)),
+                            }
+                        }
+                        Ok(Endpoint::Udp { host, port, bind })
+                    }
+                    _ => {
+                        let mut binary = None;
+                        let mut keepalive_ms = None;
+                        for (k, v) in &params {
+                            match k.as_str() {
+                                "binary" => binary = Some(parse_param(k, v)?),
+                                "keepalive_ms" => keepalive_ms = Some(parse_param(k, v)?),
+                                _ => return Err(unknown(k)),
+                            }
+                        }
+                        Ok(Endpoint::Telnet {
+                            host,
+                            port: port.unwrap_or(gcodekit_device_adapters::telnet::DEFAULT_PORT),
+                            binary,
+                            keepalive_ms,
+                        })
+                    }
+                }
+            }
+            "ws" | "wss" => {
+                let (authority, path) = match target.find('/') {
+                    Some(i) => (&target[..i], target[i..].to_string()),
+                    None => (target, String::new()),
+                };
+                let (host, port) = split_host_port(authority)?;
+                require_host(&host, scheme)?;
+                // Query params belong to the server request for WebSockets
+                let path = match query {
+                    Some(q) => format!("{}?{}", if path.is_empty() { "/" } else { &path }, q),
+                    None => path,
+                };
+                Ok(Endpoint::WebSocket {
+                    secure: scheme == "wss",
+                    host,
+                    port,
+                    path,
+                })
+            }
+            "sim" => {
+                let firmware = target
+                    .trim_end_matches('/')
+                    .parse::<SimFirmware>()
+                    .map_err(|e| invalid(e.to_string()))?;
+                let mut latency_ms = None;
+                for (k, v) in &params {
+                    match k.as_str() {
+                        "latency_ms" => latency_ms = Some(parse_param(k, v)?),
+                        _ => return Err(unknown(k)),
+                    }
+                }
+                Ok(Endpoint::Sim { firmware, latency_ms })
+            }
+            "replay" => {
+                if target.is_empty() {
+                    return Err(invalid("replay endpoint requires a session file path"));
+                }
+                let mut delay_ms = None;
+                for (k, v) in &params {
+                    match k.as_str() {
+                        "delay_ms" => delay_ms = Some(parse_param(k, v)?),
+                        _ => return Err(unknown(k)),
+                    }
+                }
+                Ok(Endpoint::Replay {
+                    path: PathBuf::from(decode_path(target)?),
+                    delay_ms,
+                })
+            }
+            other => Err(invalid(format!("unsupported scheme: {}", other))),
+        }
+    }
+}
+
+const SCHEMES: &[&str] = &["serial", "tcp", "udp", "ws", "wss", "telnet", "sim", "replay"];
+
+impl FromStr for Endpoint {
+    type Err = CoreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some((scheme, rest)) = s.split_once(':') {
+            let scheme = scheme.to_ascii_lowercase();
+            if SCHEMES.contains(&scheme.as_str()) {
+                return Endpoint::parse_url(&scheme, rest);
+            }
+        }
+        // Scheme-less legacy forms: device paths and host:port
+        if is_serial_path(s) {
+            return Endpoint::parse_url("serial", s);
+        }
+        if s.contains(':') {
+            return Endpoint::parse_url("tcp", s);
+        }
+        Err(invalid(format!("unrecognized endpoint: {}", s)))
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        match self {
+            Endpoint::Serial {
+                path,
+                baud,
+                timeout_ms,
+                parity,
+                flow_control,
+            } => {
+                write!(f, "serial://{}", utf8_percent_encode(path, PATH))?;
+                if let Some(b) = baud {
+                    query.append_pair("baud", &b.to_string());
+                }
+                if let Some(t) = timeout_ms {
+                    query.append_pair("timeout_ms", &t.to_string());
+                }
+                if let Some(p) = parity {
+                    query.append_pair("parity", p);
+                }
+                if let Some(fc) = flow_control {
+                    query.append_pair("flow_control", fc);
+                }
+            }
+            Endpoint::Tcp { host, port } => write!(f, "tcp://{}:{}", fmt_host(host), port)?,
+            Endpoint::Udp { host, port, bind } => {
+                write!(f, "udp://{}:{}", fmt_host(host), port)?;
+                if let Some(b) = bind {
+                    query.append_pair("bind", b);
+                }
+            }
+            Endpoint::WebSocket {
+                secure,
+                host,
+                port,
+                path,
+            } => {
+                write!(f, "{}://{}", if *secure { "wss" } else { "ws" }, fmt_host(host))?;
+                if let Some(p) = port {
+                    write!(f, ":{}", p)?;
+                }
+                // The request path carries its own query string
+                return f.write_str(path);
+            }
+            Endpoint::Telnet {
+                host,
+                port,
+                binary,
+                keepalive_ms,
+            } => {
+                write!(f, "telnet://{}:{}", fmt_host(host), port)?;
+                if let Some(b) = binary {
+                    query.append_pair("binary", &b.to_string());
+                }
+                if let Some(k) = keepalive_ms {
+                    query.append_pair("keepalive_ms", &k.to_string());
+                }
+            }
+            Endpoint::Sim { firmware, latency_ms } => {
+                write!(f, "sim:{}", firmware)?;
+                if let Some(l) = latency_ms {
+                    query.append_pair("latency_ms", &l.to_string());
+                }
+            }
+            Endpoint::Replay { path, delay_ms } => {
+                write!(f, "replay://{}", utf8_percent_encode(&path.to_string_lossy(), PATH))?;
+                if let Some(d) = delay_ms {
+                    query.append_pair("delay_ms", &d.to_string());
+                }
+            }
+        }
+        let query = query.finish();
+        if !query.is_empty() {
+            write!(f, "?{}", query)?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for Endpoint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Endpoint {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
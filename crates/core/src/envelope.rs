@@ -0,0 +1,100 @@
+//! Soft-limit checks against a machine's work envelope.
+//!
+//! `check_job` places a job's per-WCS extents (from `gcode::analyze`) in
+//! machine coordinates using the controller's offsets and reports every
+//! axis that would leave the profile's `WorkEnvelope`. Offsets must have
+//! been read (`$#`) first; `unreported_offsets` lists the systems a job uses
+//! that the controller has not reported. `check_position` does the same for
+//! a live machine position while a job streams.
+
+use crate::gcode::JobAnalysis;
+use crate::machine_state::MachineState;
+use crate::profile::WorkEnvelope;
+use std::fmt;
+
+/// Reported positions are rounded, so limits are this lenient (mm).
+const TOLERANCE: f64 = 0.001;
+
+const AXES: [char; 3] = ['X', 'Y', 'Z'];
+
+/// An axis that leaves the envelope.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvelopeViolation {
+    /// Work coordinate system of the offending moves; `None` for a live
+    /// machine position.
+    pub wcs: Option<String>,
+    pub axis: char,
+    /// Furthest machine coordinate reached on `axis`.
+    pub position: f64,
+    /// The limit it passes.
+    pub limit: f64,
+}
+
+impl fmt::Display for EnvelopeViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(wcs) = &self.wcs {
+            write!(f, "{}: ", wcs)?;
+        }
+        write!(f, "{} {:.3} is past the limit {:.3}", self.axis, self.position, self.limit)
+    }
+}
+
+/// Origin of `wcs` in machine coordinates: its `$#` offset plus any G92
+/// offset. `None` when the controller has not reported the offset.
+pub fn wcs_origin(state: &MachineState, wcs: &str) -> Option<[f64; 3]> {
+    let offset = state.offsets.get(wcs)?;
+    let g92 = state.offsets.get("G92").copied().unwrap_or_default();
+    Some([0, 1, 2].map(|i| offset[i] + g92[i]))
+}
+
+/// Work coordinate systems `analysis` uses that have no reported offset.
+pub fn unreported_offsets(analysis: &JobAnalysis, state: &MachineState) -> Vec<String> {
+    analysis
+        .bounds
+        .keys()
+        .filter(|wcs| !state.offsets.contains_key(wcs.as_str()))
+        .cloned()
+        .collect()
+}
+
+fn check(wcs: Option<&str>, min: [f64; 3], max: [f64; 3], envelope: &WorkEnvelope) -> Vec<EnvelopeViolation> {
+    let mut out = Vec::new();
+    for i in 0..3 {
+        let mut push = |position: f64, limit: f64| {
+            out.push(EnvelopeViolation {
+                wcs: wcs.map(str::to_string),
+                axis: AXES[i],
+                position,
+                limit,
+            })
+        };
+        if min[i] < envelope.min[i] - TOLERANCE {
+            push(min[i], envelope.min[i]);
+        }
+        if max[i] > envelope.max[i] + TOLERANCE {
+            push(max[i], envelope.max[i]);
+        }
+    }
+    out
+}
+
+/// Moves of an analyzed job that would leave `envelope`, given the
+/// controller's current offsets. Systems without a reported offset are
+/// placed at the active offset (`WCO`), for controllers that cannot report
+/// them; see `unreported_offsets`.
+pub fn check_job(analysis: &JobAnalysis, envelope: &WorkEnvelope, state: &MachineState) -> Vec<EnvelopeViolation> {
+    analysis
+        .bounds
+        .iter()
+        .flat_map(|(wcs, b)| {
+            let origin = wcs_origin(state, wcs).unwrap_or(state.wco);
+            let shift = |p: [f64; 3]| [0, 1, 2].map(|i| p[i] + origin[i]);
+            check(Some(wcs), shift(b.min), shift(b.max), envelope)
+        })
+        .collect()
+}
+
+/// Axes of a machine position outside `envelope`.
+pub fn check_position(envelope: &WorkEnvelope, mpos: [f64; 3]) -> Vec<EnvelopeViolation> {
+    check(None, mpos, mpos, envelope)
+}
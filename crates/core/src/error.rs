@@ -11,6 +11,9 @@ pub enum CoreError {
     #[error("Config error: {0}")]
     Config(String),
 
+    #[error("Invalid endpoint: {0}")]
+    Endpoint(String),
+
     #[error("Other error: {0}")]
     Other(String),
 }
@@ -0,0 +1,377 @@
+//! Pre-flight report on a G-code program.
+//!
+//! `analyze` walks the program once and reports its size, extents per work
+//! coordinate system, cut and rapid distances, tools, feed and spindle
+//! ranges, commands the target firmware does not accept and likely
+//! mistakes. Distances, bounds and feeds are in millimetres; the program is
+//! assumed to start at the work origin.
+
+use super::modal::{words, ModalState};
+use super::reader::LineSource;
+use crate::profile::FirmwareKind;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::f64::consts::{PI, TAU};
+use std::fmt;
+
+/// Axis-aligned box in work coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Bounds {
+    pub min: [f64; 3],
+    pub max: [f64; 3],
+}
+
+impl Bounds {
+    fn at(p: [f64; 3]) -> Self {
+        Bounds { min: p, max: p }
+    }
+
+    fn extend(&mut self, p: [f64; 3]) {
+        for (i, v) in p.into_iter().enumerate() {
+            self.min[i] = self.min[i].min(v);
+            self.max[i] = self.max[i].max(v);
+        }
+    }
+
+    pub fn size(&self) -> [f64; 3] {
+        [0, 1, 2].map(|i| self.max[i] - self.min[i])
+    }
+}
+
+/// Inclusive value range.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Range {
+    pub min: f64,
+    pub max: f64,
+}
+
+fn widen(range: &mut Option<Range>, v: f64) {
+    match range {
+        Some(r) => {
+            r.min = r.min.min(v);
+            r.max = r.max.max(v);
+        }
+        None => *range = Some(Range { min: v, max: v }),
+    }
+}
+
+/// A command the selected firmware does not accept.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnsupportedCommand {
+    /// Program line index (comments and blank lines excluded).
+    pub line: usize,
+    pub code: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum WarningKind {
+    /// Motion before any G20/G21; the controller's current units apply.
+    MissingUnits,
+    /// A feed move with no feed rate set.
+    NoFeedBeforeCut,
+    /// Arc whose start and end are not the same distance from its centre,
+    /// or whose radius is too small to reach its end point.
+    ArcRadiusMismatch,
+    /// Homing or machine-coordinate move; the position after it is unknown
+    /// to the analysis.
+    UnknownPosition,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnalysisWarning {
+    /// Program line index.
+    pub line: usize,
+    pub kind: WarningKind,
+    pub message: String,
+}
+
+impl fmt::Display for AnalysisWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line + 1, self.message)
+    }
+}
+
+/// What a program will do, worked out before it is streamed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct JobAnalysis {
+    pub line_count: usize,
+    /// Extents of all moves, keyed by work coordinate system (`G54`, ...).
+    pub bounds: BTreeMap<String, Bounds>,
+    pub cut_distance: f64,
+    pub rapid_distance: f64,
+    pub tools: Vec<u32>,
+    /// Feed rates in mm/min (or inverse time under G93).
+    pub feed_range: Option<Range>,
+    pub spindle_range: Option<Range>,
+    pub unsupported: Vec<UnsupportedCommand>,
+    pub warnings: Vec<AnalysisWarning>,
+}
+
+impl JobAnalysis {
+    /// True when there is nothing for the operator to review.
+    pub fn is_clean(&self) -> bool {
+        self.unsupported.is_empty() && self.warnings.is_empty()
+    }
+}
+
+const GRBL_G: &[&str] = &[
+    "G0", "G1", "G2", "G3", "G4", "G10", "G17", "G18", "G19", "G20", "G21", "G28", "G28.1", "G30", "G30.1", "G38.2",
+    "G38.3", "G38.4", "G38.5", "G40", "G43.1", "G49", "G53", "G54", "G55", "G56", "G57", "G58", "G59", "G61", "G80",
+    "G90", "G91", "G91.1", "G92", "G92.1", "G93", "G94",
+];
+const GRBL_M: &[&str] = &["M0", "M1", "M2", "M3", "M4", "M5", "M7", "M8", "M9", "M30", "M56"];
+/// grblHAL and FluidNC add tool changes, digital/analog outputs and more WCSs.
+const GRBL_EXT_G: &[&str] = &["G5", "G5.1", "G33", "G59.1", "G59.2", "G59.3", "G61.1", "G64", "G73", "G76", "G81", "G82", "G83", "G85", "G86", "G89", "G92.2", "G92.3", "G98", "G99"];
+const GRBL_EXT_M: &[&str] = &["M6", "M48", "M49", "M50", "M51", "M61", "M62", "M63", "M64", "M65", "M66", "M67", "M68"];
+const MARLIN_G: &[&str] = &[
+    "G0", "G1", "G2", "G3", "G4", "G5", "G10", "G11", "G12", "G17", "G18", "G19", "G20", "G21", "G26", "G27", "G28", "G29",
+    "G30", "G31", "G32", "G33", "G34", "G35", "G38.2", "G38.3", "G38.4", "G38.5", "G42", "G53", "G54", "G55", "G56",
+    "G57", "G58", "G59", "G59.1", "G59.2", "G59.3", "G60", "G61", "G76", "G80", "G90", "G91", "G92", "G425",
+];
+
+fn supported(firmware: FirmwareKind, code: &str) -> bool {
+    let (g, m): (Vec<&[&str]>, Vec<&[&str]>) = match firmware {
+        FirmwareKind::Grbl => (vec![GRBL_G], vec![GRBL_M]),
+        FirmwareKind::GrblHal | FirmwareKind::FluidNc => (vec![GRBL_G, GRBL_EXT_G], vec![GRBL_M, GRBL_EXT_M]),
+        // Marlin and Smoothieware accept a wide, build-dependent M set
+        FirmwareKind::Marlin | FirmwareKind::Smoothieware => (vec![MARLIN_G], vec![]),
+    };
+    match code.as_bytes().first() {
+        Some(b'G') => g.iter().any(|set| set.contains(&code)),
+        Some(b'M') => m.is_empty() || m.iter().any(|set| set.contains(&code)),
+        _ => true,
+    }
+}
+
+pub(super) fn code(letter: char, value: &str) -> Option<String> {
+    let n: f64 = value.parse().ok()?;
+    Some(if n.fract() == 0.0 {
+        format!("{}{}", letter, n as i64)
+    } else {
+        format!("{}{}", letter, (n * 10.0).round() / 10.0)
+    })
+}
+
+fn distance(a: [f64; 3], b: [f64; 3]) -> f64 {
+    (0..3).map(|i| (b[i] - a[i]).powi(2)).sum::<f64>().sqrt()
+}
+
+/// Arc geometry in the active plane: `(axis0, axis1, linear)` indices.
+fn plane_axes(plane: &str) -> (usize, usize, usize) {
+    match plane {
+        "G18" => (2, 0, 1),
+        "G19" => (1, 2, 0),
+        _ => (0, 1, 2),
+    }
+}
+
+/// An arc move resolved to its centre, radius and sweep.
+pub(super) struct Arc {
+    pub length: f64,
+    pub radius: f64,
+    /// Signed sweep in radians (negative for clockwise).
+    pub sweep: f64,
+    pub radius_error: Option<String>,
+    start: [f64; 3],
+    centre: (f64, f64),
+    t0: f64,
+    dz: f64,
+    axes: (usize, usize, usize),
+}
+
+impl Arc {
+    /// `steps` evenly spaced points along the arc, the last at its end.
+    pub fn points(&self, steps: usize) -> impl Iterator<Item = [f64; 3]> + '_ {
+        let (a0, a1, lin) = self.axes;
+        (1..=steps).map(move |i| {
+            let f = i as f64 / steps as f64;
+            let t = self.t0 + self.sweep * f;
+            let mut p = self.start;
+            p[a0] = self.centre.0 + self.radius * t.cos();
+            p[a1] = self.centre.1 + self.radius * t.sin();
+            p[lin] = self.start[lin] + self.dz * f;
+            p
+        })
+    }
+}
+
+/// Resolve an arc from `start` to `end`. `offsets` are the I/J/K words
+/// (already in mm); `radius` the R word.
+pub(super) fn arc(start: [f64; 3], end: [f64; 3], clockwise: bool, plane: &str, offsets: [f64; 3], radius: Option<f64>) -> Arc {
+    let (a0, a1, lin) = plane_axes(plane);
+    let mut radius_error = None;
+    let (c0, c1) = match radius {
+        Some(r) => {
+            // GRBL's radius-format centre calculation
+            let (x, y) = (end[a0] - start[a0], end[a1] - start[a1]);
+            let d = x.hypot(y);
+            let mut disc = 4.0 * r * r - x * x - y * y;
+            if disc < 0.0 {
+                if d - 2.0 * r.abs() > 0.005 {
+                    radius_error = Some(format!("arc radius {} is too small to reach its end point", r));
+                }
+                disc = 0.0;
+            }
+            let mut h = if d == 0.0 { 0.0 } else { -disc.sqrt() / d };
+            if !clockwise {
+                h = -h;
+            }
+            if r < 0.0 {
+                h = -h;
+            }
+            (start[a0] + 0.5 * (x - y * h), start[a1] + 0.5 * (y + x * h))
+        }
+        None => (start[a0] + offsets[a0], start[a1] + offsets[a1]),
+    };
+    let r_start = (start[a0] - c0).hypot(start[a1] - c1);
+    let r_end = (end[a0] - c0).hypot(end[a1] - c1);
+    let dr = (r_end - r_start).abs();
+    // Same tolerance GRBL uses before rejecting an arc
+    if radius.is_none() && dr > 0.005 && dr > 0.001 * r_start {
+        radius_error = Some(format!("arc radii differ: start {:.4}, end {:.4}", r_start, r_end));
+    }
+    let t0 = (start[a1] - c1).atan2(start[a0] - c0);
+    let t1 = (end[a1] - c1).atan2(end[a0] - c0);
+    let mut sweep = t1 - t0;
+    if clockwise {
+        if sweep >= -1e-9 {
+            sweep -= TAU;
+        }
+    } else if sweep <= 1e-9 {
+        sweep += TAU;
+    }
+    let dz = end[lin] - start[lin];
+    Arc {
+        length: (r_start * sweep).hypot(dz),
+        radius: r_start,
+        sweep,
+        radius_error,
+        start,
+        centre: (c0, c1),
+        t0,
+        dz,
+        axes: (a0, a1, lin),
+    }
+}
+
+/// Analyze `lines` for `firmware` (unsupported commands are not checked
+/// when it is `None`).
+pub fn analyze<L: LineSource + ?Sized>(lines: &L, firmware: Option<FirmwareKind>) -> JobAnalysis {
+    let mut report = JobAnalysis {
+        line_count: lines.len(),
+        ..Default::default()
+    };
+    let mut modal = ModalState::default();
+    let mut position = [0.0f64; 3];
+    let mut units_set = false;
+    let mut warned_units = false;
+    let mut warned_feed = false;
+    let mut tools = std::collections::BTreeSet::new();
+
+    for (n, line) in (0..lines.len()).filter_map(|i| lines.line(i).map(|l| (i, l))) {
+        let ws = words(&line);
+        modal.apply(&line);
+        let scale = if modal.units == "G20" { 25.4 } else { 1.0 };
+        let mut codes = Vec::new();
+        let mut axes: [Option<f64>; 3] = [None; 3];
+        let mut offsets = [0.0; 3];
+        let mut radius = None;
+        for (letter, value) in &ws {
+            let v: Option<f64> = value.parse().ok();
+            match letter {
+                'G' | 'M' => codes.extend(code(*letter, value)),
+                'X' => axes[0] = v,
+                'Y' => axes[1] = v,
+                'Z' => axes[2] = v,
+                'I' => offsets[0] = v.unwrap_or(0.0) * scale,
+                'J' => offsets[1] = v.unwrap_or(0.0) * scale,
+                'K' => offsets[2] = v.unwrap_or(0.0) * scale,
+                'R' => radius = v.map(|r| r * scale),
+                'T' => tools.extend(v.map(|t| t as u32)),
+                'F' => {
+                    if let Some(f) = v {
+                        let f = if modal.feed_mode == "G93" { f } else { f * scale };
+                        widen(&mut report.feed_range, f);
+                    }
+                }
+                'S' => {
+                    if let Some(s) = v {
+                        widen(&mut report.spindle_range, s);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some(fw) = firmware {
+            for c in codes.iter().filter(|c| !supported(fw, c)) {
+                report.unsupported.push(UnsupportedCommand { line: n, code: c.clone() });
+            }
+        }
+        units_set |= codes.iter().any(|c| c == "G20" || c == "G21");
+        let has = |c: &str| codes.iter().any(|x| x == c);
+        if has("G28") || has("G30") || (has("G53") && axes.iter().any(Option::is_some)) {
+            report.warnings.push(AnalysisWarning {
+                line: n,
+                kind: WarningKind::UnknownPosition,
+                message: "machine-coordinate move; later extents assume the work origin".into(),
+            });
+            position = [0.0; 3];
+            continue;
+        }
+        // G10/G92 axis words set offsets, not positions
+        if has("G10") || has("G92") || modal.motion == "G80" || axes.iter().all(Option::is_none) {
+            continue;
+        }
+
+        if !units_set && !warned_units {
+            warned_units = true;
+            report.warnings.push(AnalysisWarning {
+                line: n,
+                kind: WarningKind::MissingUnits,
+                message: "motion before G20/G21; the controller's current units apply".into(),
+            });
+        }
+        let cutting = modal.motion != "G0";
+        if cutting && modal.feed.is_none() && !warned_feed {
+            warned_feed = true;
+            report.warnings.push(AnalysisWarning {
+                line: n,
+                kind: WarningKind::NoFeedBeforeCut,
+                message: format!("{} with no feed rate set", modal.motion),
+            });
+        }
+
+        let relative = modal.distance == "G91";
+        let mut target = position;
+        for i in 0..3 {
+            if let Some(v) = axes[i] {
+                target[i] = if relative { position[i] + v * scale } else { v * scale };
+            }
+        }
+        let bounds = report.bounds.entry(modal.wcs.clone()).or_insert_with(|| Bounds::at(position));
+        bounds.extend(position);
+        match modal.motion.as_str() {
+            "G2" | "G3" => {
+                let a = arc(position, target, modal.motion == "G2", &modal.plane, offsets, radius);
+                let steps = ((a.sweep.abs() / (PI / 18.0)).ceil() as usize).max(1);
+                for p in a.points(steps) {
+                    bounds.extend(p);
+                }
+                report.cut_distance += a.length;
+                if let Some(message) = a.radius_error {
+                    report.warnings.push(AnalysisWarning {
+                        line: n,
+                        kind: WarningKind::ArcRadiusMismatch,
+                        message,
+                    });
+                }
+            }
+            "G0" => report.rapid_distance += distance(position, target),
+            _ => report.cut_distance += distance(position, target),
+        }
+        bounds.extend(target);
+        position = target;
+    }
+    report.tools = tools.into_iter().collect();
+    report
+}
@@ -0,0 +1,350 @@
+//! Run-time estimates that follow the controller's motion planner.
+//!
+//! Distance over feed ignores acceleration, which dominates programs made
+//! of many short segments. `estimate` replays the program through a model
+//! of GRBL's planner: per-axis rate and acceleration limits, junction
+//! deviation at corners, arcs split into chords by the arc tolerance, and
+//! lookahead limited to the planner buffer (every block must be able to
+//! stop by the end of the buffer). Dwells and commands that make GRBL
+//! drain its buffer (spindle, coolant, tool change, pauses) stop motion.
+//! `Estimate::remaining` refines the prediction while a job streams.
+
+use super::analysis::{arc, code};
+use super::modal::{words, ModalState};
+use super::reader::LineSource;
+use crate::profile::{AxisConfig, AxisLimits, MachineProfile};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+
+/// Planner parameters the estimate is made for.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MotionLimits {
+    pub axes: AxisLimits,
+    /// Junction deviation in mm (GRBL `$11`).
+    pub junction_deviation: f64,
+    /// Largest chord error when arcs are split into lines, in mm (GRBL `$12`).
+    pub arc_tolerance: f64,
+    /// Blocks the planner looks ahead over (GRBL: 16).
+    pub planner_blocks: usize,
+}
+
+impl Default for MotionLimits {
+    fn default() -> Self {
+        MotionLimits {
+            axes: AxisLimits::default(),
+            junction_deviation: 0.01,
+            arc_tolerance: 0.002,
+            planner_blocks: 16,
+        }
+    }
+}
+
+impl MotionLimits {
+    pub fn from_profile(profile: &MachineProfile) -> Self {
+        MotionLimits {
+            axes: profile.axis_limits,
+            ..Default::default()
+        }
+    }
+
+    /// Override limits with the controller's own settings (`$11`, `$12`,
+    /// `$110`-`$112`, `$120`-`$122`, `$130`-`$132`). Others are ignored.
+    pub fn with_grbl_settings(mut self, settings: &BTreeMap<u32, f64>) -> Self {
+        fn axis(axes: &mut AxisLimits, i: u32) -> &mut AxisConfig {
+            match i {
+                0 => &mut axes.x,
+                1 => &mut axes.y,
+                _ => &mut axes.z,
+            }
+        }
+        for (&n, &v) in settings {
+            match n {
+                11 if v >= 0.0 => self.junction_deviation = v,
+                12 if v > 0.0 => self.arc_tolerance = v,
+                110..=112 if v > 0.0 => axis(&mut self.axes, n - 110).max_rate = v,
+                120..=122 if v > 0.0 => axis(&mut self.axes, n - 120).acceleration = v,
+                130..=132 if v > 0.0 => axis(&mut self.axes, n - 130).max_travel = v,
+                _ => {}
+            }
+        }
+        self
+    }
+}
+
+/// Predicted timing of a program.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Estimate {
+    /// Seconds from the start of the program to the end of each line.
+    pub line_end: Vec<f64>,
+}
+
+impl Estimate {
+    /// Seconds until the first `lines` program lines have run.
+    fn at(&self, lines: usize) -> f64 {
+        match lines.min(self.line_end.len()) {
+            0 => 0.0,
+            n => self.line_end[n - 1],
+        }
+    }
+
+    pub fn total(&self) -> Duration {
+        Duration::from_secs_f64(self.at(self.line_end.len()))
+    }
+
+    /// Predicted time from line `start` to the end of the program.
+    pub fn from_line(&self, start: usize) -> Duration {
+        Duration::from_secs_f64(self.at(self.line_end.len()) - self.at(start))
+    }
+
+    /// Time left in a run that started at line `start` and has had lines up
+    /// to `done` acknowledged after `elapsed`. The prediction is scaled by
+    /// the pace observed so far, trusted more as the run progresses.
+    pub fn remaining(&self, start: usize, done: usize, elapsed: Duration) -> Duration {
+        let total = self.at(self.line_end.len());
+        let (from, reached) = (self.at(start), self.at(done.max(start)));
+        let left = total - reached;
+        let predicted = reached - from;
+        // Too little history to judge the pace by
+        if predicted < 1.0 {
+            return Duration::from_secs_f64(left);
+        }
+        let ratio = (elapsed.as_secs_f64() / predicted).clamp(0.25, 4.0);
+        let weight = predicted / (total - from);
+        Duration::from_secs_f64(left * (1.0 + (ratio - 1.0) * weight))
+    }
+}
+
+/// Commands that make GRBL finish all buffered motion first.
+const SYNC: &[&str] = &["M0", "M1", "M2", "M3", "M4", "M5", "M6", "M7", "M8", "M9", "M30"];
+
+/// Smallest value of `limits[i] / |unit[i]|` over the axes `unit` moves.
+fn axis_limit(limits: [f64; 3], unit: [f64; 3]) -> f64 {
+    (0..3)
+        .filter(|&i| unit[i].abs() > 1e-12)
+        .map(|i| limits[i] / unit[i].abs())
+        .fold(f64::INFINITY, f64::min)
+}
+
+struct Block {
+    line: usize,
+    length: f64,
+    unit: [f64; 3],
+    /// Speeds in mm/s, acceleration in mm/s².
+    nominal: f64,
+    accel: f64,
+    max_entry: f64,
+}
+
+/// Blocks are kept only while they are inside the lookahead window, so
+/// memory does not grow with the program.
+struct Planner<'a> {
+    limits: &'a MotionLimits,
+    blocks: VecDeque<Block>,
+    stopped: bool,
+    /// Entry speed of the oldest block still queued.
+    entry: f64,
+    /// Execution time per program line.
+    line_time: Vec<f64>,
+}
+
+impl Planner<'_> {
+    fn stop(&mut self) {
+        self.stopped = true;
+    }
+
+    /// Queue a straight move at `nominal` mm/s (before axis limits).
+    fn push(&mut self, line: usize, from: [f64; 3], to: [f64; 3], nominal: f64) {
+        let delta = [0, 1, 2].map(|i| to[i] - from[i]);
+        let length = delta.iter().map(|d| d * d).sum::<f64>().sqrt();
+        if length < 1e-9 {
+            return;
+        }
+        let unit = delta.map(|d| d / length);
+        let axes = self.limits.axes.axes();
+        let rates = axes.map(|a| a.max_rate / 60.0);
+        let accels = axes.map(|a| a.acceleration);
+        let nominal = nominal.min(axis_limit(rates, unit));
+        let accel = axis_limit(accels, unit);
+        let max_entry = match self.blocks.back() {
+            Some(prev) if !self.stopped => {
+                // GRBL's junction deviation: the corner is treated as a
+                // circle tangent to both moves, `junction_deviation` from
+                // the corner
+                let cos = -(0..3).map(|i| prev.unit[i] * unit[i]).sum::<f64>();
+                let speed = if cos > 0.999999 {
+                    0.0
+                } else if cos < -0.999999 {
+                    f64::INFINITY
+                } else {
+                    let mut junction = [0, 1, 2].map(|i| unit[i] - prev.unit[i]);
+                    let norm = junction.iter().map(|d| d * d).sum::<f64>().sqrt();
+                    junction = junction.map(|d| d / norm);
+                    let sin_half = (0.5 * (1.0 - cos)).sqrt();
+                    (axis_limit(accels, junction) * self.limits.junction_deviation * sin_half / (1.0 - sin_half)).sqrt()
+                };
+                speed.min(prev.nominal).min(nominal)
+            }
+            _ => 0.0,
+        };
+        self.stopped = false;
+        self.blocks.push_back(Block {
+            line,
+            length,
+            unit,
+            nominal,
+            accel,
+            max_entry,
+        });
+        // The oldest block has its whole window behind it now
+        if self.blocks.len() > self.limits.planner_blocks.max(1) {
+            self.retire();
+        }
+    }
+
+    /// Plan the oldest block and add its execution time to its line.
+    fn retire(&mut self) {
+        let window = self.limits.planner_blocks.max(1);
+        let Some(b) = self.blocks.pop_front() else {
+            return;
+        };
+        // Fastest exit that still lets the machine stop by the end of the
+        // lookahead window
+        let mut v = 0.0f64;
+        for next in self.blocks.iter().take(window - 1).rev() {
+            v = next.max_entry.min((v * v + 2.0 * next.accel * next.length).sqrt());
+        }
+        let exit = v.min((self.entry * self.entry + 2.0 * b.accel * b.length).sqrt());
+        self.line_time[b.line] += block_time(&b, self.entry, exit);
+        self.entry = exit;
+    }
+
+    /// Plan the blocks still queued and return the time per line.
+    fn finish(mut self) -> Vec<f64> {
+        while !self.blocks.is_empty() {
+            self.retire();
+        }
+        self.line_time
+    }
+}
+
+/// Trapezoidal (or triangular) velocity profile time for one block.
+fn block_time(b: &Block, entry: f64, exit: f64) -> f64 {
+    let (a, vn) = (b.accel, b.nominal);
+    let accel_d = (vn * vn - entry * entry) / (2.0 * a);
+    let decel_d = (vn * vn - exit * exit) / (2.0 * a);
+    if accel_d + decel_d <= b.length {
+        (vn - entry) / a + (vn - exit) / a + (b.length - accel_d - decel_d) / vn
+    } else {
+        let peak = ((2.0 * a * b.length + entry * entry + exit * exit) / 2.0).sqrt();
+        (peak - entry) / a + (peak - exit) / a
+    }
+}
+
+/// Predict how long `lines` take to run under `limits`. Like `analyze`,
+/// the program is assumed to start at the work origin. G4 `P` is taken in
+/// seconds, as GRBL does.
+pub fn estimate<L: LineSource + ?Sized>(lines: &L, limits: &MotionLimits) -> Estimate {
+    let mut planner = Planner {
+        limits,
+        blocks: VecDeque::new(),
+        stopped: true,
+        entry: 0.0,
+        line_time: vec![0.0; lines.len()],
+    };
+    let mut modal = ModalState::default();
+    let mut position = [0.0f64; 3];
+
+    for (n, line) in (0..lines.len()).filter_map(|i| lines.line(i).map(|l| (i, l))) {
+        modal.apply(&line);
+        let scale = if modal.units == "G20" { 25.4 } else { 1.0 };
+        let mut codes = Vec::new();
+        let mut axes: [Option<f64>; 3] = [None; 3];
+        let mut offsets = [0.0; 3];
+        let (mut radius, mut dwell) = (None, None);
+        for (letter, value) in words(&line) {
+            let v: Option<f64> = value.parse().ok();
+            match letter {
+                'G' | 'M' => codes.extend(code(letter, &value)),
+                'X' => axes[0] = v,
+                'Y' => axes[1] = v,
+                'Z' => axes[2] = v,
+                'I' => offsets[0] = v.unwrap_or(0.0) * scale,
+                'J' => offsets[1] = v.unwrap_or(0.0) * scale,
+                'K' => offsets[2] = v.unwrap_or(0.0) * scale,
+                'R' => radius = v.map(|r| r * scale),
+                'P' => dwell = v,
+                _ => {}
+            }
+        }
+        let has = |c: &str| codes.iter().any(|x| x == c);
+        if has("G4") {
+            planner.stop();
+            planner.line_time[n] += dwell.unwrap_or(0.0).max(0.0);
+            continue;
+        }
+        if SYNC.iter().any(|c| has(c)) {
+            planner.stop();
+        }
+        if has("G28") || has("G30") || (has("G53") && axes.iter().any(Option::is_some)) {
+            // Where these end up is unknown, as in `analyze`
+            planner.stop();
+            position = [0.0; 3];
+            continue;
+        }
+        if has("G10") || has("G92") || modal.motion == "G80" || axes.iter().all(Option::is_none) {
+            continue;
+        }
+
+        let relative = modal.distance == "G91";
+        let mut target = position;
+        for i in 0..3 {
+            if let Some(v) = axes[i] {
+                target[i] = if relative { position[i] + v * scale } else { v * scale };
+            }
+        }
+        let arc = matches!(modal.motion.as_str(), "G2" | "G3")
+            .then(|| arc(position, target, modal.motion == "G2", &modal.plane, offsets, radius));
+        // Inverse time (G93): F is the reciprocal of the move's duration in minutes
+        let nominal = match modal.feed {
+            _ if modal.motion == "G0" => f64::INFINITY,
+            Some(f) if modal.feed_mode == "G93" => {
+                let length = arc.as_ref().map_or_else(|| (0..3).map(|i| (target[i] - position[i]).powi(2)).sum::<f64>().sqrt(), |a| a.length);
+                length * f / 60.0
+            }
+            Some(f) => f * scale / 60.0,
+            None => f64::INFINITY,
+        };
+        match arc {
+            Some(a) => {
+                // GRBL splits arcs into chords no further than the arc
+                // tolerance from the true arc
+                let tol = limits.arc_tolerance;
+                let half_chord = (tol * (2.0 * a.radius - tol)).max(0.0).sqrt();
+                let steps = if half_chord > 0.0 {
+                    (((0.5 * a.sweep * a.radius).abs() / half_chord).floor() as usize).max(1)
+                } else {
+                    1
+                };
+                let mut from = position;
+                for (i, p) in a.points(steps).enumerate() {
+                    let p = if i + 1 == steps { target } else { p };
+                    planner.push(n, from, p, nominal);
+                    from = p;
+                }
+            }
+            None => planner.push(n, position, target, nominal),
+        }
+        position = target;
+    }
+    let mut elapsed = 0.0;
+    let line_end = planner
+        .finish()
+        .into_iter()
+        .map(|t| {
+            elapsed += t;
+            elapsed
+        })
+        .collect();
+    Estimate { line_end }
+}
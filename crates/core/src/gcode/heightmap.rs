@@ -0,0 +1,370 @@
+//! Height-map leveling for uneven stock (PCB isolation milling).
+//!
+//! A `HeightMap` holds surface Z probed on a regular XY grid in work
+//! coordinates (`probe::Prober::height_map` fills one). `level` rewrites a
+//! job so every move follows the surface: lines and arcs are split into
+//! short `G1`/`G0` segments and each end point gets the interpolated height
+//! added to its Z. Heights and grid are in mm; jobs in inches are converted.
+//! `Leveled` does the same lazily over a `LineSource` for streaming.
+
+use super::analysis::{arc, code, JobAnalysis};
+use super::modal::{words, ModalState};
+use super::reader::LineSource;
+use crate::error::CoreError;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// How heights between probe points are estimated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Interpolation {
+    /// From the four surrounding points.
+    #[default]
+    Bilinear,
+    /// Catmull-Rom through the sixteen surrounding points; smoother over
+    /// warped boards.
+    Bicubic,
+}
+
+/// Surface heights on a regular grid.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeightMap {
+    /// Work XY of the first point (the grid's minimum corner).
+    pub origin: [f64; 2],
+    /// Distance between points along X and Y.
+    pub spacing: [f64; 2],
+    /// Number of points along X and Y.
+    pub size: [usize; 2],
+    /// Work Z per point, row by row from the origin (X varies fastest).
+    pub heights: Vec<f64>,
+}
+
+/// Catmull-Rom spline through `p` at `t` in 0..1 between `p[1]` and `p[2]`.
+fn cubic(p: [f64; 4], t: f64) -> f64 {
+    p[1] + 0.5
+        * t
+        * (p[2] - p[0] + t * (2.0 * p[0] - 5.0 * p[1] + 4.0 * p[2] - p[3] + t * (3.0 * (p[1] - p[2]) + p[3] - p[0])))
+}
+
+impl HeightMap {
+    /// A flat grid covering `min`-`max` with points at most `max_spacing`
+    /// apart (at least two per axis).
+    pub fn covering(min: [f64; 2], max: [f64; 2], max_spacing: f64) -> Self {
+        let mut size = [2; 2];
+        let mut spacing = [0.0; 2];
+        for i in 0..2 {
+            let span = (max[i] - min[i]).max(0.0);
+            if max_spacing > 0.0 {
+                size[i] = ((span / max_spacing).ceil() as usize + 1).max(2);
+            }
+            spacing[i] = span / (size[i] - 1) as f64;
+        }
+        HeightMap {
+            origin: min,
+            spacing,
+            size,
+            heights: vec![0.0; size[0] * size[1]],
+        }
+    }
+
+    /// A flat grid over the XY extent of a job's moves in `wcs`.
+    pub fn for_job(analysis: &JobAnalysis, wcs: &str, max_spacing: f64) -> Option<Self> {
+        let b = analysis.bounds.get(wcs)?;
+        Some(Self::covering([b.min[0], b.min[1]], [b.max[0], b.max[1]], max_spacing))
+    }
+
+    /// Work XY of point (`column`, `row`).
+    pub fn point(&self, column: usize, row: usize) -> [f64; 2] {
+        [
+            self.origin[0] + column as f64 * self.spacing[0],
+            self.origin[1] + row as f64 * self.spacing[1],
+        ]
+    }
+
+    pub fn height(&self, column: usize, row: usize) -> f64 {
+        self.heights[row * self.size[0] + column]
+    }
+
+    pub fn set_height(&mut self, column: usize, row: usize, z: f64) {
+        self.heights[row * self.size[0] + column] = z;
+    }
+
+    /// Height at clamped grid indices.
+    fn at(&self, column: isize, row: isize) -> f64 {
+        let c = column.clamp(0, self.size[0] as isize - 1) as usize;
+        let r = row.clamp(0, self.size[1] as isize - 1) as usize;
+        self.height(c, r)
+    }
+
+    /// Surface height at work `x`, `y`. Outside the grid the nearest edge
+    /// is extended.
+    pub fn interpolate(&self, x: f64, y: f64, method: Interpolation) -> f64 {
+        // Grid position as cell index plus fraction, clamped to the grid
+        let cell = |i: usize, v: f64| {
+            let last = (self.size[i] - 1) as f64;
+            let g = if self.spacing[i] > 0.0 {
+                ((v - self.origin[i]) / self.spacing[i]).clamp(0.0, last)
+            } else {
+                0.0
+            };
+            let c = g.floor().min(last - 1.0).max(0.0);
+            (c as isize, g - c)
+        };
+        let ((c, tx), (r, ty)) = (cell(0, x), cell(1, y));
+        match method {
+            Interpolation::Bilinear => {
+                let lower = self.at(c, r) * (1.0 - tx) + self.at(c + 1, r) * tx;
+                let upper = self.at(c, r + 1) * (1.0 - tx) + self.at(c + 1, r + 1) * tx;
+                lower * (1.0 - ty) + upper * ty
+            }
+            Interpolation::Bicubic => {
+                let row = |dr: isize| cubic([-1, 0, 1, 2].map(|dc| self.at(c + dc, r + dr)), tx);
+                cubic([-1, 0, 1, 2].map(row), ty)
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(&tmp, path)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let map: HeightMap = serde_json::from_slice(&fs::read(path)?)?;
+        if map.size.contains(&0) || map.heights.len() != map.size[0] * map.size[1] {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "height map size does not match its heights"));
+        }
+        Ok(map)
+    }
+}
+
+/// Settings for `level`.
+#[derive(Debug, Clone)]
+pub struct LevelOptions {
+    /// Longest XY segment emitted, mm.
+    pub segment_length: f64,
+    pub interpolation: Interpolation,
+}
+
+impl Default for LevelOptions {
+    fn default() -> Self {
+        LevelOptions {
+            segment_length: 1.0,
+            interpolation: Interpolation::Bilinear,
+        }
+    }
+}
+
+/// Rewrite `lines` to follow `map`. Motion becomes explicit `G0`/`G1`
+/// moves (arcs are linearized) with compensated Z; other words on a motion
+/// line are sent on their own line first. Relative moves are refused, and
+/// machine-coordinate and offset lines pass through unchanged.
+pub fn level(lines: &[String], map: &HeightMap, options: &LevelOptions) -> Result<Vec<String>, CoreError> {
+    let mut out = Vec::with_capacity(lines.len());
+    let mut cursor = Cursor::default();
+    for (n, line) in lines.iter().enumerate() {
+        cursor.expand(n, line, map, options, &mut out)?;
+    }
+    Ok(out)
+}
+
+/// Leveling state carried from line to line.
+#[derive(Debug, Clone, Default)]
+struct Cursor {
+    modal: ModalState,
+    position: [f64; 3],
+    // Axes whose position is known; moves are kept as written until X and Y are
+    known: [bool; 3],
+}
+
+impl Cursor {
+    /// Append the leveled form of source line `n` to `out`.
+    fn expand(
+        &mut self,
+        n: usize,
+        line: &str,
+        map: &HeightMap,
+        options: &LevelOptions,
+        out: &mut Vec<String>,
+    ) -> Result<(), CoreError> {
+        let segment = options.segment_length.max(0.001);
+        let ws = words(line);
+        self.modal.apply(line);
+        let modal = &self.modal;
+        let scale = if modal.units == "G20" { 25.4 } else { 1.0 };
+        let decimals = if scale == 1.0 { 3 } else { 4 };
+        let mut codes = Vec::new();
+        let mut axes: [Option<f64>; 3] = [None; 3];
+        let mut offsets = [0.0; 3];
+        let mut radius = None;
+        let mut rest = Vec::new();
+        for (letter, value) in &ws {
+            let v: Option<f64> = value.parse().ok();
+            match letter {
+                'X' => axes[0] = v,
+                'Y' => axes[1] = v,
+                'Z' => axes[2] = v,
+                'I' => offsets[0] = v.unwrap_or(0.0) * scale,
+                'J' => offsets[1] = v.unwrap_or(0.0) * scale,
+                'K' => offsets[2] = v.unwrap_or(0.0) * scale,
+                'R' => radius = v.map(|r| r * scale),
+                'N' => {}
+                'G' => {
+                    let code = code('G', value).unwrap_or_default();
+                    if !matches!(code.as_str(), "G0" | "G1" | "G2" | "G3") {
+                        rest.push(format!("G{}", value));
+                    }
+                    codes.push(code);
+                }
+                _ => rest.push(format!("{}{}", letter, value)),
+            }
+        }
+        let has = |c: &str| codes.iter().any(|x| x == c);
+        let motion = modal.motion.as_str();
+        if ["G10", "G28", "G30", "G53", "G92"].iter().any(|c| has(c))
+            || !matches!(motion, "G0" | "G1" | "G2" | "G3")
+            || axes.iter().all(Option::is_none)
+        {
+            if has("G28") || has("G30") || has("G53") || has("G38.2") || has("G38.3") {
+                self.known = [false; 3];
+            }
+            out.push(line.to_string());
+            return Ok(());
+        }
+        if modal.distance == "G91" {
+            return Err(CoreError::Other(format!("line {}: leveling needs absolute moves (G90)", n + 1)));
+        }
+
+        let position = self.position;
+        let mut target = position;
+        let from_known = self.known[0] && self.known[1];
+        for i in 0..3 {
+            if let Some(v) = axes[i] {
+                target[i] = v * scale;
+                self.known[i] = true;
+            }
+        }
+        self.position = target;
+        if !(self.known[0] && self.known[1]) {
+            out.push(line.to_string());
+            return Ok(());
+        }
+        if !rest.is_empty() {
+            out.push(rest.join(" "));
+        }
+        let points: Vec<[f64; 3]> = match motion {
+            _ if !from_known => vec![target],
+            "G2" | "G3" => {
+                let a = arc(position, target, modal.motion == "G2", &modal.plane, offsets, radius);
+                a.points(((a.length / segment).ceil() as usize).max(1)).collect()
+            }
+            "G1" => {
+                let xy = (target[0] - position[0]).hypot(target[1] - position[1]);
+                let steps = ((xy / segment).ceil() as usize).max(1);
+                (1..=steps)
+                    .map(|k| {
+                        let f = k as f64 / steps as f64;
+                        [0, 1, 2].map(|i| position[i] + (target[i] - position[i]) * f)
+                    })
+                    .collect()
+            }
+            _ => vec![target],
+        };
+        let motion = if motion == "G0" { "G0" } else { "G1" };
+        for p in points {
+            let [x, y] = [p[0], p[1]].map(|v| v / scale);
+            let mut move_line = format!("{} X{:.*} Y{:.*}", motion, decimals, x, decimals, y);
+            if self.known[2] {
+                let z = (p[2] + map.interpolate(p[0], p[1], options.interpolation)) / scale;
+                move_line.push_str(&format!(" Z{:.*}", decimals, z));
+            }
+            out.push(move_line);
+        }
+        Ok(())
+    }
+}
+
+/// Source lines between saved cursors in `Leveled`.
+const CHECKPOINT_LINES: usize = 256;
+
+/// `level` applied lazily to a `LineSource`, so a mapped file can be
+/// leveled as it streams. Lines are expanded again when read; only the
+/// cursor every `CHECKPOINT_LINES` source lines is kept.
+#[derive(Debug)]
+pub struct Leveled {
+    source: Arc<dyn LineSource>,
+    map: HeightMap,
+    options: LevelOptions,
+    /// Index of the first leveled line of each source line, plus the total.
+    starts: Vec<usize>,
+    checkpoints: Vec<Cursor>,
+    /// The last source line expanded, its lines and the cursor after it;
+    /// streaming reads in order, so most reads hit it.
+    last: Mutex<Option<(usize, Vec<String>, Cursor)>>,
+}
+
+impl Leveled {
+    /// Levels every line once to index the output and catch relative moves
+    /// before anything is sent.
+    pub fn new(source: Arc<dyn LineSource>, map: HeightMap, options: LevelOptions) -> Result<Self, CoreError> {
+        let mut cursor = Cursor::default();
+        let mut starts = Vec::with_capacity(source.len() + 1);
+        let mut checkpoints = Vec::new();
+        let mut out = Vec::new();
+        let mut count = 0;
+        for n in 0..source.len() {
+            if n % CHECKPOINT_LINES == 0 {
+                checkpoints.push(cursor.clone());
+            }
+            starts.push(count);
+            out.clear();
+            cursor.expand(n, &source.line(n).unwrap_or_default(), &map, &options, &mut out)?;
+            count += out.len();
+        }
+        starts.push(count);
+        Ok(Leveled {
+            source,
+            map,
+            options,
+            starts,
+            checkpoints,
+            last: Mutex::new(None),
+        })
+    }
+}
+
+impl LineSource for Leveled {
+    fn len(&self) -> usize {
+        self.starts.last().copied().unwrap_or(0)
+    }
+
+    fn line(&self, index: usize) -> Option<Cow<'_, str>> {
+        if index >= self.len() {
+            return None;
+        }
+        let n = self.starts.partition_point(|&s| s <= index) - 1;
+        let mut last = self.last.lock().unwrap();
+        if !matches!(&*last, Some((done, ..)) if *done == n) {
+            let checkpoint = n / CHECKPOINT_LINES * CHECKPOINT_LINES;
+            let (mut from, mut cursor) = match last.take() {
+                Some((done, _, cursor)) if done < n && done >= checkpoint => (done + 1, cursor),
+                _ => (checkpoint, self.checkpoints[n / CHECKPOINT_LINES].clone()),
+            };
+            let mut out = Vec::new();
+            while from <= n {
+                out.clear();
+                let line = self.source.line(from).unwrap_or_default();
+                // Every line was leveled once in `new`
+                cursor.expand(from, &line, &self.map, &self.options, &mut out).ok()?;
+                from += 1;
+            }
+            *last = Some((n, out, cursor));
+        }
+        let (_, out, _) = last.as_ref()?;
+        out.get(index - self.starts[n]).cloned().map(Cow::Owned)
+    }
+}
@@ -1,3 +1,15 @@
+pub mod analysis;
+pub mod estimate;
+pub mod heightmap;
+pub mod modal;
 pub mod parser;
+pub mod preprocess;
+pub mod reader;
+pub mod resume;
 
+pub use analysis::{analyze, JobAnalysis};
+pub use estimate::{estimate, Estimate, MotionLimits};
+pub use heightmap::{level, HeightMap, Interpolation, LevelOptions, Leveled};
+pub use modal::ModalState;
 pub use parser::parse_lines;
+pub use reader::{GcodeFile, GcodeReader, LineSource};
@@ -0,0 +1,125 @@
+//! Modal G-code state: the settings that persist between lines.
+
+use serde::{Deserialize, Serialize};
+
+/// Split a line into upper-cased `(letter, value)` words. Comments and
+/// whitespace are skipped; a letter without a value gets an empty string.
+pub fn words(line: &str) -> Vec<(char, String)> {
+    let mut out: Vec<(char, String)> = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => break,
+            '(' => {
+                for c in chars.by_ref() {
+                    if c == ')' {
+                        break;
+                    }
+                }
+            }
+            c if c.is_ascii_alphabetic() => out.push((c.to_ascii_uppercase(), String::new())),
+            c if c.is_ascii_digit() || matches!(c, '.' | '-' | '+') => {
+                if let Some((_, value)) = out.last_mut() {
+                    value.push(c);
+                }
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Modal groups tracked while streaming. Codes are stored in canonical form
+/// (`G0`, `G54`, `M5`) so they can be sent back to the controller as-is.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModalState {
+    pub motion: String,
+    pub plane: String,
+    pub distance: String,
+    pub units: String,
+    pub feed_mode: String,
+    pub wcs: String,
+    pub spindle: String,
+    pub coolant: String,
+    pub feed: Option<f64>,
+    pub spindle_speed: Option<f64>,
+    pub tool: Option<u32>,
+}
+
+impl Default for ModalState {
+    /// GRBL power-on defaults.
+    fn default() -> Self {
+        ModalState {
+            motion: "G0".into(),
+            plane: "G17".into(),
+            distance: "G90".into(),
+            units: "G21".into(),
+            feed_mode: "G94".into(),
+            wcs: "G54".into(),
+            spindle: "M5".into(),
+            coolant: "M9".into(),
+            feed: None,
+            spindle_speed: None,
+            tool: None,
+        }
+    }
+}
+
+fn canonical(letter: char, value: &str) -> Option<String> {
+    let n: f64 = value.parse().ok()?;
+    Some(if n.fract() == 0.0 {
+        format!("{}{}", letter, n as i64)
+    } else {
+        format!("{}{}", letter, n)
+    })
+}
+
+impl ModalState {
+    /// Update the state from one line of G-code.
+    pub fn apply(&mut self, line: &str) {
+        for (letter, value) in words(line) {
+            match letter {
+                'G' => {
+                    let Some(code) = canonical('G', &value) else { continue };
+                    match code.as_str() {
+                        "G0" | "G1" | "G2" | "G3" | "G80" => self.motion = code,
+                        c if c.starts_with("G38.") => self.motion = code,
+                        "G17" | "G18" | "G19" => self.plane = code,
+                        "G90" | "G91" => self.distance = code,
+                        "G20" | "G21" => self.units = code,
+                        "G93" | "G94" => self.feed_mode = code,
+                        "G54" | "G55" | "G56" | "G57" | "G58" | "G59" | "G59.1" | "G59.2" | "G59.3" => {
+                            self.wcs = code
+                        }
+                        _ => {}
+                    }
+                }
+                'M' => {
+                    let Some(code) = canonical('M', &value) else { continue };
+                    match code.as_str() {
+                        "M3" | "M4" | "M5" => self.spindle = code,
+                        "M7" | "M8" | "M9" => self.coolant = code,
+                        _ => {}
+                    }
+                }
+                'F' => self.feed = value.parse().ok().or(self.feed),
+                'S' => self.spindle_speed = value.parse().ok().or(self.spindle_speed),
+                'T' => self.tool = value.parse().ok().or(self.tool),
+                _ => {}
+            }
+        }
+    }
+
+    /// State after running `lines` from power-on defaults.
+    pub fn after<I, S>(lines: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut state = ModalState::default();
+        for line in lines {
+            state.apply(line.as_ref());
+        }
+        state
+    }
+}
@@ -1,17 +1,18 @@
 //! Minimal G-code parser for streaming: yields clean command lines
 
+/// Strip the comment from one line of G-code and trim it. The result is a
+/// prefix of `line` (before any `;` or `(`), empty for comment-only lines.
+pub fn clean_line(line: &str) -> &str {
+    let end = line.find([';', '(']).unwrap_or(line.len());
+    line[..end].trim()
+}
+
 /// Parse G-code text into an iterator of lines suitable for sending to device.
 pub fn parse_lines(input: &str) -> Vec<String> {
     input
         .lines()
-        .map(|l| {
-            // Strip comments after ';' or '('
-            let mut s = l.split(';').next().unwrap_or("").to_string();
-            if let Some(idx) = s.find('(') {
-                s.truncate(idx);
-            }
-            s.trim().to_string()
-        })
+        .map(clean_line)
         .filter(|s| !s.is_empty())
+        .map(str::to_string)
         .collect()
 }
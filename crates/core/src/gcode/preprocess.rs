@@ -0,0 +1,120 @@
+//! Named line transforms applied to a job before it is streamed.
+//!
+//! Machine profiles list preprocessors by name; `apply` runs them in order.
+//! `Preprocessed` applies them lazily to a `LineSource` as lines are read.
+
+use super::reader::{GcodeFile, LineSource, SparseIndex};
+use crate::error::CoreError;
+use std::borrow::Cow;
+use std::sync::Arc;
+
+/// Preprocessor names accepted by `apply`.
+pub const PREPROCESSORS: &[&str] = &[
+    "strip_line_numbers",
+    "strip_checksums",
+    "uppercase",
+    "collapse_whitespace",
+];
+
+fn strip_line_number(line: &str) -> String {
+    let t = line.trim_start();
+    match t.strip_prefix(['N', 'n']) {
+        Some(rest) if rest.starts_with(|c: char| c.is_ascii_digit()) => {
+            rest.trim_start_matches(|c: char| c.is_ascii_digit()).trim_start().to_string()
+        }
+        _ => line.to_string(),
+    }
+}
+
+fn strip_checksum(line: &str) -> String {
+    match line.rsplit_once('*') {
+        Some((body, sum)) if !sum.is_empty() && sum.chars().all(|c| c.is_ascii_digit()) => {
+            body.trim_end().to_string()
+        }
+        _ => line.to_string(),
+    }
+}
+
+type Step = fn(&str) -> String;
+
+fn steps(names: &[String]) -> Result<Vec<Step>, CoreError> {
+    names
+        .iter()
+        .map(|name| {
+            Ok(match name.as_str() {
+                "strip_line_numbers" => strip_line_number as Step,
+                "strip_checksums" => strip_checksum,
+                "uppercase" => |l: &str| l.to_ascii_uppercase(),
+                "collapse_whitespace" => |l: &str| l.split_whitespace().collect::<Vec<_>>().join(" "),
+                other => return Err(CoreError::Config(format!("unknown preprocessor: {}", other))),
+            })
+        })
+        .collect()
+}
+
+/// Run `steps` over one line; `None` if it ends up empty.
+fn run(steps: &[Step], line: &str) -> Option<String> {
+    let mut line = line.to_string();
+    for f in steps {
+        line = f(&line);
+    }
+    (!line.trim().is_empty()).then_some(line)
+}
+
+/// Apply the named preprocessors in order. Lines left empty are dropped.
+pub fn apply(names: &[String], lines: Vec<String>) -> Result<Vec<String>, CoreError> {
+    let steps = steps(names)?;
+    Ok(lines.iter().filter_map(|l| run(&steps, l)).collect())
+}
+
+/// Read a G-code file, drop comments and blank lines, then apply `names`.
+pub fn load_file(path: &std::path::Path, names: &[String]) -> Result<Vec<String>, CoreError> {
+    let text = std::fs::read_to_string(path)?;
+    apply(names, super::parse_lines(&text))
+}
+
+/// Map a G-code file for streaming with `names` applied as lines are read.
+pub fn open_file(path: &std::path::Path, names: &[String]) -> Result<Arc<dyn LineSource>, CoreError> {
+    let file: Arc<dyn LineSource> = Arc::new(GcodeFile::open(path)?);
+    if names.is_empty() {
+        return Ok(file);
+    }
+    Ok(Arc::new(Preprocessed::new(file, names)?))
+}
+
+/// A `LineSource` with preprocessors applied on read.
+#[derive(Debug)]
+pub struct Preprocessed {
+    source: Arc<dyn LineSource>,
+    steps: Vec<Step>,
+    /// Source indices of the lines that survive preprocessing.
+    kept: SparseIndex<usize>,
+}
+
+impl Preprocessed {
+    /// Lines preprocessing empties are found lazily, so indices stay stable
+    /// without checking the whole source up front.
+    pub fn new(source: Arc<dyn LineSource>, names: &[String]) -> Result<Self, CoreError> {
+        Ok(Preprocessed {
+            source,
+            steps: steps(names)?,
+            kept: SparseIndex::new(),
+        })
+    }
+
+    /// The first source line at or after `from` that preprocessing keeps.
+    fn scan(&self, from: usize) -> Option<usize> {
+        (from..self.source.len()).find(|&i| self.source.line(i).is_some_and(|l| run(&self.steps, &l).is_some()))
+    }
+}
+
+impl LineSource for Preprocessed {
+    fn len(&self) -> usize {
+        self.kept.len(|| self.scan(0), |i| self.scan(i + 1))
+    }
+
+    fn line(&self, index: usize) -> Option<Cow<'_, str>> {
+        let index = self.kept.get(index, || self.scan(0), |i| self.scan(i + 1))?;
+        run(&self.steps, &self.source.line(index)?).map(Cow::Owned)
+    }
+}
@@ -0,0 +1,282 @@
+//! Reading G-code without loading whole files into memory.
+//!
+//! `GcodeReader` parses any `BufRead` lazily, one clean line at a time.
+//! `GcodeFile` memory-maps a file and keeps a sparse index of its program
+//! lines so they can be fetched by number (for resume and preview) and
+//! streamed straight from the map. Both yield the same lines as `parse_lines`.
+
+use super::parser::clean_line;
+use memmap2::Mmap;
+use std::borrow::Cow;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use tracing::debug;
+
+/// Program lines addressable by index. Sessions stream from a shared
+/// source instead of copying the program.
+pub trait LineSource: Send + Sync + fmt::Debug {
+    fn len(&self) -> usize;
+
+    /// Program line `index`, or `None` past the end.
+    fn line(&self, index: usize) -> Option<Cow<'_, str>>;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl LineSource for Vec<String> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn line(&self, index: usize) -> Option<Cow<'_, str>> {
+        self.get(index).map(|l| Cow::Borrowed(l.as_str()))
+    }
+}
+
+fn decode(raw: &[u8]) -> Cow<'_, str> {
+    String::from_utf8_lossy(raw.strip_suffix(b"\r").unwrap_or(raw))
+}
+
+fn clean(raw: &[u8]) -> Cow<'_, str> {
+    match decode(raw) {
+        Cow::Borrowed(s) => Cow::Borrowed(clean_line(s)),
+        Cow::Owned(s) => Cow::Owned(clean_line(&s).to_string()),
+    }
+}
+
+/// Lazily parses clean G-code lines from a reader.
+pub struct GcodeReader<R> {
+    inner: R,
+    buf: Vec<u8>,
+}
+
+impl GcodeReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(GcodeReader::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> GcodeReader<R> {
+    pub fn new(inner: R) -> Self {
+        GcodeReader { inner, buf: Vec::new() }
+    }
+}
+
+impl<R: BufRead> Iterator for GcodeReader<R> {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.buf.clear();
+            match self.inner.read_until(b'\n', &mut self.buf) {
+                Ok(0) => return None,
+                Ok(_) => {
+                    let raw = self.buf.strip_suffix(b"\n").unwrap_or(&self.buf);
+                    let line = clean(raw);
+                    if !line.is_empty() {
+                        return Some(Ok(line.into_owned()));
+                    }
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Lines between saved positions in a `SparseIndex`.
+const CHECKPOINT_LINES: usize = 256;
+
+/// Positions of the lines of a source that can only be walked forward.
+/// Built on first use; only every `CHECKPOINT_LINES`th position is kept and
+/// other lines are found by walking on from the checkpoint before them.
+#[derive(Debug)]
+pub(crate) struct SparseIndex<P> {
+    /// Checkpoint positions and the line count.
+    index: OnceLock<(Vec<P>, usize)>,
+    /// The last line found and its position; streaming reads in order, so
+    /// most lookups walk a single line.
+    last: Mutex<Option<(usize, P)>>,
+}
+
+impl<P: Copy> SparseIndex<P> {
+    pub(crate) fn new() -> Self {
+        SparseIndex {
+            index: OnceLock::new(),
+            last: Mutex::new(None),
+        }
+    }
+
+    /// `first` gives the position of line 0 and `next` the one after a
+    /// position; both return `None` past the end.
+    fn build(&self, first: impl FnOnce() -> Option<P>, next: &impl Fn(P) -> Option<P>) -> &(Vec<P>, usize) {
+        self.index.get_or_init(|| {
+            let (mut checkpoints, mut count) = (Vec::new(), 0);
+            let mut pos = first();
+            while let Some(p) = pos {
+                if count % CHECKPOINT_LINES == 0 {
+                    checkpoints.push(p);
+                }
+                count += 1;
+                pos = next(p);
+            }
+            (checkpoints, count)
+        })
+    }
+
+    pub(crate) fn len(&self, first: impl FnOnce() -> Option<P>, next: impl Fn(P) -> Option<P>) -> usize {
+        self.build(first, &next).1
+    }
+
+    /// Position of line `index`.
+    pub(crate) fn get(
+        &self,
+        index: usize,
+        first: impl FnOnce() -> Option<P>,
+        next: impl Fn(P) -> Option<P>,
+    ) -> Option<P> {
+        let (checkpoints, len) = self.build(first, &next);
+        if index >= *len {
+            return None;
+        }
+        let checkpoint = index / CHECKPOINT_LINES * CHECKPOINT_LINES;
+        let mut last = self.last.lock().unwrap();
+        let (mut at, mut pos) = match *last {
+            Some((done, p)) if done <= index && done >= checkpoint => (done, p),
+            _ => (checkpoint, checkpoints[index / CHECKPOINT_LINES]),
+        };
+        while at < index {
+            pos = next(pos)?;
+            at += 1;
+        }
+        *last = Some((index, pos));
+        Some(pos)
+    }
+
+    /// First line whose position satisfies `pred`, which must hold for every
+    /// line after one it holds for.
+    pub(crate) fn find(
+        &self,
+        pred: impl Fn(&P) -> bool,
+        first: impl FnOnce() -> Option<P>,
+        next: impl Fn(P) -> Option<P>,
+    ) -> Option<usize> {
+        let (checkpoints, len) = self.build(first, &next);
+        let block = checkpoints.partition_point(|p| !pred(p)).saturating_sub(1);
+        let (mut at, mut pos) = (block * CHECKPOINT_LINES, *checkpoints.get(block)?);
+        while !pred(&pos) {
+            at += 1;
+            if at >= *len {
+                return None;
+            }
+            pos = next(pos)?;
+        }
+        Some(at)
+    }
+}
+
+/// A memory-mapped G-code file. Program lines are indexed sparsely on first
+/// use and read straight from the map.
+pub struct GcodeFile {
+    path: PathBuf,
+    map: Option<Mmap>,
+    /// Byte offset and 1-based file line number of program lines.
+    index: SparseIndex<(usize, usize)>,
+}
+
+impl fmt::Debug for GcodeFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GcodeFile")
+            .field("path", &self.path)
+            .field("bytes", &self.bytes().len())
+            .finish()
+    }
+}
+
+impl GcodeFile {
+    /// Map `path`. The file must not be modified while open.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let file = File::open(&path)?;
+        // Zero-length files cannot be mapped on every platform
+        let map = if file.metadata()?.len() == 0 {
+            None
+        } else {
+            // SAFETY: the map is read-only; callers are told not to modify
+            // the file while it is open
+            Some(unsafe { Mmap::map(&file)? })
+        };
+        debug!(path = %path.display(), bytes = map.as_ref().map_or(0, |m| m.len()), "reader::open: mapped G-code file");
+        Ok(GcodeFile {
+            path,
+            map,
+            index: SparseIndex::new(),
+        })
+    }
+
+    fn bytes(&self) -> &[u8] {
+        self.map.as_deref().unwrap_or(&[])
+    }
+
+    fn line_end(&self, offset: usize) -> usize {
+        let rest = &self.bytes()[offset..];
+        offset + rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len())
+    }
+
+    /// The first program line at or after byte `offset`, which starts file
+    /// line `n`.
+    fn scan(&self, mut offset: usize, mut n: usize) -> Option<(usize, usize)> {
+        while offset < self.bytes().len() {
+            let end = self.line_end(offset);
+            if !clean(&self.bytes()[offset..end]).is_empty() {
+                return Some((offset, n));
+            }
+            offset = end + 1;
+            n += 1;
+        }
+        None
+    }
+
+    fn next(&self, (offset, n): (usize, usize)) -> Option<(usize, usize)> {
+        self.scan(self.line_end(offset) + 1, n + 1)
+    }
+
+    fn position(&self, index: usize) -> Option<(usize, usize)> {
+        self.index.get(index, || self.scan(0, 1), |p| self.next(p))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// File line number (1-based) of program line `index`.
+    pub fn file_line(&self, index: usize) -> Option<usize> {
+        self.position(index).map(|(_, n)| n)
+    }
+
+    /// Program line that comes from file line `line` (1-based), or the next
+    /// program line after it when that line is blank or a comment.
+    pub fn index_of_file_line(&self, line: usize) -> Option<usize> {
+        self.index.find(|&(_, n)| n >= line, || self.scan(0, 1), |p| self.next(p))
+    }
+
+    /// Program lines from `start` on.
+    pub fn iter_from(&self, start: usize) -> impl Iterator<Item = Cow<'_, str>> + '_ {
+        (start..self.len()).filter_map(move |i| self.line(i))
+    }
+}
+
+impl LineSource for GcodeFile {
+    fn len(&self) -> usize {
+        self.index.len(|| self.scan(0, 1), |p| self.next(p))
+    }
+
+    fn line(&self, index: usize) -> Option<Cow<'_, str>> {
+        let (start, _) = self.position(index)?;
+        Some(clean(&self.bytes()[start..self.line_end(start)]))
+    }
+}
@@ -0,0 +1,212 @@
+//! Start a program part-way through.
+//!
+//! `plan` replays the lines before the start line to recover the modal
+//! state and tool position, then builds a preamble that puts the machine
+//! back in that state safely: retract, rapid over the start point, restore
+//! spindle and coolant, wait for spindle speed and plunge.
+
+use super::modal::{words, ModalState};
+use super::reader::LineSource;
+use crate::error::CoreError;
+use std::borrow::Cow;
+use std::sync::Arc;
+
+/// How the resume preamble moves the machine.
+#[derive(Debug, Clone)]
+pub struct ResumeOptions {
+    /// Work Z to retract to before moving over the start point, in program
+    /// units.
+    pub safe_z: f64,
+    /// Seconds to dwell after starting the spindle.
+    pub spindle_dwell: f64,
+    /// Plunge feed; defaults to the program's feed at the start line.
+    pub plunge_feed: Option<f64>,
+}
+
+impl Default for ResumeOptions {
+    fn default() -> Self {
+        ResumeOptions {
+            safe_z: 5.0,
+            spindle_dwell: 3.0,
+            plunge_feed: None,
+        }
+    }
+}
+
+/// State recovered at the start line and the preamble that restores it.
+#[derive(Debug, Clone)]
+pub struct ResumePlan {
+    /// Index of the first program line to run.
+    pub start_line: usize,
+    pub modal: ModalState,
+    /// Work position before the start line; `None` for axes the program
+    /// had not yet positioned.
+    pub position: [Option<f64>; 3],
+    pub preamble: Vec<String>,
+}
+
+fn fmt(v: f64) -> String {
+    let s = format!("{:.4}", v);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    if s == "-0" {
+        "0".to_string()
+    } else {
+        s.to_string()
+    }
+}
+
+fn is_motion(code: &str) -> bool {
+    matches!(code, "G0" | "G1" | "G2" | "G3") || code.starts_with("G38.")
+}
+
+/// Track the programmed position through one line.
+fn track_position(position: &mut [Option<f64>; 3], modal: &ModalState, line: &str) {
+    let ws = words(line);
+    let g: Vec<f64> = ws.iter().filter(|(l, _)| *l == 'G').filter_map(|(_, v)| v.parse().ok()).collect();
+    // Homing moves leave the position unknown; G10/G92 axis words are
+    // offsets and G53 moves are in machine coordinates
+    if g.iter().any(|&c| c == 28.0 || c == 30.0) {
+        *position = [None; 3];
+        return;
+    }
+    if g.iter().any(|&c| c == 10.0 || c == 53.0 || c == 92.0) {
+        return;
+    }
+    let relative = modal.distance == "G91";
+    for (letter, value) in &ws {
+        let axis = match letter {
+            'X' => 0,
+            'Y' => 1,
+            'Z' => 2,
+            _ => continue,
+        };
+        let Ok(v) = value.parse::<f64>() else { continue };
+        position[axis] = if relative { position[axis].map(|p| p + v) } else { Some(v) };
+    }
+}
+
+/// Work out how to start `lines` at index `start`.
+pub fn plan<L: LineSource + ?Sized>(lines: &L, start: usize, opts: &ResumeOptions) -> Result<ResumePlan, CoreError> {
+    if start >= lines.len() {
+        return Err(CoreError::Config(format!(
+            "resume line {} is past the end of the program ({} lines)",
+            start,
+            lines.len()
+        )));
+    }
+    let mut modal = ModalState::default();
+    let mut position = [None; 3];
+    for line in (0..start).filter_map(|i| lines.line(i)) {
+        // Distance mode set on the line applies to its own axis words
+        modal.apply(&line);
+        track_position(&mut position, &modal, &line);
+    }
+
+    let mut preamble = vec![format!("{} {} {} {}", modal.units, modal.plane, modal.feed_mode, modal.wcs)];
+    if let Some(t) = modal.tool {
+        preamble.push(format!("T{}", t));
+    }
+    preamble.push("G90".into());
+    preamble.push(format!("G0 Z{}", fmt(opts.safe_z)));
+    match (position[0], position[1]) {
+        (Some(x), Some(y)) => preamble.push(format!("G0 X{} Y{}", fmt(x), fmt(y))),
+        (Some(x), None) => preamble.push(format!("G0 X{}", fmt(x))),
+        (None, Some(y)) => preamble.push(format!("G0 Y{}", fmt(y))),
+        (None, None) => {}
+    }
+    if modal.spindle != "M5" {
+        match modal.spindle_speed {
+            Some(s) => preamble.push(format!("{} S{}", modal.spindle, fmt(s))),
+            None => preamble.push(modal.spindle.clone()),
+        }
+        if opts.spindle_dwell > 0.0 {
+            preamble.push(format!("G4 P{}", fmt(opts.spindle_dwell)));
+        }
+    }
+    if modal.coolant != "M9" {
+        preamble.push(modal.coolant.clone());
+    }
+    if let Some(z) = position[2] {
+        let feed = opts.plunge_feed.or(modal.feed).ok_or_else(|| {
+            CoreError::Config("no feed rate set before the resume line; set a plunge feed".into())
+        })?;
+        preamble.push(format!("G1 Z{} F{}", fmt(z), fmt(feed)));
+    }
+    if let Some(f) = modal.feed {
+        preamble.push(format!("F{}", fmt(f)));
+    }
+    if modal.distance != "G90" {
+        preamble.push(modal.distance.clone());
+    }
+
+    Ok(ResumePlan {
+        start_line: start,
+        modal,
+        position,
+        preamble,
+    })
+}
+
+impl ResumePlan {
+    /// Preamble followed by the program from the start line. The preamble's
+    /// own moves change the motion mode, so the first resumed move that
+    /// relies on the modal motion gets it spelled out.
+    pub fn program(&self, lines: Arc<dyn LineSource>) -> ResumedProgram {
+        let mut restore = None;
+        if self.modal.motion != "G80" {
+            for i in self.start_line..lines.len() {
+                let Some(line) = lines.line(i) else { break };
+                let ws = words(&line);
+                let has_motion = ws
+                    .iter()
+                    .any(|(l, v)| *l == 'G' && v.parse::<f64>().is_ok_and(|n| is_motion(&format!("G{}", fmt(n)))));
+                let has_axis = ws.iter().any(|(l, _)| matches!(l, 'X' | 'Y' | 'Z' | 'I' | 'J' | 'K' | 'R'));
+                if has_axis && !has_motion {
+                    restore = Some((i, format!("{} {}", self.modal.motion, line)));
+                }
+                if has_motion || has_axis {
+                    break;
+                }
+            }
+        }
+        ResumedProgram {
+            preamble: self.preamble.clone(),
+            restore,
+            lines,
+            start: self.start_line,
+        }
+    }
+}
+
+/// A program started part-way through, read from the original source.
+#[derive(Debug)]
+pub struct ResumedProgram {
+    preamble: Vec<String>,
+    /// Source line rewritten to restore the motion mode.
+    restore: Option<(usize, String)>,
+    lines: Arc<dyn LineSource>,
+    start: usize,
+}
+
+impl ResumedProgram {
+    pub fn preamble_len(&self) -> usize {
+        self.preamble.len()
+    }
+}
+
+impl LineSource for ResumedProgram {
+    fn len(&self) -> usize {
+        self.preamble.len() + self.lines.len().saturating_sub(self.start)
+    }
+
+    fn line(&self, index: usize) -> Option<Cow<'_, str>> {
+        if let Some(l) = self.preamble.get(index) {
+            return Some(Cow::Borrowed(l));
+        }
+        let i = self.start + index - self.preamble.len();
+        match &self.restore {
+            Some((at, l)) if *at == i => Some(Cow::Borrowed(l)),
+            _ => self.lines.line(i),
+        }
+    }
+}
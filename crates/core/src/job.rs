@@ -32,6 +32,37 @@ impl JobQueue {
         let q = self.inner.lock().unwrap();
         q.len()
     }
+
+    /// Copy of the queued jobs, in order.
+    pub fn snapshot(&self) -> Vec<Job> {
+        self.inner.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// First job matching `pred`, without removing it.
+    pub fn peek_where(&self, pred: impl Fn(&Job) -> bool) -> Option<Job> {
+        self.inner.lock().unwrap().iter().find(|j| pred(j)).cloned()
+    }
+
+    /// Remove and return the job with `id`.
+    pub fn remove(&self, id: &str) -> Option<Job> {
+        let mut q = self.inner.lock().unwrap();
+        let idx = q.iter().position(|j| j.id == id)?;
+        q.remove(idx)
+    }
+
+    /// Move the job with `id` to `index` (clamped to the end of the queue).
+    /// Returns false if no such job is queued.
+    pub fn move_to(&self, id: &str, index: usize) -> bool {
+        let mut q = self.inner.lock().unwrap();
+        let Some(idx) = q.iter().position(|j| j.id == id) else {
+            return false;
+        };
+        if let Some(job) = q.remove(idx) {
+            let index = index.min(q.len());
+            q.insert(index, job);
+        }
+        true
+    }
 }
 
 impl Default for JobQueue {
@@ -0,0 +1,246 @@
+//! Manual jogging.
+//!
+//! GRBL-family controllers get `$J=` jog commands, which run in the Jog
+//! state and are cancelled with the realtime jog-cancel byte (`0x85`).
+//! Marlin gets relative `G0` moves between `G91` and `G90`. Incremental
+//! jogs move by the selected step. Continuous jogs send short segments
+//! paced in real time, with no more than `lookahead` unacknowledged, so only
+//! a couple are ever queued; `stop` (on key release) drops any still waiting
+//! in the session and cancels the rest. Jogging is refused while a job is
+//! streaming.
+
+pub use crate::machine_state::{Axis, Direction};
+use crate::protocol::Protocol;
+use crate::session::{PendingReply, Session};
+use anyhow::{bail, Result};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::TryRecvError;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// GRBL realtime jog cancel.
+pub const JOG_CANCEL: u8 = 0x85;
+
+/// Step and feed presets and continuous-jog pacing.
+#[derive(Debug, Clone)]
+pub struct JogOptions {
+    /// Step sizes in mm offered to the operator.
+    pub steps: Vec<f64>,
+    /// Feed presets in mm/min.
+    pub feeds: Vec<f64>,
+    /// Time each continuous-jog segment covers at the jog feed.
+    pub segment_time: Duration,
+    /// Segments queued ahead of the machine while jogging continuously, and
+    /// the most left unacknowledged.
+    pub lookahead: u32,
+}
+
+impl Default for JogOptions {
+    fn default() -> Self {
+        JogOptions {
+            steps: vec![0.01, 0.1, 1.0, 10.0, 100.0],
+            feeds: vec![100.0, 500.0, 1000.0, 3000.0],
+            segment_time: Duration::from_millis(100),
+            lookahead: 2,
+        }
+    }
+}
+
+fn format_move(delta: [f64; 3], feed: f64) -> String {
+    let mut words: Vec<String> = ["X", "Y", "Z"]
+        .iter()
+        .zip(delta)
+        .filter(|(_, d)| *d != 0.0)
+        .map(|(a, d)| format!("{}{:.3}", a, d))
+        .collect();
+    words.push(format!("F{:.0}", feed));
+    words.join(" ")
+}
+
+/// Jog commands for one move, in the controller's dialect.
+fn jog_lines(protocol: Protocol, delta: [f64; 3], feed: f64) -> Vec<String> {
+    match protocol {
+        Protocol::Grbl => vec![format!("$J=G91 G21 {}", format_move(delta, feed))],
+        Protocol::Marlin => vec!["G91".into(), format!("G0 {}", format_move(delta, feed)), "G90".into()],
+    }
+}
+
+struct Continuous {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+/// Jogs one session's machine.
+pub struct JogController {
+    session: Arc<Session>,
+    options: JogOptions,
+    step: f64,
+    feed: f64,
+    continuous: Option<Continuous>,
+}
+
+impl JogController {
+    /// Starts with a 1 mm step at 1000 mm/min.
+    pub fn new(session: Arc<Session>, options: JogOptions) -> Self {
+        JogController {
+            session,
+            options,
+            step: 1.0,
+            feed: 1000.0,
+            continuous: None,
+        }
+    }
+
+    pub fn options(&self) -> &JogOptions {
+        &self.options
+    }
+
+    pub fn step(&self) -> f64 {
+        self.step
+    }
+
+    pub fn feed(&self) -> f64 {
+        self.feed
+    }
+
+    pub fn set_step(&mut self, mm: f64) {
+        self.step = mm.abs();
+    }
+
+    pub fn set_feed(&mut self, mm_per_min: f64) {
+        self.feed = mm_per_min.abs();
+    }
+
+    /// Use step preset `index`. Returns false if there is none.
+    pub fn select_step(&mut self, index: usize) -> bool {
+        let Some(&step) = self.options.steps.get(index) else { return false };
+        self.step = step;
+        true
+    }
+
+    /// Use feed preset `index`. Returns false if there is none.
+    pub fn select_feed(&mut self, index: usize) -> bool {
+        let Some(&feed) = self.options.feeds.get(index) else { return false };
+        self.feed = feed;
+        true
+    }
+
+    pub fn is_jogging(&self) -> bool {
+        self.continuous.is_some()
+    }
+
+    fn check_idle(&self) -> Result<()> {
+        if self.session.progress().status.is_active() {
+            bail!("session {}: cannot jog while a job is running", self.session.id());
+        }
+        Ok(())
+    }
+
+    /// Move one step along `axis`.
+    pub fn jog(&self, axis: Axis, direction: Direction) -> Result<()> {
+        let mut delta = [0.0; 3];
+        delta[axis.index()] = self.step * direction.sign();
+        self.jog_by(delta)
+    }
+
+    /// Move by `delta` mm at the jog feed.
+    pub fn jog_by(&self, delta: [f64; 3]) -> Result<()> {
+        self.check_idle()?;
+        if self.continuous.is_some() {
+            bail!("session {}: a continuous jog is in progress", self.session.id());
+        }
+        debug!(id = %self.session.id(), delta = ?delta, feed = self.feed, "jog::jog_by: jogging");
+        for line in jog_lines(self.session.protocol(), delta, self.feed) {
+            self.session.send_line(&line)?;
+        }
+        Ok(())
+    }
+
+    /// Jog along `axis` until `stop` is called.
+    pub fn start_continuous(&mut self, axis: Axis, direction: Direction) -> Result<()> {
+        self.check_idle()?;
+        if self.continuous.is_some() {
+            bail!("session {}: a continuous jog is in progress", self.session.id());
+        }
+        let protocol = self.session.protocol();
+        let mut delta = [0.0; 3];
+        delta[axis.index()] = direction.sign() * self.feed / 60.0 * self.options.segment_time.as_secs_f64();
+        let segment = match protocol {
+            Protocol::Grbl => format!("$J=G91 G21 {}", format_move(delta, self.feed)),
+            Protocol::Marlin => {
+                self.session.send_line("G91")?;
+                format!("G0 {}", format_move(delta, self.feed))
+            }
+        };
+        info!(id = %self.session.id(), axis = ?axis, direction = ?direction, feed = self.feed, "jog::start_continuous: jogging");
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let (session, flag) = (self.session.clone(), stop.clone());
+        let (period, lookahead) = (self.options.segment_time, self.options.lookahead as u64);
+        let handle = thread::spawn(move || {
+            let started = Instant::now();
+            let mut sent = 0u64;
+            let mut unacked: VecDeque<PendingReply> = VecDeque::new();
+            while !flag.load(Ordering::SeqCst) {
+                while let Some(reply) = unacked.front() {
+                    match reply.try_recv() {
+                        Err(TryRecvError::Empty) => break,
+                        Ok(Err(e)) => {
+                            warn!(id = %session.id(), err = %e, "jog::start_continuous: segment rejected, stopping");
+                            return;
+                        }
+                        // Acknowledged, or dropped by a reset
+                        Ok(Ok(())) | Err(TryRecvError::Disconnected) => {
+                            unacked.pop_front();
+                        }
+                    }
+                }
+                // Stay `lookahead` segments ahead of where the machine
+                // should be by now, and never further ahead of the
+                // controller's replies, so a stop leaves little queued
+                let due = (started.elapsed().as_secs_f64() / period.as_secs_f64()) as u64 + lookahead;
+                while sent < due && (unacked.len() as u64) < lookahead.max(1) {
+                    match session.request(&segment) {
+                        Ok(reply) => unacked.push_back(reply),
+                        Err(e) => {
+                            warn!(id = %session.id(), err = %e, "jog::start_continuous: send failed, stopping");
+                            return;
+                        }
+                    }
+                    sent += 1;
+                }
+                thread::park_timeout(period / 4);
+            }
+        });
+        self.continuous = Some(Continuous { stop, handle });
+        Ok(())
+    }
+
+    /// Stop a continuous jog (on key release) and cancel queued motion.
+    /// Segments not yet written are dropped first, or they would run after
+    /// the cancel. Marlin has no jog cancel; segments it holds finish.
+    pub fn stop(&mut self) -> Result<()> {
+        if let Some(c) = self.continuous.take() {
+            c.stop.store(true, Ordering::SeqCst);
+            c.handle.thread().unpark();
+            let _ = c.handle.join();
+            debug!(id = %self.session.id(), "jog::stop: continuous jog stopped");
+        }
+        self.session.discard_queued()?;
+        match self.session.protocol() {
+            Protocol::Grbl => self.session.send_realtime(JOG_CANCEL),
+            Protocol::Marlin => self.session.send_line("G90"),
+        }
+    }
+}
+
+impl Drop for JogController {
+    fn drop(&mut self) {
+        if self.continuous.is_some() {
+            let _ = self.stop();
+        }
+    }
+}
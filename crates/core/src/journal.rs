@@ -0,0 +1,185 @@
+//! Append-only job journal for crash recovery.
+//!
+//! Each line of `journal.jsonl` is one `JournalEntry`: a job start, a
+//! progress checkpoint or a job end. Entries are flushed to disk as they
+//! are written, so after a crash or power loss the journal shows which jobs
+//! never finished and the last line the controller acknowledged.
+
+use crate::gcode::ModalState;
+use crate::models::JobStatus;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+/// Journal file name inside the data dir.
+pub const JOURNAL_FILE: &str = "journal.jsonl";
+
+/// Progress snapshot taken while a job streams.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Program lines acknowledged by the controller; the next line to send.
+    pub line: usize,
+    pub mpos: [f64; 3],
+    pub wpos: [f64; 3],
+    /// Modal state after the acknowledged lines.
+    pub modal: ModalState,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JournalEvent {
+    Started {
+        device_id: String,
+        file_path: String,
+        lines_total: usize,
+    },
+    Checkpoint(Box<Checkpoint>),
+    Finished {
+        status: JobStatus,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub job_id: String,
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: JournalEvent,
+}
+
+/// A job that started but has no `Finished` entry.
+#[derive(Debug, Clone)]
+pub struct InterruptedJob {
+    pub job_id: String,
+    pub device_id: String,
+    pub file_path: String,
+    pub lines_total: usize,
+    pub started_at: DateTime<Utc>,
+    pub last_checkpoint: Option<Checkpoint>,
+}
+
+impl InterruptedJob {
+    /// First line that may not have run.
+    pub fn resume_line(&self) -> usize {
+        self.last_checkpoint.as_ref().map_or(0, |c| c.line)
+    }
+}
+
+/// Appends entries to a journal file.
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+}
+
+impl Journal {
+    /// `journal.jsonl` in the data dir.
+    pub fn default_path() -> Option<PathBuf> {
+        gcodekit_utils::storage::file_path(JOURNAL_FILE)
+    }
+
+    /// Open `path` for appending, creating it and its directory if needed.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        debug!(path = %path.display(), "journal::open: opened journal");
+        Ok(Journal { path, file })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write one entry and sync it to disk.
+    pub fn append(&mut self, job_id: &str, event: JournalEvent) -> io::Result<()> {
+        let entry = JournalEntry {
+            job_id: job_id.to_string(),
+            at: Utc::now(),
+            event,
+        };
+        let mut line = serde_json::to_vec(&entry).map_err(io::Error::other)?;
+        line.push(b'\n');
+        // One write per entry so concurrent appenders do not interleave
+        self.file.write_all(&line)?;
+        self.file.sync_data()
+    }
+}
+
+/// Read every entry in the journal. A missing file is empty; lines that do
+/// not parse (e.g. torn by a crash mid-write) are skipped.
+pub fn read_entries(path: &Path) -> io::Result<Vec<JournalEntry>> {
+    let text = match fs::read_to_string(path) {
+        Ok(t) => t,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut entries = Vec::new();
+    for (n, line) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        match serde_json::from_str(line) {
+            Ok(e) => entries.push(e),
+            Err(e) => warn!(line = n + 1, err = %e, "journal::read_entries: skipping unreadable entry"),
+        }
+    }
+    Ok(entries)
+}
+
+/// Jobs that were started but never finished, in start order.
+pub fn interrupted_jobs(path: &Path) -> io::Result<Vec<InterruptedJob>> {
+    let mut jobs: Vec<InterruptedJob> = Vec::new();
+    for entry in read_entries(path)? {
+        match entry.event {
+            JournalEvent::Started {
+                device_id,
+                file_path,
+                lines_total,
+            } => {
+                jobs.retain(|j| j.job_id != entry.job_id);
+                jobs.push(InterruptedJob {
+                    job_id: entry.job_id,
+                    device_id,
+                    file_path,
+                    lines_total,
+                    started_at: entry.at,
+                    last_checkpoint: None,
+                });
+            }
+            JournalEvent::Checkpoint(c) => {
+                if let Some(job) = jobs.iter_mut().find(|j| j.job_id == entry.job_id) {
+                    job.last_checkpoint = Some(*c);
+                }
+            }
+            JournalEvent::Finished { .. } => jobs.retain(|j| j.job_id != entry.job_id),
+        }
+    }
+    if !jobs.is_empty() {
+        info!(count = jobs.len(), "journal::interrupted_jobs: found unfinished jobs");
+    }
+    Ok(jobs)
+}
+
+/// Drop the entries of finished jobs, keeping those still needed for
+/// recovery. Returns the number of entries removed. Run this before any
+/// scheduler has the journal open.
+pub fn compact(path: &Path) -> io::Result<usize> {
+    let entries = read_entries(path)?;
+    let open: Vec<String> = interrupted_jobs(path)?.into_iter().map(|j| j.job_id).collect();
+    let (keep, drop): (Vec<_>, Vec<_>) = entries.into_iter().partition(|e| open.contains(&e.job_id));
+    if drop.is_empty() {
+        return Ok(0);
+    }
+    let mut out = Vec::new();
+    for entry in &keep {
+        serde_json::to_writer(&mut out, entry).map_err(io::Error::other)?;
+        out.push(b'\n');
+    }
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, &out)?;
+    fs::rename(&tmp, path)?;
+    info!(removed = drop.len(), kept = keep.len(), "journal::compact: compacted journal");
+    Ok(drop.len())
+}
@@ -1,15 +1,33 @@
 //! Core library for GCodeKit6: device communication primitives and safety logic
 pub mod async_streamer;
 pub mod config;
+pub mod control;
 pub mod device_manager;
 pub mod device;
+pub mod endpoint;
+pub mod envelope;
 pub mod error;
 pub mod gcode;
 pub mod job;
+pub mod jog;
+pub mod journal;
+pub mod macros;
+pub mod machine_state;
 pub mod models;
+pub mod overrides;
 pub mod persistence;
+pub mod probe;
+pub mod profile;
+pub mod protocol;
+pub mod scheduler;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+pub mod session;
 pub mod streamer;
 pub mod streamer_worker;
+pub mod toolchange;
+pub mod tools;
+pub mod wcs;
 
 pub fn hello_core() -> &'static str {
     "gcodekit-core: ready"
@@ -0,0 +1,266 @@
+//! Machine state tracked from controller status reports.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Controller state as reported in GRBL status reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub enum MachineStatus {
+    #[default]
+    Unknown,
+    Idle,
+    Run,
+    Hold,
+    Jog,
+    Alarm,
+    Door,
+    Check,
+    Home,
+    Sleep,
+    Tool,
+}
+
+impl MachineStatus {
+    /// Parse the state field of a status report, e.g. `Idle` or `Hold:0`.
+    pub fn parse(s: &str) -> Self {
+        match s.split(':').next().unwrap_or_default() {
+            "Idle" => MachineStatus::Idle,
+            "Run" => MachineStatus::Run,
+            "Hold" => MachineStatus::Hold,
+            "Jog" => MachineStatus::Jog,
+            "Alarm" => MachineStatus::Alarm,
+            "Door" => MachineStatus::Door,
+            "Check" => MachineStatus::Check,
+            "Home" => MachineStatus::Home,
+            "Sleep" => MachineStatus::Sleep,
+            "Tool" => MachineStatus::Tool,
+            _ => MachineStatus::Unknown,
+        }
+    }
+}
+
+/// A linear machine axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    /// Position of the axis in coordinate triples.
+    pub fn index(self) -> usize {
+        match self {
+            Axis::X => 0,
+            Axis::Y => 1,
+            Axis::Z => 2,
+        }
+    }
+
+    pub fn letter(self) -> char {
+        ['X', 'Y', 'Z'][self.index()]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Direction {
+    Positive,
+    Negative,
+}
+
+impl Direction {
+    pub fn sign(self) -> f64 {
+        match self {
+            Direction::Positive => 1.0,
+            Direction::Negative => -1.0,
+        }
+    }
+
+    pub fn opposite(self) -> Self {
+        match self {
+            Direction::Positive => Direction::Negative,
+            Direction::Negative => Direction::Positive,
+        }
+    }
+}
+
+/// Outcome of a probing cycle (`[PRB:10.000,0.000,-2.500:1]`).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ProbeResult {
+    /// Machine position where the probe stopped.
+    pub position: [f64; 3],
+    /// Whether the probe made contact.
+    pub success: bool,
+}
+
+/// Feed, rapid and spindle overrides in percent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Overrides {
+    pub feed: u32,
+    pub rapid: u32,
+    pub spindle: u32,
+}
+
+impl Default for Overrides {
+    fn default() -> Self {
+        Overrides {
+            feed: 100,
+            rapid: 100,
+            spindle: 100,
+        }
+    }
+}
+
+/// One parsed GRBL status report (`<Idle|MPos:0.000,0.000,0.000|FS:0,0>`).
+/// Fields absent from the report are `None`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StatusReport {
+    pub status: MachineStatus,
+    /// Sub-state such as the hold or door code (`Hold:1`).
+    pub sub_state: Option<u8>,
+    pub mpos: Option<[f64; 3]>,
+    pub wpos: Option<[f64; 3]>,
+    pub wco: Option<[f64; 3]>,
+    pub feed: Option<f64>,
+    pub spindle: Option<f64>,
+    /// Planner blocks and receive buffer bytes available (`Bf:`).
+    pub buffer: Option<(u32, u32)>,
+    /// Line number being executed (`Ln:`).
+    pub line: Option<u64>,
+    pub overrides: Option<Overrides>,
+    /// Input pins reported active (`Pn:`), e.g. `XZP`.
+    pub pins: Option<String>,
+}
+
+fn parse_axes(s: &str) -> Option<[f64; 3]> {
+    let mut out = [0.0; 3];
+    let mut n = 0;
+    for (slot, v) in out.iter_mut().zip(s.split(',')) {
+        *slot = v.trim().parse().ok()?;
+        n += 1;
+    }
+    (n == 3).then_some(out)
+}
+
+impl StatusReport {
+    /// Parse a status report line. Returns `None` for anything else.
+    pub fn parse(line: &str) -> Option<Self> {
+        let body = line.trim().strip_prefix('<')?.strip_suffix('>')?;
+        let mut fields = body.split('|');
+        let state = fields.next()?;
+        let mut report = StatusReport {
+            status: MachineStatus::parse(state),
+            sub_state: state.split_once(':').and_then(|(_, s)| s.parse().ok()),
+            ..Default::default()
+        };
+        for field in fields {
+            let Some((key, value)) = field.split_once(':') else { continue };
+            match key {
+                "MPos" => report.mpos = parse_axes(value),
+                "WPos" => report.wpos = parse_axes(value),
+                "WCO" => report.wco = parse_axes(value),
+                "F" => report.feed = value.parse().ok(),
+                "FS" => {
+                    let mut it = value.split(',');
+                    report.feed = it.next().and_then(|v| v.parse().ok());
+                    report.spindle = it.next().and_then(|v| v.parse().ok());
+                }
+                "Bf" => {
+                    let mut it = value.split(',').filter_map(|v| v.parse().ok());
+                    if let (Some(blocks), Some(bytes)) = (it.next(), it.next()) {
+                        report.buffer = Some((blocks, bytes));
+                    }
+                }
+                "Ln" => report.line = value.parse().ok(),
+                "Ov" => {
+                    let v: Vec<u32> = value.split(',').filter_map(|v| v.parse().ok()).collect();
+                    if let [feed, rapid, spindle] = v[..] {
+                        report.overrides = Some(Overrides { feed, rapid, spindle });
+                    }
+                }
+                "Pn" => report.pins = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        Some(report)
+    }
+}
+
+/// Last known state of a connected machine.
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct MachineState {
+    pub status: MachineStatus,
+    pub mpos: [f64; 3],
+    pub wpos: [f64; 3],
+    /// Work coordinate offset (`WPos = MPos - WCO`).
+    pub wco: [f64; 3],
+    pub feed: f64,
+    pub spindle: f64,
+    pub overrides: Overrides,
+    pub buffer: Option<(u32, u32)>,
+    pub pins: Option<String>,
+    /// Last `ALARM:` code, cleared when the machine leaves the alarm state.
+    pub alarm: Option<String>,
+    /// Last `error:` reply.
+    pub last_error: Option<String>,
+    /// Firmware welcome/version string.
+    pub firmware: Option<String>,
+    /// Numeric `$N` settings the controller has reported (`$$`).
+    pub settings: BTreeMap<u32, f64>,
+    /// Coordinate offsets the controller has reported (`$#`): `G54`-`G59`,
+    /// `G28`, `G30` and `G92`, in machine coordinates, and the tool length
+    /// offset as `TLO` (Z only).
+    pub offsets: BTreeMap<String, [f64; 3]>,
+    /// Modal words from the last parser-state report (`$G`), e.g. `G0 G54
+    /// G17 G21 G90 G94 M5 M9 T0 F0 S0`.
+    pub modes: Vec<String>,
+    /// Result of the last probing cycle.
+    pub probe: Option<ProbeResult>,
+}
+
+impl MachineState {
+    /// Active work coordinate system (`G54`-`G59.3`) from `modes`, if the
+    /// parser state has been reported.
+    pub fn wcs(&self) -> Option<&str> {
+        self.modes.iter().map(String::as_str).find(|w| crate::wcs::WORK_SYSTEMS.contains(w))
+    }
+
+    /// Merge a status report. Returns whether anything changed.
+    pub fn apply_report(&mut self, r: &StatusReport) -> bool {
+        let before = self.clone();
+        self.status = r.status;
+        if r.status != MachineStatus::Alarm {
+            self.alarm = None;
+        }
+        if let Some(wco) = r.wco {
+            self.wco = wco;
+        }
+        let sub = |a: [f64; 3], b: [f64; 3]| [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+        let add = |a: [f64; 3], b: [f64; 3]| [a[0] + b[0], a[1] + b[1], a[2] + b[2]];
+        match (r.mpos, r.wpos) {
+            (Some(m), _) => {
+                self.mpos = m;
+                self.wpos = sub(m, self.wco);
+            }
+            (None, Some(w)) => {
+                self.wpos = w;
+                self.mpos = add(w, self.wco);
+            }
+            (None, None) => {}
+        }
+        if let Some(f) = r.feed {
+            self.feed = f;
+        }
+        if let Some(s) = r.spindle {
+            self.spindle = s;
+        }
+        if let Some(ov) = r.overrides {
+            self.overrides = ov;
+        }
+        if r.buffer.is_some() {
+            self.buffer = r.buffer;
+        }
+        self.pins = r.pins.clone();
+        *self != before
+    }
+}
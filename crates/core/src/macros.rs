@@ -0,0 +1,272 @@
+//! Named G-code macros with `{{var}}` substitution.
+//!
+//! Macros are stored as a list in `macros.json` in the platform data
+//! directory, keyed by name. Before a macro runs its `{{var}}` placeholders
+//! are replaced, as plain text, by values from the machine state (see
+//! `state_vars`) or by the answers to the macro's parameters, which the UI
+//! prompts for. Substituted values must be single numbers or words, so an
+//! answer cannot add lines or comments. The expanded lines are streamed as
+//! a job, with the same acknowledgement and error handling. Macros written
+//! in Rhai are run by `scripting::ScriptRunner` instead (feature
+//! `scripting`); their parameters become script variables.
+
+use crate::error::CoreError;
+use crate::machine_state::MachineState;
+use crate::session::Session;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use tracing::{debug, info};
+
+const MACROS_FILE: &str = "macros.json";
+
+/// A value the user is asked for before a macro runs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MacroParam {
+    /// Placeholder name, used as `{{name}}`.
+    pub name: String,
+    /// Question shown to the user; the name when empty.
+    #[serde(default)]
+    pub prompt: String,
+    /// Answer used when the user gives none.
+    #[serde(default)]
+    pub default: Option<String>,
+}
+
+/// What a macro's text is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MacroLanguage {
+    /// G-code lines with `{{var}}` placeholders.
+    #[default]
+    Gcode,
+    /// A Rhai script.
+    Rhai,
+}
+
+/// A named G-code snippet or script.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Macro {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub language: MacroLanguage,
+    /// G-code lines, or the script text for `MacroLanguage::Rhai`.
+    pub gcode: String,
+    #[serde(default)]
+    pub params: Vec<MacroParam>,
+}
+
+impl Macro {
+    pub fn new(name: impl Into<String>, gcode: impl Into<String>) -> Self {
+        Macro {
+            name: name.into(),
+            description: String::new(),
+            language: MacroLanguage::Gcode,
+            gcode: gcode.into(),
+            params: Vec::new(),
+        }
+    }
+
+    /// Check the macro is usable before it is saved.
+    pub fn validate(&self) -> Result<(), CoreError> {
+        if self.name.trim().is_empty() {
+            return Err(CoreError::Config("macro name is empty".into()));
+        }
+        for p in &self.params {
+            if !is_var_name(&p.name) || state_var_names().contains(&p.name.as_str()) {
+                return Err(CoreError::Config(format!("macro {}: bad parameter name {:?}", self.name, p.name)));
+            }
+            if let Some(default) = &p.default {
+                check_value(&p.name, default).map_err(|e| CoreError::Config(format!("macro {}: {}", self.name, e)))?;
+            }
+        }
+        if self.language == MacroLanguage::Rhai {
+            return Ok(());
+        }
+        for var in placeholders(&self.gcode) {
+            if !state_var_names().contains(&var) && !self.params.iter().any(|p| p.name == var) {
+                return Err(CoreError::Config(format!("macro {}: unknown variable {{{{{}}}}}", self.name, var)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace the placeholders with `answers` (falling back to parameter
+    /// defaults) and values from `state`, and return the non-empty lines.
+    pub fn expand(&self, state: &MachineState, answers: &BTreeMap<String, String>) -> Result<Vec<String>, CoreError> {
+        if self.language != MacroLanguage::Gcode {
+            return Err(CoreError::Other(format!("macro {} is a script, not G-code", self.name)));
+        }
+        let mut vars = state_vars(state);
+        vars.extend(self.param_values(answers)?);
+        let mut lines = Vec::new();
+        for line in self.gcode.lines() {
+            let mut out = String::new();
+            let mut rest = line;
+            while let Some(start) = rest.find("{{") {
+                let Some(len) = rest[start..].find("}}") else { break };
+                let var = rest[start + 2..start + len].trim();
+                let value = vars
+                    .get(var)
+                    .ok_or_else(|| CoreError::Other(format!("macro {}: unknown variable {{{{{}}}}}", self.name, var)))?;
+                out.push_str(&rest[..start]);
+                out.push_str(value);
+                rest = &rest[start + len + 2..];
+            }
+            out.push_str(rest);
+            let out = out.trim();
+            if !out.is_empty() {
+                lines.push(out.to_string());
+            }
+        }
+        Ok(lines)
+    }
+
+    /// Each parameter's answer, or its default when not answered.
+    pub fn param_values(&self, answers: &BTreeMap<String, String>) -> Result<BTreeMap<String, String>, CoreError> {
+        let mut values = BTreeMap::new();
+        for p in &self.params {
+            let value = answers
+                .get(&p.name)
+                .or(p.default.as_ref())
+                .ok_or_else(|| CoreError::Other(format!("macro {}: no value for {{{{{}}}}}", self.name, p.name)))?;
+            check_value(&p.name, value)?;
+            values.insert(p.name.clone(), value.trim().to_string());
+        }
+        Ok(values)
+    }
+}
+
+fn state_var_names() -> [&'static str; 10] {
+    ["x", "y", "z", "mx", "my", "mz", "wcs", "tool", "feed", "spindle"]
+}
+
+/// Variables taken from the machine state: work position `x` `y` `z`,
+/// machine position `mx` `my` `mz`, `wcs` (e.g. `G54`), `tool`, `feed` and
+/// `spindle`. `wcs` and `tool` need a parser-state report (`$G`) and
+/// default to `G54` and `0`.
+pub fn state_vars(state: &MachineState) -> BTreeMap<String, String> {
+    let tool = state.modes.iter().find_map(|w| w.strip_prefix('T')).unwrap_or("0");
+    let values = [
+        format!("{:.3}", state.wpos[0]),
+        format!("{:.3}", state.wpos[1]),
+        format!("{:.3}", state.wpos[2]),
+        format!("{:.3}", state.mpos[0]),
+        format!("{:.3}", state.mpos[1]),
+        format!("{:.3}", state.mpos[2]),
+        state.wcs().unwrap_or("G54").to_string(),
+        tool.to_string(),
+        format!("{}", state.feed),
+        format!("{}", state.spindle),
+    ];
+    state_var_names().iter().map(|n| n.to_string()).zip(values).collect()
+}
+
+/// Placeholder names used in `gcode`, in order of appearance.
+fn placeholders(gcode: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut rest = gcode;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start..].find("}}") else { break };
+        out.push(rest[start + 2..start + len].trim());
+        rest = &rest[start + len + 2..];
+    }
+    out
+}
+
+fn is_var_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Answers must be a single number or word: no spaces, comments or lines.
+fn check_value(name: &str, value: &str) -> Result<(), CoreError> {
+    let value = value.trim();
+    if value.is_empty() || !value.chars().all(|c| c.is_ascii_alphanumeric() || "._-+".contains(c)) {
+        return Err(CoreError::Other(format!("bad value {:?} for {{{{{}}}}}", value, name)));
+    }
+    Ok(())
+}
+
+impl Session {
+    /// Expand `m` against the current machine state and `answers`, and
+    /// stream the result as a job. Fails while another job is running.
+    pub fn run_macro(&self, m: &Macro, answers: &BTreeMap<String, String>) -> anyhow::Result<()> {
+        let lines = m.expand(&self.state(), answers)?;
+        info!(id = %self.id(), name = %m.name, lines = lines.len(), "macros::run_macro: running macro");
+        self.stream(&lines)
+    }
+}
+
+/// Load all macros. Returns an empty Vec when `macros.json` does not exist.
+pub fn load_macros() -> io::Result<Vec<Macro>> {
+    match gcodekit_utils::storage::read_json::<Vec<Macro>>(MACROS_FILE) {
+        Ok(v) => {
+            debug!(count = v.len(), "macros::load_macros: loaded macros");
+            Ok(v)
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => {
+            debug!(err = ?e, "macros::load_macros: error reading macros");
+            Err(e)
+        }
+    }
+}
+
+/// Insert or replace macros in `macros.json`, keyed by name.
+fn store(macros: Vec<Macro>) -> Result<usize, CoreError> {
+    for m in &macros {
+        m.validate()?;
+    }
+    let count = macros.len();
+    gcodekit_utils::storage::update_json(MACROS_FILE, |stored: &mut Vec<Macro>| {
+        for m in macros {
+            match stored.iter_mut().find(|s| s.name == m.name) {
+                Some(existing) => *existing = m,
+                None => stored.push(m),
+            }
+        }
+    })?;
+    Ok(count)
+}
+
+/// Insert or replace a macro in `macros.json`.
+pub fn save_macro(m: Macro) -> Result<(), CoreError> {
+    let name = m.name.clone();
+    store(vec![m])?;
+    info!(name = %name, "macros::save_macro: macro saved");
+    Ok(())
+}
+
+/// Remove a macro from `macros.json`. Returns whether it existed.
+pub fn delete_macro(name: &str) -> io::Result<bool> {
+    let removed = gcodekit_utils::storage::update_json(MACROS_FILE, |macros: &mut Vec<Macro>| {
+        let before = macros.len();
+        macros.retain(|m| m.name != name);
+        macros.len() != before
+    })?;
+    debug!(name, removed, "macros::delete_macro: done");
+    Ok(removed)
+}
+
+/// Write every stored macro to `path` as one JSON file. Returns how many.
+pub fn export_macros(path: &Path) -> io::Result<usize> {
+    let macros = load_macros()?;
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_vec_pretty(&macros)?)?;
+    fs::rename(&tmp, path)?;
+    info!(count = macros.len(), path = %path.display(), "macros::export_macros: exported");
+    Ok(macros.len())
+}
+
+/// Add the macros in a file written by `export_macros`, replacing stored
+/// ones with the same name. Nothing is saved if any of them is invalid.
+pub fn import_macros(path: &Path) -> Result<usize, CoreError> {
+    let macros: Vec<Macro> = serde_json::from_slice(&fs::read(path)?)
+        .map_err(|e| CoreError::Config(format!("{}: {}", path.display(), e)))?;
+    let count = store(macros)?;
+    info!(count, path = %path.display(), "macros::import_macros: imported");
+    Ok(count)
+}
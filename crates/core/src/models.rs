@@ -1,5 +1,7 @@
 //! Core data models: Device and Job
 
+use crate::endpoint::Endpoint;
+use crate::gcode::JobAnalysis;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
@@ -15,6 +17,10 @@ pub enum Transport {
     Serial,
     Tcp,
     Udp,
+    WebSocket,
+    Telnet,
+    Sim,
+    Replay,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,6 +33,38 @@ pub struct Device {
     pub capabilities: Vec<String>,
     pub status: DeviceStatus,
     pub transport: Transport,
+    /// Endpoint URL used to reconnect; absent in files written before
+    /// endpoints were recorded.
+    #[serde(default)]
+    pub endpoint: Option<Endpoint>,
+}
+
+impl Device {
+    /// A connected device record for `endpoint`, keyed by its URL.
+    pub fn from_endpoint(endpoint: &Endpoint, name: impl Into<String>) -> Self {
+        let (port, baud) = match endpoint {
+            Endpoint::Serial { path, baud, .. } => (path.clone(), *baud),
+            Endpoint::Replay { path, .. } => (path.display().to_string(), None),
+            other => (
+                other
+                    .host_port()
+                    .map(|(h, p)| format!("{}:{}", h, p))
+                    .unwrap_or_default(),
+                None,
+            ),
+        };
+        Device {
+            id: endpoint.to_string(),
+            name: name.into(),
+            port,
+            baud,
+            firmware: None,
+            capabilities: vec![],
+            status: DeviceStatus::Connected,
+            transport: endpoint.transport(),
+            endpoint: Some(endpoint.clone()),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,6 +74,7 @@ pub enum JobStatus {
     Paused,
     Completed,
     Failed(String),
+    Cancelled,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -47,4 +86,34 @@ pub struct Job {
     pub progress: f32,
     pub status: JobStatus,
     pub created_at: DateTime<Utc>,
+    /// Pre-flight report, filled in when the job is queued.
+    #[serde(default)]
+    pub analysis: Option<JobAnalysis>,
+    /// Predicted run time in seconds, filled in when the job is queued.
+    #[serde(default)]
+    pub estimated_seconds: Option<f64>,
+    /// Time left while the job runs, refined from streaming progress.
+    #[serde(default)]
+    pub remaining_seconds: Option<f64>,
+}
+
+impl Job {
+    /// A queued job for `file_path` with a process-unique id.
+    pub fn new(file_path: impl Into<String>) -> Self {
+        static NEXT_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1);
+        let created_at = Utc::now();
+        let seq = NEXT_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        Job {
+            id: format!("job-{}-{}", created_at.timestamp_millis(), seq),
+            file_path: file_path.into(),
+            lines_total: 0,
+            lines_sent: 0,
+            progress: 0.0,
+            status: JobStatus::Queued,
+            created_at,
+            analysis: None,
+            estimated_seconds: None,
+            remaining_seconds: None,
+        }
+    }
 }
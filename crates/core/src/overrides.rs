@@ -0,0 +1,120 @@
+//! Feed, rapid and spindle overrides and coolant toggles.
+//!
+//! GRBL-family controllers take these as realtime bytes (`0x90`-`0xA1`)
+//! that act at once, mid-job included; the values in effect come back in
+//! the `Ov:` field of status reports and land in `MachineState::overrides`.
+//! Marlin only has feed (`M220`) and flow (`M221`, standing in for the
+//! spindle) percentages, set by command lines; the state is updated from
+//! the value sent once the controller accepts it.
+
+use crate::machine_state::Overrides;
+use crate::protocol::Protocol;
+use crate::session::Session;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+/// Lowest and highest feed and spindle override GRBL accepts, in percent.
+pub const OVERRIDE_RANGE: (u32, u32) = (10, 200);
+
+/// One override step or toggle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Override {
+    FeedReset,
+    FeedPlus10,
+    FeedMinus10,
+    FeedPlus1,
+    FeedMinus1,
+    Rapid100,
+    Rapid50,
+    Rapid25,
+    SpindleReset,
+    SpindlePlus10,
+    SpindleMinus10,
+    SpindlePlus1,
+    SpindleMinus1,
+    /// Stop the spindle during a feed hold; sent again to restart it.
+    SpindleStop,
+    FloodToggle,
+    MistToggle,
+}
+
+impl Override {
+    /// GRBL realtime byte.
+    pub fn byte(self) -> u8 {
+        match self {
+            Override::FeedReset => 0x90,
+            Override::FeedPlus10 => 0x91,
+            Override::FeedMinus10 => 0x92,
+            Override::FeedPlus1 => 0x93,
+            Override::FeedMinus1 => 0x94,
+            Override::Rapid100 => 0x95,
+            Override::Rapid50 => 0x96,
+            Override::Rapid25 => 0x97,
+            Override::SpindleReset => 0x99,
+            Override::SpindlePlus10 => 0x9A,
+            Override::SpindleMinus10 => 0x9B,
+            Override::SpindlePlus1 => 0x9C,
+            Override::SpindleMinus1 => 0x9D,
+            Override::SpindleStop => 0x9E,
+            Override::FloodToggle => 0xA0,
+            Override::MistToggle => 0xA1,
+        }
+    }
+
+    /// Overrides after this step, clamped like GRBL clamps them. Toggles
+    /// leave the values alone.
+    pub fn apply(self, mut ov: Overrides) -> Overrides {
+        let step = |value: u32, delta: i32| {
+            (value as i32 + delta).clamp(OVERRIDE_RANGE.0 as i32, OVERRIDE_RANGE.1 as i32) as u32
+        };
+        match self {
+            Override::FeedReset => ov.feed = 100,
+            Override::FeedPlus10 => ov.feed = step(ov.feed, 10),
+            Override::FeedMinus10 => ov.feed = step(ov.feed, -10),
+            Override::FeedPlus1 => ov.feed = step(ov.feed, 1),
+            Override::FeedMinus1 => ov.feed = step(ov.feed, -1),
+            Override::Rapid100 => ov.rapid = 100,
+            Override::Rapid50 => ov.rapid = 50,
+            Override::Rapid25 => ov.rapid = 25,
+            Override::SpindleReset => ov.spindle = 100,
+            Override::SpindlePlus10 => ov.spindle = step(ov.spindle, 10),
+            Override::SpindleMinus10 => ov.spindle = step(ov.spindle, -10),
+            Override::SpindlePlus1 => ov.spindle = step(ov.spindle, 1),
+            Override::SpindleMinus1 => ov.spindle = step(ov.spindle, -1),
+            Override::SpindleStop | Override::FloodToggle | Override::MistToggle => {}
+        }
+        ov
+    }
+}
+
+impl Session {
+    /// Apply an override step. On GRBL the realtime byte is sent and the
+    /// next status reports show the result; on Marlin the new feed or flow
+    /// percentage is sent with `M220`/`M221`.
+    pub fn apply_override(&self, step: Override) -> Result<()> {
+        match self.protocol() {
+            Protocol::Grbl => self.send_realtime(step.byte()),
+            Protocol::Marlin => {
+                let next = step.apply(self.state().overrides);
+                let line = match step {
+                    Override::FeedReset
+                    | Override::FeedPlus10
+                    | Override::FeedMinus10
+                    | Override::FeedPlus1
+                    | Override::FeedMinus1 => format!("M220 S{}", next.feed),
+                    Override::SpindleReset
+                    | Override::SpindlePlus10
+                    | Override::SpindleMinus10
+                    | Override::SpindlePlus1
+                    | Override::SpindleMinus1 => format!("M221 S{}", next.spindle),
+                    _ => bail!("session {}: {:?} is not supported by Marlin controllers", self.id(), step),
+                };
+                self.send_command(&line, self.options().command_timeout)?;
+                debug!(id = %self.id(), line = %line, "overrides::apply_override: override set");
+                self.update_state(|s| s.overrides = next);
+                Ok(())
+            }
+        }
+    }
+}
@@ -38,6 +38,18 @@ pub fn load_job_history() -> std::io::Result<Vec<Job>> {
     }
 }
 
+/// Insert or update one job in `jobs.json`, keyed by id, keeping the rest
+/// of the history.
+pub fn record_job(job: &Job) -> std::io::Result<()> {
+    debug!(id = %job.id, "persistence::record_job: recording job");
+    gcodekit_utils::storage::update_json("jobs.json", |jobs: &mut Vec<Job>| {
+        match jobs.iter_mut().find(|j| j.id == job.id) {
+            Some(existing) => *existing = job.clone(),
+            None => jobs.push(job.clone()),
+        }
+    })
+}
+
 /// Write a sample config.json into the platform data dir under gcodekit6.
 /// Overwrites existing config.
 pub fn write_sample_config(network_timeout_secs: u64) -> std::io::Result<PathBuf> {
@@ -0,0 +1,427 @@
+//! Probing routines: Z touch plate, edges, corners, bore and boss centres.
+//!
+//! Probe moves are `G38.2`-`G38.5` cycles relative to the current position,
+//! and results come back in machine coordinates from `[PRB:...]` reports.
+//! Each touch searches at the fast feed, backs off, probes again at the slow
+//! feed for the reading it keeps, then retracts. Routines use `G38.2`, so a
+//! probe that does not trigger within `max_travel` raises an alarm on the
+//! controller and nothing moves until `Session::unlock`. Routines run in
+//! millimetres (`G21`) and put inch mode back when they end. GRBL-family
+//! controllers only.
+
+use crate::gcode::HeightMap;
+use crate::machine_state::{Axis, Direction, MachineStatus, ProbeResult};
+use crate::protocol::Protocol;
+use crate::session::Session;
+use anyhow::{bail, Result};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// `G38.x` probing cycles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeCycle {
+    /// `G38.2`: toward the work; alarm when there is no contact.
+    Touch,
+    /// `G38.3`: toward the work; no alarm.
+    TryTouch,
+    /// `G38.4`: away until contact is lost; alarm when it is not.
+    Release,
+    /// `G38.5`: away until contact is lost; no alarm.
+    TryRelease,
+}
+
+impl ProbeCycle {
+    fn code(self) -> &'static str {
+        match self {
+            ProbeCycle::Touch => "G38.2",
+            ProbeCycle::TryTouch => "G38.3",
+            ProbeCycle::Release => "G38.4",
+            ProbeCycle::TryRelease => "G38.5",
+        }
+    }
+}
+
+/// Distances (mm) and feeds (mm/min) used by the routines.
+#[derive(Debug, Clone)]
+pub struct ProbeOptions {
+    /// Feed of the first, searching probe.
+    pub fast_feed: f64,
+    /// Feed of the second, measuring probe; `None` keeps the first reading.
+    pub slow_feed: Option<f64>,
+    /// Farthest a search moves looking for contact.
+    pub max_travel: f64,
+    /// Back-off after each contact.
+    pub retract: f64,
+    /// Probe tip diameter, compensated in edge positions.
+    pub tip_diameter: f64,
+    /// Touch plate thickness for `z_surface` and `height_map`.
+    pub plate_thickness: f64,
+    /// How far past an edge the probe travels to reach the next side of a
+    /// corner or boss.
+    pub clearance: f64,
+    /// How far below its top the sides of a boss are probed.
+    pub depth: f64,
+}
+
+impl Default for ProbeOptions {
+    fn default() -> Self {
+        ProbeOptions {
+            fast_feed: 200.0,
+            slow_feed: Some(25.0),
+            max_travel: 20.0,
+            retract: 2.0,
+            tip_diameter: 0.0,
+            plate_thickness: 0.0,
+            clearance: 10.0,
+            depth: 5.0,
+        }
+    }
+}
+
+/// A corner of the stock or pocket, seen from above (front is -Y).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Corner {
+    FrontLeft,
+    FrontRight,
+    BackLeft,
+    BackRight,
+}
+
+impl Corner {
+    /// Which way the corner lies from the centre of the stock or pocket.
+    fn directions(self) -> (Direction, Direction) {
+        use Direction::{Negative, Positive};
+        match self {
+            Corner::FrontLeft => (Negative, Negative),
+            Corner::FrontRight => (Positive, Negative),
+            Corner::BackLeft => (Negative, Positive),
+            Corner::BackRight => (Positive, Positive),
+        }
+    }
+}
+
+/// A bore or boss found by probing, in machine coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Circle {
+    pub center: [f64; 2],
+    pub diameter: f64,
+}
+
+fn along(axis: Axis, distance: f64) -> [f64; 3] {
+    let mut delta = [0.0; 3];
+    delta[axis.index()] = distance;
+    delta
+}
+
+fn axis_words(values: [Option<f64>; 3]) -> String {
+    ["X", "Y", "Z"]
+        .iter()
+        .zip(values)
+        .filter_map(|(a, v)| v.map(|v| format!(" {}{:.3}", a, v)))
+        .collect()
+}
+
+/// Runs probing routines on one session.
+pub struct Prober {
+    session: Arc<Session>,
+    options: ProbeOptions,
+}
+
+impl Prober {
+    pub fn new(session: Arc<Session>, options: ProbeOptions) -> Self {
+        Prober { session, options }
+    }
+
+    pub fn options(&self) -> &ProbeOptions {
+        &self.options
+    }
+
+    fn send(&self, line: &str) -> Result<()> {
+        self.session.send_command(line, self.session.options().command_timeout)
+    }
+
+    fn check_ready(&self) -> Result<()> {
+        let id = self.session.id();
+        if self.session.protocol() != Protocol::Grbl {
+            bail!("session {}: probing needs a GRBL-family controller", id);
+        }
+        // A job held for a tool change may measure the new tool
+        let progress = self.session.progress();
+        if progress.status.is_active() && progress.tool_change.is_none() {
+            bail!("session {}: cannot probe while a job is running", id);
+        }
+        let state = self.session.state();
+        // A probe that starts triggered is refused by the controller (alarm 4)
+        if state.status == MachineStatus::Alarm {
+            bail!("session {}: clear the alarm before probing", id);
+        }
+        // A failed cycle can leave the controller in relative mode
+        self.send("G90")
+    }
+
+    /// Run `routine` in millimetres, which every distance and feed in
+    /// `ProbeOptions` is in. Units read back with `$G` are restored after.
+    fn in_mm<T>(&self, routine: impl FnOnce() -> Result<T>) -> Result<T> {
+        self.check_ready()?;
+        // Replies are applied to the state before the `ok` is delivered
+        self.send("$G")?;
+        if !self.session.state().modes.iter().any(|m| m == "G20") {
+            return routine();
+        }
+        self.send("G21")?;
+        let out = routine();
+        // Refused while a failed cycle has the controller alarmed
+        match (out, self.send("G20")) {
+            (Ok(v), restored) => restored.map(|_| v),
+            (Err(e), Err(_)) => {
+                warn!(id = %self.session.id(), "probe::in_mm: units left at G21 after a failed routine");
+                Err(e)
+            }
+            (Err(e), Ok(())) => Err(e),
+        }
+    }
+
+    /// Machine Z of work Z zero: the active work system's offset, G92 and
+    /// the tool length offset. Offsets are read again (`$#`) rather than
+    /// trusted from the last report.
+    fn work_origin(&self) -> Result<f64> {
+        self.send("$#")?;
+        let state = self.session.state();
+        let tlo = state.offsets.get("TLO").map_or(0.0, |o| o[2]);
+        match state.wcs().and_then(|wcs| crate::envelope::wcs_origin(&state, wcs)) {
+            Some(origin) => Ok(origin[2] + tlo),
+            None => {
+                let timeout = self.session.options().command_timeout;
+                Ok(self.session.wait_for_report(timeout, |_| true)?.wco[2])
+            }
+        }
+    }
+
+    /// Machine position from a fresh status report.
+    fn position(&self) -> Result<[f64; 3]> {
+        let timeout = self.session.options().command_timeout;
+        Ok(self.session.wait_for_report(timeout, |_| true)?.mpos)
+    }
+
+    /// Rapid by `delta` from the current position.
+    fn move_by(&self, delta: [f64; 3]) -> Result<()> {
+        let words = axis_words(delta.map(|d| (d != 0.0).then_some(d)));
+        let moved = self.send(&format!("G91 G0{}", words));
+        moved.and(self.send("G90"))
+    }
+
+    /// Rapid to machine coordinates on the given axes.
+    fn move_to(&self, target: [Option<f64>; 3]) -> Result<()> {
+        self.send(&format!("G53 G0{}", axis_words(target)))
+    }
+
+    /// Run one probing cycle moving by `delta` mm at `feed` mm/min. Fails
+    /// when the controller alarms; a cycle without alarm reports
+    /// `success: false`.
+    pub fn probe(&self, cycle: ProbeCycle, delta: [f64; 3], feed: f64) -> Result<ProbeResult> {
+        self.in_mm(|| self.cycle(cycle, delta, feed))
+    }
+
+    fn cycle(&self, cycle: ProbeCycle, delta: [f64; 3], feed: f64) -> Result<ProbeResult> {
+        self.check_ready()?;
+        let id = self.session.id();
+        let distance = delta.iter().map(|d| d * d).sum::<f64>().sqrt();
+        let timeout = self.session.options().command_timeout + Duration::from_secs_f64(distance / feed * 60.0);
+        let since = self.session.signals().probes;
+        let words = axis_words(delta.map(|d| (d != 0.0).then_some(d)));
+        let sent = self.session.send_command(&format!("G91 {}{} F{:.0}", cycle.code(), words, feed), timeout);
+        // Back to absolute; refused until unlock if the cycle alarmed
+        let _ = self.send("G90");
+        if let Err(e) = sent {
+            let state = self.session.state();
+            match state.alarm {
+                Some(alarm) if state.status == MachineStatus::Alarm => bail!(
+                    "session {}: probe failed with {}; unlock before moving (distance mode is still G91)",
+                    id,
+                    alarm
+                ),
+                _ => return Err(e),
+            }
+        }
+        // The report arrives before the `ok`
+        match self.session.state().probe {
+            Some(result) if self.session.signals().probes > since => Ok(result),
+            _ => bail!("session {}: controller reported no probe result", id),
+        }
+    }
+
+    /// Probe along `axis` toward `direction`, then retract. Returns the
+    /// contact point of the measuring probe.
+    fn touch(&self, axis: Axis, direction: Direction) -> Result<[f64; 3]> {
+        let o = &self.options;
+        let sign = direction.sign();
+        let mut contact = self.expect_contact(along(axis, sign * o.max_travel), o.fast_feed)?;
+        if let Some(slow) = o.slow_feed {
+            self.move_by(along(axis, -sign * o.retract))?;
+            contact = self.expect_contact(along(axis, sign * 2.0 * o.retract), slow)?;
+        }
+        self.move_by(along(axis, -sign * o.retract))?;
+        Ok(contact)
+    }
+
+    fn expect_contact(&self, delta: [f64; 3], feed: f64) -> Result<[f64; 3]> {
+        let result = self.cycle(ProbeCycle::Touch, delta, feed)?;
+        if !result.success {
+            bail!("session {}: probe did not trigger", self.session.id());
+        }
+        Ok(result.position)
+    }
+
+    /// Probe down onto a touch plate. Returns the machine Z of the surface
+    /// the plate sits on.
+    pub fn z_surface(&self) -> Result<f64> {
+        let contact = self.in_mm(|| self.touch(Axis::Z, Direction::Negative))?;
+        let surface = contact[2] - self.options.plate_thickness;
+        info!(id = %self.session.id(), surface, "probe::z_surface: found surface");
+        Ok(surface)
+    }
+
+    /// Find the edge met moving along `axis` (X or Y) toward `direction`.
+    /// Returns its machine coordinate, corrected for the tip radius.
+    pub fn edge(&self, axis: Axis, direction: Direction) -> Result<f64> {
+        self.in_mm(|| self.find_edge(axis, direction))
+    }
+
+    fn find_edge(&self, axis: Axis, direction: Direction) -> Result<f64> {
+        if axis == Axis::Z {
+            bail!("use z_surface to probe Z");
+        }
+        let contact = self.touch(axis, direction)?;
+        Ok(contact[axis.index()] + direction.sign() * self.options.tip_diameter / 2.0)
+    }
+
+    /// Find an outside corner of the stock. Start off the corner diagonally,
+    /// below the top surface; the probe slides `clearance` along each side
+    /// before probing it, and ends off the corner again.
+    pub fn outside_corner(&self, corner: Corner) -> Result<[f64; 2]> {
+        self.in_mm(|| {
+            let (dx, dy) = corner.directions();
+            let clearance = self.options.clearance;
+            self.move_by([0.0, -dy.sign() * clearance, 0.0])?;
+            let x = self.find_edge(Axis::X, dx.opposite())?;
+            self.move_by([0.0, dy.sign() * clearance, 0.0])?;
+            self.move_to([Some(x - dx.sign() * clearance), None, None])?;
+            let y = self.find_edge(Axis::Y, dy.opposite())?;
+            self.move_to([Some(x + dx.sign() * clearance), None, None])?;
+            info!(id = %self.session.id(), corner = ?corner, x, y, "probe::outside_corner: found corner");
+            Ok([x, y])
+        })
+    }
+
+    /// Find an inside corner of a pocket, starting near it below the top.
+    pub fn inside_corner(&self, corner: Corner) -> Result<[f64; 2]> {
+        self.in_mm(|| {
+            let (dx, dy) = corner.directions();
+            let x = self.find_edge(Axis::X, dx)?;
+            let y = self.find_edge(Axis::Y, dy)?;
+            info!(id = %self.session.id(), corner = ?corner, x, y, "probe::inside_corner: found corner");
+            Ok([x, y])
+        })
+    }
+
+    /// Centre a bore, starting inside it below the top. X is probed again
+    /// once Y is centred, so a start off the middle does not skew it. Ends
+    /// at the centre.
+    pub fn bore_center(&self) -> Result<Circle> {
+        self.in_mm(|| {
+            let mut center = [0.0; 2];
+            let mut span = [0.0; 2];
+            for axis in [Axis::X, Axis::Y, Axis::X] {
+                let high = self.find_edge(axis, Direction::Positive)?;
+                let low = self.find_edge(axis, Direction::Negative)?;
+                let i = axis.index();
+                center[i] = (high + low) / 2.0;
+                span[i] = high - low;
+                let mut target = [None; 3];
+                target[i] = Some(center[i]);
+                self.move_to(target)?;
+            }
+            let circle = Circle {
+                center,
+                diameter: (span[0] + span[1]) / 2.0,
+            };
+            info!(id = %self.session.id(), circle = ?circle, "probe::bore_center: found bore");
+            Ok(circle)
+        })
+    }
+
+    /// Centre a boss about `diameter` across, starting above its middle.
+    /// Each side is probed `depth` below the start, reached by going out
+    /// `clearance` past the expected edge. Ends above the centre.
+    pub fn boss_center(&self, diameter: f64) -> Result<Circle> {
+        self.in_mm(|| {
+            let start = self.position()?;
+            let o = &self.options;
+            let reach = diameter / 2.0 + o.clearance;
+            let mut center = [start[0], start[1]];
+            let mut span = [0.0; 2];
+            for axis in [Axis::X, Axis::Y] {
+                let i = axis.index();
+                let mut edges = [0.0; 2];
+                for (edge, side) in edges.iter_mut().zip([Direction::Positive, Direction::Negative]) {
+                    self.move_by(along(axis, side.sign() * reach))?;
+                    self.lower(o.depth)?;
+                    *edge = self.find_edge(axis, side.opposite())?;
+                    self.move_by([0.0, 0.0, o.depth])?;
+                    let mut back = [None; 3];
+                    back[i] = Some(center[i]);
+                    self.move_to(back)?;
+                }
+                center[i] = (edges[0] + edges[1]) / 2.0;
+                span[i] = edges[0] - edges[1];
+                let mut target = [None; 3];
+                target[i] = Some(center[i]);
+                self.move_to(target)?;
+            }
+            let circle = Circle {
+                center,
+                diameter: (span[0] + span[1]) / 2.0,
+            };
+            info!(id = %self.session.id(), circle = ?circle, "probe::boss_center: found boss");
+            Ok(circle)
+        })
+    }
+
+    /// Probe the surface at every point of `map`'s grid and store the work
+    /// Z found, less the plate thickness. Start above the surface: the probe
+    /// travels between points at the starting height, row by row in
+    /// alternating directions.
+    pub fn height_map(&self, map: &mut HeightMap) -> Result<()> {
+        self.in_mm(|| {
+            let safe = self.position()?[2];
+            let wco = self.work_origin()?;
+            for row in 0..map.size[1] {
+                let columns: Vec<usize> = match row % 2 {
+                    0 => (0..map.size[0]).collect(),
+                    _ => (0..map.size[0]).rev().collect(),
+                };
+                for column in columns {
+                    let [x, y] = map.point(column, row);
+                    self.move_to([None, None, Some(safe)])?;
+                    self.send(&format!("G0 X{:.3} Y{:.3}", x, y))?;
+                    let contact = self.touch(Axis::Z, Direction::Negative)?;
+                    map.set_height(column, row, contact[2] - self.options.plate_thickness - wco);
+                }
+            }
+            self.move_to([None, None, Some(safe)])?;
+            info!(id = %self.session.id(), points = map.heights.len(), "probe::height_map: surface mapped");
+            Ok(())
+        })
+    }
+
+    /// Drop `depth` beside a boss with a non-alarming probe, so landing on
+    /// something stops the routine instead of the tip.
+    fn lower(&self, depth: f64) -> Result<()> {
+        let result = self.cycle(ProbeCycle::TryTouch, [0.0, 0.0, -depth], self.options.fast_feed)?;
+        if result.success {
+            bail!("session {}: probe touched down beside the boss; is the diameter right?", self.session.id());
+        }
+        Ok(())
+    }
+}
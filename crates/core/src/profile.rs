@@ -0,0 +1,221 @@
+//! Named machine profiles: how to reach a machine and how it is set up.
+//!
+//! Profiles are stored as a list in `profiles.json` in the platform data
+//! directory. Saves go through `storage::update_json`, so changing one
+//! profile never drops the others.
+
+use crate::endpoint::Endpoint;
+use crate::error::CoreError;
+use crate::toolchange::ToolChangeConfig;
+use serde::{Deserialize, Serialize};
+use std::io;
+use tracing::{debug, info};
+
+const PROFILES_FILE: &str = "profiles.json";
+
+/// Controller firmware family a profile talks to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FirmwareKind {
+    Grbl,
+    GrblHal,
+    FluidNc,
+    Marlin,
+    Smoothieware,
+}
+
+impl FirmwareKind {
+    /// GRBL and its descendants share the realtime command set and `$` settings.
+    pub fn is_grbl_like(self) -> bool {
+        matches!(self, FirmwareKind::Grbl | FirmwareKind::GrblHal | FirmwareKind::FluidNc)
+    }
+}
+
+/// How lines are paced to the controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum StreamingMode {
+    /// Keep the controller's receive buffer full, tracking bytes in flight.
+    #[default]
+    CharacterCounting,
+    /// Send one line and wait for its `ok` before the next.
+    SendResponse,
+}
+
+/// Motion limits for one axis.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AxisConfig {
+    /// Maximum rate in mm/min (GRBL `$110`-`$112`).
+    pub max_rate: f64,
+    /// Acceleration in mm/s² (GRBL `$120`-`$122`).
+    pub acceleration: f64,
+    /// Maximum travel in mm (GRBL `$130`-`$132`).
+    pub max_travel: f64,
+}
+
+impl Default for AxisConfig {
+    fn default() -> Self {
+        AxisConfig {
+            max_rate: 1000.0,
+            acceleration: 50.0,
+            max_travel: 200.0,
+        }
+    }
+}
+
+/// Per-axis motion limits.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct AxisLimits {
+    pub x: AxisConfig,
+    pub y: AxisConfig,
+    pub z: AxisConfig,
+}
+
+impl AxisLimits {
+    pub fn axes(&self) -> [AxisConfig; 3] {
+        [self.x, self.y, self.z]
+    }
+}
+
+/// Reachable region in machine coordinates (mm).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WorkEnvelope {
+    pub min: [f64; 3],
+    pub max: [f64; 3],
+}
+
+impl WorkEnvelope {
+    /// Whether a machine position lies inside the envelope (inclusive).
+    pub fn contains(&self, pos: [f64; 3]) -> bool {
+        (0..3).all(|i| pos[i] >= self.min[i] && pos[i] <= self.max[i])
+    }
+}
+
+/// What is mounted on the machine.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ToolHead {
+    #[default]
+    None,
+    Spindle { max_rpm: u32 },
+    Laser { max_power: u32 },
+}
+
+/// A G-code snippet attached to a profile.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileMacro {
+    pub name: String,
+    pub gcode: String,
+}
+
+/// A named machine and its configuration.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MachineProfile {
+    pub id: String,
+    pub name: String,
+    pub endpoint: Endpoint,
+    /// Firmware family; `None` means detect it on connect.
+    #[serde(default)]
+    pub firmware: Option<FirmwareKind>,
+    #[serde(default)]
+    pub streaming_mode: StreamingMode,
+    /// Controller receive buffer size in bytes (128 on stock GRBL).
+    #[serde(default = "default_buffer_size")]
+    pub buffer_size: usize,
+    #[serde(default)]
+    pub axis_limits: AxisLimits,
+    #[serde(default)]
+    pub envelope: Option<WorkEnvelope>,
+    /// Names of G-code preprocessors applied before streaming, in order.
+    #[serde(default)]
+    pub preprocessors: Vec<String>,
+    #[serde(default)]
+    pub macros: Vec<ProfileMacro>,
+    #[serde(default)]
+    pub tool_head: ToolHead,
+    /// Tool change position and method; `None` sends `M6` to the controller.
+    #[serde(default)]
+    pub tool_change: Option<ToolChangeConfig>,
+}
+
+fn default_buffer_size() -> usize {
+    128
+}
+
+impl MachineProfile {
+    /// A profile with default settings whose id is derived from `name`.
+    pub fn new(name: impl Into<String>, endpoint: Endpoint) -> Self {
+        let name = name.into();
+        MachineProfile {
+            id: slugify(&name),
+            name,
+            endpoint,
+            firmware: None,
+            streaming_mode: StreamingMode::default(),
+            buffer_size: default_buffer_size(),
+            axis_limits: AxisLimits::default(),
+            envelope: None,
+            preprocessors: vec![],
+            macros: vec![],
+            tool_head: ToolHead::default(),
+            tool_change: None,
+        }
+    }
+
+    /// Check the profile is usable before it is saved.
+    pub fn validate(&self) -> Result<(), CoreError> {
+        if self.id.trim().is_empty() {
+            return Err(CoreError::Config("profile id is empty".into()));
+        }
+        if self.buffer_size == 0 {
+            return Err(CoreError::Config(format!("profile {}: buffer_size must be > 0", self.id)));
+        }
+        if let Some(env) = &self.envelope {
+            if (0..3).any(|i| env.min[i] >= env.max[i]) {
+                return Err(CoreError::Config(format!("profile {}: envelope min must be below max", self.id)));
+            }
+        }
+        let bad_axis = self
+            .axis_limits
+            .axes()
+            .iter()
+            .any(|a| a.max_rate <= 0.0 || a.acceleration <= 0.0 || a.max_travel <= 0.0);
+        if bad_axis {
+            return Err(CoreError::Config(format!("profile {}: axis limits must be positive", self.id)));
+        }
+        Ok(())
+    }
+}
+
+/// Lowercase `name`, keeping ASCII letters and digits and joining the rest with `-`.
+fn slugify(name: &str) -> String {
+    let mut out = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            out.push(c.to_ascii_lowercase());
+        } else if !out.is_empty() && !out.ends_with('-') {
+            out.push('-');
+        }
+    }
+    out.trim_end_matches('-').to_string()
+}
+
+/// Load all profiles. Returns an empty Vec when `profiles.json` does not exist.
+pub fn load_profiles() -> io::Result<Vec<MachineProfile>> {
+    match gcodekit_utils::storage::read_json::<Vec<MachineProfile>>(PROFILES_FILE) {
+        Ok(v) => {
+            debug!(count = v.len(), "profile::load_profiles: loaded profiles");
+            Ok(v)
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => {
+            debug!(err = ?e, "profile::load_profiles: error reading profiles");
+            Err(e)
+        }
+    }
+}
+
+/// Apply `f` to the stored profile list and save the result.
+pub(crate) fn update_profiles<R>(f: impl FnOnce(&mut Vec<MachineProfile>) -> R) -> io::Result<R> {
+    let out = gcodekit_utils::storage::update_json(PROFILES_FILE, f)?;
+    info!("profile::update_profiles: saved profiles");
+    Ok(out)
+}
@@ -0,0 +1,166 @@
+//! Controller line protocols: classifying what the firmware sends back.
+
+use crate::endpoint::Endpoint;
+use crate::machine_state::{ProbeResult, StatusReport};
+use crate::profile::FirmwareKind;
+use gcodekit_device_adapters::sim::SimFirmware;
+
+/// A classified line received from the controller.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    /// Command accepted (`ok`).
+    Ok,
+    /// Command rejected; carries the raw reply (`error:20`, `Error:...`).
+    Error(String),
+    /// Controller entered an alarm state (`ALARM:1`).
+    Alarm(String),
+    Status(StatusReport),
+    /// Marlin `M114` position report.
+    Position([f64; 3]),
+    /// Startup banner after a reset (`Grbl 1.1h ['$' for help]`, `start`).
+    Welcome(String),
+    /// Bracketed GRBL feedback (`[MSG:...]`, `[GC:...]`, `[PRB:...]`) or
+    /// Marlin `echo:` messages.
+    Feedback(String),
+    /// GRBL setting from `$$` (`$110=500.000`).
+    Setting(u32, f64),
+    /// Coordinate offset from `$#` (`[G54:10.000,0.000,-5.000]`).
+    Offset(String, [f64; 3]),
+    Other(String),
+}
+
+/// Line protocol spoken by a controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Protocol {
+    /// GRBL, grblHAL, FluidNC and Smoothieware.
+    #[default]
+    Grbl,
+    Marlin,
+}
+
+impl Protocol {
+    pub fn for_firmware(firmware: FirmwareKind) -> Self {
+        match firmware {
+            FirmwareKind::Marlin => Protocol::Marlin,
+            _ => Protocol::Grbl,
+        }
+    }
+
+    /// Protocol for an endpoint, preferring an explicit firmware kind.
+    pub fn for_endpoint(endpoint: &Endpoint, firmware: Option<FirmwareKind>) -> Self {
+        match (firmware, endpoint) {
+            (Some(f), _) => Protocol::for_firmware(f),
+            (None, Endpoint::Sim { firmware: SimFirmware::Marlin, .. }) => Protocol::Marlin,
+            _ => Protocol::Grbl,
+        }
+    }
+
+    /// Realtime byte that requests a status report, if the protocol has one.
+    pub fn status_query(self) -> Option<u8> {
+        match self {
+            Protocol::Grbl => Some(b'?'),
+            Protocol::Marlin => None,
+        }
+    }
+
+    /// Classify one received line.
+    pub fn parse(self, line: &str) -> Response {
+        let l = line.trim();
+        match self {
+            Protocol::Grbl => {
+                if l == "ok" {
+                    Response::Ok
+                } else if l.starts_with("error:") {
+                    Response::Error(l.to_string())
+                } else if l.starts_with("ALARM:") {
+                    Response::Alarm(l.to_string())
+                } else if let Some(report) = StatusReport::parse(l) {
+                    Response::Status(report)
+                } else if l.starts_with("Grbl") || l.starts_with("GrblHAL") {
+                    Response::Welcome(l.to_string())
+                } else if let Some((name, offset)) = parse_offset(l) {
+                    Response::Offset(name, offset)
+                } else if l.starts_with('[') {
+                    Response::Feedback(l.to_string())
+                } else if let Some((n, v)) = parse_setting(l) {
+                    Response::Setting(n, v)
+                } else {
+                    Response::Other(l.to_string())
+                }
+            }
+            Protocol::Marlin => {
+                if l == "ok" || l.starts_with("ok ") {
+                    Response::Ok
+                } else if l.starts_with("Error:") || l.starts_with("!!") {
+                    Response::Error(l.to_string())
+                } else if l == "start" || l.starts_with("FIRMWARE_NAME:") {
+                    Response::Welcome(l.to_string())
+                } else if let Some(pos) = parse_marlin_position(l) {
+                    Response::Position(pos)
+                } else if l.starts_with("echo:") || l.starts_with("//") {
+                    Response::Feedback(l.to_string())
+                } else {
+                    Response::Other(l.to_string())
+                }
+            }
+        }
+    }
+}
+
+/// Parse `$N=value`, ignoring any trailing description (`$11=0.010 (junction deviation, mm)`).
+fn parse_setting(line: &str) -> Option<(u32, f64)> {
+    let (n, v) = line.strip_prefix('$')?.split_once('=')?;
+    let v = v.split_whitespace().next()?;
+    Some((n.parse().ok()?, v.parse().ok()?))
+}
+
+/// Parse `[G54:1.000,2.000,3.000]` (also `G28`, `G30`, `G92`); extra axes are ignored.
+fn parse_offset(line: &str) -> Option<(String, [f64; 3])> {
+    // Tool length offset applies along Z only
+    if let Some(z) = line.strip_prefix("[TLO:").and_then(|v| v.strip_suffix(']')) {
+        return Some(("TLO".to_string(), [0.0, 0.0, z.parse().ok()?]));
+    }
+    let (name, values) = line.strip_prefix("[G")?.strip_suffix(']')?.split_once(':')?;
+    let mut axes = values.split(',').map(|v| v.parse::<f64>().ok());
+    let offset = [axes.next()??, axes.next()??, axes.next()??];
+    Some((format!("G{}", name), offset))
+}
+
+/// Words of a `$G` parser-state report (`[GC:G0 G54 G17 G21 G90 G94 M5 M9 T0 F0 S0]`).
+pub fn parse_parser_state(line: &str) -> Option<Vec<String>> {
+    let words = line.strip_prefix("[GC:")?.strip_suffix(']')?;
+    Some(words.split_whitespace().map(str::to_string).collect())
+}
+
+/// Parse a probe report (`[PRB:1.000,2.000,-3.000:1]`); extra axes are ignored.
+pub fn parse_probe(line: &str) -> Option<ProbeResult> {
+    let (values, success) = line.strip_prefix("[PRB:")?.strip_suffix(']')?.rsplit_once(':')?;
+    let mut axes = values.split(',').map(|v| v.parse::<f64>().ok());
+    let position = [axes.next()??, axes.next()??, axes.next()??];
+    Some(ProbeResult {
+        position,
+        success: success == "1",
+    })
+}
+
+/// Parse `X:1.00 Y:2.00 Z:3.00 E:0.00 Count ...` into XYZ.
+fn parse_marlin_position(line: &str) -> Option<[f64; 3]> {
+    if !line.starts_with("X:") {
+        return None;
+    }
+    let mut pos = [None; 3];
+    for word in line.split_whitespace() {
+        if word == "Count" {
+            break;
+        }
+        let Some((axis, v)) = word.split_once(':') else { continue };
+        let idx = match axis {
+            "X" => 0,
+            "Y" => 1,
+            "Z" => 2,
+            _ => continue,
+        };
+        pos[idx] = v.parse().ok();
+    }
+    Some([pos[0]?, pos[1]?, pos[2]?])
+}
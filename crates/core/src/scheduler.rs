@@ -0,0 +1,695 @@
+//! Runs queued jobs on a session one after another.
+//!
+//! The scheduler takes jobs from the session's queue (the session stops
+//! starting them itself), loads and preprocesses each file, streams it
+//! through the session and keeps the `Job` record current.
+//! Queued jobs can be reordered, held or cancelled until they start. A job
+//! that fails or is cancelled halts the queue until `resume` is called, and
+//! with `confirm_between_jobs` every job after the first waits for `confirm`
+//! (e.g. after a material change). Progress is checkpointed to the job
+//! journal so an interrupted job can be found and resumed after a crash;
+//! `submit_from` starts a program part-way through with a generated
+//! preamble (see `gcode::resume`). With `motion` limits set, each job gets
+//! a planner-based run-time estimate that is refined while it streams.
+//! With an `envelope`, offsets are read back (`$#`, `$G`) before each job
+//! and a job whose moves would leave the machine's travel, or that uses a
+//! work system with no reported offset, is refused unless the operator
+//! overrides it; a running job is stopped if the machine is reported
+//! outside the envelope.
+
+use crate::gcode::resume::{self, ResumeOptions};
+use crate::gcode::{Estimate, HeightMap, LevelOptions, Leveled, LineSource, ModalState, MotionLimits};
+use crate::journal::{Checkpoint, InterruptedJob, Journal, JournalEvent};
+use crate::machine_state::MachineStatus;
+use crate::models::{Job, JobStatus};
+use crate::profile::{FirmwareKind, MachineProfile, WorkEnvelope};
+use crate::protocol::Protocol;
+use crate::session::{QueuedJob, Session, SessionEvent, StreamProgress, StreamStatus};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Scheduler behaviour.
+#[derive(Debug, Clone)]
+pub struct SchedulerOptions {
+    /// Wait for `confirm` before starting each job after the first.
+    pub confirm_between_jobs: bool,
+    /// Preprocessor names applied to every job (see `gcode::preprocess`).
+    pub preprocessors: Vec<String>,
+    /// How often job progress is sampled while streaming.
+    pub progress_interval: Duration,
+    /// Record finished jobs in `jobs.json`.
+    pub record_history: bool,
+    /// Journal file for progress checkpoints; `None` disables journaling.
+    pub journal: Option<PathBuf>,
+    /// Minimum time between checkpoints while a job streams.
+    pub checkpoint_interval: Duration,
+    /// Firmware that queued jobs are checked against.
+    pub firmware: Option<FirmwareKind>,
+    /// Planner limits for run-time estimates; `None` skips estimating.
+    /// GRBL's own settings are read (`$$`) and take precedence.
+    pub motion: Option<MotionLimits>,
+    /// Machine travel that jobs must stay inside; `None` disables
+    /// soft-limit checks.
+    pub envelope: Option<WorkEnvelope>,
+    /// Surface every job is leveled to as it streams (see
+    /// `gcode::heightmap`); `None` sends jobs as written.
+    pub height_map: Option<HeightMap>,
+    pub leveling: LevelOptions,
+}
+
+impl Default for SchedulerOptions {
+    fn default() -> Self {
+        SchedulerOptions {
+            confirm_between_jobs: false,
+            preprocessors: vec![],
+            progress_interval: Duration::from_millis(50),
+            record_history: true,
+            journal: Journal::default_path(),
+            checkpoint_interval: Duration::from_secs(1),
+            firmware: None,
+            motion: None,
+            envelope: None,
+            height_map: None,
+            leveling: LevelOptions::default(),
+        }
+    }
+}
+
+impl SchedulerOptions {
+    pub fn from_profile(profile: &MachineProfile) -> Self {
+        SchedulerOptions {
+            preprocessors: profile.preprocessors.clone(),
+            firmware: profile.firmware,
+            motion: Some(MotionLimits::from_profile(profile)),
+            envelope: profile.envelope,
+            ..Default::default()
+        }
+    }
+
+    /// Limits to estimate with. Reads GRBL's settings first if it has not
+    /// reported them yet and is not streaming (`$$` is refused mid-job).
+    fn motion_limits(&self, session: &Session) -> Option<MotionLimits> {
+        let motion = self.motion?;
+        let unread = session.protocol() == Protocol::Grbl && session.state().settings.is_empty();
+        if unread && !session.progress().status.is_active() {
+            // Replies are applied to the state before the `ok` is delivered
+            if let Err(e) = session.send_command("$$", session.options().command_timeout) {
+                debug!(id = %session.id(), err = %e, "scheduler::motion_limits: could not read settings");
+            }
+        }
+        Some(motion.with_grbl_settings(&session.state().settings))
+    }
+}
+
+/// What the scheduler is doing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchedulerStatus {
+    Idle,
+    Running(String),
+    AwaitingConfirmation(String),
+    /// Queue stopped after a job did not complete; carries the reason.
+    Halted(String),
+    Stopped,
+}
+
+struct State {
+    status: SchedulerStatus,
+    held: HashSet<String>,
+    halted: bool,
+    /// Job the operator confirmed may start.
+    confirmed: Option<String>,
+    current: Option<Job>,
+    /// Start line and preamble options for jobs submitted with `submit_from`.
+    starts: HashMap<String, (usize, ResumeOptions)>,
+    /// Jobs the operator allowed to run outside the work envelope.
+    unchecked: HashSet<String>,
+    /// Estimates made when jobs were queued and the limits they used,
+    /// taken when they start.
+    estimates: HashMap<String, (MotionLimits, Estimate)>,
+    finished: Vec<Job>,
+    jobs_run: usize,
+    stop: bool,
+}
+
+type Shared = Arc<(Mutex<State>, Condvar)>;
+
+/// Drives a session's job queue on a background thread.
+pub struct JobScheduler {
+    session: Arc<Session>,
+    options: SchedulerOptions,
+    shared: Shared,
+    worker: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl JobScheduler {
+    pub fn start(session: Arc<Session>, options: SchedulerOptions) -> Self {
+        if let Err(e) = session.set_scheduled(true) {
+            warn!(id = %session.id(), err = %e, "scheduler::start: session is not running");
+        }
+        let shared: Shared = Arc::new((
+            Mutex::new(State {
+                status: SchedulerStatus::Idle,
+                held: HashSet::new(),
+                halted: false,
+                confirmed: None,
+                current: None,
+                starts: HashMap::new(),
+                unchecked: HashSet::new(),
+                estimates: HashMap::new(),
+                finished: Vec::new(),
+                jobs_run: 0,
+                stop: false,
+            }),
+            Condvar::new(),
+        ));
+        let worker = Worker {
+            session: session.clone(),
+            shared: shared.clone(),
+            options: options.clone(),
+        };
+        let handle = thread::Builder::new()
+            .name(format!("scheduler-{}", session.id()))
+            .spawn(move || worker.run())
+            .expect("spawn scheduler thread");
+        JobScheduler {
+            session,
+            options,
+            shared,
+            worker: Mutex::new(Some(handle)),
+        }
+    }
+
+    fn update<R>(&self, f: impl FnOnce(&mut State) -> R) -> R {
+        let (lock, cv) = &*self.shared;
+        let out = f(&mut lock.lock().unwrap());
+        cv.notify_all();
+        out
+    }
+
+    /// Queue a G-code file and return the job id.
+    pub fn submit(&self, path: impl AsRef<Path>) -> String {
+        self.enqueue(Job::new(path.as_ref().display().to_string()))
+    }
+
+    /// Queue a G-code file to start at program line `line` (counted after
+    /// comments and blank lines are removed, as in job progress).
+    pub fn submit_from(&self, path: impl AsRef<Path>, line: usize, opts: ResumeOptions) -> String {
+        let job = Job::new(path.as_ref().display().to_string());
+        self.update(|st| st.starts.insert(job.id.clone(), (line, opts)));
+        self.enqueue(job)
+    }
+
+    /// Queue an interrupted job from the journal at its last checkpoint.
+    pub fn resume_interrupted(&self, job: &InterruptedJob, opts: ResumeOptions) -> String {
+        self.submit_from(&job.file_path, job.resume_line(), opts)
+    }
+
+    /// Queue a job record; its `file_path` is loaded when the job starts.
+    /// The file is analyzed in the background so the report is available
+    /// before it runs; the queued job is republished with it.
+    pub fn enqueue(&self, mut job: Job) -> String {
+        job.status = JobStatus::Queued;
+        let id = job.id.clone();
+        debug!(id = %self.session.id(), job = %id, "scheduler::enqueue: job queued");
+        self.publish(&job);
+        let analyze = job.analysis.is_none().then(|| job.clone());
+        self.session.queue().push_back(QueuedJob::from_job(job));
+        self.update(|_| ());
+        if let Some(job) = analyze {
+            self.analyze(job);
+        }
+        id
+    }
+
+    /// Analyze and estimate a queued job on its own thread. The estimate is
+    /// kept for when the job starts, so it is made once.
+    fn analyze(&self, job: Job) {
+        let (session, shared, options) = (self.session.clone(), self.shared.clone(), self.options.clone());
+        let spawned = thread::Builder::new().name(format!("analyze-{}", job.id)).spawn(move || {
+            let lines = match crate::gcode::preprocess::open_file(Path::new(&job.file_path), &options.preprocessors) {
+                Ok(lines) => lines,
+                Err(e) => {
+                    debug!(job = %job.id, err = %e, "scheduler::analyze: could not analyze job");
+                    return;
+                }
+            };
+            let analysis = crate::gcode::analyze(lines.as_ref(), options.firmware);
+            let estimate = options
+                .motion_limits(&session)
+                .map(|limits| (limits, crate::gcode::estimate(lines.as_ref(), &limits)));
+            let seconds = estimate.as_ref().map(|(_, e)| e.total().as_secs_f64());
+            if let Some(estimate) = estimate {
+                shared.0.lock().unwrap().estimates.insert(job.id.clone(), estimate);
+            }
+            let updated = session.queue().iter_mut().find(|q| q.job.id == job.id).map(|q| {
+                q.job.lines_total = analysis.line_count;
+                q.job.analysis = Some(analysis);
+                q.job.estimated_seconds = seconds;
+                q.job.clone()
+            });
+            match updated {
+                Some(job) => session.publish(SessionEvent::Job {
+                    id: session.id().to_string(),
+                    job,
+                }),
+                // Started or cancelled meanwhile; it was analyzed when it started
+                None => {
+                    shared.0.lock().unwrap().estimates.remove(&job.id);
+                }
+            }
+        });
+        if let Err(e) = spawned {
+            warn!(err = %e, "scheduler::analyze: could not spawn analysis thread");
+        }
+    }
+
+    pub fn queued(&self) -> Vec<Job> {
+        self.session.queued_jobs()
+    }
+
+    fn is_queued(&self, id: &str) -> bool {
+        self.session.queue().iter().any(|q| q.job.id == id)
+    }
+
+    pub fn current(&self) -> Option<Job> {
+        self.shared.0.lock().unwrap().current.clone()
+    }
+
+    /// Jobs that have completed, failed or been cancelled, oldest first.
+    pub fn finished(&self) -> Vec<Job> {
+        self.shared.0.lock().unwrap().finished.clone()
+    }
+
+    pub fn status(&self) -> SchedulerStatus {
+        self.shared.0.lock().unwrap().status.clone()
+    }
+
+    /// Move a queued job to `index` in the queue.
+    pub fn move_job(&self, id: &str, index: usize) -> bool {
+        let moved = {
+            let mut q = self.session.queue();
+            match q.iter().position(|j| j.job.id == id).and_then(|i| q.remove(i)) {
+                Some(job) => {
+                    let index = index.min(q.len());
+                    q.insert(index, job);
+                    true
+                }
+                None => false,
+            }
+        };
+        self.update(|_| ());
+        moved
+    }
+
+    /// Keep a queued job from starting until it is released.
+    pub fn hold(&self, id: &str) -> bool {
+        if !self.is_queued(id) {
+            return false;
+        }
+        self.update(|st| st.held.insert(id.to_string()))
+    }
+
+    pub fn release(&self, id: &str) -> bool {
+        self.update(|st| st.held.remove(id))
+    }
+
+    /// Let a queued job run even if it leaves the work envelope.
+    pub fn override_envelope(&self, id: &str) -> bool {
+        if !self.is_queued(id) {
+            return false;
+        }
+        info!(job = %id, "scheduler::override_envelope: soft-limit checks disabled for job");
+        self.update(|st| st.unchecked.insert(id.to_string()))
+    }
+
+    /// Cancel a queued job, or stop the running one. Returns false if the
+    /// id is neither queued nor running.
+    pub fn cancel(&self, id: &str) -> anyhow::Result<bool> {
+        let queued = {
+            let mut q = self.session.queue();
+            q.iter().position(|j| j.job.id == id).and_then(|i| q.remove(i))
+        };
+        if let Some(QueuedJob { mut job, .. }) = queued {
+            info!(job = %id, "scheduler::cancel: removed queued job");
+            job.status = JobStatus::Cancelled;
+            self.publish(&job);
+            self.update(|st| {
+                st.held.remove(id);
+                st.starts.remove(id);
+                st.unchecked.remove(id);
+                st.estimates.remove(id);
+                st.finished.push(job);
+            });
+            return Ok(true);
+        }
+        let running = self.current().is_some_and(|j| j.id == id);
+        if running {
+            info!(job = %id, "scheduler::cancel: cancelling running job");
+            self.session.cancel()?;
+        }
+        Ok(running)
+    }
+
+    /// Let the job the scheduler is waiting on start. Returns false when
+    /// nothing is awaiting confirmation.
+    pub fn confirm(&self) -> bool {
+        self.update(|st| match &st.status {
+            SchedulerStatus::AwaitingConfirmation(id) => {
+                st.confirmed = Some(id.clone());
+                true
+            }
+            _ => false,
+        })
+    }
+
+    /// Restart a queue halted by a failed or cancelled job.
+    pub fn resume(&self) {
+        self.update(|st| {
+            st.halted = false;
+            if matches!(st.status, SchedulerStatus::Halted(_)) {
+                st.status = SchedulerStatus::Idle;
+            }
+        });
+    }
+
+    /// Stop taking new jobs and wait for the worker to exit. A running job
+    /// is left to finish.
+    pub fn shutdown(&self) {
+        self.update(|st| st.stop = true);
+        if let Some(handle) = self.worker.lock().unwrap().take() {
+            let _ = handle.join();
+        }
+    }
+
+    fn publish(&self, job: &Job) {
+        self.session.publish(SessionEvent::Job {
+            id: self.session.id().to_string(),
+            job: job.clone(),
+        });
+    }
+}
+
+impl Drop for JobScheduler {
+    fn drop(&mut self) {
+        // Do not join: the worker may be waiting on a long job
+        self.update(|st| st.stop = true);
+    }
+}
+
+struct Worker {
+    session: Arc<Session>,
+    shared: Shared,
+    options: SchedulerOptions,
+}
+
+impl Worker {
+    fn publish(&self, job: &Job) {
+        self.session.publish(SessionEvent::Job {
+            id: self.session.id().to_string(),
+            job: job.clone(),
+        });
+    }
+
+    fn run(self) {
+        while let Some(job) = self.next_job() {
+            let job = self.run_job(job);
+            let (lock, cv) = &*self.shared;
+            let mut st = lock.lock().unwrap();
+            st.current = None;
+            st.jobs_run += 1;
+            match &job.status {
+                JobStatus::Completed => st.status = SchedulerStatus::Idle,
+                other => {
+                    let reason = match other {
+                        JobStatus::Failed(e) => e.clone(),
+                        _ => "cancelled".to_string(),
+                    };
+                    warn!(id = %self.session.id(), job = %job.id, reason = %reason, "scheduler::run: halting queue");
+                    st.halted = true;
+                    st.status = SchedulerStatus::Halted(reason);
+                }
+            }
+            st.finished.push(job);
+            cv.notify_all();
+        }
+        let _ = self.session.set_scheduled(false);
+        debug!(id = %self.session.id(), "scheduler::run: stopped");
+    }
+
+    /// Wait for the next job that may start, once the session is free.
+    /// Returns `None` on shutdown.
+    fn next_job(&self) -> Option<QueuedJob> {
+        let (lock, cv) = &*self.shared;
+        let mut st = lock.lock().unwrap();
+        loop {
+            if st.stop {
+                st.status = SchedulerStatus::Stopped;
+                return None;
+            }
+            if !st.halted && !self.session.progress().status.is_active() {
+                let next = self.session.queue().iter().find(|q| !st.held.contains(&q.job.id)).map(|q| q.job.clone());
+                match next {
+                    Some(next) => {
+                        let needs_confirm = self.options.confirm_between_jobs && st.jobs_run > 0;
+                        if !needs_confirm || st.confirmed.as_deref() == Some(next.id.as_str()) {
+                            let mut q = self.session.queue();
+                            if let Some(queued) = q.iter().position(|j| j.job.id == next.id).and_then(|i| q.remove(i)) {
+                                st.confirmed = None;
+                                st.current = Some(queued.job.clone());
+                                st.status = SchedulerStatus::Running(next.id);
+                                return Some(queued);
+                            }
+                        } else if st.status != SchedulerStatus::AwaitingConfirmation(next.id.clone()) {
+                            info!(id = %self.session.id(), job = %next.id, "scheduler::next_job: awaiting operator confirmation");
+                            st.status = SchedulerStatus::AwaitingConfirmation(next.id.clone());
+                            self.session.publish(SessionEvent::ConfirmationRequired {
+                                id: self.session.id().to_string(),
+                                job_id: next.id,
+                            });
+                        }
+                    }
+                    None => st.status = SchedulerStatus::Idle,
+                }
+            }
+            // Timed wait so a missed notification cannot stall the queue
+            st = cv.wait_timeout(st, Duration::from_millis(200)).unwrap().0;
+        }
+    }
+
+    fn set_current(&self, job: &Job) {
+        self.shared.0.lock().unwrap().current = Some(job.clone());
+        self.publish(job);
+    }
+
+    /// Update `job` from stream progress. The stream runs `preamble` extra
+    /// lines before program line `start`.
+    fn apply_progress(job: &mut Job, p: &StreamProgress, start: usize, preamble: usize) {
+        job.lines_sent = start + p.acked.saturating_sub(preamble);
+        job.progress = if job.lines_total == 0 {
+            1.0
+        } else {
+            job.lines_sent as f32 / job.lines_total as f32
+        };
+        job.status = match p.status {
+            StreamStatus::Paused => JobStatus::Paused,
+            StreamStatus::Completed => JobStatus::Completed,
+            StreamStatus::Cancelled => JobStatus::Cancelled,
+            StreamStatus::Failed => JobStatus::Failed(p.error.clone().unwrap_or_else(|| "failed".into())),
+            StreamStatus::Idle | StreamStatus::Running => JobStatus::Running,
+        };
+    }
+
+    fn open_journal(&self) -> Option<Journal> {
+        let path = self.options.journal.as_ref()?;
+        Journal::open(path)
+            .map_err(|e| warn!(path = %path.display(), err = %e, "scheduler::open_journal: journaling disabled"))
+            .ok()
+    }
+
+    fn journal(&self, journal: &mut Option<Journal>, job_id: &str, event: JournalEvent) {
+        if let Some(j) = journal {
+            if let Err(e) = j.append(job_id, event) {
+                warn!(job = %job_id, err = %e, "scheduler::journal: write failed, journaling disabled");
+                *journal = None;
+            }
+        }
+    }
+
+    /// Load a queued job's lines with the preprocessors applied, then
+    /// leveled to the height map if there is one; a file is mapped and read
+    /// line by line as it streams.
+    fn load(&self, queued: &QueuedJob) -> Result<Arc<dyn LineSource>, crate::error::CoreError> {
+        let names = &self.options.preprocessors;
+        let lines = match &queued.lines {
+            Some(lines) if names.is_empty() => lines.clone(),
+            Some(lines) => Arc::new(crate::gcode::preprocess::Preprocessed::new(lines.clone(), names)?),
+            None => crate::gcode::preprocess::open_file(Path::new(&queued.job.file_path), names)?,
+        };
+        match &self.options.height_map {
+            Some(map) => Ok(Arc::new(Leveled::new(lines, map.clone(), self.options.leveling.clone())?)),
+            None => Ok(lines),
+        }
+    }
+
+    fn run_job(&self, queued: QueuedJob) -> Job {
+        let mut job = queued.job.clone();
+        info!(id = %self.session.id(), job = %job.id, file = %job.file_path, "scheduler::run_job: starting");
+        job.status = JobStatus::Running;
+
+        let lines = match self.load(&queued) {
+            Ok(lines) => lines,
+            Err(e) => return self.fail(job, e.to_string(), &mut None),
+        };
+        job.lines_total = lines.len();
+        let (start, unchecked, estimate) = {
+            let mut st = self.shared.0.lock().unwrap();
+            (st.starts.remove(&job.id), st.unchecked.remove(&job.id), st.estimates.remove(&job.id))
+        };
+        // Started before the background analysis finished
+        let analysis = job
+            .analysis
+            .get_or_insert_with(|| crate::gcode::analyze(lines.as_ref(), self.options.firmware));
+        let envelope = self.options.envelope.filter(|_| !unchecked);
+        if let Some(envelope) = &envelope {
+            let grbl = self.session.protocol() == Protocol::Grbl;
+            // Place the job with the offsets the controller holds now
+            if grbl {
+                if let Err(e) = crate::wcs::WcsManager::new(self.session.clone()).refresh() {
+                    return self.fail(job, format!("could not read work offsets: {}", e), &mut None);
+                }
+            }
+            let state = self.session.state();
+            let unreported = crate::envelope::unreported_offsets(analysis, &state);
+            if !unreported.is_empty() {
+                let list = unreported.join(", ");
+                if grbl {
+                    return self.fail(job, format!("no offset reported for {}; cannot check the work envelope", list), &mut None);
+                }
+                warn!(job = %job.id, wcs = %list, "scheduler::run_job: offsets not reported, checking against the active offset");
+            }
+            let violations = crate::envelope::check_job(analysis, envelope, &state);
+            if !violations.is_empty() {
+                let list: Vec<String> = violations.iter().map(ToString::to_string).collect();
+                return self.fail(job, format!("outside work envelope: {}", list.join("; ")), &mut None);
+            }
+        }
+        self.set_current(&job);
+        let (program, start, mut modal) = match start {
+            Some((line, opts)) => match resume::plan(lines.as_ref(), line, &opts) {
+                Ok(plan) => {
+                    info!(job = %job.id, line, preamble = plan.preamble.len(), "scheduler::run_job: resuming part-way");
+                    let program: Arc<dyn LineSource> = Arc::new(plan.program(lines.clone()));
+                    (program, plan.start_line, plan.modal)
+                }
+                Err(e) => return self.fail(job, e.to_string(), &mut None),
+            },
+            None => (lines.clone(), 0, ModalState::default()),
+        };
+        let preamble = program.len() - (lines.len() - start);
+        job.lines_sent = start;
+        // Reuse the estimate made when the job was queued unless the
+        // controller has since reported different settings
+        let estimate = self.options.motion_limits(&self.session).map(|limits| match estimate {
+            Some((made_for, estimate)) if made_for == limits => estimate,
+            _ => crate::gcode::estimate(lines.as_ref(), &limits),
+        });
+        if let Some(est) = &estimate {
+            job.estimated_seconds = Some(est.total().as_secs_f64());
+            job.remaining_seconds = Some(est.from_line(start).as_secs_f64());
+        }
+        let mut journal = self.open_journal();
+        self.journal(
+            &mut journal,
+            &job.id,
+            JournalEvent::Started {
+                device_id: self.session.id().to_string(),
+                file_path: job.file_path.clone(),
+                lines_total: job.lines_total,
+            },
+        );
+        if let Err(e) = self.session.stream_source(program) {
+            return self.fail(job, e.to_string(), &mut journal);
+        }
+
+        let started = Instant::now();
+        let mut checkpointed = start;
+        let mut last_checkpoint = Instant::now();
+        let mut breach = None;
+        loop {
+            let done = self.session.wait_for_stream(self.options.progress_interval);
+            if let (Some(envelope), None) = (&envelope, &breach) {
+                let state = self.session.state();
+                let outside = crate::envelope::check_position(envelope, state.mpos);
+                if state.status != MachineStatus::Unknown && !outside.is_empty() {
+                    warn!(job = %job.id, mpos = ?state.mpos, "scheduler::run_job: machine left the work envelope, stopping");
+                    if let Err(e) = self.session.emergency_stop() {
+                        warn!(job = %job.id, err = %e, "scheduler::run_job: emergency stop failed");
+                    }
+                    breach = Some(format!("position outside work envelope: {}", outside[0]));
+                }
+            }
+            let before = (job.lines_sent, std::mem::discriminant(&job.status));
+            Self::apply_progress(&mut job, done.as_ref().unwrap_or(&self.session.progress()), start, preamble);
+            if let Some(reason) = &breach {
+                job.status = JobStatus::Failed(reason.clone());
+            }
+            if let Some(est) = &estimate {
+                job.remaining_seconds = Some(est.remaining(start, job.lines_sent, started.elapsed()).as_secs_f64());
+            }
+            if job.lines_sent > checkpointed
+                && (done.is_some() || last_checkpoint.elapsed() >= self.options.checkpoint_interval)
+            {
+                for line in (checkpointed..job.lines_sent).filter_map(|i| lines.line(i)) {
+                    modal.apply(&line);
+                }
+                checkpointed = job.lines_sent;
+                last_checkpoint = Instant::now();
+                let state = self.session.state();
+                self.journal(
+                    &mut journal,
+                    &job.id,
+                    JournalEvent::Checkpoint(Box::new(Checkpoint {
+                        line: checkpointed,
+                        mpos: state.mpos,
+                        wpos: state.wpos,
+                        modal: modal.clone(),
+                    })),
+                );
+            }
+            if done.is_some() {
+                return self.finish(job, &mut journal);
+            }
+            if (job.lines_sent, std::mem::discriminant(&job.status)) != before {
+                self.set_current(&job);
+            }
+        }
+    }
+
+    fn fail(&self, mut job: Job, err: String, journal: &mut Option<Journal>) -> Job {
+        warn!(job = %job.id, err = %err, "scheduler::run_job: could not start job");
+        job.status = JobStatus::Failed(err);
+        self.finish(job, journal)
+    }
+
+    fn finish(&self, job: Job, journal: &mut Option<Journal>) -> Job {
+        info!(job = %job.id, status = ?job.status, "scheduler::run_job: finished");
+        self.journal(
+            journal,
+            &job.id,
+            JournalEvent::Finished {
+                status: job.status.clone(),
+            },
+        );
+        self.publish(&job);
+        if self.options.record_history {
+            if let Err(e) = crate::persistence::record_job(&job) {
+                debug!(job = %job.id, err = %e, "scheduler::finish: could not record job");
+            }
+        }
+        job
+    }
+}
@@ -0,0 +1,236 @@
+//! Rhai scripts with a sandboxed machine API (feature `scripting`).
+//!
+//! A script runs on the calling thread and drives one session through a
+//! small set of functions:
+//!
+//! - `send(line)`: send a command and wait for `ok`; an `error:` reply
+//!   ends the script.
+//! - `state()`: map of `status`, work `x` `y` `z`, machine `mx` `my` `mz`,
+//!   `wcs`, `tool`, `feed` and `spindle`.
+//! - `wait_idle()`: wait for the machine to stop moving.
+//! - `probe_result()`: map of machine `x` `y` `z` and `ok` for the last
+//!   probing cycle, or `()` before the first.
+//! - `prompt(message)` / `prompt(message, default)`: ask the operator.
+//! - `sleep(ms)`; `print` and `debug` go to the log.
+//!
+//! The engine has no module loader, no `eval` and limits on operations,
+//! call depth and sizes, so scripts cannot reach files, the network or
+//! anything besides the session. `ScriptRunner::emergency_stop` stops the
+//! machine and ends the script at its next step.
+
+use crate::machine_state::{MachineState, MachineStatus};
+use crate::macros::{Macro, MacroLanguage};
+use crate::session::Session;
+use anyhow::{anyhow, bail, Result};
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Dynamic, Engine, EvalAltResult, Map, Scope, FLOAT, INT};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+/// Answers `prompt(message, default)` calls; `None` cancels the script.
+pub type PromptFn = dyn Fn(&str, Option<&str>) -> Option<String> + Send + Sync;
+
+/// Limits for scripts.
+#[derive(Debug, Clone)]
+pub struct ScriptOptions {
+    /// Most operations a script may run; 0 for no limit.
+    pub max_operations: u64,
+    /// Longest `wait_idle` waits for the machine.
+    pub wait_timeout: Duration,
+}
+
+impl Default for ScriptOptions {
+    fn default() -> Self {
+        ScriptOptions {
+            max_operations: 1_000_000,
+            wait_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Runs scripts on one session.
+pub struct ScriptRunner {
+    session: Arc<Session>,
+    options: ScriptOptions,
+    prompt: Option<Arc<PromptFn>>,
+    abort: Arc<AtomicBool>,
+}
+
+/// How often blocking script calls check for an emergency stop.
+const POLL: Duration = Duration::from_millis(20);
+
+fn state_map(state: &MachineState) -> Map {
+    let tool = state
+        .modes
+        .iter()
+        .find_map(|w| w.strip_prefix('T')?.parse::<INT>().ok())
+        .unwrap_or(0);
+    let mut map = Map::new();
+    map.insert("status".into(), format!("{:?}", state.status).into());
+    for (i, axis) in ["x", "y", "z"].into_iter().enumerate() {
+        map.insert(axis.into(), (state.wpos[i] as FLOAT).into());
+        map.insert(format!("m{}", axis).into(), (state.mpos[i] as FLOAT).into());
+    }
+    map.insert("wcs".into(), state.wcs().unwrap_or("G54").to_string().into());
+    map.insert("tool".into(), tool.into());
+    map.insert("feed".into(), (state.feed as FLOAT).into());
+    map.insert("spindle".into(), (state.spindle as FLOAT).into());
+    map
+}
+
+impl ScriptRunner {
+    pub fn new(session: Arc<Session>, options: ScriptOptions) -> Self {
+        ScriptRunner {
+            session,
+            options,
+            prompt: None,
+            abort: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Answer `prompt` calls with `f`. Without one, prompts take their
+    /// default or end the script.
+    pub fn with_prompt(mut self, f: impl Fn(&str, Option<&str>) -> Option<String> + Send + Sync + 'static) -> Self {
+        self.prompt = Some(Arc::new(f));
+        self
+    }
+
+    /// Stop the machine and end the running script.
+    pub fn emergency_stop(&self) -> Result<()> {
+        self.abort.store(true, Ordering::SeqCst);
+        info!(id = %self.session.id(), "scripting::emergency_stop: aborting script");
+        self.session.emergency_stop()
+    }
+
+    fn engine(&self) -> Engine {
+        let mut engine = Engine::new();
+        engine.set_module_resolver(DummyModuleResolver::new());
+        engine.disable_symbol("eval");
+        engine.set_max_operations(self.options.max_operations);
+        engine.set_max_call_levels(32);
+        engine.set_max_expr_depths(64, 32);
+        engine.set_max_string_size(64 * 1024);
+        engine.set_max_array_size(10_000);
+        engine.set_max_map_size(10_000);
+
+        let id = self.session.id().to_string();
+        let abort = self.abort.clone();
+        engine.on_progress(move |_| abort.load(Ordering::SeqCst).then(|| "emergency stop".into()));
+        let print_id = id.clone();
+        engine.on_print(move |text| info!(id = %print_id, text, "scripting::print"));
+        engine.on_debug(move |text, _, pos| debug!(id = %id, text, pos = %pos, "scripting::debug"));
+
+        let aborted = {
+            let abort = self.abort.clone();
+            move || -> std::result::Result<(), Box<EvalAltResult>> {
+                match abort.load(Ordering::SeqCst) {
+                    true => Err("emergency stop".into()),
+                    false => Ok(()),
+                }
+            }
+        };
+
+        let (session, check) = (self.session.clone(), aborted.clone());
+        engine.register_fn("send", move |line: &str| -> std::result::Result<(), Box<EvalAltResult>> {
+            check()?;
+            let timeout = session.options().command_timeout;
+            session.send_command(line, timeout).map_err(|e| e.to_string().into())
+        });
+
+        let session = self.session.clone();
+        engine.register_fn("state", move || state_map(&session.state()));
+
+        let session = self.session.clone();
+        engine.register_fn("probe_result", move || match session.state().probe {
+            Some(p) => {
+                let mut map = Map::new();
+                for (i, axis) in ["x", "y", "z"].into_iter().enumerate() {
+                    map.insert(axis.into(), (p.position[i] as FLOAT).into());
+                }
+                map.insert("ok".into(), p.success.into());
+                Dynamic::from_map(map)
+            }
+            None => Dynamic::UNIT,
+        });
+
+        let (session, check, wait) = (self.session.clone(), aborted.clone(), self.options.wait_timeout);
+        engine.register_fn("wait_idle", move || -> std::result::Result<(), Box<EvalAltResult>> {
+            let start = Instant::now();
+            loop {
+                check()?;
+                if session.wait_for_report(POLL, |s| s.status == MachineStatus::Idle).is_ok() {
+                    return Ok(());
+                }
+                if !session.is_connected() || start.elapsed() > wait {
+                    return Err(format!("machine not idle within {:?}", wait).into());
+                }
+            }
+        });
+
+        let check = aborted.clone();
+        engine.register_fn("sleep", move |ms: INT| -> std::result::Result<(), Box<EvalAltResult>> {
+            let end = Instant::now() + Duration::from_millis(ms.max(0) as u64);
+            while Instant::now() < end {
+                check()?;
+                thread::sleep(POLL.min(end - Instant::now()));
+            }
+            check()
+        });
+
+        let ask = {
+            let prompt = self.prompt.clone();
+            move |message: &str, default: Option<&str>| -> std::result::Result<String, Box<EvalAltResult>> {
+                aborted()?;
+                let answer = match &prompt {
+                    Some(f) => f(message, default),
+                    None => default.map(str::to_string),
+                };
+                answer.ok_or_else(|| format!("no answer to prompt {:?}", message).into())
+            }
+        };
+        let ask2 = ask.clone();
+        engine.register_fn("prompt", move |message: &str| ask(message, None));
+        engine.register_fn("prompt", move |message: &str, default: &str| ask2(message, Some(default)));
+        engine
+    }
+
+    /// Run `script` with `vars` defined as constants: numbers as floats,
+    /// anything else as strings. Fails while a job is streaming.
+    pub fn run(&self, script: &str, vars: &BTreeMap<String, String>) -> Result<()> {
+        let id = self.session.id();
+        if self.session.progress().status.is_active() {
+            bail!("session {}: cannot run a script while a job is streaming", id);
+        }
+        self.abort.store(false, Ordering::SeqCst);
+        let engine = self.engine();
+        let ast = engine
+            .compile(script)
+            .map_err(|e| anyhow!("session {}: script does not compile: {}", id, e))?;
+        let mut scope = Scope::new();
+        for (name, value) in vars {
+            match value.parse::<FLOAT>() {
+                Ok(n) => scope.push_constant(name.as_str(), n),
+                Err(_) => scope.push_constant(name.as_str(), value.clone()),
+            };
+        }
+        info!(id = %id, "scripting::run: running script");
+        match engine.run_ast_with_scope(&mut scope, &ast) {
+            Ok(()) => Ok(()),
+            Err(_) if self.abort.load(Ordering::SeqCst) => bail!("session {}: script aborted by emergency stop", id),
+            Err(e) => bail!("session {}: script failed: {}", id, e),
+        }
+    }
+
+    /// Run a macro: Rhai macros with their parameters as variables, G-code
+    /// macros through `Session::run_macro`.
+    pub fn run_macro(&self, m: &Macro, answers: &BTreeMap<String, String>) -> Result<()> {
+        match m.language {
+            MacroLanguage::Rhai => self.run(&m.gcode, &m.param_values(answers)?),
+            MacroLanguage::Gcode => self.session.run_macro(m, answers),
+        }
+    }
+}
@@ -0,0 +1,1120 @@
+//! A live connection to one machine.
+//!
+//! A `Session` owns its transport through a dedicated I/O thread. The thread
+//! sends queued lines (respecting the controller's receive buffer), polls
+//! status, reads and classifies replies, keeps the `MachineState` current and
+//! publishes `SessionEvent`s. Callers talk to the thread through a command
+//! channel, so sending never blocks on a pending read.
+
+use crate::control::Operation;
+use crate::endpoint::Endpoint;
+use crate::gcode::reader::{GcodeFile, LineSource};
+use crate::machine_state::{MachineState, MachineStatus};
+use crate::models::Job;
+use crate::profile::{MachineProfile, StreamingMode};
+use crate::protocol::{parse_parser_state, parse_probe, Protocol, Response};
+use crate::toolchange::{self, ToolChange};
+use anyhow::{anyhow, bail, Result};
+use gcodekit_device_adapters::Transport;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
+
+/// How long the I/O thread waits for input before servicing commands again.
+pub const DEFAULT_POLL: Duration = Duration::from_millis(20);
+/// Default interval between status queries.
+pub const DEFAULT_STATUS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Tuning for a session's I/O thread.
+#[derive(Debug, Clone)]
+pub struct SessionOptions {
+    pub streaming_mode: StreamingMode,
+    /// Controller receive buffer in bytes, used for character counting.
+    pub buffer_size: usize,
+    pub poll: Duration,
+    /// Interval between status queries; `None` disables polling.
+    pub status_interval: Option<Duration>,
+    /// How long commands wait for a reply or the expected state change.
+    pub command_timeout: Duration,
+    /// How long a homing cycle may take.
+    pub homing_timeout: Duration,
+    /// Hold jobs at `M6` lines for a `toolchange::ToolChanger`.
+    pub tool_changes: bool,
+}
+
+impl Default for SessionOptions {
+    fn default() -> Self {
+        SessionOptions {
+            streaming_mode: StreamingMode::CharacterCounting,
+            buffer_size: 128,
+            poll: DEFAULT_POLL,
+            status_interval: Some(DEFAULT_STATUS_INTERVAL),
+            command_timeout: Duration::from_secs(10),
+            homing_timeout: Duration::from_secs(60),
+            tool_changes: false,
+        }
+    }
+}
+
+impl SessionOptions {
+    pub fn from_profile(profile: &MachineProfile) -> Self {
+        SessionOptions {
+            streaming_mode: profile.streaming_mode,
+            buffer_size: profile.buffer_size,
+            tool_changes: profile.tool_change.is_some(),
+            ..Default::default()
+        }
+    }
+}
+
+/// State of the job being streamed on a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StreamStatus {
+    #[default]
+    Idle,
+    Running,
+    Paused,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+impl StreamStatus {
+    /// Whether a job is still in progress.
+    pub fn is_active(self) -> bool {
+        matches!(self, StreamStatus::Running | StreamStatus::Paused)
+    }
+}
+
+/// Progress of the current (or last) streamed job.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StreamProgress {
+    /// Id of the queued job being streamed; `None` for `Session::stream`.
+    pub job_id: Option<String>,
+    pub status: StreamStatus,
+    pub total: usize,
+    pub sent: usize,
+    pub acked: usize,
+    /// Reply or reason that ended the job early.
+    pub error: Option<String>,
+    /// Tool change the job is paused for.
+    pub tool_change: Option<ToolChange>,
+}
+
+/// A job waiting in a session's queue, with its G-code lines.
+#[derive(Debug, Clone)]
+pub struct QueuedJob {
+    pub job: Job,
+    /// `None` opens `job.file_path` when the job starts.
+    pub lines: Option<Arc<dyn LineSource>>,
+}
+
+impl QueuedJob {
+    /// Queue `lines` under a name (usually the source file path).
+    pub fn new(name: impl Into<String>, lines: Vec<String>) -> Self {
+        QueuedJob::from_source(name, Arc::new(lines))
+    }
+
+    /// Queue lines from a shared source without copying them.
+    pub fn from_source(name: impl Into<String>, lines: Arc<dyn LineSource>) -> Self {
+        let mut job = Job::new(name);
+        job.lines_total = lines.len();
+        QueuedJob { job, lines: Some(lines) }
+    }
+
+    /// Queue a job record; its file is opened when the job starts.
+    pub fn from_job(job: Job) -> Self {
+        QueuedJob { job, lines: None }
+    }
+
+    /// Map and index a G-code file; lines are read as they are sent.
+    pub fn from_file(path: &Path) -> std::io::Result<Self> {
+        let file = GcodeFile::open(path)?;
+        Ok(QueuedJob::from_source(path.display().to_string(), Arc::new(file)))
+    }
+}
+
+/// Notifications published by sessions. Every event names its device id.
+#[derive(Debug, Clone)]
+pub enum SessionEvent {
+    Connected { id: String, endpoint: String },
+    Disconnected { id: String, reason: Option<String> },
+    /// A line from the controller other than a status report.
+    Received { id: String, line: String },
+    StateChanged { id: String, state: MachineState },
+    Stream { id: String, progress: StreamProgress },
+    /// An `error:`/`ALARM:` reply or a transport failure.
+    Error { id: String, message: String },
+    /// A scheduled job changed status or progress.
+    Job { id: String, job: Job },
+    /// The scheduler is waiting for the operator before starting `job_id`.
+    ConfirmationRequired { id: String, job_id: String },
+    /// The job is paused at an `M6` line until the tool is changed.
+    ToolChange { id: String, request: ToolChange },
+    /// A control operation (homing, unlock, ...) finished; `error` says why
+    /// it failed.
+    Operation {
+        id: String,
+        operation: Operation,
+        error: Option<String>,
+    },
+}
+
+impl SessionEvent {
+    pub fn device_id(&self) -> &str {
+        match self {
+            SessionEvent::Connected { id, .. }
+            | SessionEvent::Disconnected { id, .. }
+            | SessionEvent::Received { id, .. }
+            | SessionEvent::StateChanged { id, .. }
+            | SessionEvent::Stream { id, .. }
+            | SessionEvent::Error { id, .. }
+            | SessionEvent::Job { id, .. }
+            | SessionEvent::ConfirmationRequired { id, .. }
+            | SessionEvent::ToolChange { id, .. }
+            | SessionEvent::Operation { id, .. } => id,
+        }
+    }
+}
+
+/// Where the reply to a command line is delivered: `Err` carries an
+/// `error:` reply. Dropped unanswered on alarm, reset or e-stop.
+type Reply = mpsc::Sender<std::result::Result<(), String>>;
+
+/// The waiting end of a `Reply`; disconnected if the line is dropped.
+pub(crate) type PendingReply = mpsc::Receiver<std::result::Result<(), String>>;
+
+enum Command {
+    Line(String),
+    /// A line whose reply the caller waits for.
+    Request(String, Reply),
+    Realtime(u8),
+    /// Drop command lines not yet written to the controller.
+    DiscardQueued,
+    Stream(Arc<dyn LineSource>),
+    /// A job was queued; start it if the queue is not halted.
+    Enqueued,
+    /// Clear a halted queue and start the next job.
+    RunQueue,
+    /// A `JobScheduler` takes over (`true`) or hands back the queue.
+    Scheduled(bool),
+    Pause,
+    Resume,
+    Cancel,
+    EmergencyStop,
+    Disconnect,
+}
+
+/// Counters the I/O thread bumps so callers can wait for fresh replies.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Signals {
+    /// Status reports received.
+    pub reports: u64,
+    /// Startup banners received, i.e. controller resets.
+    pub resets: u64,
+    /// Probe results received.
+    pub probes: u64,
+}
+
+struct Shared {
+    state: RwLock<MachineState>,
+    signals: Mutex<Signals>,
+    signalled: Condvar,
+    progress: Mutex<StreamProgress>,
+    queue: Mutex<VecDeque<QueuedJob>>,
+    progress_changed: Condvar,
+    connected: AtomicBool,
+}
+
+/// A connected machine.
+pub struct Session {
+    id: String,
+    endpoint: Endpoint,
+    protocol: Protocol,
+    options: SessionOptions,
+    commands: Mutex<mpsc::Sender<Command>>,
+    shared: Arc<Shared>,
+    events: broadcast::Sender<SessionEvent>,
+    io: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Session {
+    /// Start a session over an already connected transport.
+    pub fn start(
+        id: impl Into<String>,
+        endpoint: Endpoint,
+        transport: Box<dyn Transport>,
+        protocol: Protocol,
+        options: SessionOptions,
+        events: broadcast::Sender<SessionEvent>,
+    ) -> Self {
+        let id = id.into();
+        let (tx, rx) = mpsc::channel();
+        let shared = Arc::new(Shared {
+            state: RwLock::new(MachineState::default()),
+            signals: Mutex::new(Signals::default()),
+            signalled: Condvar::new(),
+            progress: Mutex::new(StreamProgress::default()),
+            queue: Mutex::new(VecDeque::new()),
+            progress_changed: Condvar::new(),
+            connected: AtomicBool::new(true),
+        });
+        let io = IoLoop {
+            id: id.clone(),
+            transport,
+            commands: rx,
+            shared: shared.clone(),
+            events: events.clone(),
+            protocol,
+            options: options.clone(),
+            manual: VecDeque::new(),
+            job: None,
+            in_flight: VecDeque::new(),
+            paused: false,
+            tool: None,
+            tool_changed: false,
+            queue_halted: false,
+            scheduled: false,
+            last_status: None,
+        };
+        info!(id = %id, endpoint = %endpoint, "session::start: connected");
+        let _ = events.send(SessionEvent::Connected {
+            id: id.clone(),
+            endpoint: endpoint.to_string(),
+        });
+        let handle = thread::Builder::new()
+            .name(format!("session-{}", id))
+            .spawn(move || {
+                // A panic in one machine's I/O must not take down the others;
+                // record it as a disconnect so callers and the UI see it
+                let id = io.id.clone();
+                let shared = io.shared.clone();
+                let events = io.events.clone();
+                if std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| io.run())).is_err() {
+                    warn!(id = %id, "session::run: I/O thread panicked");
+                    shared.connected.store(false, Ordering::SeqCst);
+                    let mut p = shared.progress.lock().unwrap_or_else(|e| e.into_inner());
+                    if p.status.is_active() {
+                        p.status = StreamStatus::Failed;
+                        p.error = Some("I/O thread panicked".into());
+                    }
+                    drop(p);
+                    shared.progress_changed.notify_all();
+                    let _ = events.send(SessionEvent::Disconnected {
+                        id,
+                        reason: Some("I/O thread panicked".into()),
+                    });
+                }
+            })
+            .expect("spawn session I/O thread");
+        Session {
+            id,
+            endpoint,
+            protocol,
+            options,
+            commands: Mutex::new(tx),
+            shared,
+            events,
+            io: Mutex::new(Some(handle)),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    pub fn options(&self) -> &SessionOptions {
+        &self.options
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.shared.connected.load(Ordering::SeqCst)
+    }
+
+    /// Snapshot of the machine state.
+    pub fn state(&self) -> MachineState {
+        self.shared.state.read().unwrap().clone()
+    }
+
+    /// Change the state for controllers that do not report it and publish
+    /// the change.
+    pub(crate) fn update_state(&self, f: impl FnOnce(&mut MachineState)) {
+        let state = {
+            let mut state = self.shared.state.write().unwrap();
+            f(&mut state);
+            state.clone()
+        };
+        self.publish(SessionEvent::StateChanged {
+            id: self.id.clone(),
+            state,
+        });
+    }
+
+    /// Snapshot of the current job's progress.
+    pub fn progress(&self) -> StreamProgress {
+        self.shared.progress.lock().unwrap().clone()
+    }
+
+    /// Publish an event on this session's channel (used by the scheduler).
+    pub fn publish(&self, event: SessionEvent) {
+        let _ = self.events.send(event);
+    }
+
+    fn command(&self, cmd: Command) -> Result<()> {
+        if !self.is_connected() {
+            bail!("session {} is disconnected", self.id);
+        }
+        self.commands
+            .lock()
+            .unwrap()
+            .send(cmd)
+            .map_err(|_| anyhow!("session {} is disconnected", self.id))
+    }
+
+    /// Queue a single command line. It is sent ahead of any job lines.
+    pub fn send_line(&self, line: &str) -> Result<()> {
+        self.command(Command::Line(line.trim().to_string()))
+    }
+
+    /// Send a command line and wait for the controller's reply. An `error:`
+    /// reply, or the line being dropped by an alarm or reset, is an error.
+    pub fn send_command(&self, line: &str, timeout: Duration) -> Result<()> {
+        let line = line.trim();
+        let rx = self.request(line)?;
+        match rx.recv_timeout(timeout) {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(reply)) => bail!("session {}: `{}` rejected with {}", self.id, line, reply),
+            Err(RecvTimeoutError::Timeout) => bail!("session {}: no reply to `{}` within {:?}", self.id, line, timeout),
+            Err(RecvTimeoutError::Disconnected) => {
+                bail!("session {}: `{}` was dropped before the controller replied", self.id, line)
+            }
+        }
+    }
+
+    /// Queue a command line without waiting; its reply arrives on the
+    /// returned channel.
+    pub(crate) fn request(&self, line: &str) -> Result<PendingReply> {
+        let (tx, rx) = mpsc::channel();
+        self.command(Command::Request(line.trim().to_string(), tx))?;
+        Ok(rx)
+    }
+
+    /// Drop command lines queued with `send_line` or `send_command` that
+    /// have not been written to the controller yet; waiting `send_command`
+    /// calls fail. Job lines are not affected.
+    pub fn discard_queued(&self) -> Result<()> {
+        self.command(Command::DiscardQueued)
+    }
+
+    pub(crate) fn signals(&self) -> Signals {
+        *self.shared.signals.lock().unwrap()
+    }
+
+    /// Block until `done` holds for the reply counters and machine state.
+    /// `what` names the awaited condition in the timeout error.
+    pub(crate) fn wait_for(
+        &self,
+        what: &str,
+        timeout: Duration,
+        done: impl Fn(&Signals, &MachineState) -> bool,
+    ) -> Result<MachineState> {
+        let check = |s: &Signals| done(s, &self.shared.state.read().unwrap());
+        let guard = self.shared.signals.lock().unwrap();
+        let (guard, _) = self
+            .shared
+            .signalled
+            .wait_timeout_while(guard, timeout, |s| self.is_connected() && !check(s))
+            .unwrap();
+        if check(&guard) {
+            return Ok(self.state());
+        }
+        if !self.is_connected() {
+            bail!("session {} disconnected while waiting for {}", self.id, what);
+        }
+        bail!("session {}: timed out after {:?} waiting for {}", self.id, timeout, what)
+    }
+
+    /// Wait for a status report, received after this call, whose state
+    /// satisfies `pred`.
+    pub fn wait_for_report(&self, timeout: Duration, pred: impl Fn(&MachineState) -> bool) -> Result<MachineState> {
+        let since = self.signals().reports;
+        self.wait_for("a matching status report", timeout, |s, state| s.reports > since && pred(state))
+    }
+
+    /// Send a realtime byte immediately, outside the line queue.
+    pub fn send_realtime(&self, byte: u8) -> Result<()> {
+        self.command(Command::Realtime(byte))
+    }
+
+    /// Start streaming a job. Blank lines are skipped. Fails while another
+    /// job is still running or paused.
+    pub fn stream<I>(&self, lines: I) -> Result<()>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let lines: Vec<String> = lines
+            .into_iter()
+            .map(|l| l.as_ref().trim().to_string())
+            .filter(|l| !l.is_empty())
+            .collect();
+        self.stream_source(Arc::new(lines))
+    }
+
+    /// Start streaming a job from a shared source (e.g. a `GcodeFile`).
+    /// Lines are read from it as they are sent.
+    pub fn stream_source(&self, lines: Arc<dyn LineSource>) -> Result<()> {
+        {
+            let mut progress = self.shared.progress.lock().unwrap();
+            if progress.status.is_active() {
+                bail!("session {} is already streaming a job", self.id);
+            }
+            *progress = StreamProgress {
+                status: StreamStatus::Running,
+                total: lines.len(),
+                ..Default::default()
+            };
+        }
+        debug!(id = %self.id, lines = lines.len(), "session::stream: job queued");
+        self.command(Command::Stream(lines)).inspect_err(|_| {
+            self.shared.progress.lock().unwrap().status = StreamStatus::Failed;
+        })
+    }
+
+    /// Add a job to this session's queue and return its id. The queue runs
+    /// jobs one after another; it stops after a job fails or is cancelled
+    /// until `run_queue` is called. While a `JobScheduler` drives the
+    /// session, it starts queued jobs instead.
+    pub fn enqueue(&self, job: QueuedJob) -> Result<String> {
+        let id = job.job.id.clone();
+        self.shared.queue.lock().unwrap().push_back(job);
+        debug!(id = %self.id, job = %id, "session::enqueue: job queued");
+        self.command(Command::Enqueued)?;
+        Ok(id)
+    }
+
+    /// Restart a queue halted by a failed or cancelled job.
+    pub fn run_queue(&self) -> Result<()> {
+        self.command(Command::RunQueue)
+    }
+
+    /// Jobs waiting in the queue, in order.
+    pub fn queued_jobs(&self) -> Vec<Job> {
+        self.shared.queue.lock().unwrap().iter().map(|q| q.job.clone()).collect()
+    }
+
+    /// Remove a waiting job from the queue. Returns whether it was queued.
+    pub fn dequeue(&self, job_id: &str) -> bool {
+        let mut q = self.shared.queue.lock().unwrap();
+        let before = q.len();
+        q.retain(|j| j.job.id != job_id);
+        q.len() != before
+    }
+
+    /// The job queue, for a `JobScheduler` to pick jobs from.
+    pub(crate) fn queue(&self) -> std::sync::MutexGuard<'_, VecDeque<QueuedJob>> {
+        self.shared.queue.lock().unwrap()
+    }
+
+    /// Stop (`true`) or resume starting queued jobs on the I/O thread. A
+    /// queue handed back stays halted until `run_queue`.
+    pub(crate) fn set_scheduled(&self, scheduled: bool) -> Result<()> {
+        self.command(Command::Scheduled(scheduled))
+    }
+
+    /// Stop feeding job lines; GRBL controllers also get a feed hold.
+    /// Pausing with no job running holds the next job until `resume`.
+    pub fn pause(&self) -> Result<()> {
+        self.command(Command::Pause)
+    }
+
+    /// Continue a paused job; GRBL controllers also get a cycle start. A
+    /// job held for a tool change continues past its `M6`.
+    pub fn resume(&self) -> Result<()> {
+        self.command(Command::Resume)
+    }
+
+    /// Drop the rest of the job. Lines already sent still complete.
+    pub fn cancel(&self) -> Result<()> {
+        self.command(Command::Cancel)
+    }
+
+    /// Stop the machine and drop all queued and in-flight lines. GRBL gets a
+    /// soft reset; other controllers the transport's emergency stop.
+    pub fn emergency_stop(&self) -> Result<()> {
+        self.command(Command::EmergencyStop)
+    }
+
+    /// Block until the current job finishes or `timeout` elapses. Returns
+    /// the final progress, or `None` on timeout.
+    pub fn wait_for_stream(&self, timeout: Duration) -> Option<StreamProgress> {
+        let guard = self.shared.progress.lock().unwrap();
+        let (guard, res) = self
+            .shared
+            .progress_changed
+            .wait_timeout_while(guard, timeout, |p| p.status.is_active())
+            .unwrap();
+        (!res.timed_out()).then(|| guard.clone())
+    }
+
+    /// Close the transport and stop the I/O thread.
+    pub fn disconnect(&self) -> Result<()> {
+        let _ = self.commands.lock().unwrap().send(Command::Disconnect);
+        if let Some(handle) = self.io.lock().unwrap().take() {
+            handle
+                .join()
+                .map_err(|_| anyhow!("session {} I/O thread panicked", self.id))?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let _ = self.disconnect();
+    }
+}
+
+/// The active job: its lines and the index of the next one to send.
+struct JobCursor {
+    lines: Arc<dyn LineSource>,
+    next: usize,
+}
+
+impl JobCursor {
+    fn is_done(&self) -> bool {
+        self.next >= self.lines.len()
+    }
+}
+
+/// Line waiting for its `ok`: byte count, whether it belongs to the job,
+/// and who wants its reply.
+struct InFlight {
+    bytes: usize,
+    job: bool,
+    reply: Option<Reply>,
+}
+
+struct IoLoop {
+    id: String,
+    transport: Box<dyn Transport>,
+    commands: mpsc::Receiver<Command>,
+    shared: Arc<Shared>,
+    events: broadcast::Sender<SessionEvent>,
+    protocol: Protocol,
+    options: SessionOptions,
+    manual: VecDeque<(String, Option<Reply>)>,
+    job: Option<JobCursor>,
+    in_flight: VecDeque<InFlight>,
+    paused: bool,
+    /// Last tool number sent in a job.
+    tool: Option<u32>,
+    /// The held `M6` line may be sent.
+    tool_changed: bool,
+    /// Set when a job fails or is cancelled; queued jobs wait for `run_queue`.
+    queue_halted: bool,
+    /// A `JobScheduler` starts queued jobs; the I/O thread leaves them alone.
+    scheduled: bool,
+    last_status: Option<Instant>,
+}
+
+impl IoLoop {
+    fn run(mut self) {
+        if let Err(e) = self.transport.set_read_timeout(self.options.poll) {
+            debug!(id = %self.id, err = %e, "session::run: transport ignores read timeout");
+        }
+        let reason = loop {
+            if !self.drain_commands() {
+                break None;
+            }
+            if let Err(e) = self.fill().and_then(|_| self.poll_status()) {
+                break Some(e.to_string());
+            }
+            let started = Instant::now();
+            match self.transport.read_line() {
+                Ok(line) => self.on_line(&line),
+                Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {
+                    // Transports that return at once (sim, replay) would spin;
+                    // wait on the command channel for the rest of the poll
+                    let waited = started.elapsed();
+                    if waited < self.options.poll {
+                        match self.commands.recv_timeout(self.options.poll - waited) {
+                            Ok(cmd) => {
+                                if !self.handle(cmd) {
+                                    break None;
+                                }
+                            }
+                            Err(RecvTimeoutError::Timeout) => {}
+                            Err(RecvTimeoutError::Disconnected) => break None,
+                        }
+                    }
+                }
+                Err(e) => break Some(e.to_string()),
+            }
+        };
+        self.finish(reason);
+    }
+
+    /// Handle queued commands. Returns `false` when asked to disconnect.
+    fn drain_commands(&mut self) -> bool {
+        loop {
+            match self.commands.try_recv() {
+                Ok(cmd) => {
+                    if !self.handle(cmd) {
+                        return false;
+                    }
+                }
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => return false,
+            }
+        }
+    }
+
+    fn emit(&self, event: SessionEvent) {
+        // No subscribers is fine
+        let _ = self.events.send(event);
+    }
+
+    fn emit_progress(&self, progress: &StreamProgress) {
+        self.emit(SessionEvent::Stream {
+            id: self.id.clone(),
+            progress: progress.clone(),
+        });
+        self.shared.progress_changed.notify_all();
+    }
+
+    fn update_progress(&self, f: impl FnOnce(&mut StreamProgress)) {
+        let snapshot = {
+            let mut p = self.shared.progress.lock().unwrap();
+            f(&mut p);
+            p.clone()
+        };
+        self.emit_progress(&snapshot);
+    }
+
+    fn realtime(&mut self, byte: u8) {
+        if let Err(e) = self.transport.send_realtime(byte) {
+            warn!(id = %self.id, byte, err = %e, "session::realtime: send failed");
+            self.emit(SessionEvent::Error {
+                id: self.id.clone(),
+                message: format!("realtime 0x{:02x}: {}", byte, e),
+            });
+        }
+    }
+
+    /// Apply one command. Returns `false` to end the session.
+    fn handle(&mut self, cmd: Command) -> bool {
+        match cmd {
+            Command::Line(line) => self.manual.push_back((line, None)),
+            Command::Request(line, reply) => self.manual.push_back((line, Some(reply))),
+            Command::Realtime(byte) => self.realtime(byte),
+            Command::DiscardQueued => {
+                debug!(id = %self.id, lines = self.manual.len(), "session::discard_queued: dropping queued lines");
+                self.manual.clear();
+            }
+            Command::Enqueued => {
+                if !self.queue_halted {
+                    self.start_next();
+                }
+            }
+            Command::RunQueue => {
+                self.queue_halted = false;
+                self.start_next();
+            }
+            Command::Scheduled(scheduled) => {
+                self.scheduled = scheduled;
+                self.queue_halted |= !scheduled;
+            }
+            Command::Stream(lines) => {
+                self.job = Some(JobCursor { lines, next: 0 });
+                let paused = self.paused;
+                self.update_progress(|p| {
+                    if paused {
+                        p.status = StreamStatus::Paused;
+                    }
+                });
+                self.check_complete();
+            }
+            Command::Pause => {
+                self.paused = true;
+                if self.protocol == Protocol::Grbl {
+                    self.realtime(b'!');
+                }
+                self.update_progress(|p| {
+                    if p.status == StreamStatus::Running {
+                        p.status = StreamStatus::Paused;
+                    }
+                });
+            }
+            Command::Resume => {
+                self.paused = false;
+                if self.protocol == Protocol::Grbl {
+                    self.realtime(b'~');
+                }
+                let mut changed = false;
+                self.update_progress(|p| {
+                    if p.status == StreamStatus::Paused {
+                        p.status = StreamStatus::Running;
+                    }
+                    changed = p.tool_change.take().is_some();
+                });
+                self.tool_changed |= changed;
+            }
+            Command::Cancel => self.end_job(StreamStatus::Cancelled, None),
+            Command::EmergencyStop => {
+                // `!` only holds feed; GRBL stops spindle and motion on a
+                // soft reset, which also drops everything it had buffered
+                let reset = match self.protocol {
+                    Protocol::Grbl => self.transport.send_realtime(0x18),
+                    _ => Err(io::Error::from(io::ErrorKind::Unsupported)),
+                };
+                match reset {
+                    Ok(()) => info!(id = %self.id, "session::emergency_stop: soft reset sent"),
+                    Err(e) => {
+                        debug!(id = %self.id, err = %e, "session::emergency_stop: invoking transport emergency_stop");
+                        if let Err(e) = self.transport.emergency_stop() {
+                            warn!(id = %self.id, err = %e, "session::emergency_stop: transport failed");
+                        }
+                    }
+                }
+                self.in_flight.clear();
+                self.manual.clear();
+                self.end_job(StreamStatus::Cancelled, Some("emergency stop".into()));
+            }
+            Command::Disconnect => return false,
+        }
+        true
+    }
+
+    /// Stop the job (if one is active) with a final status.
+    fn end_job(&mut self, status: StreamStatus, error: Option<String>) {
+        self.job = None;
+        self.paused = false;
+        self.tool_changed = false;
+        let (active, queued) = {
+            let p = self.shared.progress.lock().unwrap();
+            (p.status.is_active(), p.job_id.is_some())
+        };
+        if active {
+            debug!(id = %self.id, ?status, "session::end_job: job ended");
+            // Only a queued job halts the queue, not a macro or a scheduled job
+            self.queue_halted |= queued;
+            self.update_progress(|p| {
+                p.status = status;
+                p.error = error;
+                p.tool_change = None;
+            });
+        }
+    }
+
+    fn check_complete(&mut self) {
+        let running = self.shared.progress.lock().unwrap().status == StreamStatus::Running;
+        // Progress turns Running before the loop receives the job; until then
+        // there is nothing to complete
+        if running && self.job.as_ref().is_some_and(JobCursor::is_done) && !self.in_flight.iter().any(|f| f.job) {
+            info!(id = %self.id, "session::stream: job completed");
+            self.update_progress(|p| p.status = StreamStatus::Completed);
+            self.start_next();
+        }
+    }
+
+    /// Start the next queued job unless one is already active or a
+    /// scheduler owns the queue.
+    fn start_next(&mut self) {
+        if self.scheduled {
+            return;
+        }
+        let (next, lines) = {
+            let mut p = self.shared.progress.lock().unwrap();
+            if p.status.is_active() {
+                return;
+            }
+            let Some(next) = self.shared.queue.lock().unwrap().pop_front() else {
+                return;
+            };
+            let lines = match &next.lines {
+                Some(lines) => Ok(lines.clone()),
+                None => GcodeFile::open(Path::new(&next.job.file_path)).map(|f| Arc::new(f) as Arc<dyn LineSource>),
+            };
+            *p = StreamProgress {
+                job_id: Some(next.job.id.clone()),
+                status: match &lines {
+                    Err(_) => StreamStatus::Failed,
+                    Ok(_) if self.paused => StreamStatus::Paused,
+                    Ok(_) => StreamStatus::Running,
+                },
+                total: lines.as_ref().map_or(0, |l| l.len()),
+                error: lines.as_ref().err().map(ToString::to_string),
+                ..Default::default()
+            };
+            (next, lines)
+        };
+        let progress = self.shared.progress.lock().unwrap().clone();
+        let lines = match lines {
+            Ok(lines) => lines,
+            Err(e) => {
+                warn!(id = %self.id, job = %next.job.id, err = %e, "session::start_next: could not open job");
+                self.queue_halted = true;
+                self.emit_progress(&progress);
+                return;
+            }
+        };
+        info!(id = %self.id, job = %next.job.id, lines = lines.len(), "session::start_next: starting queued job");
+        self.job = Some(JobCursor { lines, next: 0 });
+        self.emit_progress(&progress);
+        self.check_complete();
+    }
+
+    /// Send queued lines while the controller has room for them.
+    fn fill(&mut self) -> std::io::Result<()> {
+        loop {
+            let (line, job) = match (self.manual.front(), &self.job) {
+                (Some((l, _)), _) => (Cow::Borrowed(l.as_str()), false),
+                (None, Some(cursor)) if !self.paused => match cursor.lines.line(cursor.next) {
+                    Some(l) => (l, true),
+                    None => return Ok(()),
+                },
+                _ => return Ok(()),
+            };
+            let mut line = line;
+            if job && self.options.tool_changes {
+                let (m6, tool) = toolchange::parse_line(&line);
+                let tool = tool.or(self.tool);
+                if m6 && !self.tool_changed {
+                    // Hold once the lines before it are done, so the machine stops there
+                    if !self.in_flight.iter().any(|f| f.job) {
+                        let line = self.job.as_ref().map_or(0, |c| c.next);
+                        self.hold_for_tool_change(ToolChange { tool, line });
+                    }
+                    return Ok(());
+                }
+                self.tool = tool;
+                if m6 {
+                    line = Cow::Owned(toolchange::strip_m6(&line));
+                    if line.is_empty() {
+                        self.skip_line();
+                        continue;
+                    }
+                }
+            }
+            let bytes = line.len() + 1;
+            let in_flight: usize = self.in_flight.iter().map(|f| f.bytes).sum();
+            let room = match self.options.streaming_mode {
+                StreamingMode::SendResponse => self.in_flight.is_empty(),
+                // A line longer than the buffer is sent once the buffer is empty
+                StreamingMode::CharacterCounting => {
+                    self.in_flight.is_empty() || in_flight + bytes <= self.options.buffer_size
+                }
+            };
+            if !room {
+                return Ok(());
+            }
+            let line = line.into_owned();
+            let reply = match &mut self.job {
+                Some(cursor) if job => {
+                    cursor.next += 1;
+                    self.tool_changed = false;
+                    None
+                }
+                _ => self.manual.pop_front().and_then(|(_, reply)| reply),
+            };
+            debug!(id = %self.id, line = %line, "session::fill: sending line");
+            self.transport.send_line(&line)?;
+            self.in_flight.push_back(InFlight { bytes, job, reply });
+            if job {
+                // Progress events are published on acks; sends only bump the counter
+                self.shared.progress.lock().unwrap().sent += 1;
+            }
+        }
+    }
+
+    /// Pause the job at its `M6` line until `Session::resume`.
+    fn hold_for_tool_change(&mut self, request: ToolChange) {
+        info!(id = %self.id, tool = ?request.tool, line = request.line, "session::fill: holding for tool change");
+        self.paused = true;
+        self.update_progress(|p| {
+            p.status = StreamStatus::Paused;
+            p.tool_change = Some(request);
+        });
+        self.emit(SessionEvent::ToolChange {
+            id: self.id.clone(),
+            request,
+        });
+    }
+
+    /// Count a job line with nothing left to send as sent and acknowledged.
+    fn skip_line(&mut self) {
+        if let Some(cursor) = &mut self.job {
+            cursor.next += 1;
+        }
+        self.tool_changed = false;
+        self.update_progress(|p| {
+            p.sent += 1;
+            p.acked += 1;
+        });
+        self.check_complete();
+    }
+
+    fn poll_status(&mut self) -> std::io::Result<()> {
+        let (Some(interval), Some(query)) = (self.options.status_interval, self.protocol.status_query()) else {
+            return Ok(());
+        };
+        if self.last_status.is_none_or(|t| t.elapsed() >= interval) {
+            self.last_status = Some(Instant::now());
+            match self.transport.send_realtime(query) {
+                Ok(()) => {}
+                // Transports without realtime support simply do not poll
+                Err(e) if e.kind() == std::io::ErrorKind::Unsupported => self.options.status_interval = None,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn signal(&self, f: impl FnOnce(&mut Signals)) {
+        f(&mut self.shared.signals.lock().unwrap());
+        self.shared.signalled.notify_all();
+    }
+
+    fn set_state(&self, f: impl FnOnce(&mut MachineState) -> bool) {
+        let changed = {
+            let mut state = self.shared.state.write().unwrap();
+            f(&mut state).then(|| state.clone())
+        };
+        if let Some(state) = changed {
+            self.emit(SessionEvent::StateChanged {
+                id: self.id.clone(),
+                state,
+            });
+        }
+    }
+
+    fn ack(&mut self, error: Option<String>) {
+        let Some(done) = self.in_flight.pop_front() else {
+            debug!(id = %self.id, "session::ack: reply with nothing in flight");
+            return;
+        };
+        if let Some(reply) = &done.reply {
+            let _ = reply.send(error.clone().map_or(Ok(()), Err));
+        }
+        if done.job {
+            let snapshot = {
+                let mut p = self.shared.progress.lock().unwrap();
+                p.acked += 1;
+                p.clone()
+            };
+            self.emit_progress(&snapshot);
+        }
+        if let Some(err) = error {
+            warn!(id = %self.id, reply = %err, "session::ack: controller reported error");
+            self.set_state(|s| {
+                s.last_error = Some(err.clone());
+                true
+            });
+            self.emit(SessionEvent::Error {
+                id: self.id.clone(),
+                message: err.clone(),
+            });
+            if done.job {
+                self.end_job(StreamStatus::Failed, Some(err));
+            }
+        }
+        self.check_complete();
+    }
+
+    fn on_line(&mut self, line: &str) {
+        if line.trim().is_empty() {
+            return;
+        }
+        let response = self.protocol.parse(line);
+        if !matches!(response, Response::Status(_)) {
+            self.emit(SessionEvent::Received {
+                id: self.id.clone(),
+                line: line.to_string(),
+            });
+        }
+        match response {
+            Response::Ok => self.ack(None),
+            Response::Error(e) => self.ack(Some(e)),
+            Response::Alarm(a) => {
+                // The controller discards its buffers on alarm
+                self.in_flight.clear();
+                self.set_state(|s| {
+                    s.status = MachineStatus::Alarm;
+                    s.alarm = Some(a.clone());
+                    true
+                });
+                self.emit(SessionEvent::Error {
+                    id: self.id.clone(),
+                    message: a.clone(),
+                });
+                self.end_job(StreamStatus::Failed, Some(a));
+            }
+            Response::Status(report) => {
+                self.set_state(|s| s.apply_report(&report));
+                self.signal(|s| s.reports += 1);
+            }
+            Response::Position(pos) => self.set_state(|s| {
+                let changed = s.mpos != pos;
+                s.mpos = pos;
+                s.wpos = [pos[0] - s.wco[0], pos[1] - s.wco[1], pos[2] - s.wco[2]];
+                changed
+            }),
+            Response::Welcome(banner) => {
+                // A banner means the controller reset and lost everything queued
+                self.in_flight.clear();
+                self.set_state(|s| {
+                    s.firmware = Some(banner.clone());
+                    true
+                });
+                self.signal(|s| s.resets += 1);
+                self.end_job(StreamStatus::Failed, Some("controller reset".into()));
+            }
+            Response::Setting(n, v) => self.set_state(|s| s.settings.insert(n, v) != Some(v)),
+            Response::Offset(name, offset) => self.set_state(|s| s.offsets.insert(name, offset) != Some(offset)),
+            Response::Feedback(text) => {
+                if let Some(modes) = parse_parser_state(&text) {
+                    self.set_state(|s| std::mem::replace(&mut s.modes, modes.clone()) != modes);
+                } else if let Some(probe) = parse_probe(&text) {
+                    self.set_state(|s| {
+                        s.probe = Some(probe);
+                        true
+                    });
+                    self.signal(|s| s.probes += 1);
+                }
+            }
+            Response::Other(_) => {}
+        }
+    }
+
+    fn finish(mut self, reason: Option<String>) {
+        if let Err(e) = self.transport.disconnect() {
+            debug!(id = %self.id, err = %e, "session::finish: transport disconnect failed");
+        }
+        self.shared.connected.store(false, Ordering::SeqCst);
+        self.end_job(StreamStatus::Failed, Some(reason.clone().unwrap_or_else(|| "disconnected".into())));
+        self.shared.progress_changed.notify_all();
+        self.signal(|_| ());
+        info!(id = %self.id, reason = ?reason, "session::finish: disconnected");
+        self.emit(SessionEvent::Disconnected {
+            id: self.id.clone(),
+            reason,
+        });
+    }
+}
@@ -0,0 +1,323 @@
+//! Tool changes for jobs containing `M6`.
+//!
+//! With `SessionOptions::tool_changes` set, a session holds its job when it
+//! reaches an `M6` line, once every line before it has been acknowledged,
+//! and publishes `SessionEvent::ToolChange`. `ToolChanger` runs the change:
+//! `begin` parks the spindle at the profile's change position for the
+//! operator, and `finish` measures the new tool on the tool setter, applies
+//! the length difference with `G43.1`, returns to where the job stopped and
+//! resumes it; without a setter the offset stored in the tool table is
+//! used. A changer made `with_library` saves measured offsets to the
+//! profile's tools in `tools.json`. Machines with an automatic changer run
+//! a macro instead. The `M6`
+//! word itself is never sent; the rest of its line is. GRBL-family
+//! controllers only.
+
+use crate::gcode::modal::words;
+use crate::machine_state::MachineStatus;
+use crate::probe::{ProbeOptions, Prober};
+use crate::protocol::Protocol;
+use crate::session::Session;
+use crate::tools::{save_tool, ToolTable};
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+/// A fixed tool length sensor.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ToolSetter {
+    /// Machine XY of the sensor and the Z to start probing from.
+    pub position: [f64; 3],
+    /// Farthest to probe down looking for the sensor.
+    pub max_travel: f64,
+    /// Probing feed in mm/min.
+    pub feed: f64,
+}
+
+/// Where and how a machine changes tools.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolChangeConfig {
+    /// Machine position the spindle parks at for the operator. Z is raised
+    /// to this height before any move in XY.
+    pub position: [f64; 3],
+    /// Tool setter; `None` applies offsets stored in the tool table.
+    #[serde(default)]
+    pub setter: Option<ToolSetter>,
+    /// G-code run instead of the manual change on machines with an
+    /// automatic changer; `{{tool}}` is replaced by the tool number.
+    #[serde(default)]
+    pub atc_macro: Option<String>,
+    /// Seconds to dwell after restarting the spindle, before the tool goes
+    /// back down.
+    #[serde(default = "default_spindle_dwell")]
+    pub spindle_dwell: f64,
+}
+
+fn default_spindle_dwell() -> f64 {
+    3.0
+}
+
+/// A job held at an `M6` line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ToolChange {
+    /// Tool asked for: the line's `T` word, else the last one sent.
+    pub tool: Option<u32>,
+    /// Index of the `M6` line in the job.
+    pub line: usize,
+}
+
+/// Whether `line` changes tools (`M6`), and its `T` word.
+pub(crate) fn parse_line(line: &str) -> (bool, Option<u32>) {
+    let mut m6 = false;
+    let mut tool = None;
+    for (letter, value) in words(line) {
+        match letter {
+            'M' => m6 |= value.parse::<f64>() == Ok(6.0),
+            'T' => tool = value.parse::<f64>().ok().map(|t| t as u32),
+            _ => {}
+        }
+    }
+    (m6, tool)
+}
+
+/// `line` without its `M6` word.
+pub(crate) fn strip_m6(line: &str) -> String {
+    words(line)
+        .into_iter()
+        .filter(|(letter, value)| !(*letter == 'M' && value.parse::<f64>() == Ok(6.0)))
+        .map(|(letter, value)| format!("{}{}", letter, value))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Runs tool changes on one session.
+pub struct ToolChanger {
+    session: Arc<Session>,
+    config: ToolChangeConfig,
+    /// Setter reading of the tool the job's Z zero was set with.
+    reference: Mutex<Option<f64>>,
+    /// Length offset applied for the tool in the spindle.
+    offset: Mutex<f64>,
+    /// Machine position the job stopped at.
+    resume_at: Mutex<Option<[f64; 3]>>,
+    /// Parser state (`$G`) when the job stopped, for the spindle, coolant
+    /// and units to restore.
+    modes: Mutex<Vec<String>>,
+    /// Known tools; measured length offsets are recorded here.
+    tools: Mutex<ToolTable>,
+    /// Profile whose library measured offsets are saved to.
+    library: Option<String>,
+}
+
+impl ToolChanger {
+    pub fn new(session: Arc<Session>, config: ToolChangeConfig) -> Self {
+        ToolChanger {
+            session,
+            config,
+            reference: Mutex::new(None),
+            offset: Mutex::new(0.0),
+            resume_at: Mutex::new(None),
+            modes: Mutex::new(Vec::new()),
+            tools: Mutex::new(ToolTable::default()),
+            library: None,
+        }
+    }
+
+    /// Use `tools` for stored length offsets and to record measured ones.
+    pub fn with_tools(self, tools: ToolTable) -> Self {
+        *self.tools.lock().unwrap() = tools;
+        self
+    }
+
+    /// Use the tool library of `profile_id` and save measured offsets to it.
+    /// Lengths belong to the machine, so a shared tool gets its own entry
+    /// for the profile.
+    pub fn with_library(self, profile_id: &str) -> io::Result<Self> {
+        let tools = ToolTable::for_profile(profile_id)?;
+        Ok(ToolChanger {
+            library: Some(profile_id.to_string()),
+            ..self.with_tools(tools)
+        })
+    }
+
+    /// The tool table, with the offsets measured so far.
+    pub fn tools(&self) -> ToolTable {
+        self.tools.lock().unwrap().clone()
+    }
+
+    pub fn config(&self) -> &ToolChangeConfig {
+        &self.config
+    }
+
+    /// The tool change the session's job is held at.
+    pub fn pending(&self) -> Option<ToolChange> {
+        self.session.progress().tool_change
+    }
+
+    /// Setter reading new tools are compared with.
+    pub fn reference(&self) -> Option<f64> {
+        *self.reference.lock().unwrap()
+    }
+
+    /// Set the reference reading, e.g. from a tool measured before the job;
+    /// `None` makes `begin` measure the outgoing tool.
+    pub fn set_reference(&self, z: Option<f64>) {
+        *self.reference.lock().unwrap() = z;
+    }
+
+    fn send(&self, line: &str) -> Result<()> {
+        self.session.send_command(line, self.session.options().command_timeout)
+    }
+
+    /// Raise Z to the change height, rapid over `target`, then down to it.
+    fn travel(&self, target: [f64; 3]) -> Result<()> {
+        self.over(target)?;
+        self.send(&format!("G53 G0 Z{:.3}", target[2]))
+    }
+
+    /// Raise Z to the change height and rapid over `target`, in mm.
+    fn over(&self, target: [f64; 3]) -> Result<()> {
+        self.send("G21")?;
+        self.send(&format!("G53 G0 Z{:.3}", self.config.position[2]))?;
+        self.send(&format!("G53 G0 X{:.3} Y{:.3}", target[0], target[1]))
+    }
+
+    /// Restart the spindle and coolant the job stopped with, and wait for
+    /// the spindle to reach speed.
+    fn restore_spindle(&self, modes: &[String]) -> Result<()> {
+        let find = |codes: &[&str]| modes.iter().filter(|m| codes.contains(&m.as_str())).cloned().collect::<Vec<_>>();
+        let spindle = find(&["M3", "M4"]);
+        let coolant = find(&["M7", "M8"]);
+        if let Some(m) = spindle.first() {
+            match modes.iter().find(|w| w.starts_with('S')) {
+                Some(speed) => self.send(&format!("{} {}", m, speed))?,
+                None => self.send(m)?,
+            }
+        }
+        if !coolant.is_empty() {
+            self.send(&coolant.join(" "))?;
+        }
+        if !spindle.is_empty() && self.config.spindle_dwell > 0.0 {
+            self.send(&format!("G4 P{:.1}", self.config.spindle_dwell))?;
+        }
+        Ok(())
+    }
+
+    /// Touch the tool in the spindle off on the setter and return the
+    /// machine Z of contact.
+    pub fn measure(&self) -> Result<f64> {
+        let Some(setter) = self.config.setter else {
+            bail!("session {}: no tool setter configured", self.session.id());
+        };
+        self.travel(setter.position)?;
+        let options = ProbeOptions {
+            fast_feed: setter.feed,
+            max_travel: setter.max_travel,
+            ..Default::default()
+        };
+        let z = Prober::new(self.session.clone(), options).z_surface()?;
+        self.send(&format!("G53 G0 Z{:.3}", self.config.position[2]))?;
+        Ok(z)
+    }
+
+    /// Start the change the job is held at: wait for the machine to stop,
+    /// note where and with which spindle and coolant, stop both, measure the
+    /// outgoing tool if there is no reference yet, and park for the operator. With an ATC macro the macro runs and the
+    /// change is finished at once.
+    pub fn begin(&self) -> Result<ToolChange> {
+        let id = self.session.id();
+        if self.session.protocol() != Protocol::Grbl {
+            bail!("session {}: tool changes need a GRBL-family controller", id);
+        }
+        let Some(request) = self.pending() else {
+            bail!("session {}: no job is waiting for a tool change", id);
+        };
+        if self.config.atc_macro.is_some() && request.tool.is_none() {
+            bail!("session {}: M6 on line {} names no tool", id, request.line + 1);
+        }
+        let timeout = self.session.options().command_timeout;
+        let stopped = self.session.wait_for_report(timeout, |s| s.status == MachineStatus::Idle)?;
+        *self.resume_at.lock().unwrap() = Some(stopped.mpos);
+        // Replies are applied to the state before the `ok` is delivered
+        self.send("$G")?;
+        *self.modes.lock().unwrap() = self.session.state().modes;
+        self.send("M5 M9")?;
+        if self.config.setter.is_some() && self.reference().is_none() {
+            let z = self.measure()?;
+            self.set_reference(Some(z));
+        }
+        let description = request
+            .tool
+            .and_then(|t| self.tools.lock().unwrap().get(t).map(|t| t.description.clone()));
+        info!(id = %id, tool = ?request.tool, description = ?description, line = request.line, "toolchange::begin: changing tool");
+        match &self.config.atc_macro {
+            Some(gcode) => {
+                let tool = request.tool.unwrap_or_default().to_string();
+                for line in gcode.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                    self.send(&line.replace("{{tool}}", &tool))?;
+                }
+                self.finish()?;
+            }
+            None => self.travel(self.config.position)?,
+        }
+        Ok(request)
+    }
+
+    /// Finish once the new tool is in: measure it (or look up its stored
+    /// offset) and apply the difference from the reference with `G43.1`,
+    /// go back over where the job stopped, restart the spindle and coolant,
+    /// lower the tool and resume the job in its own units. Returns the length
+    /// offset now applied, if one is known.
+    pub fn finish(&self) -> Result<Option<f64>> {
+        let id = self.session.id();
+        let Some(request) = self.pending() else {
+            bail!("session {}: no job is waiting for a tool change", id);
+        };
+        let Some(mut back) = *self.resume_at.lock().unwrap() else {
+            bail!("session {}: tool change was not started", id);
+        };
+        let stored = request.tool.and_then(|t| self.tools.lock().unwrap().get(t)?.length_offset);
+        let measured = match self.config.setter {
+            Some(_) => {
+                let z = self.measure()?;
+                let offset = z - self.reference().unwrap_or(z);
+                let mut tools = self.tools.lock().unwrap();
+                if let Some(mut tool) = request.tool.and_then(|t| tools.get(t).cloned()) {
+                    tool.length_offset = Some(offset);
+                    tools.insert(tool.clone());
+                    if let Some(profile_id) = &self.library {
+                        tool.profile_id = Some(profile_id.clone());
+                        // The change itself succeeded; the job goes on
+                        if let Err(e) = save_tool(tool) {
+                            warn!(id = %id, err = %e, "toolchange::finish: could not save the measured offset");
+                        }
+                    }
+                }
+                Some(offset)
+            }
+            None => None,
+        };
+        let mut applied = None;
+        if let Some(offset) = measured.or(stored) {
+            self.send(&format!("G43.1 Z{:.3}", offset))?;
+            // Keep the tip where the old tool's was
+            let mut current = self.offset.lock().unwrap();
+            back[2] += offset - *current;
+            *current = offset;
+            applied = Some(offset);
+        }
+        let modes = self.modes.lock().unwrap().clone();
+        self.over(back)?;
+        self.restore_spindle(&modes)?;
+        self.send(&format!("G53 G0 Z{:.3}", back[2]))?;
+        if modes.iter().any(|m| m == "G20") {
+            self.send("G20")?;
+        }
+        *self.resume_at.lock().unwrap() = None;
+        info!(id = %id, offset = ?applied, "toolchange::finish: resuming job");
+        self.session.resume()?;
+        Ok(applied)
+    }
+}
@@ -0,0 +1,204 @@
+//! Tool library: cutters with their sizes, length offsets and cutting data.
+//!
+//! Tools are stored as a list in `tools.json` in the platform data
+//! directory, saved through `storage::update_json` like profiles. A tool
+//! either belongs to one profile (`profile_id`) or is shared by every
+//! machine; `ToolTable::for_profile` merges the two, the profile's own
+//! entries winning for a tool number. The table gives `ToolChanger` stored
+//! length offsets, checks a job's tools against the library and gives the
+//! cut width to draw toolpaths with.
+
+use crate::error::CoreError;
+use crate::gcode::JobAnalysis;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use tracing::{debug, info};
+
+const TOOLS_FILE: &str = "tools.json";
+
+/// Cutter shape.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ToolKind {
+    #[default]
+    FlatEndMill,
+    BallEndMill,
+    /// Included angle in degrees.
+    VBit { angle: f64 },
+    Drill,
+    Engraver,
+    Other,
+}
+
+/// One tool in the library.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tool {
+    /// `T` number used in programs.
+    pub number: u32,
+    /// Profile whose machine holds this tool; `None` for every machine.
+    #[serde(default)]
+    pub profile_id: Option<String>,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub kind: ToolKind,
+    /// Cutting diameter in mm.
+    pub diameter: f64,
+    #[serde(default)]
+    pub flutes: u32,
+    /// Length difference from the reference tool in mm, as given to
+    /// `G43.1`; `None` until measured.
+    #[serde(default)]
+    pub length_offset: Option<f64>,
+    /// Default cutting feed in mm/min.
+    #[serde(default)]
+    pub feed: Option<f64>,
+    /// Default plunge feed in mm/min.
+    #[serde(default)]
+    pub plunge_feed: Option<f64>,
+    /// Default spindle speed in RPM.
+    #[serde(default)]
+    pub spindle_speed: Option<u32>,
+}
+
+impl Tool {
+    pub fn new(number: u32, kind: ToolKind, diameter: f64) -> Self {
+        Tool {
+            number,
+            profile_id: None,
+            description: String::new(),
+            kind,
+            diameter,
+            flutes: 0,
+            length_offset: None,
+            feed: None,
+            plunge_feed: None,
+            spindle_speed: None,
+        }
+    }
+
+    /// Check the tool is usable before it is saved.
+    pub fn validate(&self) -> Result<(), CoreError> {
+        if self.diameter <= 0.0 {
+            return Err(CoreError::Config(format!("tool {}: diameter must be > 0", self.number)));
+        }
+        if let ToolKind::VBit { angle } = self.kind {
+            if angle <= 0.0 || angle >= 180.0 {
+                return Err(CoreError::Config(format!("tool {}: V-bit angle must be between 0 and 180", self.number)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Width of the cut at `depth` below the surface: narrower than the
+    /// diameter near the tip of V-bits and ball end mills.
+    pub fn kerf(&self, depth: f64) -> f64 {
+        let depth = depth.max(0.0);
+        let r = self.diameter / 2.0;
+        match self.kind {
+            ToolKind::VBit { angle } => (2.0 * depth * (angle.to_radians() / 2.0).tan()).min(self.diameter),
+            ToolKind::BallEndMill if depth < r => 2.0 * (r * r - (r - depth).powi(2)).sqrt(),
+            _ => self.diameter,
+        }
+    }
+}
+
+/// Tools a job uses, split into those in the table and those missing.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ToolUsage {
+    pub found: Vec<Tool>,
+    pub missing: Vec<u32>,
+}
+
+/// Tools available on one machine, by number.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ToolTable {
+    tools: BTreeMap<u32, Tool>,
+}
+
+impl ToolTable {
+    /// A table of `tools`; later entries replace earlier ones with the
+    /// same number.
+    pub fn new(tools: impl IntoIterator<Item = Tool>) -> Self {
+        ToolTable {
+            tools: tools.into_iter().map(|t| (t.number, t)).collect(),
+        }
+    }
+
+    /// Shared tools and those of `profile_id`, from `tools.json`.
+    pub fn for_profile(profile_id: &str) -> io::Result<Self> {
+        let (own, shared): (Vec<Tool>, Vec<Tool>) = load_tools()?
+            .into_iter()
+            .filter(|t| t.profile_id.as_deref().is_none_or(|p| p == profile_id))
+            .partition(|t| t.profile_id.is_some());
+        Ok(ToolTable::new(shared.into_iter().chain(own)))
+    }
+
+    pub fn get(&self, number: u32) -> Option<&Tool> {
+        self.tools.get(&number)
+    }
+
+    /// Add or replace a tool; returns the one it replaced.
+    pub fn insert(&mut self, tool: Tool) -> Option<Tool> {
+        self.tools.insert(tool.number, tool)
+    }
+
+    pub fn tools(&self) -> impl Iterator<Item = &Tool> {
+        self.tools.values()
+    }
+
+    /// Look up the tools `analysis` found in a job. `T0` (no tool) is
+    /// never missing.
+    pub fn usage(&self, analysis: &JobAnalysis) -> ToolUsage {
+        let mut usage = ToolUsage::default();
+        for &number in &analysis.tools {
+            match self.get(number) {
+                Some(tool) => usage.found.push(tool.clone()),
+                None if number != 0 => usage.missing.push(number),
+                None => {}
+            }
+        }
+        usage
+    }
+}
+
+/// Load the library. Returns an empty Vec when `tools.json` does not exist.
+pub fn load_tools() -> io::Result<Vec<Tool>> {
+    match gcodekit_utils::storage::read_json::<Vec<Tool>>(TOOLS_FILE) {
+        Ok(v) => {
+            debug!(count = v.len(), "tools::load_tools: loaded tools");
+            Ok(v)
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => {
+            debug!(err = ?e, "tools::load_tools: error reading tools");
+            Err(e)
+        }
+    }
+}
+
+/// Insert or replace a tool in `tools.json`, keyed by profile and number.
+pub fn save_tool(tool: Tool) -> Result<(), CoreError> {
+    tool.validate()?;
+    let (number, profile_id) = (tool.number, tool.profile_id.clone());
+    gcodekit_utils::storage::update_json(TOOLS_FILE, |tools: &mut Vec<Tool>| {
+        match tools.iter_mut().find(|t| t.number == tool.number && t.profile_id == tool.profile_id) {
+            Some(existing) => *existing = tool,
+            None => tools.push(tool),
+        }
+    })?;
+    info!(number, profile = ?profile_id, "tools::save_tool: tool saved");
+    Ok(())
+}
+
+/// Remove a tool from `tools.json`. Returns whether it existed.
+pub fn delete_tool(profile_id: Option<&str>, number: u32) -> io::Result<bool> {
+    let removed = gcodekit_utils::storage::update_json(TOOLS_FILE, |tools: &mut Vec<Tool>| {
+        let before = tools.len();
+        tools.retain(|t| !(t.number == number && t.profile_id.as_deref() == profile_id));
+        tools.len() != before
+    })?;
+    debug!(number, profile = ?profile_id, removed, "tools::delete_tool: done");
+    Ok(removed)
+}
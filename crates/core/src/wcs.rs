@@ -0,0 +1,217 @@
+//! Work coordinate systems: reading, zeroing and editing offsets.
+//!
+//! Offsets are read back with `$#` around every change, so `MachineState`
+//! holds what the controller actually stored. Each change is recorded with
+//! the job it was made for; `restore` puts back what a change replaced, for
+//! the axis someone zeroed by mistake. GRBL-family controllers only.
+
+use crate::machine_state::Axis;
+use crate::protocol::Protocol;
+use crate::session::Session;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tracing::info;
+
+/// Work coordinate systems in `G10 P` order (`P1` is `G54`).
+pub const WORK_SYSTEMS: [&str; 9] = ["G54", "G55", "G56", "G57", "G58", "G59", "G59.1", "G59.2", "G59.3"];
+
+/// A stored position that `G28`/`G30` return to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReturnPosition {
+    G28,
+    G30,
+}
+
+impl ReturnPosition {
+    fn name(self) -> &'static str {
+        match self {
+            ReturnPosition::G28 => "G28",
+            ReturnPosition::G30 => "G30",
+        }
+    }
+}
+
+/// One offset change, in machine coordinates.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OffsetChange {
+    pub at: DateTime<Utc>,
+    /// Job being set up when the change was made.
+    pub job_id: Option<String>,
+    /// Offset that changed: a work system, `G92`, `G28` or `G30`.
+    pub offset: String,
+    pub before: [f64; 3],
+    pub after: [f64; 3],
+}
+
+fn axis_words(values: &[(Axis, f64)]) -> String {
+    values
+        .iter()
+        .map(|(a, v)| format!(" {}{:.3}", a.letter(), v))
+        .collect()
+}
+
+/// Manages one session's coordinate offsets.
+pub struct WcsManager {
+    session: Arc<Session>,
+    job: Mutex<Option<String>>,
+    history: Mutex<Vec<OffsetChange>>,
+}
+
+impl WcsManager {
+    pub fn new(session: Arc<Session>) -> Self {
+        WcsManager {
+            session,
+            job: Mutex::new(None),
+            history: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Record following changes against `job_id`.
+    pub fn set_job(&self, job_id: Option<&str>) {
+        *self.job.lock().unwrap() = job_id.map(str::to_string);
+    }
+
+    fn check_supported(&self) -> Result<()> {
+        if self.session.protocol() != Protocol::Grbl {
+            bail!("session {}: coordinate systems need a GRBL-family controller", self.session.id());
+        }
+        Ok(())
+    }
+
+    /// Read every offset (`$#`) and the parser state (`$G`).
+    pub fn refresh(&self) -> Result<BTreeMap<String, [f64; 3]>> {
+        self.check_supported()?;
+        let timeout = self.session.options().command_timeout;
+        // Replies are applied to the state before the `ok` is delivered
+        self.session.send_command("$#", timeout)?;
+        self.session.send_command("$G", timeout)?;
+        Ok(self.session.state().offsets)
+    }
+
+    /// Active work coordinate system as last reported; `G54` until then.
+    pub fn active(&self) -> String {
+        self.session.state().wcs().unwrap_or("G54").to_string()
+    }
+
+    /// Make `wcs` the active work coordinate system.
+    pub fn select(&self, wcs: &str) -> Result<()> {
+        self.check_supported()?;
+        let Some(wcs) = WORK_SYSTEMS.iter().find(|w| w.eq_ignore_ascii_case(wcs)) else {
+            bail!("unknown work coordinate system {}", wcs);
+        };
+        self.session.send_command(wcs, self.session.options().command_timeout)?;
+        self.refresh()?;
+        Ok(())
+    }
+
+    /// `wcs` (the active one when `None`) with its `G10 P` number.
+    fn target(&self, wcs: Option<&str>) -> Result<(String, usize)> {
+        let wcs = wcs.map_or_else(|| self.active(), str::to_string);
+        match WORK_SYSTEMS.iter().position(|w| w.eq_ignore_ascii_case(&wcs)) {
+            Some(i) => Ok((WORK_SYSTEMS[i].to_string(), i + 1)),
+            None => bail!("unknown work coordinate system {}", wcs),
+        }
+    }
+
+    /// Send `line`, which changes `offset`, and record the change.
+    fn change(&self, offset: &str, line: &str) -> Result<OffsetChange> {
+        self.check_supported()?;
+        if self.session.progress().status.is_active() {
+            bail!("session {}: cannot change offsets while a job is running", self.session.id());
+        }
+        let before = self.refresh()?.get(offset).copied().unwrap_or_default();
+        self.session.send_command(line, self.session.options().command_timeout)?;
+        let after = self.refresh()?.get(offset).copied().unwrap_or_default();
+        let change = OffsetChange {
+            at: Utc::now(),
+            job_id: self.job.lock().unwrap().clone(),
+            offset: offset.to_string(),
+            before,
+            after,
+        };
+        info!(id = %self.session.id(), offset = %offset, before = ?before, after = ?after, "wcs::change: offset changed");
+        self.history.lock().unwrap().push(change.clone());
+        Ok(change)
+    }
+
+    /// Make the current position zero on `axes` of `wcs` (the active one
+    /// when `None`).
+    pub fn zero(&self, wcs: Option<&str>, axes: &[Axis]) -> Result<OffsetChange> {
+        let values: Vec<(Axis, f64)> = axes.iter().map(|&a| (a, 0.0)).collect();
+        self.set_position(wcs, &values)
+    }
+
+    /// Make the current position read `values` in `wcs` (`G10 L20`).
+    pub fn set_position(&self, wcs: Option<&str>, values: &[(Axis, f64)]) -> Result<OffsetChange> {
+        if values.is_empty() {
+            bail!("no axes given");
+        }
+        let (wcs, p) = self.target(wcs)?;
+        self.change(&wcs, &format!("G10 L20 P{}{}", p, axis_words(values)))
+    }
+
+    /// Set the offset of `wcs` numerically, in machine coordinates (`G10 L2`).
+    pub fn set_offset(&self, wcs: Option<&str>, values: &[(Axis, f64)]) -> Result<OffsetChange> {
+        if values.is_empty() {
+            bail!("no axes given");
+        }
+        let (wcs, p) = self.target(wcs)?;
+        self.change(&wcs, &format!("G10 L2 P{}{}", p, axis_words(values)))
+    }
+
+    /// Remove the temporary `G92` offset (`G92.1`).
+    pub fn clear_g92(&self) -> Result<OffsetChange> {
+        self.change("G92", "G92.1")
+    }
+
+    /// Store the current machine position as the `G28`/`G30` position.
+    pub fn store_position(&self, slot: ReturnPosition) -> Result<OffsetChange> {
+        self.change(slot.name(), &format!("{}.1", slot.name()))
+    }
+
+    /// Move to the stored `G28`/`G30` position at the rapid rate.
+    pub fn go_to_position(&self, slot: ReturnPosition) -> Result<()> {
+        self.check_supported()?;
+        if self.session.progress().status.is_active() {
+            bail!("session {}: cannot move while a job is running", self.session.id());
+        }
+        self.session.send_command(slot.name(), self.session.options().command_timeout)
+    }
+
+    /// Put back the offset `change` replaced. Stored `G28`/`G30` positions
+    /// can only be set by moving there, so they are not restored.
+    pub fn restore(&self, change: &OffsetChange) -> Result<OffsetChange> {
+        let axes = [Axis::X, Axis::Y, Axis::Z];
+        let values: Vec<(Axis, f64)> = axes.iter().map(|&a| (a, change.before[a.index()])).collect();
+        if change.offset == "G92" {
+            // `G92` takes the position to report, not the offset, so it
+            // needs the machine position as of now
+            let wcs = self.target(None)?.0;
+            let state = self.session.wait_for_report(self.session.options().command_timeout, |_| true)?;
+            let origin = state.offsets.get(&wcs).copied().unwrap_or_default();
+            let values: Vec<(Axis, f64)> = axes
+                .iter()
+                .map(|&a| (a, state.mpos[a.index()] - origin[a.index()] - change.before[a.index()]))
+                .collect();
+            return self.change("G92", &format!("G92{}", axis_words(&values)));
+        }
+        if change.offset == "G28" || change.offset == "G30" {
+            bail!("{} positions can only be stored by moving to them", change.offset);
+        }
+        self.set_offset(Some(&change.offset), &values)
+    }
+
+    /// Every offset change made through this manager, oldest first.
+    pub fn history(&self) -> Vec<OffsetChange> {
+        self.history.lock().unwrap().clone()
+    }
+
+    /// Offset changes made while setting up `job_id`.
+    pub fn history_for(&self, job_id: &str) -> Vec<OffsetChange> {
+        let history = self.history.lock().unwrap();
+        history.iter().filter(|c| c.job_id.as_deref() == Some(job_id)).cloned().collect()
+    }
+}
@@ -0,0 +1,124 @@
+use gcodekit_core::device_manager::DeviceManager;
+use gcodekit_core::gcode::analysis::{analyze, WarningKind};
+use gcodekit_core::gcode::parse_lines;
+use gcodekit_core::profile::FirmwareKind;
+use gcodekit_core::protocol::Protocol;
+use gcodekit_core::scheduler::{JobScheduler, SchedulerOptions};
+use gcodekit_core::session::SessionOptions;
+use std::f64::consts::PI;
+use std::time::{Duration, Instant};
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-6
+}
+
+const PROGRAM: &str = "\
+G21 G90 G54
+T1
+M3 S10000
+G0 X0 Y0 Z5
+G1 Z-1 F100
+G1 X10 F500
+G2 X20 Y0 I5 J0
+G0 Z5
+G55
+G0 X-5 Y-5
+";
+
+#[test]
+fn test_analysis_measures_program() {
+    let a = analyze(&parse_lines(PROGRAM), Some(FirmwareKind::Grbl));
+    assert_eq!(a.line_count, 10);
+    assert!(a.is_clean(), "unexpected findings: {:?} {:?}", a.unsupported, a.warnings);
+    assert_eq!(a.tools, vec![1]);
+    let feed = a.feed_range.unwrap();
+    assert_eq!((feed.min, feed.max), (100.0, 500.0));
+    assert_eq!(a.spindle_range.map(|r| r.max), Some(10000.0));
+
+    assert!(close(a.cut_distance, 6.0 + 10.0 + 5.0 * PI), "cut {}", a.cut_distance);
+    assert!(close(a.rapid_distance, 5.0 + 6.0 + 650f64.sqrt()), "rapid {}", a.rapid_distance);
+
+    // The clockwise arc bulges to Y+5
+    let g54 = a.bounds["G54"];
+    assert_eq!(g54.min, [0.0, 0.0, -1.0]);
+    assert!(close(g54.max[0], 20.0) && close(g54.max[1], 5.0) && close(g54.max[2], 5.0), "{:?}", g54);
+    assert_eq!(a.bounds["G55"].min, [-5.0, -5.0, 5.0]);
+}
+
+#[test]
+fn test_analysis_warnings_and_inch_units() {
+    let a = analyze(&parse_lines("G0 X1\nG1 X2\nG2 X3 Y0 I1 J1\nG3 X10 Y0 R2"), None);
+    let kinds: Vec<(usize, WarningKind)> = a.warnings.iter().map(|w| (w.line, w.kind.clone())).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (0, WarningKind::MissingUnits),
+            (1, WarningKind::NoFeedBeforeCut),
+            (2, WarningKind::ArcRadiusMismatch),
+            (3, WarningKind::ArcRadiusMismatch),
+        ]
+    );
+    assert_eq!(a.warnings[0].to_string(), "line 1: motion before G20/G21; the controller's current units apply");
+
+    let inch = analyze(&parse_lines("G20 G90\nG1 X1 F10"), None);
+    assert!(close(inch.cut_distance, 25.4));
+    assert_eq!(inch.feed_range.map(|r| r.max), Some(254.0));
+}
+
+#[test]
+fn test_unsupported_commands_depend_on_firmware() {
+    let program = parse_lines("G21\nG5 X1 Y1 I1 J1 P1 Q1\nM6 T2\nM62 P1\nG38.2 Z-5 F50");
+    let grbl = analyze(&program, Some(FirmwareKind::Grbl));
+    let codes: Vec<(usize, &str)> = grbl.unsupported.iter().map(|u| (u.line, u.code.as_str())).collect();
+    assert_eq!(codes, vec![(1, "G5"), (2, "M6"), (3, "M62")]);
+    assert!(analyze(&program, Some(FirmwareKind::FluidNc)).unsupported.is_empty());
+    assert!(analyze(&program, None).unsupported.is_empty());
+}
+
+#[test]
+fn test_scheduler_attaches_analysis_when_queued() {
+    let td = tempfile::tempdir().unwrap();
+    let path = td.path().join("job.nc");
+    std::fs::write(&path, PROGRAM).unwrap();
+    let dm = DeviceManager::new();
+    let session = dm.open_session(
+        "router",
+        "sim:grbl".parse().unwrap(),
+        gcodekit_device_adapters::create_sim_transport(Default::default()).unwrap(),
+        Protocol::Grbl,
+        SessionOptions::default(),
+    );
+    session.pause().unwrap();
+    let sched = JobScheduler::start(
+        session.clone(),
+        SchedulerOptions {
+            record_history: false,
+            journal: None,
+            firmware: Some(FirmwareKind::Grbl),
+            ..Default::default()
+        },
+    );
+    sched.submit(&path);
+    // Analysis runs in the background; the report reaches the job whether
+    // it is still queued or already running
+    let analyzed = || sched.queued().into_iter().chain(sched.current()).find(|j| j.analysis.is_some());
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while analyzed().is_none() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    let job = analyzed().expect("job analyzed");
+    assert_eq!(job.lines_total, 10);
+    let analysis = job.analysis.expect("analysis attached");
+    assert_eq!(analysis.tools, vec![1]);
+
+    // The report survives a round trip through jobs.json-style serialization
+    let json = serde_json::to_string(&gcodekit_core::models::Job {
+        analysis: Some(analysis.clone()),
+        ..gcodekit_core::models::Job::new("x.nc")
+    })
+    .unwrap();
+    let back: gcodekit_core::models::Job = serde_json::from_str(&json).unwrap();
+    let back = back.analysis.expect("analysis persisted");
+    assert_eq!((back.line_count, back.bounds, back.tools), (analysis.line_count, analysis.bounds, analysis.tools));
+    session.resume().unwrap();
+}
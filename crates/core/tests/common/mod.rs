@@ -0,0 +1,53 @@
+//! Helpers shared by the integration tests. Each test binary uses some of
+//! them.
+#![allow(dead_code)]
+
+use gcodekit_core::device_manager::DeviceManager;
+use gcodekit_core::endpoint::Endpoint;
+use gcodekit_core::protocol::Protocol;
+use gcodekit_core::session::{Session, SessionOptions};
+use gcodekit_device_adapters::sim::{SimFirmware, SimOptions};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub const WAIT: Duration = Duration::from_secs(5);
+
+/// Poll `f` until it holds or `WAIT` passes.
+pub fn wait_until(mut f: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + WAIT;
+    while Instant::now() < deadline {
+        if f() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    false
+}
+
+/// Session options with a status report every 10 ms.
+pub fn options() -> SessionOptions {
+    SessionOptions {
+        status_interval: Some(Duration::from_millis(10)),
+        ..Default::default()
+    }
+}
+
+/// Open session `mill` on a simulator with `options()`.
+pub fn open(dm: &DeviceManager, sim: SimOptions) -> Arc<Session> {
+    open_with(dm, sim, options())
+}
+
+/// Open session `mill` on a simulator; the endpoint and protocol follow
+/// its firmware.
+pub fn open_with(dm: &DeviceManager, sim: SimOptions, options: SessionOptions) -> Arc<Session> {
+    let protocol = match sim.firmware {
+        SimFirmware::Grbl => Protocol::Grbl,
+        SimFirmware::Marlin => Protocol::Marlin,
+    };
+    let endpoint = Endpoint::Sim {
+        firmware: sim.firmware,
+        latency_ms: (!sim.latency.is_zero()).then_some(sim.latency.as_millis() as u64),
+    };
+    let transport = gcodekit_device_adapters::create_sim_transport(sim).unwrap();
+    dm.open_session("mill", endpoint, transport, protocol, options)
+}
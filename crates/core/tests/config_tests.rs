@@ -15,7 +15,8 @@ fn test_config_file_overrides_default() {
 
     // Write config with network_timeout_secs = 7
     let mut f = fs::File::create(&cfg_path).expect("create cfg");
-    f.write_all(b"{ \"network_timeout_secs\": 7 }")
+    f.write_all(br#"{ "network_timeout_secs": 7,
+        "tls": { "ca_bundle": "/etc/shop-ca.pem", "client_cert": "client.pem", "client_key": "keys/client.key" } }"#)
         .expect("write");
     drop(f);
 
@@ -24,6 +25,24 @@ fn test_config_file_overrides_default() {
 
     let d = gcodekit_core::config::network_timeout();
     assert_eq!(d, Duration::from_secs(7));
+
+    // Relative TLS paths are taken from the config directory
+    let tls = gcodekit_core::config::tls_options();
+    assert_eq!(tls.ca_bundle.as_deref(), Some(std::path::Path::new("/etc/shop-ca.pem")));
+    assert_eq!(tls.client_cert, Some(cfg_dir.join("client.pem")));
+    assert_eq!(tls.client_key, Some(cfg_dir.join("keys/client.key")));
+
+    // mDNS discovery is on without a discovery section and can be turned off
+    let opts = gcodekit_core::config::discovery_options();
+    assert_eq!(
+        opts.mdns.map(|q| q.timeout),
+        Some(gcodekit_device_adapters::discovery::DEFAULT_MDNS_TIMEOUT)
+    );
+    fs::write(&cfg_path, r#"{ "discovery": { "mdns": false } }"#).expect("write");
+    assert!(gcodekit_core::config::discovery_options().mdns.is_none());
+    fs::write(&cfg_path, r#"{ "discovery": { "mdns_timeout_ms": 150 } }"#).expect("write");
+    let opts = gcodekit_core::config::discovery_options();
+    assert_eq!(opts.mdns.map(|q| q.timeout), Some(Duration::from_millis(150)));
 }
 
 #[test]
@@ -32,3 +32,43 @@ fn test_discover_devices_and_connect_network() {
     let raw = fs::read_to_string(&devices_path).expect("read devices");
     assert!(raw.contains("tcp"), "persisted devices should reference tcp");
 }
+
+#[test]
+fn test_discover_network_returns_connectable_endpoints() {
+    use gcodekit_device_adapters::discovery::DiscoveryOptions;
+    use gcodekit_device_adapters::mdns::{self, MdnsQuery, MdnsService};
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
+    // Stand-in mDNS responder advertising a telnet board on loopback
+    let responder = UdpSocket::bind("127.0.0.1:0").expect("bind");
+    let target = responder.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 1500];
+        if let Ok((_, from)) = responder.recv_from(&mut buf) {
+            let svc = MdnsService {
+                instance: "fluidnc".into(),
+                service: "_telnet._tcp.local".into(),
+                host: "fluidnc.local".into(),
+                port: 2323,
+                addrs: vec!["127.0.0.1".parse().unwrap()],
+                txt: vec![],
+            };
+            let _ = responder.send_to(&mdns::build_response(&[svc]), from);
+        }
+    });
+
+    let opts = DiscoveryOptions {
+        mdns: Some(MdnsQuery {
+            target,
+            timeout: Duration::from_millis(300),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let found = DeviceManager::discover_network(&opts).expect("discover");
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].id, "telnet://127.0.0.1:2323");
+    assert_eq!(found[0].transport, "telnet");
+    assert!(found[0].display.starts_with("fluidnc"));
+}
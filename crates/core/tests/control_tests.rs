@@ -0,0 +1,113 @@
+mod common;
+
+use gcodekit_core::control::Operation;
+use gcodekit_core::device_manager::DeviceManager;
+use gcodekit_core::machine_state::{Axis, MachineStatus};
+use gcodekit_core::session::{Session, SessionEvent, SessionOptions};
+use gcodekit_device_adapters::sim::{SimFirmware, SimOptions};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn open(dm: &DeviceManager, sim: SimOptions) -> Arc<Session> {
+    let options = SessionOptions {
+        command_timeout: Duration::from_secs(2),
+        ..common::options()
+    };
+    common::open_with(dm, sim, options)
+}
+
+#[test]
+fn test_homing_clears_alarm_and_publishes_events() {
+    let dm = DeviceManager::new();
+    let mut events = dm.subscribe();
+    let session = open(
+        &dm,
+        SimOptions {
+            homing_required: true,
+            ..Default::default()
+        },
+    );
+    let err = session.send_command("G0 X10", Duration::from_secs(1)).unwrap_err();
+    assert!(err.to_string().contains("error:9"), "{}", err);
+
+    session.home(&[]).unwrap();
+    assert_eq!(session.state().status, MachineStatus::Idle);
+    session.send_command("G0 X10", Duration::from_secs(1)).unwrap();
+
+    let mut seen = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        if let SessionEvent::Operation { operation, error, .. } = event {
+            seen.push((operation, error));
+        }
+    }
+    assert_eq!(seen, [(Operation::Home, None)]);
+}
+
+#[test]
+fn test_unlock_reset_and_sleep() {
+    let dm = DeviceManager::new();
+    let session = open(
+        &dm,
+        SimOptions {
+            homing_required: true,
+            ..Default::default()
+        },
+    );
+    session.unlock().unwrap();
+    assert_eq!(session.state().status, MachineStatus::Idle);
+
+    // A reset brings the alarm lock back
+    session.soft_reset().unwrap();
+    session.wait_for_report(Duration::from_secs(1), |s| s.status == MachineStatus::Alarm).unwrap();
+    session.home(&[Axis::X, Axis::Y]).unwrap();
+
+    session.sleep().unwrap();
+    assert_eq!(session.state().status, MachineStatus::Sleep);
+}
+
+#[test]
+fn test_check_mode_toggles_and_resets_on_exit() {
+    let dm = DeviceManager::new();
+    let session = open(&dm, SimOptions::default());
+    session.check_mode(true).unwrap();
+    assert_eq!(session.state().status, MachineStatus::Check);
+    // Already on: nothing is sent, so `$C` does not toggle it back off
+    session.check_mode(true).unwrap();
+    session.send_command("G0 X10", Duration::from_secs(1)).unwrap();
+    session.check_mode(false).unwrap();
+    let state = session.wait_for_report(Duration::from_secs(1), |_| true).unwrap();
+    assert_eq!((state.status, state.mpos), (MachineStatus::Idle, [0.0; 3]));
+}
+
+#[test]
+fn test_operations_refuse_jobs_and_unsupported_firmware() {
+    let dm = DeviceManager::new();
+    let session = open(&dm, SimOptions::default());
+    session.pause().unwrap();
+    session.stream(["G0 X10"]).unwrap();
+    let err = session.home(&[]).unwrap_err();
+    assert!(err.to_string().contains("while a job is running"), "{}", err);
+    session.cancel().unwrap();
+
+    let marlin = open(
+        &dm,
+        SimOptions {
+            firmware: SimFirmware::Marlin,
+            ..Default::default()
+        },
+    );
+    marlin.home(&[Axis::Z]).unwrap();
+    marlin.unlock().unwrap();
+    let err = marlin.check_mode(true).unwrap_err();
+    assert!(err.to_string().contains("not supported by Marlin"), "{}", err);
+}
+
+#[test]
+fn test_wait_times_out_with_clear_error() {
+    let dm = DeviceManager::new();
+    let session = open(&dm, SimOptions::default());
+    let err = session
+        .wait_for_report(Duration::from_millis(100), |s| s.status == MachineStatus::Door)
+        .unwrap_err();
+    assert!(err.to_string().contains("timed out after 100ms"), "{}", err);
+}
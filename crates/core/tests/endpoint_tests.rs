@@ -0,0 +1,129 @@
+use gcodekit_core::device_manager::DeviceManager;
+use gcodekit_core::endpoint::Endpoint;
+use gcodekit_core::models::{Device, Transport};
+use gcodekit_device_adapters::sim::SimFirmware;
+
+#[test]
+fn test_endpoint_urls_round_trip() {
+    let urls = [
+        "serial:///dev/ttyUSB0?baud=250000&timeout_ms=500",
+        "serial://COM3",
+        "tcp://192.168.1.20:8888",
+        "udp://10.0.0.7:5000?bind=0.0.0.0%3A5001",
+        "ws://fluidnc.local:81",
+        "wss://cnc.example.com/ws?token=abc",
+        "telnet://[fe80::1]:23?binary=false&keepalive_ms=0",
+        "sim:marlin?latency_ms=5",
+        "replay:///tmp/session.log?delay_ms=10",
+    ];
+    for url in urls {
+        let ep: Endpoint = url.parse().unwrap_or_else(|e| panic!("{}: {}", url, e));
+        assert_eq!(ep.to_string(), url);
+    }
+}
+
+#[test]
+fn test_endpoint_paths_are_percent_encoded() {
+    let serial = Endpoint::serial("/dev/serial/by-id/usb-Arduino#1?x");
+    assert_eq!(serial.to_string(), "serial:///dev/serial/by-id/usb-Arduino%231%3Fx");
+    assert_eq!(serial.to_string().parse::<Endpoint>().unwrap(), serial);
+
+    let replay = Endpoint::Replay {
+        path: "/tmp/run 100%?.log".into(),
+        delay_ms: Some(5),
+    };
+    assert_eq!(replay.to_string(), "replay:///tmp/run%20100%25%3F.log?delay_ms=5");
+    assert_eq!(replay.to_string().parse::<Endpoint>().unwrap(), replay);
+}
+
+#[test]
+fn test_endpoint_typed_fields_and_defaults() {
+    assert_eq!(
+        "telnet://fluidnc.local".parse::<Endpoint>().unwrap(),
+        Endpoint::Telnet {
+            host: "fluidnc.local".into(),
+            port: 23,
+            binary: None,
+            keepalive_ms: None
+        }
+    );
+    assert_eq!(
+        "sim:".parse::<Endpoint>().unwrap(),
+        Endpoint::Sim {
+            firmware: SimFirmware::Grbl,
+            latency_ms: None
+        }
+    );
+    let ws: Endpoint = "wss://cnc.example.com".parse().unwrap();
+    assert_eq!(ws.host_port(), Some(("cnc.example.com", 443)));
+    assert!(matches!(ws.transport(), Transport::WebSocket));
+}
+
+#[test]
+fn test_endpoint_accepts_legacy_forms() {
+    assert_eq!("serial:/dev/ttyACM0".parse::<Endpoint>().unwrap(), Endpoint::serial("/dev/ttyACM0"));
+    assert_eq!("/dev/ttyACM0".parse::<Endpoint>().unwrap(), Endpoint::serial("/dev/ttyACM0"));
+    assert_eq!("COM4".parse::<Endpoint>().unwrap(), Endpoint::serial("COM4"));
+    let tcp = Endpoint::Tcp {
+        host: "127.0.0.1".into(),
+        port: 9,
+    };
+    assert_eq!("tcp:127.0.0.1:9".parse::<Endpoint>().unwrap(), tcp);
+    assert_eq!("127.0.0.1:9".parse::<Endpoint>().unwrap(), tcp);
+    assert_eq!(tcp.to_string(), "tcp://127.0.0.1:9");
+}
+
+#[test]
+fn test_endpoint_rejects_invalid_input() {
+    for bad in [
+        "tcp://host-without-port",
+        "serial:///dev/ttyUSB0?baud=fast",
+        "serial:///dev/ttyUSB0?speed=9600",
+        "sim:tinyg",
+        "telnet://:23",
+        "gopher://example.com",
+    ] {
+        assert!(bad.parse::<Endpoint>().is_err(), "{} should not parse", bad);
+    }
+}
+
+#[test]
+fn test_endpoint_resolves_hostnames() {
+    let ep: Endpoint = "tcp://localhost:8888".parse().unwrap();
+    let addrs = ep.resolve().expect("resolve localhost");
+    assert!(addrs.iter().all(|a| a.port() == 8888 && a.ip().is_loopback()));
+    assert!(Endpoint::serial("/dev/null").resolve().is_err());
+}
+
+#[test]
+fn test_device_records_persist_endpoint_url() {
+    let ep: Endpoint = "telnet://10.0.0.5:2323".parse().unwrap();
+    let dev = Device::from_endpoint(&ep, "Shop router");
+    let json = serde_json::to_string(&dev).unwrap();
+    assert!(json.contains("\"endpoint\":\"telnet://10.0.0.5:2323\""));
+    let back: Device = serde_json::from_str(&json).unwrap();
+    assert_eq!(back.endpoint, Some(ep));
+
+    // Records written before endpoints were stored still load
+    let old = r#"{"id":"tcp:1.2.3.4:23","name":"x","port":"1.2.3.4:23","baud":null,"firmware":null,
+                  "capabilities":[],"status":"Connected","transport":"Tcp"}"#;
+    let dev: Device = serde_json::from_str(old).unwrap();
+    assert!(dev.endpoint.is_none());
+}
+
+#[test]
+fn test_connect_endpoint_sim_and_hostname_tcp() {
+    let mut sim = DeviceManager::connect_endpoint("sim:grbl").expect("sim connect");
+    sim.send_line("G0 X5").unwrap();
+    assert_eq!(sim.read_line().unwrap(), "ok");
+    sim.send_line("?").unwrap();
+    assert!(sim.read_line().unwrap().starts_with("<Idle|MPos:5.000,0.000,0.000"));
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("bind");
+    let port = listener.local_addr().unwrap().port();
+    std::thread::spawn(move || {
+        let _ = listener.accept();
+    });
+    let mut tcp = DeviceManager::connect_endpoint(&format!("tcp://localhost:{}", port)).expect("tcp connect");
+    let _ = tcp.send_line("M115");
+}
@@ -0,0 +1,166 @@
+use gcodekit_core::device_manager::DeviceManager;
+use gcodekit_core::envelope::{check_job, check_position, unreported_offsets, wcs_origin};
+use gcodekit_core::gcode::{analyze, parse_lines};
+use gcodekit_core::machine_state::MachineState;
+use gcodekit_core::models::JobStatus;
+use gcodekit_core::profile::WorkEnvelope;
+use gcodekit_core::protocol::{Protocol, Response};
+use gcodekit_core::scheduler::{JobScheduler, SchedulerOptions, SchedulerStatus};
+use gcodekit_core::session::SessionOptions;
+use gcodekit_device_adapters::sim::SimOptions;
+use std::time::{Duration, Instant};
+
+const ENVELOPE: WorkEnvelope = WorkEnvelope {
+    min: [0.0, 0.0, -50.0],
+    max: [100.0, 100.0, 0.0],
+};
+
+#[test]
+fn test_job_bounds_are_checked_in_machine_coordinates() {
+    let analysis = analyze(&parse_lines("G21\nG0 X10 Y10\nG55\nG0 X50"), None);
+    let mut state = MachineState::default();
+    state.offsets.insert("G54".into(), [60.0, 0.0, -10.0]);
+    assert!(check_job(&analysis, &ENVELOPE, &state).is_empty());
+
+    // G55 has no reported offset; only the active WCO can stand in for it
+    state.wco = [60.0, 0.0, 0.0];
+    assert_eq!(wcs_origin(&state, "G55"), None);
+    assert_eq!(unreported_offsets(&analysis, &state), ["G55"]);
+    state.offsets.insert("G92".into(), [0.0, -5.0, 0.0]);
+    let violations: Vec<String> = check_job(&analysis, &ENVELOPE, &state).iter().map(ToString::to_string).collect();
+    assert_eq!(
+        violations,
+        ["G54: Y -5.000 is past the limit 0.000", "G55: X 110.000 is past the limit 100.000"]
+    );
+    state.offsets.insert("G55".into(), [0.0, 10.0, 0.0]);
+    assert_eq!(wcs_origin(&state, "G55"), Some([0.0, 5.0, 0.0]));
+    assert!(unreported_offsets(&analysis, &state).is_empty());
+
+    assert!(check_position(&ENVELOPE, [100.0005, 0.0, 0.0]).is_empty());
+    let outside = check_position(&ENVELOPE, [50.0, 50.0, 1.0]);
+    assert_eq!((outside.len(), outside[0].axis, outside[0].wcs.as_deref()), (1, 'Z', None));
+}
+
+#[test]
+fn test_offsets_are_parsed_from_grbl_feedback() {
+    let g = Protocol::Grbl;
+    assert_eq!(g.parse("[G54:1.000,2.000,-3.500]"), Response::Offset("G54".into(), [1.0, 2.0, -3.5]));
+    assert_eq!(g.parse("[G28:0.000,0.000,0.000,0.000]"), Response::Offset("G28".into(), [0.0; 3]));
+    assert_eq!(g.parse("[TLO:1.250]"), Response::Offset("TLO".into(), [0.0, 0.0, 1.25]));
+    assert!(matches!(g.parse("[GC:G0 G54 G17 G21 G90 G94 M5 M9 T0 F0 S0]"), Response::Feedback(_)));
+    assert!(matches!(g.parse("[PRB:0.000,0.000,-1.000:1]"), Response::Feedback(_)));
+}
+
+fn wait_finished(sched: &JobScheduler, n: usize) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while sched.finished().len() < n && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn test_scheduler_refuses_jobs_outside_envelope_until_overridden() {
+    let td = tempfile::tempdir().unwrap();
+    let path = td.path().join("wide.nc");
+    std::fs::write(&path, "G21 G90\nG1 X150 F500\nG0 X0").unwrap();
+    let dm = DeviceManager::new();
+    let session = dm.open_session(
+        "router",
+        "sim:grbl".parse().unwrap(),
+        gcodekit_device_adapters::create_sim_transport(Default::default()).unwrap(),
+        Protocol::Grbl,
+        SessionOptions::default(),
+    );
+    let sched = JobScheduler::start(
+        session.clone(),
+        SchedulerOptions {
+            record_history: false,
+            journal: None,
+            envelope: Some(ENVELOPE),
+            ..Default::default()
+        },
+    );
+
+    sched.submit(&path);
+    wait_finished(&sched, 1);
+    match &sched.finished()[0].status {
+        JobStatus::Failed(reason) => assert_eq!(reason, "outside work envelope: G54: X 150.000 is past the limit 100.000"),
+        other => panic!("unexpected status {:?}", other),
+    }
+    assert!(matches!(sched.status(), SchedulerStatus::Halted(_)));
+
+    let id = sched.submit(&path);
+    assert!(sched.override_envelope(&id));
+    assert!(!sched.override_envelope("job-nope"));
+    sched.resume();
+    wait_finished(&sched, 2);
+    assert!(matches!(sched.finished()[1].status, JobStatus::Completed));
+
+    // Offsets are read back before the check: G55 sits 60 mm along X
+    session.send_command("G10 L2 P2 X60", Duration::from_secs(1)).unwrap();
+    let g55 = td.path().join("g55.nc");
+    std::fs::write(&g55, "G21 G90 G55\nG1 X50 F500\nG54 G0 X0").unwrap();
+    sched.submit(&g55);
+    sched.resume();
+    wait_finished(&sched, 3);
+    match &sched.finished()[2].status {
+        JobStatus::Failed(reason) => assert_eq!(reason, "outside work envelope: G55: X 110.000 is past the limit 100.000"),
+        other => panic!("unexpected status {:?}", other),
+    }
+
+    // GRBL reports no G59.1 offset, so the job cannot be placed
+    let g59 = td.path().join("g59.nc");
+    std::fs::write(&g59, "G21 G90 G59.1\nG1 X5 F500").unwrap();
+    sched.submit(&g59);
+    sched.resume();
+    wait_finished(&sched, 4);
+    match &sched.finished()[3].status {
+        JobStatus::Failed(reason) => assert_eq!(reason, "no offset reported for G59.1; cannot check the work envelope"),
+        other => panic!("unexpected status {:?}", other),
+    }
+}
+
+#[test]
+fn test_scheduler_stops_when_machine_leaves_envelope() {
+    let td = tempfile::tempdir().unwrap();
+    let path = td.path().join("g53.nc");
+    // Machine-coordinate moves are not in the job bounds, so only the live
+    // position shows the problem
+    let body: String = std::iter::once("G21 G90\n".to_string())
+        .chain((0..500).map(|_| "G53 G0 X150\n".to_string()))
+        .collect();
+    std::fs::write(&path, body).unwrap();
+    let dm = DeviceManager::new();
+    let transport = gcodekit_device_adapters::create_sim_transport(SimOptions {
+        latency: Duration::from_millis(2),
+        ..Default::default()
+    })
+    .unwrap();
+    let session = dm.open_session(
+        "router",
+        "sim:grbl".parse().unwrap(),
+        transport,
+        Protocol::Grbl,
+        SessionOptions {
+            status_interval: Some(Duration::from_millis(20)),
+            ..Default::default()
+        },
+    );
+    let sched = JobScheduler::start(
+        session,
+        SchedulerOptions {
+            record_history: false,
+            journal: None,
+            envelope: Some(ENVELOPE),
+            ..Default::default()
+        },
+    );
+    sched.submit(&path);
+    wait_finished(&sched, 1);
+    let job = &sched.finished()[0];
+    match &job.status {
+        JobStatus::Failed(reason) => assert_eq!(reason, "position outside work envelope: X 150.000 is past the limit 100.000"),
+        other => panic!("unexpected status {:?}", other),
+    }
+    assert!(job.lines_sent < 501, "stopped before the end");
+}
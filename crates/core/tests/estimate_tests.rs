@@ -0,0 +1,154 @@
+use gcodekit_core::device_manager::DeviceManager;
+use gcodekit_core::gcode::{estimate, parse_lines, Estimate, MotionLimits};
+use gcodekit_core::profile::AxisConfig;
+use gcodekit_core::protocol::{Protocol, Response};
+use gcodekit_core::scheduler::{JobScheduler, SchedulerOptions};
+use gcodekit_core::session::SessionOptions;
+use gcodekit_device_adapters::sim::SimOptions;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+fn limits() -> MotionLimits {
+    let axis = AxisConfig {
+        max_rate: 6000.0,
+        acceleration: 100.0,
+        max_travel: 500.0,
+    };
+    let mut limits = MotionLimits::default();
+    limits.axes.x = axis;
+    limits.axes.y = axis;
+    limits.axes.z = axis;
+    limits
+}
+
+fn secs(program: &str, limits: &MotionLimits) -> f64 {
+    estimate(&parse_lines(program), limits).total().as_secs_f64()
+}
+
+#[test]
+fn test_single_move_follows_trapezoid() {
+    // 10 mm/s reached after 0.5 mm at 100 mm/s², both ends
+    let t = secs("G21 G90\nG1 X100 F600", &limits());
+    assert!((t - 10.1).abs() < 1e-6, "{}", t);
+
+    // Rapids run at the axis max rate, limited by the slowest axis moved
+    let mut slow_z = limits();
+    slow_z.axes.z.max_rate = 600.0;
+    let t = secs("G0 Z100", &slow_z);
+    assert!((t - 10.1).abs() < 1e-6, "{}", t);
+}
+
+#[test]
+fn test_short_segments_are_slower_than_distance_over_feed() {
+    // 1000 segments of 0.1 mm with 90 degree corners at 50 mm/s
+    let zigzag: String = (1..=1000)
+        .map(|i| {
+            let (x, y) = ((i / 2) as f64 * 0.1, ((i + 1) / 2) as f64 * 0.1);
+            format!("G1 X{:.1} Y{:.1} F3000\n", x, y)
+        })
+        .collect();
+    let naive = 100.0 / 50.0;
+    let t = secs(&zigzag, &limits());
+    assert!(t > 5.0 * naive, "zigzag {} vs naive {}", t, naive);
+
+    // Collinear segments only slow down for the limited lookahead
+    let straight: String = (1..=1000).map(|i| format!("G1 X{:.1} F3000\n", i as f64 * 0.1)).collect();
+    let buffered = secs(&straight, &limits());
+    let unbounded = secs(&straight, &MotionLimits { planner_blocks: 2000, ..limits() });
+    let single = secs("G1 X100 F3000", &limits());
+    assert!((unbounded - single).abs() < 1e-6, "{} vs {}", unbounded, single);
+    assert!(buffered > 2.0 * single && buffered < t, "{}", buffered);
+}
+
+#[test]
+fn test_arcs_dwells_and_line_timestamps() {
+    let program = parse_lines("G21 G90\nG4 P2.5\nG1 X10 F600\nG2 X10 Y0 I10 J0\nM5\nG1 X0");
+    let est = estimate(&program, &limits());
+    let ends = &est.line_end;
+    assert_eq!(ends.len(), 6);
+    assert_eq!((ends[0], ends[1]), (0.0, 2.5));
+    assert!(ends.windows(2).all(|w| w[0] <= w[1]));
+    // A full circle of radius 10 at 10 mm/s takes a little over 2*pi seconds
+    let circle = ends[3] - ends[2];
+    assert!(circle > std::f64::consts::TAU && circle < 6.6, "{}", circle);
+    assert_eq!(ends[4], ends[3], "M5 only stops motion");
+    assert_eq!(est.total(), Duration::from_secs_f64(ends[5]));
+    assert_eq!(est.from_line(2), Duration::from_secs_f64(ends[5] - 2.5));
+}
+
+#[test]
+fn test_remaining_follows_observed_pace() {
+    let est = Estimate {
+        line_end: vec![10.0, 20.0, 30.0, 40.0],
+    };
+    assert_eq!(est.remaining(0, 0, Duration::ZERO), Duration::from_secs(40));
+    // Twice as slow as predicted halfway through: half the correction applies
+    assert_eq!(est.remaining(0, 2, Duration::from_secs(40)), Duration::from_secs(30));
+    assert_eq!(est.remaining(1, 4, Duration::from_secs(60)), Duration::ZERO);
+}
+
+#[test]
+fn test_grbl_settings_override_limits() {
+    let g = Protocol::Grbl;
+    assert_eq!(g.parse("$110=500.000"), Response::Setting(110, 500.0));
+    assert_eq!(g.parse("$11=0.020 (junction deviation, mm)"), Response::Setting(11, 0.02));
+    assert!(matches!(g.parse("$N0="), Response::Other(_)));
+
+    let settings = BTreeMap::from([(11, 0.02), (110, 500.0), (121, 25.0), (32, 1.0), (122, 0.0)]);
+    let limits = MotionLimits::default().with_grbl_settings(&settings);
+    assert_eq!(limits.junction_deviation, 0.02);
+    assert_eq!(limits.axes.x.max_rate, 500.0);
+    assert_eq!(limits.axes.y.acceleration, 25.0);
+    assert_eq!(limits.axes.z, MotionLimits::default().axes.z, "zero values are ignored");
+}
+
+#[test]
+fn test_scheduler_estimates_queued_jobs() {
+    let td = tempfile::tempdir().unwrap();
+    let path = td.path().join("job.nc");
+    std::fs::write(&path, "G21 G90\nG1 X10 F600\nG1 Y10\nG0 X0 Y0").unwrap();
+    // The controller's own rates and accelerations (`$$`) replace the profile's
+    let settings = BTreeMap::from([(110, 3000.0), (111, 3000.0), (120, 50.0), (121, 50.0)]);
+    let transport = gcodekit_device_adapters::create_sim_transport(SimOptions {
+        settings: settings.clone(),
+        ..Default::default()
+    })
+    .unwrap();
+    let dm = DeviceManager::new();
+    let session = dm.open_session("router", "sim:grbl".parse().unwrap(), transport, Protocol::Grbl, SessionOptions::default());
+    session.pause().unwrap();
+    let sched = JobScheduler::start(
+        session.clone(),
+        SchedulerOptions {
+            record_history: false,
+            journal: None,
+            motion: Some(limits()),
+            ..Default::default()
+        },
+    );
+    let expected = secs("G21 G90\nG1 X10 F600\nG1 Y10\nG0 X0 Y0", &limits().with_grbl_settings(&settings));
+    assert_ne!(expected, secs("G21 G90\nG1 X10 F600\nG1 Y10\nG0 X0 Y0", &limits()));
+    let first = sched.submit(&path);
+    let second = sched.submit(&path);
+    // The waiting job is analyzed off the caller's thread before it runs
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let queued = loop {
+        let queued = sched.queued().into_iter().find(|j| j.id == second && j.analysis.is_some());
+        if queued.is_some() || Instant::now() > deadline {
+            break queued.expect("queued job analyzed");
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    };
+    assert_eq!((queued.lines_total, queued.estimated_seconds), (4, Some(expected)));
+
+    session.resume().unwrap();
+    while sched.finished().len() < 2 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    let finished = sched.finished();
+    assert_eq!(finished.iter().map(|j| j.id.clone()).collect::<Vec<_>>(), vec![first, second]);
+    for job in &finished {
+        assert_eq!(job.estimated_seconds, Some(expected));
+        assert_eq!(job.remaining_seconds, Some(0.0));
+    }
+}
@@ -0,0 +1,163 @@
+mod common;
+
+use gcodekit_core::device_manager::DeviceManager;
+use gcodekit_core::gcode::{level, HeightMap, Interpolation, LevelOptions, Leveled, LineSource};
+use gcodekit_core::probe::{ProbeOptions, Prober};
+use gcodekit_device_adapters::sim::SimOptions;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A 4x4 grid, 10 mm apart, over the plane z = 0.01x + 0.02y.
+fn plane() -> HeightMap {
+    let mut map = HeightMap::covering([0.0, 0.0], [30.0, 30.0], 10.0);
+    for row in 0..4 {
+        for column in 0..4 {
+            let [x, y] = map.point(column, row);
+            map.set_height(column, row, 0.01 * x + 0.02 * y);
+        }
+    }
+    map
+}
+
+fn lines(text: &str) -> Vec<String> {
+    text.lines().map(str::to_string).collect()
+}
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-9
+}
+
+#[test]
+fn test_grid_and_interpolation() {
+    let map = plane();
+    assert_eq!((map.size, map.spacing), ([4, 4], [10.0, 10.0]));
+    assert_eq!(HeightMap::covering([0.0, 0.0], [25.0, 0.0], 10.0).size, [4, 2]);
+
+    assert!(close(map.interpolate(12.5, 17.0, Interpolation::Bilinear), 0.465));
+    assert!(close(map.interpolate(15.0, 15.0, Interpolation::Bicubic), 0.45));
+    // Outside the grid the edge is extended
+    assert!(close(map.interpolate(-5.0, 40.0, Interpolation::Bilinear), 0.6));
+
+    let mut bump = HeightMap::covering([0.0, 0.0], [30.0, 30.0], 10.0);
+    bump.set_height(1, 1, 1.0);
+    let bilinear = bump.interpolate(12.0, 12.0, Interpolation::Bilinear);
+    let bicubic = bump.interpolate(12.0, 12.0, Interpolation::Bicubic);
+    assert!(bicubic > bilinear && bicubic < 1.0, "{} {}", bilinear, bicubic);
+}
+
+#[test]
+fn test_save_and_load() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("board.json");
+    let map = plane();
+    map.save(&path).unwrap();
+    assert_eq!(HeightMap::load(&path).unwrap(), map);
+
+    let mut broken = map.clone();
+    broken.heights.pop();
+    broken.save(&path).unwrap();
+    assert!(HeightMap::load(&path).is_err());
+}
+
+#[test]
+fn test_level_segments_moves_and_arcs() {
+    let map = plane();
+    let options = LevelOptions {
+        segment_length: 5.0,
+        ..Default::default()
+    };
+    let job = lines("G21 G90\nG0 Z5\nG0 X0 Y0\nG1 Z-0.1 F100\nN7 G1 X10\nG2 X20 Y0 I5 J0");
+    let out = level(&job, &map, &options).unwrap();
+    assert_eq!(
+        out[..7],
+        [
+            "G21 G90",
+            "G0 Z5",
+            "G0 X0.000 Y0.000 Z5.000",
+            "F100",
+            "G1 X0.000 Y0.000 Z-0.100",
+            "G1 X5.000 Y0.000 Z-0.050",
+            "G1 X10.000 Y0.000 Z0.000",
+        ]
+    );
+    // The half circle (15.7 mm) is split into four chords on its surface
+    let arc = &out[7..];
+    assert_eq!(arc.len(), 4);
+    assert_eq!(arc[1], "G1 X15.000 Y5.000 Z0.150");
+    assert_eq!(arc[3], "G1 X20.000 Y0.000 Z0.100");
+}
+
+#[test]
+fn test_level_inches_and_refusals() {
+    let map = plane();
+    let job = lines("G20 G90 G0 X0.5 Y0.5 Z0\nG1 X1 Y0.5");
+    let out = level(&job, &map, &LevelOptions::default()).unwrap();
+    assert_eq!(out[..2], ["G20 G90", "G0 X0.5000 Y0.5000 Z0.0150"]);
+    assert_eq!(out.last().unwrap(), "G1 X1.0000 Y0.5000 Z0.0200");
+
+    // Machine-coordinate moves pass through and forget the position
+    let job = lines("G0 X0 Y0 Z1\nG53 G0 Z0\nG0 X10 Y10");
+    let out = level(&job, &map, &LevelOptions::default()).unwrap();
+    assert_eq!(out, ["G0 X0.000 Y0.000 Z1.000", "G53 G0 Z0", "G0 X10.000 Y10.000"]);
+
+    let err = level(&lines("G0 X0 Y0\nG91 G1 X1"), &map, &LevelOptions::default()).unwrap_err();
+    assert!(err.to_string().contains("line 2"), "{}", err);
+}
+
+#[test]
+fn test_leveled_source_matches_level() {
+    let map = plane();
+    let options = LevelOptions {
+        segment_length: 2.0,
+        ..Default::default()
+    };
+    // Long enough to cross several checkpoints
+    let mut job = lines("G21 G90\nG0 Z1\nG0 X0 Y0\nG1 Z-0.1 F100");
+    for i in 0..600 {
+        job.push(format!("G1 X{} Y{}", (i * 7) % 30, (i * 3) % 30));
+    }
+    let expected = level(&job, &map, &options).unwrap();
+    let leveled = Leveled::new(Arc::new(job.clone()), map.clone(), options.clone()).unwrap();
+    assert_eq!(leveled.len(), expected.len());
+    let read: Vec<String> = (0..leveled.len()).map(|i| leveled.line(i).unwrap().into_owned()).collect();
+    assert_eq!(read, expected);
+    // Out of order, as resume and preview read
+    for i in [expected.len() - 1, 3, 1500, 2, 900] {
+        assert_eq!(leveled.line(i).unwrap(), expected[i]);
+    }
+    assert!(leveled.line(expected.len()).is_none());
+
+    let err = Leveled::new(Arc::new(lines("G0 X0 Y0\nG91 G1 X1")), map, options).unwrap_err();
+    assert!(err.to_string().contains("line 2"), "{}", err);
+}
+
+#[test]
+fn test_probing_a_stepped_surface() {
+    let dm = DeviceManager::new();
+    let sim = SimOptions {
+        solids: vec![([-5.0, -5.0, -10.0], [5.0, 15.0, 0.0]), ([5.0, -5.0, -10.0], [25.0, 15.0, 0.5])],
+        ..Default::default()
+    };
+    let session = common::open(&dm, sim);
+    // Work Z is 1 mm above machine Z
+    session.send_command("G10 L2 P1 Z-1", Duration::from_secs(1)).unwrap();
+    session.send_command("G53 G0 X0 Y0 Z5", Duration::from_secs(1)).unwrap();
+
+    let mut map = HeightMap::covering([0.0, 0.0], [20.0, 10.0], 10.0);
+    Prober::new(session.clone(), ProbeOptions::default()).height_map(&mut map).unwrap();
+    assert_eq!(map.heights, [1.0, 1.5, 1.5, 1.0, 1.5, 1.5]);
+    let mpos = session.wait_for_report(Duration::from_secs(1), |_| true).unwrap().mpos;
+    assert_eq!(mpos, [0.0, 10.0, 5.0]);
+
+    // A plate on the surface, and the active G55 moved since the last report
+    session.send_command("G53 G0 X0 Y0 Z5", Duration::from_secs(1)).unwrap();
+    session.wait_for_report(Duration::from_secs(1), |_| true).unwrap();
+    session.send_command("G10 L2 P2 Z-2", Duration::from_secs(1)).unwrap();
+    session.send_command("G55", Duration::from_secs(1)).unwrap();
+    let options = ProbeOptions {
+        plate_thickness: 0.5,
+        ..Default::default()
+    };
+    Prober::new(session.clone(), options).height_map(&mut map).unwrap();
+    assert_eq!(map.heights, [1.5, 2.0, 2.0, 1.5, 2.0, 2.0]);
+}
@@ -14,11 +14,9 @@ fn integration_simulated_device_identify_and_persist() {
         if let Ok((mut s, _)) = listener.accept() {
             let mut reader = std::io::BufReader::new(s.try_clone().expect("clone"));
             let mut line = String::new();
-            if let Ok(_) = reader.read_line(&mut line) {
-                if line.contains("M115") {
-                    let _ = s.write_all(b"FIRMWARE_NAME:SimDevice 1.0\n");
-                    let _ = s.flush();
-                }
+            if reader.read_line(&mut line).is_ok() && line.contains("M115") {
+                let _ = s.write_all(b"FIRMWARE_NAME:SimDevice 1.0\n");
+                let _ = s.flush();
             }
             // keep connection open longer to avoid RST race
             std::thread::sleep(Duration::from_millis(1000));
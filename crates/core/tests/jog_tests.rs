@@ -0,0 +1,118 @@
+mod common;
+
+use gcodekit_core::device_manager::DeviceManager;
+use gcodekit_core::jog::{Axis, Direction, JogController, JogOptions};
+use gcodekit_core::session::Session;
+use gcodekit_device_adapters::sim::{SimFirmware, SimOptions};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+fn open(dm: &DeviceManager, firmware: SimFirmware) -> Arc<Session> {
+    common::open(dm, SimOptions { firmware, ..Default::default() })
+}
+
+fn wait_for(session: &Session, mpos: [f64; 3]) -> [f64; 3] {
+    let deadline = Instant::now() + Duration::from_secs(3);
+    while session.state().mpos != mpos && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    session.state().mpos
+}
+
+#[test]
+fn test_incremental_jogs_use_step_and_feed_presets() {
+    let dm = DeviceManager::new();
+    let session = open(&dm, SimFirmware::Grbl);
+    let mut jog = JogController::new(session.clone(), JogOptions::default());
+    assert_eq!((jog.step(), jog.feed()), (1.0, 1000.0));
+    jog.jog(Axis::X, Direction::Positive).unwrap();
+    assert!(jog.select_step(3) && jog.select_feed(0));
+    assert!(!jog.select_step(9));
+    assert_eq!((jog.step(), jog.feed()), (10.0, 100.0));
+    jog.jog(Axis::Y, Direction::Negative).unwrap();
+    jog.jog_by([0.5, 0.0, -2.0]).unwrap();
+    assert_eq!(wait_for(&session, [1.5, -10.0, -2.0]), [1.5, -10.0, -2.0]);
+
+    // Jog commands leave the controller's distance mode alone
+    session.send_line("G0 X0 Y0 Z0").unwrap();
+    assert_eq!(wait_for(&session, [0.0; 3]), [0.0; 3]);
+}
+
+#[test]
+fn test_continuous_jog_stays_shallow_and_stops() {
+    let dm = DeviceManager::new();
+    let session = open(&dm, SimFirmware::Grbl);
+    let options = JogOptions {
+        segment_time: Duration::from_millis(50),
+        lookahead: 2,
+        ..Default::default()
+    };
+    let mut jog = JogController::new(session.clone(), options);
+    jog.set_feed(600.0);
+    jog.start_continuous(Axis::Z, Direction::Negative).unwrap();
+    assert!(jog.is_jogging());
+    assert!(jog.jog(Axis::X, Direction::Positive).is_err(), "no steps during a continuous jog");
+    std::thread::sleep(Duration::from_millis(300));
+    jog.stop().unwrap();
+    assert!(!jog.is_jogging());
+
+    // 0.5 mm segments: about six paced ones plus the two queued ahead
+    std::thread::sleep(Duration::from_millis(100));
+    let z = session.state().mpos[2];
+    let segments = -z / 0.5;
+    assert!((4.0..=10.0).contains(&segments) && segments.fract() == 0.0, "z {}", z);
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(session.state().mpos[2], z, "nothing sent after stop");
+}
+
+#[test]
+fn test_release_stops_a_slow_link_at_once() {
+    let dm = DeviceManager::new();
+    // Replies take longer than a segment, so segments would pile up
+    let sim = SimOptions {
+        latency: Duration::from_millis(30),
+        ..Default::default()
+    };
+    let session = common::open(&dm, sim);
+    let options = JogOptions {
+        segment_time: Duration::from_millis(10),
+        lookahead: 2,
+        ..Default::default()
+    };
+    let mut jog = JogController::new(session.clone(), options);
+    jog.set_feed(600.0);
+    jog.start_continuous(Axis::X, Direction::Positive).unwrap();
+    std::thread::sleep(Duration::from_millis(300));
+    jog.stop().unwrap();
+
+    std::thread::sleep(Duration::from_millis(150));
+    let x = session.wait_for_report(Duration::from_secs(1), |_| true).unwrap().mpos[0];
+    assert!(x > 0.0);
+    std::thread::sleep(Duration::from_millis(300));
+    let later = session.wait_for_report(Duration::from_secs(1), |_| true).unwrap().mpos[0];
+    assert_eq!(later, x, "machine kept moving after release");
+}
+
+#[test]
+fn test_marlin_jogs_use_relative_moves() {
+    let dm = DeviceManager::new();
+    let session = open(&dm, SimFirmware::Marlin);
+    let jog = JogController::new(session.clone(), JogOptions::default());
+    jog.jog(Axis::X, Direction::Positive).unwrap();
+    jog.jog(Axis::X, Direction::Positive).unwrap();
+    session.send_line("M114").unwrap();
+    assert_eq!(wait_for(&session, [2.0, 0.0, 0.0]), [2.0, 0.0, 0.0]);
+}
+
+#[test]
+fn test_jogging_is_refused_while_streaming() {
+    let dm = DeviceManager::new();
+    let session = open(&dm, SimFirmware::Grbl);
+    session.pause().unwrap();
+    session.stream(["G0 X10", "G0 X0"]).unwrap();
+    let mut jog = JogController::new(session.clone(), JogOptions::default());
+    let err = jog.jog(Axis::X, Direction::Positive).unwrap_err();
+    assert!(err.to_string().contains("job is running"), "{}", err);
+    assert!(jog.start_continuous(Axis::X, Direction::Positive).is_err());
+    session.cancel().unwrap();
+}
@@ -0,0 +1,110 @@
+use gcodekit_core::device_manager::DeviceManager;
+use gcodekit_core::gcode::ModalState;
+use gcodekit_core::journal::{self, Checkpoint, Journal, JournalEvent};
+use gcodekit_core::models::JobStatus;
+use gcodekit_core::protocol::Protocol;
+use gcodekit_core::scheduler::{JobScheduler, SchedulerOptions};
+use gcodekit_core::session::SessionOptions;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_modal_state_tracks_groups() {
+    let m = ModalState::after(["G20 G91 (relative) G55", "g1 x1 f250.5", "M3 S12000 T2", "G18 M8", "G0 X0"]);
+    assert_eq!(m.units, "G20");
+    assert_eq!(m.distance, "G91");
+    assert_eq!(m.wcs, "G55");
+    assert_eq!(m.motion, "G0");
+    assert_eq!(m.plane, "G18");
+    assert_eq!((m.spindle.as_str(), m.coolant.as_str()), ("M3", "M8"));
+    assert_eq!((m.feed, m.spindle_speed, m.tool), (Some(250.5), Some(12000.0), Some(2)));
+    assert_eq!(ModalState::after(["G38.2 Z-5 F50"]).motion, "G38.2");
+}
+
+#[test]
+fn test_journal_finds_interrupted_jobs_and_compacts() {
+    let td = tempfile::tempdir().unwrap();
+    let path = td.path().join("journal.jsonl");
+    let mut j = Journal::open(&path).unwrap();
+    for id in ["done", "crashed"] {
+        j.append(
+            id,
+            JournalEvent::Started {
+                device_id: "router".into(),
+                file_path: format!("{}.nc", id),
+                lines_total: 10,
+            },
+        )
+        .unwrap();
+    }
+    let checkpoint = Checkpoint {
+        line: 7,
+        mpos: [1.0, 2.0, -0.5],
+        wpos: [1.0, 2.0, -0.5],
+        modal: ModalState::after(["G1 F300", "M3 S1000"]),
+    };
+    j.append("crashed", JournalEvent::Checkpoint(Box::new(checkpoint.clone()))).unwrap();
+    j.append("done", JournalEvent::Finished { status: JobStatus::Completed }).unwrap();
+    drop(j);
+    // Power loss while writing the next entry
+    let mut f = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+    f.write_all(b"{\"job_id\":\"crashed\",\"at\":\"2024-").unwrap();
+
+    let interrupted = journal::interrupted_jobs(&path).unwrap();
+    assert_eq!(interrupted.len(), 1);
+    assert_eq!(interrupted[0].job_id, "crashed");
+    assert_eq!(interrupted[0].file_path, "crashed.nc");
+    assert_eq!(interrupted[0].resume_line(), 7);
+    assert_eq!(interrupted[0].last_checkpoint.as_ref(), Some(&checkpoint));
+
+    assert_eq!(journal::compact(&path).unwrap(), 2);
+    let left = journal::read_entries(&path).unwrap();
+    assert_eq!(left.len(), 2);
+    assert!(left.iter().all(|e| e.job_id == "crashed"));
+    assert_eq!(journal::compact(&path).unwrap(), 0);
+}
+
+#[test]
+fn test_scheduler_checkpoints_progress() {
+    let td = tempfile::tempdir().unwrap();
+    let path = td.path().join("journal.jsonl");
+    let program = td.path().join("job.nc");
+    std::fs::write(&program, "G21 G90\nM3 S8000\nG1 X5 F400\nG1 Y5\n").unwrap();
+
+    let dm = DeviceManager::new();
+    let transport = gcodekit_device_adapters::create_sim_transport(Default::default()).unwrap();
+    let session = dm.open_session("mill", "sim:grbl".parse().unwrap(), transport, Protocol::Grbl, SessionOptions::default());
+    let sched = JobScheduler::start(
+        session,
+        SchedulerOptions {
+            record_history: false,
+            journal: Some(path.clone()),
+            checkpoint_interval: Duration::ZERO,
+            ..Default::default()
+        },
+    );
+    let id = sched.submit(&program);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while sched.finished().is_empty() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+
+    let entries = journal::read_entries(&path).unwrap();
+    assert!(entries.iter().all(|e| e.job_id == id));
+    assert!(matches!(entries.first().map(|e| &e.event), Some(JournalEvent::Started { lines_total: 4, .. })));
+    assert!(matches!(
+        entries.last().map(|e| &e.event),
+        Some(JournalEvent::Finished { status: JobStatus::Completed })
+    ));
+    let last = entries
+        .iter()
+        .rev()
+        .find_map(|e| match &e.event {
+            JournalEvent::Checkpoint(c) => Some(c.as_ref().clone()),
+            _ => None,
+        })
+        .expect("checkpoint");
+    assert_eq!(last.line, 4);
+    assert_eq!((last.modal.spindle.as_str(), last.modal.feed), ("M3", Some(400.0)));
+    assert!(journal::interrupted_jobs(&path).unwrap().is_empty());
+}
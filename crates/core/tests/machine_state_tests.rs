@@ -0,0 +1,67 @@
+use gcodekit_core::machine_state::{MachineState, MachineStatus, Overrides, StatusReport};
+use gcodekit_core::protocol::{Protocol, Response};
+
+#[test]
+fn test_parse_grbl_status_report() {
+    let r = StatusReport::parse("<Hold:1|MPos:10.000,-5.500,2.000|Bf:15,128|FS:500,12000|WCO:1.000,1.000,0.000|Ov:120,100,90|Pn:XZ|Ln:42>")
+        .expect("status report");
+    assert_eq!(r.status, MachineStatus::Hold);
+    assert_eq!(r.sub_state, Some(1));
+    assert_eq!(r.mpos, Some([10.0, -5.5, 2.0]));
+    assert_eq!(r.buffer, Some((15, 128)));
+    assert_eq!((r.feed, r.spindle), (Some(500.0), Some(12000.0)));
+    assert_eq!(r.line, Some(42));
+    assert_eq!(
+        r.overrides,
+        Some(Overrides {
+            feed: 120,
+            rapid: 100,
+            spindle: 90
+        })
+    );
+    assert_eq!(r.pins.as_deref(), Some("XZ"));
+    assert!(StatusReport::parse("ok").is_none());
+}
+
+#[test]
+fn test_machine_state_derives_work_position() {
+    let mut s = MachineState::default();
+    let with_wco = StatusReport::parse("<Idle|MPos:10.000,10.000,0.000|FS:0,0|WCO:2.000,3.000,0.000>").unwrap();
+    assert!(s.apply_report(&with_wco));
+    assert_eq!(s.wpos, [8.0, 7.0, 0.0]);
+
+    // WCO is only sent occasionally; later reports reuse the last one
+    let wpos_only = StatusReport::parse("<Run|WPos:1.000,1.000,1.000|FS:100,0>").unwrap();
+    assert!(s.apply_report(&wpos_only));
+    assert_eq!(s.mpos, [3.0, 4.0, 1.0]);
+    assert!(!s.apply_report(&wpos_only), "identical report is not a change");
+}
+
+#[test]
+fn test_protocol_classifies_replies() {
+    let g = Protocol::Grbl;
+    assert_eq!(g.parse("ok"), Response::Ok);
+    assert_eq!(g.parse("error:9"), Response::Error("error:9".into()));
+    assert_eq!(g.parse("ALARM:2"), Response::Alarm("ALARM:2".into()));
+    assert!(matches!(g.parse("Grbl 1.1h ['$' for help]"), Response::Welcome(_)));
+    assert!(matches!(g.parse("[MSG:Caution: Unlocked]"), Response::Feedback(_)));
+    assert!(matches!(g.parse("<Idle|MPos:0.000,0.000,0.000|FS:0,0>"), Response::Status(_)));
+
+    let m = Protocol::Marlin;
+    assert_eq!(m.parse("ok T:21.0 /0.0"), Response::Ok);
+    assert_eq!(m.parse("X:1.00 Y:2.50 Z:0.30 E:0.00 Count X:80 Y:200 Z:120"), Response::Position([1.0, 2.5, 0.3]));
+    assert!(matches!(m.parse("Error:Printer halted. kill() called!"), Response::Error(_)));
+    assert!(matches!(m.parse("echo:busy: processing"), Response::Feedback(_)));
+    assert_eq!(Protocol::Marlin.status_query(), None);
+}
+
+#[test]
+fn test_active_wcs_from_parser_state() {
+    let mut s = MachineState::default();
+    assert_eq!(s.wcs(), None);
+    s.modes = "G0 G59.1 G17 G21 G90 G94 M5 M9 T0 F0 S0".split(' ').map(str::to_string).collect();
+    assert_eq!(s.wcs(), Some("G59.1"));
+    // Words that only sort between G54 and G59.3 are not work systems
+    s.modes = ["G54.1", "G59.4", "G56"].map(str::to_string).to_vec();
+    assert_eq!(s.wcs(), Some("G56"));
+}
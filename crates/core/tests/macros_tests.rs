@@ -0,0 +1,110 @@
+mod common;
+
+use gcodekit_core::device_manager::DeviceManager;
+use gcodekit_core::macros::{delete_macro, export_macros, import_macros, load_macros, save_macro, Macro, MacroParam};
+use gcodekit_core::machine_state::MachineState;
+use gcodekit_core::session::{Session, StreamStatus};
+use gcodekit_device_adapters::sim::SimOptions;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn open(dm: &DeviceManager) -> Arc<Session> {
+    common::open(dm, SimOptions::default())
+}
+
+fn param(name: &str, default: Option<&str>) -> MacroParam {
+    MacroParam {
+        name: name.into(),
+        prompt: String::new(),
+        default: default.map(str::to_string),
+    }
+}
+
+fn answers(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+    pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+#[test]
+fn test_expand_substitutes_state_and_answers() {
+    let state = MachineState {
+        mpos: [10.0, 20.0, -5.0],
+        wpos: [0.0, 5.0, 1.25],
+        modes: "G0 G55 G17 G21 G90 G94 M5 M9 T3 F0 S0".split(' ').map(str::to_string).collect(),
+        ..Default::default()
+    };
+
+    let mut m = Macro::new("lift", "G91 G0 Z{{ lift }}\n\n{{wcs}} G90 G0 X{{x}} Y{{my}}\n(T{{tool}} at {{z}})");
+    m.params.push(param("lift", Some("5")));
+    m.validate().unwrap();
+    let expected = ["G91 G0 Z5", "G55 G90 G0 X0.000 Y20.000", "(T3 at 1.250)"];
+    assert_eq!(m.expand(&state, &BTreeMap::new()).unwrap(), expected);
+    assert_eq!(m.expand(&state, &answers(&[("lift", "2.5")])).unwrap()[0], "G91 G0 Z2.5");
+}
+
+#[test]
+fn test_validation_and_unsafe_answers_are_rejected() {
+    let state = MachineState::default();
+    let mut m = Macro::new("probe", "G38.2 Z-{{depth}} F{{feed_rate}}");
+    m.params = vec![param("depth", None), param("feed_rate", Some("100"))];
+    assert!(m.expand(&state, &BTreeMap::new()).is_err());
+    for bad in ["5\nM3 S24000", "5 G0 X0", "5;", "(x)", ""] {
+        assert!(m.expand(&state, &answers(&[("depth", bad)])).is_err(), "{:?}", bad);
+    }
+    assert_eq!(m.expand(&state, &answers(&[("depth", "10")])).unwrap(), ["G38.2 Z-10 F100"]);
+
+    assert!(Macro::new("bad", "G0 X{{nope}}").validate().is_err());
+    assert!(Macro::new(" ", "G0 X0").validate().is_err());
+    m.params.push(param("x", None));
+    assert!(m.validate().is_err());
+}
+
+#[test]
+fn test_crud_and_import_export() {
+    // Isolated data dir; the only test in this file that touches storage
+    let td = tempfile::tempdir().expect("tempdir");
+    std::env::set_var("XDG_DATA_HOME", td.path());
+
+    let park = Macro::new("park", "G53 G0 Z0\nG53 G0 X0 Y0");
+    let mut probe = Macro::new("probe", "G38.2 Z-{{depth}} F100");
+    probe.params.push(param("depth", Some("10")));
+    save_macro(park.clone()).unwrap();
+    save_macro(probe.clone()).unwrap();
+    assert!(save_macro(Macro::new("broken", "G0 X{{missing}}")).is_err());
+    assert_eq!(load_macros().unwrap(), vec![park.clone(), probe.clone()]);
+
+    let file = td.path().join("macros-export.json");
+    assert_eq!(export_macros(&file).unwrap(), 2);
+    assert!(delete_macro("park").unwrap());
+    assert!(!delete_macro("park").unwrap());
+    save_macro(Macro::new("probe", "G38.2 Z-5 F50")).unwrap();
+
+    // Import replaces macros with the same name and adds the rest
+    assert_eq!(import_macros(&file).unwrap(), 2);
+    assert_eq!(load_macros().unwrap(), vec![probe, park]);
+    std::fs::write(&file, r#"[{"name": "ok", "gcode": "G0 X0"}, {"name": "", "gcode": "G0 X1"}]"#).unwrap();
+    assert!(import_macros(&file).is_err());
+    assert_eq!(load_macros().unwrap().len(), 2);
+}
+
+#[test]
+fn test_run_macro_streams_as_a_job() {
+    let dm = DeviceManager::new();
+    let session = open(&dm);
+    session.send_command("G0 X10 Y5", Duration::from_secs(1)).unwrap();
+    session.wait_for_report(Duration::from_secs(1), |s| s.mpos == [10.0, 5.0, 0.0]).unwrap();
+
+    let mut m = Macro::new("step", "G0 X{{to}} Y{{y}}\nG0 Z{{z}}");
+    m.params.push(param("to", None));
+    session.run_macro(&m, &answers(&[("to", "30")])).unwrap();
+    let done = session.wait_for_stream(Duration::from_secs(2)).unwrap();
+    assert_eq!((done.status, done.total, done.acked), (StreamStatus::Completed, 2, 2));
+    let state = session.wait_for_report(Duration::from_secs(1), |_| true).unwrap();
+    assert_eq!(state.mpos, [30.0, 5.0, 0.0]);
+
+    // Error replies fail the macro like a job
+    session.run_macro(&Macro::new("change", "G0 X1\nM6 T2\nG0 X2"), &BTreeMap::new()).unwrap();
+    let done = session.wait_for_stream(Duration::from_secs(2)).unwrap();
+    assert_eq!((done.status, done.error.as_deref()), (StreamStatus::Failed, Some("error:20")));
+    assert!(session.run_macro(&m, &BTreeMap::new()).is_err());
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use common::{wait_until, WAIT};
+use gcodekit_core::device_manager::DeviceManager;
+use gcodekit_core::protocol::Protocol;
+use gcodekit_core::session::{QueuedJob, SessionOptions, StreamStatus};
+use gcodekit_device_adapters::replay::{ReplayOptions, ReplayTransport};
+use gcodekit_device_adapters::sim::{SimFirmware, SimOptions};
+use gcodekit_device_adapters::Transport;
+use std::io;
+use std::time::Duration;
+
+fn sim(latency_ms: u64) -> Box<dyn Transport> {
+    gcodekit_device_adapters::create_sim_transport(SimOptions {
+        firmware: SimFirmware::Grbl,
+        latency: Duration::from_millis(latency_ms),
+        ..Default::default()
+    })
+    .unwrap()
+}
+
+fn job(n: usize) -> QueuedJob {
+    QueuedJob::new("job.nc", (0..n).map(|i| format!("G1 X{}", i)).collect())
+}
+
+/// Transport whose reads panic, standing in for a misbehaving driver.
+struct PanickingTransport;
+
+impl Transport for PanickingTransport {
+    fn send_line(&mut self, _line: &str) -> io::Result<()> {
+        Ok(())
+    }
+    fn emergency_stop(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+    fn disconnect(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+    fn is_alive(&self) -> io::Result<bool> {
+        Ok(true)
+    }
+    fn read_line(&mut self) -> io::Result<String> {
+        panic!("driver bug");
+    }
+}
+
+#[test]
+fn test_session_queue_runs_jobs_in_order() {
+    let dm = DeviceManager::new();
+    let s = dm.open_session("router", "sim:grbl".parse().unwrap(), sim(0), Protocol::Grbl, SessionOptions::default());
+    s.pause().unwrap();
+    let first = s.enqueue(job(3)).unwrap();
+    let second = s.enqueue(job(2)).unwrap();
+    assert!(wait_until(|| s.progress().job_id.as_deref() == Some(first.as_str())));
+    assert_eq!(s.queued_jobs().iter().map(|j| j.id.clone()).collect::<Vec<_>>(), vec![second.clone()]);
+
+    s.resume().unwrap();
+    assert!(wait_until(|| {
+        let p = s.progress();
+        p.job_id.as_deref() == Some(second.as_str()) && p.status == StreamStatus::Completed
+    }));
+    assert!(s.queued_jobs().is_empty());
+}
+
+#[test]
+fn test_failed_job_halts_queue_until_run_queue() {
+    let dm = DeviceManager::new();
+    let replay = ReplayTransport::from_session("> BAD\n< error:2\n> G1 X0\n< ok\n", ReplayOptions::default());
+    let s = dm.open_session("laser", "sim:grbl".parse().unwrap(), Box::new(replay), Protocol::Grbl, SessionOptions::default());
+    s.enqueue(QueuedJob::new("bad.nc", vec!["BAD".into()])).unwrap();
+    let next = s.enqueue(job(1)).unwrap();
+    assert!(wait_until(|| s.progress().status == StreamStatus::Failed));
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(s.queued_jobs().len(), 1, "queue halts after a failure");
+
+    s.run_queue().unwrap();
+    assert!(wait_until(|| {
+        let p = s.progress();
+        p.job_id.as_deref() == Some(next.as_str()) && p.status == StreamStatus::Completed
+    }));
+}
+
+#[test]
+fn test_machines_are_isolated() {
+    let dm = DeviceManager::new();
+    let a = dm.open_session("a", "sim:grbl".parse().unwrap(), sim(1), Protocol::Grbl, SessionOptions::default());
+    let b = dm.open_session("b", "sim:grbl".parse().unwrap(), sim(1), Protocol::Grbl, SessionOptions::default());
+    let c = dm.open_session("c", "sim:grbl".parse().unwrap(), Box::new(PanickingTransport), Protocol::Grbl, SessionOptions::default());
+
+    a.enqueue(job(200)).unwrap();
+    b.enqueue(job(40)).unwrap();
+
+    // A crashed driver only takes its own session down
+    assert!(wait_until(|| !c.is_connected()));
+    assert!(a.is_connected() && b.is_connected());
+
+    // E-stop on one machine does not stop the other
+    a.emergency_stop().unwrap();
+    assert!(wait_until(|| a.progress().status == StreamStatus::Cancelled));
+    // GRBL is soft reset rather than held, so it answers with its banner
+    assert!(wait_until(|| a.state().firmware.is_some()));
+    let done = b.wait_for_stream(WAIT).expect("b finishes");
+    assert_eq!(done.status, StreamStatus::Completed);
+    assert_eq!(done.acked, 40);
+
+    let fleet = dm.fleet_status();
+    assert_eq!(fleet.sessions.len(), 3);
+    assert_eq!(fleet.connected, 2);
+    assert_eq!(fleet.streaming, 0);
+    let c_info = fleet.sessions.iter().find(|s| s.id == "c").unwrap();
+    assert!(!c_info.connected);
+
+    // The manager-wide stop reports the machine it could not reach
+    let failed = dm.emergency_stop_all();
+    assert_eq!(failed.iter().map(|(id, _)| id.as_str()).collect::<Vec<_>>(), vec!["c"]);
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use gcodekit_core::device_manager::DeviceManager;
+use gcodekit_core::machine_state::{Overrides, StatusReport};
+use gcodekit_core::overrides::Override;
+use gcodekit_core::session::{Session, SessionEvent, StreamStatus};
+use gcodekit_device_adapters::sim::{SimFirmware, SimOptions};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn open(dm: &DeviceManager, firmware: SimFirmware) -> Arc<Session> {
+    let sim = SimOptions {
+        firmware,
+        latency: Duration::from_millis(2),
+        ..Default::default()
+    };
+    common::open(dm, sim)
+}
+
+fn ov(feed: u32, rapid: u32, spindle: u32) -> Overrides {
+    Overrides { feed, rapid, spindle }
+}
+
+#[test]
+fn test_steps_map_to_realtime_bytes_and_clamp() {
+    assert_eq!(Override::FeedReset.byte(), 0x90);
+    assert_eq!(Override::Rapid25.byte(), 0x97);
+    assert_eq!(Override::SpindleStop.byte(), 0x9E);
+    assert_eq!(Override::MistToggle.byte(), 0xA1);
+
+    assert_eq!(Override::FeedPlus10.apply(ov(195, 100, 100)), ov(200, 100, 100));
+    assert_eq!(Override::SpindleMinus10.apply(ov(100, 100, 15)), ov(100, 100, 10));
+    assert_eq!(Override::Rapid50.apply(ov(120, 100, 90)), ov(120, 50, 90));
+    assert_eq!(Override::FloodToggle.apply(ov(120, 100, 90)), ov(120, 100, 90));
+
+    let report = StatusReport::parse("<Run|MPos:0.000,0.000,0.000|FS:500,8000|Ov:120,50,90>").unwrap();
+    assert_eq!(report.overrides, Some(ov(120, 50, 90)));
+}
+
+#[test]
+fn test_grbl_overrides_apply_mid_job_and_are_reported() {
+    let dm = DeviceManager::new();
+    let session = open(&dm, SimFirmware::Grbl);
+    let job: Vec<String> = (0..200).map(|i| format!("G1 X{} F500", i)).collect();
+    session.stream(&job).unwrap();
+    for step in [Override::FeedPlus10, Override::FeedPlus1, Override::Rapid25, Override::SpindleMinus10] {
+        session.apply_override(step).unwrap();
+    }
+    let state = session
+        .wait_for_report(Duration::from_secs(1), |s| s.overrides != Overrides::default())
+        .unwrap();
+    assert_eq!(state.overrides, ov(111, 25, 90));
+    assert_eq!(session.progress().status, StreamStatus::Running);
+
+    session.apply_override(Override::FeedReset).unwrap();
+    session.apply_override(Override::FloodToggle).unwrap();
+    let state = session.wait_for_report(Duration::from_secs(1), |s| s.overrides.feed == 100).unwrap();
+    assert_eq!(state.overrides, ov(100, 25, 90));
+    assert_eq!(session.wait_for_stream(Duration::from_secs(5)).unwrap().status, StreamStatus::Completed);
+}
+
+#[test]
+fn test_marlin_overrides_use_m220_and_m221() {
+    let dm = DeviceManager::new();
+    let session = open(&dm, SimFirmware::Marlin);
+    let mut events = dm.subscribe();
+    session.apply_override(Override::FeedMinus10).unwrap();
+    session.apply_override(Override::SpindlePlus1).unwrap();
+    assert_eq!(session.state().overrides, ov(90, 100, 101));
+
+    let err = session.apply_override(Override::Rapid50).unwrap_err();
+    assert!(err.to_string().contains("not supported"), "{}", err);
+    let mut sent_ok = 0;
+    while let Ok(event) = events.try_recv() {
+        if let SessionEvent::Received { line, .. } = event {
+            sent_ok += (line == "ok") as usize;
+        }
+    }
+    assert_eq!(sent_ok, 2);
+}
@@ -17,6 +17,9 @@ fn test_save_and_load_job_history() {
         progress: 1.0,
         status: gcodekit_core::models::JobStatus::Completed,
         created_at: Utc::now(),
+        analysis: None,
+        estimated_seconds: None,
+        remaining_seconds: None,
     };
     let j2 = Job {
         id: "j2".into(),
@@ -26,6 +29,9 @@ fn test_save_and_load_job_history() {
         progress: 0.25,
         status: gcodekit_core::models::JobStatus::Running,
         created_at: Utc::now(),
+        analysis: None,
+        estimated_seconds: None,
+        remaining_seconds: None,
     };
 
     let jobs = vec![j1.clone(), j2.clone()];
@@ -0,0 +1,133 @@
+mod common;
+
+use gcodekit_core::device_manager::DeviceManager;
+use gcodekit_core::machine_state::{Axis, Direction, MachineStatus};
+use gcodekit_core::probe::{Corner, ProbeCycle, ProbeOptions, Prober};
+use gcodekit_core::protocol::parse_probe;
+use gcodekit_core::session::Session;
+use gcodekit_device_adapters::sim::SimOptions;
+use std::sync::Arc;
+use std::time::Duration;
+
+type Solid = ([f64; 3], [f64; 3]);
+
+/// Stock at X0-100 Y0-50 with its top at Z0 and a 5 mm plate on it.
+const STOCK: [Solid; 2] = [([0.0, 0.0, -20.0], [100.0, 50.0, 0.0]), ([40.0, 20.0, 0.0], [60.0, 30.0, 5.0])];
+
+/// A 20 mm square bore centred on X100 Y100.
+const BORE: [Solid; 4] = [
+    ([70.0, 70.0, -10.0], [90.0, 130.0, 0.0]),
+    ([110.0, 70.0, -10.0], [130.0, 130.0, 0.0]),
+    ([90.0, 70.0, -10.0], [110.0, 90.0, 0.0]),
+    ([90.0, 110.0, -10.0], [110.0, 130.0, 0.0]),
+];
+
+/// A 20 mm square boss centred on X150 Y150.
+const BOSS: Solid = ([140.0, 140.0, -10.0], [160.0, 160.0, 0.0]);
+
+fn open(dm: &DeviceManager, solids: &[Solid], start: [f64; 3]) -> Arc<Session> {
+    let session = common::open(dm, SimOptions { solids: solids.to_vec(), ..Default::default() });
+    let [x, y, z] = start;
+    session
+        .send_command(&format!("G53 G0 X{} Y{} Z{}", x, y, z), Duration::from_secs(1))
+        .unwrap();
+    session
+}
+
+#[test]
+fn test_probe_reports_are_parsed() {
+    let result = parse_probe("[PRB:1.000,-2.500,3.000:1]").unwrap();
+    assert_eq!((result.position, result.success), ([1.0, -2.5, 3.0], true));
+    assert!(!parse_probe("[PRB:0.000,0.000,0.000,0.000:0]").unwrap().success);
+    assert!(parse_probe("[GC:G0 G54]").is_none());
+}
+
+#[test]
+fn test_z_surface_and_edges() {
+    let dm = DeviceManager::new();
+    let session = open(&dm, &STOCK, [50.0, 25.0, 15.0]);
+    let options = ProbeOptions {
+        plate_thickness: 5.0,
+        tip_diameter: 2.0,
+        ..Default::default()
+    };
+    let prober = Prober::new(session.clone(), options);
+    assert_eq!(prober.z_surface().unwrap(), 0.0);
+    // Retracted off the plate afterwards
+    assert_eq!(session.wait_for_report(Duration::from_secs(1), |_| true).unwrap().mpos[2], 7.0);
+
+    session.send_command("G53 G0 X-5 Y25 Z-5", Duration::from_secs(1)).unwrap();
+    assert_eq!(prober.edge(Axis::X, Direction::Positive).unwrap(), 1.0);
+    assert!(prober.edge(Axis::Z, Direction::Negative).is_err());
+
+    // A controller left in inches is probed in mm and put back in inches
+    session.send_command("G20", Duration::from_secs(1)).unwrap();
+    session.send_command("G53 G0 X2 Y1 Z0.5", Duration::from_secs(1)).unwrap();
+    assert_eq!(prober.z_surface().unwrap(), 0.0);
+    assert_eq!(session.wait_for_report(Duration::from_secs(1), |_| true).unwrap().mpos[2], 7.0);
+    session.send_command("$G", Duration::from_secs(1)).unwrap();
+    assert!(session.state().modes.iter().any(|m| m == "G20"));
+}
+
+#[test]
+fn test_corners() {
+    let dm = DeviceManager::new();
+    let session = open(&dm, &STOCK, [-5.0, -5.0, -5.0]);
+    let prober = Prober::new(session.clone(), ProbeOptions::default());
+    assert_eq!(prober.outside_corner(Corner::FrontLeft).unwrap(), [0.0, 0.0]);
+
+    let session = open(&dm, &BORE, [105.0, 105.0, -5.0]);
+    let prober = Prober::new(session, ProbeOptions::default());
+    assert_eq!(prober.inside_corner(Corner::BackRight).unwrap(), [110.0, 110.0]);
+}
+
+#[test]
+fn test_bore_and_boss_centres() {
+    let dm = DeviceManager::new();
+    let session = open(&dm, &BORE, [97.0, 104.0, -5.0]);
+    let prober = Prober::new(session.clone(), ProbeOptions::default());
+    let bore = prober.bore_center().unwrap();
+    assert_eq!((bore.center, bore.diameter), ([100.0, 100.0], 20.0));
+    let mpos = session.wait_for_report(Duration::from_secs(1), |_| true).unwrap().mpos;
+    assert_eq!(mpos, [100.0, 100.0, -5.0]);
+
+    let session = open(&dm, &[BOSS], [152.0, 148.0, 2.0]);
+    let prober = Prober::new(session.clone(), ProbeOptions::default());
+    let boss = prober.boss_center(20.0).unwrap();
+    assert_eq!((boss.center, boss.diameter), ([150.0, 150.0], 20.0));
+
+    // A boss wider than stated is landed on, not crashed into
+    session.send_command("G53 G0 X150 Y150 Z2", Duration::from_secs(1)).unwrap();
+    let options = ProbeOptions {
+        clearance: 2.0,
+        ..Default::default()
+    };
+    let err = Prober::new(session, options).boss_center(4.0).unwrap_err();
+    assert!(err.to_string().contains("touched down"), "{}", err);
+}
+
+#[test]
+fn test_missing_contact_alarms_and_blocks_until_unlocked() {
+    let dm = DeviceManager::new();
+    let session = open(&dm, &STOCK, [200.0, 200.0, 0.0]);
+    let prober = Prober::new(session.clone(), ProbeOptions::default());
+
+    let missed = prober.probe(ProbeCycle::TryTouch, [0.0, 0.0, -5.0], 100.0).unwrap();
+    assert!(!missed.success);
+    assert_eq!(missed.position, [200.0, 200.0, -5.0]);
+
+    let err = prober.z_surface().unwrap_err();
+    assert!(err.to_string().contains("ALARM:5"), "{}", err);
+    assert_eq!(session.state().status, MachineStatus::Alarm);
+    let err = prober.z_surface().unwrap_err();
+    assert!(err.to_string().contains("clear the alarm"), "{}", err);
+
+    // Starting in contact is refused by the controller; G38.4 backs off instead
+    session.unlock().unwrap();
+    session.send_command("G53 G0 X50 Y25 Z5", Duration::from_secs(1)).unwrap();
+    let err = prober.probe(ProbeCycle::Touch, [0.0, 0.0, -1.0], 100.0).unwrap_err();
+    assert!(err.to_string().contains("ALARM:4"), "{}", err);
+    session.unlock().unwrap();
+    let released = prober.probe(ProbeCycle::Release, [0.0, 30.0, 0.0], 100.0).unwrap();
+    assert_eq!((released.position, released.success), ([50.0, 30.0, 5.0], true));
+}
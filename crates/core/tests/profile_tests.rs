@@ -0,0 +1,92 @@
+use gcodekit_core::device_manager::DeviceManager;
+use gcodekit_core::endpoint::Endpoint;
+use gcodekit_core::profile::{
+    FirmwareKind, MachineProfile, ProfileMacro, StreamingMode, ToolHead, WorkEnvelope,
+};
+
+fn sim_profile(name: &str) -> MachineProfile {
+    MachineProfile::new(name, "sim:grbl".parse::<Endpoint>().unwrap())
+}
+
+#[test]
+fn test_profile_crud_and_merge_on_save() {
+    // Isolated data dir; the only test in this file that touches storage
+    let td = tempfile::tempdir().expect("tempdir");
+    std::env::set_var("XDG_DATA_HOME", td.path());
+
+    let mut router = sim_profile("Shop Router 1");
+    assert_eq!(router.id, "shop-router-1");
+    router.firmware = Some(FirmwareKind::FluidNc);
+    router.tool_head = ToolHead::Spindle { max_rpm: 24000 };
+    router.macros.push(ProfileMacro {
+        name: "Park".into(),
+        gcode: "G53 G0 Z-1".into(),
+    });
+    let laser = sim_profile("Diode Laser");
+
+    DeviceManager::create_profile(router.clone()).expect("create router");
+    DeviceManager::create_profile(laser.clone()).expect("create laser");
+    assert!(DeviceManager::create_profile(laser.clone()).is_err(), "duplicate id must be rejected");
+    assert_eq!(DeviceManager::list_profiles().unwrap().len(), 2);
+
+    // Updating one profile keeps the other
+    let mut updated = laser.clone();
+    updated.streaming_mode = StreamingMode::SendResponse;
+    updated.tool_head = ToolHead::Laser { max_power: 1000 };
+    DeviceManager::update_profile(updated.clone()).expect("update laser");
+    assert_eq!(DeviceManager::get_profile("diode-laser").unwrap(), Some(updated));
+    assert_eq!(DeviceManager::get_profile("shop-router-1").unwrap(), Some(router.clone()));
+    assert!(DeviceManager::update_profile(sim_profile("Missing")).is_err());
+
+    // Connecting records the device without clobbering other devices
+    let mut t = DeviceManager::connect_profile("shop-router-1").expect("connect profile");
+    t.send_line("$I").unwrap();
+    let other = gcodekit_core::models::Device::from_endpoint(&"tcp://10.0.0.9:23".parse().unwrap(), "Other");
+    gcodekit_core::device::upsert_device(other).unwrap();
+    let devices = gcodekit_core::device::load_devices().unwrap();
+    assert_eq!(devices.len(), 2);
+    let dev = devices.iter().find(|d| d.name == "Shop Router 1").expect("profile device");
+    assert_eq!(dev.firmware.as_deref(), Some("FluidNc"));
+
+    assert!(DeviceManager::delete_profile("diode-laser").unwrap());
+    assert!(!DeviceManager::delete_profile("diode-laser").unwrap());
+    assert_eq!(DeviceManager::list_profiles().unwrap(), vec![router]);
+}
+
+#[test]
+fn test_profile_validation() {
+    let mut p = sim_profile("Test");
+    assert!(p.validate().is_ok());
+    p.buffer_size = 0;
+    assert!(p.validate().is_err());
+
+    let mut p = sim_profile("Test");
+    p.envelope = Some(WorkEnvelope {
+        min: [0.0, 0.0, -50.0],
+        max: [300.0, 0.0, 0.0],
+    });
+    assert!(p.validate().is_err());
+
+    let mut p = sim_profile("Test");
+    p.axis_limits.z.acceleration = 0.0;
+    assert!(p.validate().is_err());
+
+    assert!(sim_profile("  ").validate().is_err());
+}
+
+#[test]
+fn test_profile_json_defaults() {
+    let p: MachineProfile =
+        serde_json::from_str(r#"{"id":"m","name":"M","endpoint":"serial:///dev/ttyUSB0"}"#).unwrap();
+    assert_eq!(p.buffer_size, 128);
+    assert_eq!(p.streaming_mode, StreamingMode::CharacterCounting);
+    assert_eq!(p.tool_head, ToolHead::None);
+    assert!(p.firmware.is_none() && p.envelope.is_none());
+
+    let env = WorkEnvelope {
+        min: [-300.0, -300.0, -80.0],
+        max: [0.0, 0.0, 0.0],
+    };
+    assert!(env.contains([-10.0, -20.0, -5.0]));
+    assert!(!env.contains([1.0, -20.0, -5.0]));
+}
@@ -0,0 +1,117 @@
+use gcodekit_core::device_manager::DeviceManager;
+use gcodekit_core::gcode::preprocess::{self, Preprocessed};
+use gcodekit_core::gcode::{parse_lines, GcodeFile, GcodeReader, LineSource};
+use gcodekit_core::protocol::Protocol;
+use gcodekit_core::session::{QueuedJob, SessionOptions, StreamStatus};
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::Duration;
+
+const PROGRAM: &str = "; header\r\nG21 G90\r\n\r\nN10 g0 x1 (rapid)\nG1 Y2 F100 ; feed\n   \n(only a comment)\nM5";
+
+fn all(source: &dyn LineSource) -> Vec<String> {
+    (0..source.len()).filter_map(|i| source.line(i)).map(|l| l.into_owned()).collect()
+}
+
+#[test]
+fn test_reader_and_file_match_parse_lines() {
+    let expected = parse_lines(PROGRAM);
+    assert_eq!(expected, ["G21 G90", "N10 g0 x1", "G1 Y2 F100", "M5"]);
+
+    let lazy: Vec<String> = GcodeReader::new(Cursor::new(PROGRAM)).map(Result::unwrap).collect();
+    assert_eq!(lazy, expected);
+
+    let td = tempfile::tempdir().unwrap();
+    let path = td.path().join("job.nc");
+    std::fs::write(&path, PROGRAM).unwrap();
+    let file = GcodeFile::open(&path).unwrap();
+    assert_eq!(all(&file), expected);
+    assert_eq!(file.line(3).as_deref(), Some("M5"));
+    assert!(file.line(4).is_none());
+    assert_eq!(file.iter_from(2).collect::<Vec<_>>(), ["G1 Y2 F100", "M5"]);
+
+    // Program lines map back to file lines, skipping comments and blanks
+    assert_eq!((file.file_line(0), file.file_line(3)), (Some(2), Some(8)));
+    assert_eq!(file.index_of_file_line(3), Some(1));
+    assert_eq!(file.index_of_file_line(6), Some(3));
+    assert_eq!(file.index_of_file_line(9), None);
+
+    let empty = td.path().join("empty.nc");
+    std::fs::write(&empty, "").unwrap();
+    assert!(GcodeFile::open(&empty).unwrap().is_empty());
+}
+
+#[test]
+fn test_non_utf8_bytes_are_replaced() {
+    let bytes = b"G0 X1 ; caf\xe9\nG1 \xff Y2\n".to_vec();
+    let lines: Vec<String> = GcodeReader::new(Cursor::new(bytes)).map(Result::unwrap).collect();
+    assert_eq!(lines, ["G0 X1", "G1 \u{fffd} Y2"]);
+}
+
+#[test]
+fn test_preprocessed_source_keeps_indices_stable() {
+    let source: Arc<dyn LineSource> = Arc::new(vec!["N5".to_string(), "n10 g0 x1".into(), "G1 Y2*71".into()]);
+    let names = vec!["strip_line_numbers".to_string(), "strip_checksums".into(), "uppercase".into()];
+    let pre = Preprocessed::new(source.clone(), &names).unwrap();
+    assert_eq!(all(&pre), ["G0 X1", "G1 Y2"]);
+    let eager = preprocess::apply(&names, all(source.as_ref())).unwrap();
+    assert_eq!(all(&pre), eager);
+    assert!(Preprocessed::new(source, &["nope".to_string()]).is_err());
+}
+
+#[test]
+fn test_session_streams_from_mapped_file() {
+    let td = tempfile::tempdir().unwrap();
+    let path = td.path().join("raster.nc");
+    let body: String = (0..2000).map(|i| format!("G1 X{:.1} ; px {}\n", i as f64 / 10.0, i)).collect();
+    std::fs::write(&path, body).unwrap();
+
+    let dm = DeviceManager::new();
+    let transport = gcodekit_device_adapters::create_sim_transport(Default::default()).unwrap();
+    let session = dm.open_session("laser", "sim:grbl".parse().unwrap(), transport, Protocol::Grbl, SessionOptions::default());
+    let job = QueuedJob::from_file(&path).unwrap();
+    assert_eq!(job.job.lines_total, 2000);
+    session.enqueue(job).unwrap();
+    let deadline = std::time::Instant::now() + Duration::from_secs(10);
+    while session.progress().status != StreamStatus::Completed && std::time::Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let done = session.progress();
+    assert_eq!(done.status, StreamStatus::Completed);
+    assert_eq!(done.acked, 2000);
+}
+
+#[test]
+fn test_random_access_across_checkpoints() {
+    let td = tempfile::tempdir().unwrap();
+    let path = td.path().join("long.nc");
+    // Every third file line is a comment, so program and file lines diverge
+    let body: String = (0..3000)
+        .map(|i| match i % 3 {
+            0 => format!("; note {}\n", i),
+            1 => format!("N{} G1 X{}\n", i, i),
+            _ => format!("N{}\n", i),
+        })
+        .collect();
+    std::fs::write(&path, body).unwrap();
+    let file = GcodeFile::open(&path).unwrap();
+    assert_eq!(file.len(), 2000);
+    for index in [1999, 0, 700, 256, 255, 1000, 1001, 513] {
+        let n = index / 2 * 3 + index % 2 + 1;
+        let expected = if index % 2 == 0 { format!("N{} G1 X{}", n, n) } else { format!("N{}", n) };
+        assert_eq!(file.line(index), Some(expected.into()));
+        assert_eq!(file.file_line(index), Some(n + 1));
+        assert_eq!(file.index_of_file_line(n + 1), Some(index));
+    }
+    assert_eq!(file.index_of_file_line(1), Some(0));
+    assert_eq!(file.index_of_file_line(2998), Some(1998));
+    assert_eq!(file.index_of_file_line(3001), None);
+
+    // Stripping line numbers empties every other program line
+    let source: Arc<dyn LineSource> = Arc::new(file);
+    let pre = Preprocessed::new(source.clone(), &["strip_line_numbers".to_string()]).unwrap();
+    let eager = preprocess::apply(&["strip_line_numbers".to_string()], all(source.as_ref())).unwrap();
+    assert_eq!(eager.len(), 1000);
+    assert_eq!(pre.line(900).as_deref(), Some(eager[900].as_str()));
+    assert_eq!(all(&pre), eager);
+}
@@ -0,0 +1,104 @@
+use gcodekit_core::device_manager::DeviceManager;
+use gcodekit_core::gcode::resume::{self, ResumeOptions};
+use gcodekit_core::gcode::LineSource;
+use gcodekit_core::models::JobStatus;
+use gcodekit_core::protocol::Protocol;
+use gcodekit_core::scheduler::{JobScheduler, SchedulerOptions};
+use gcodekit_core::session::SessionOptions;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+fn lines(text: &str) -> Vec<String> {
+    gcodekit_core::gcode::parse_lines(text)
+}
+
+const PROGRAM: &str = "\
+G21 G90 G17
+G55 T3
+M3 S12000
+M8
+G0 X10 Y10
+G1 Z-1 F200
+G1 X20 F600
+G2 X30 Y20 I5 J5
+X40 Y30 I5 J5
+G0 Z5
+";
+
+#[test]
+fn test_plan_restores_modal_state_and_position() {
+    let program = lines(PROGRAM);
+    let plan = resume::plan(&program, 8, &ResumeOptions::default()).unwrap();
+    assert_eq!(plan.position, [Some(30.0), Some(20.0), Some(-1.0)]);
+    assert_eq!(plan.modal.motion, "G2");
+    assert_eq!(
+        plan.preamble,
+        vec![
+            "G21 G17 G94 G55",
+            "T3",
+            "G90",
+            "G0 Z5",
+            "G0 X30 Y20",
+            "M3 S12000",
+            "G4 P3",
+            "M8",
+            "G1 Z-1 F600",
+            "F600",
+        ]
+    );
+    // The arc continuation line needs its motion mode back after the plunge
+    let full = plan.program(Arc::new(program));
+    let rest: Vec<String> = (full.preamble_len()..full.len()).filter_map(|i| full.line(i)).map(|l| l.into_owned()).collect();
+    assert_eq!(rest, ["G2 X40 Y30 I5 J5", "G0 Z5"]);
+}
+
+#[test]
+fn test_plan_tracks_relative_moves_and_validates() {
+    let program = lines("G0 X1 Y1\nG91\nG1 X2 Y-1 F100\nG1 X1\nG90 G0 Z3");
+    let plan = resume::plan(&program, 4, &ResumeOptions { plunge_feed: Some(50.0), spindle_dwell: 0.0, ..Default::default() }).unwrap();
+    assert_eq!(plan.position, [Some(4.0), Some(0.0), None]);
+    assert_eq!(plan.preamble.last().map(String::as_str), Some("G91"));
+    assert!(!plan.preamble.iter().any(|l| l.starts_with("G1 Z")), "no plunge before Z is known");
+
+    assert!(resume::plan(&program, 5, &ResumeOptions::default()).is_err());
+    let no_feed = lines("G0 Z-1\nG0 X5");
+    assert!(resume::plan(&no_feed, 1, &ResumeOptions::default()).is_err());
+}
+
+#[test]
+fn test_scheduler_starts_job_from_line() {
+    let td = tempfile::tempdir().unwrap();
+    let path = td.path().join("job.nc");
+    std::fs::write(&path, PROGRAM).unwrap();
+    let dm = DeviceManager::new();
+    let transport = gcodekit_device_adapters::create_sim_transport(Default::default()).unwrap();
+    let session = dm.open_session("mill", "sim:grbl".parse().unwrap(), transport, Protocol::Grbl, SessionOptions::default());
+    let sched = JobScheduler::start(
+        session.clone(),
+        SchedulerOptions {
+            record_history: false,
+            journal: None,
+            ..Default::default()
+        },
+    );
+
+    let id = sched.submit_from(&path, 6, ResumeOptions::default());
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while sched.finished().is_empty() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    let job = &sched.finished()[0];
+    assert_eq!(job.id, id);
+    assert!(matches!(job.status, JobStatus::Completed));
+    assert_eq!((job.lines_sent, job.lines_total), (10, 10));
+    while session.state().mpos != [40.0, 30.0, 5.0] && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(session.state().mpos, [40.0, 30.0, 5.0]);
+
+    sched.submit_from(&path, 10, ResumeOptions::default());
+    while sched.finished().len() < 2 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert!(matches!(sched.finished()[1].status, JobStatus::Failed(_)), "start past the end fails the job");
+}
@@ -0,0 +1,215 @@
+mod common;
+
+use common::{wait_until, WAIT};
+use gcodekit_core::device_manager::DeviceManager;
+use gcodekit_core::models::JobStatus;
+use gcodekit_core::protocol::Protocol;
+use gcodekit_core::scheduler::{JobScheduler, SchedulerOptions, SchedulerStatus};
+use gcodekit_core::session::{QueuedJob, Session, SessionEvent, SessionOptions, StreamStatus};
+use gcodekit_device_adapters::sim::{SimFirmware, SimOptions};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn session(dm: &DeviceManager, id: &str) -> Arc<Session> {
+    let transport = gcodekit_device_adapters::create_sim_transport(SimOptions {
+        firmware: SimFirmware::Grbl,
+        latency: Duration::from_millis(1),
+        ..Default::default()
+    })
+    .unwrap();
+    dm.open_session(id, "sim:grbl".parse().unwrap(), transport, Protocol::Grbl, SessionOptions::default())
+}
+
+fn write_job(dir: &tempfile::TempDir, name: &str, body: &str) -> PathBuf {
+    let path = dir.path().join(name);
+    std::fs::write(&path, body).unwrap();
+    path
+}
+
+fn options() -> SchedulerOptions {
+    SchedulerOptions {
+        record_history: false,
+        journal: None,
+        ..Default::default()
+    }
+}
+
+fn finished_ids(s: &JobScheduler) -> Vec<String> {
+    s.finished().into_iter().map(|j| j.id).collect()
+}
+
+#[test]
+fn test_scheduler_runs_preprocessed_jobs_and_records_history() {
+    let td = tempfile::tempdir().unwrap();
+    std::env::set_var("XDG_DATA_HOME", td.path());
+    let dm = DeviceManager::new();
+    let mut events = dm.subscribe();
+    let opts = SchedulerOptions {
+        preprocessors: vec!["strip_line_numbers".into(), "uppercase".into()],
+        ..Default::default()
+    };
+    let sched = JobScheduler::start(session(&dm, "router"), opts);
+
+    let path = write_job(&td, "a.nc", "N10 g21 g90\n; comment\nN20 g0 x1\nN30 g1 y2 f100\n");
+    let id = sched.submit(&path);
+    assert!(wait_until(|| sched.finished().len() == 1));
+    let job = &sched.finished()[0];
+    assert_eq!(job.id, id);
+    assert!(matches!(job.status, JobStatus::Completed));
+    assert_eq!((job.lines_total, job.lines_sent), (3, 3));
+    assert_eq!(job.progress, 1.0);
+    assert!(wait_until(|| sched.status() == SchedulerStatus::Idle));
+
+    let history = gcodekit_core::persistence::load_job_history().unwrap();
+    assert!(history.iter().any(|j| j.id == id && matches!(j.status, JobStatus::Completed)));
+
+    let mut statuses = Vec::new();
+    while let Ok(ev) = events.try_recv() {
+        if let SessionEvent::Job { job, .. } = ev {
+            statuses.push(job.status);
+        }
+    }
+    assert!(matches!(statuses.first(), Some(JobStatus::Queued)));
+    assert!(statuses.iter().any(|s| matches!(s, JobStatus::Running)));
+    assert!(matches!(statuses.last(), Some(JobStatus::Completed)));
+}
+
+#[test]
+fn test_reorder_hold_and_cancel_queued_jobs() {
+    let td = tempfile::tempdir().unwrap();
+    let dm = DeviceManager::new();
+    let s = session(&dm, "laser");
+    // Hold the controller so jobs stay queued while the test rearranges them
+    s.pause().unwrap();
+    let sched = JobScheduler::start(s.clone(), options());
+
+    let first = sched.submit(write_job(&td, "1.nc", "G0 X1\n"));
+    assert!(wait_until(|| sched.status() == SchedulerStatus::Running(first.clone())));
+    let second = sched.submit(write_job(&td, "2.nc", "G0 X2\n"));
+    let third = sched.submit(write_job(&td, "3.nc", "G0 X3\n"));
+    let fourth = sched.submit(write_job(&td, "4.nc", "G0 X4\n"));
+
+    assert!(sched.move_job(&fourth, 0));
+    assert!(sched.hold(&second));
+    assert!(sched.cancel(&third).unwrap());
+    assert!(!sched.cancel("missing").unwrap());
+    let queued: Vec<String> = sched.queued().into_iter().map(|j| j.id).collect();
+    assert_eq!(queued, vec![fourth.clone(), second.clone()]);
+
+    s.resume().unwrap();
+    assert!(wait_until(|| finished_ids(&sched).len() == 3));
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(finished_ids(&sched), vec![third.clone(), first.clone(), fourth.clone()]);
+    assert_eq!(sched.queued().len(), 1, "held job does not start");
+
+    assert!(sched.release(&second));
+    assert!(wait_until(|| finished_ids(&sched).len() == 4));
+    assert!(sched.finished().iter().all(|j| j.id == third || matches!(j.status, JobStatus::Completed)));
+    sched.shutdown();
+    assert_eq!(sched.status(), SchedulerStatus::Stopped);
+}
+
+#[test]
+fn test_scheduler_runs_jobs_queued_on_the_session() {
+    let td = tempfile::tempdir().unwrap();
+    let dm = DeviceManager::new();
+    let s = session(&dm, "plasma");
+    s.pause().unwrap();
+    let sched = JobScheduler::start(s.clone(), options());
+
+    let a = sched.submit(write_job(&td, "a.nc", "G0 X1\n"));
+    assert!(wait_until(|| sched.status() == SchedulerStatus::Running(a.clone())));
+    // Both enqueue paths feed the same queue
+    let b = s.enqueue(QueuedJob::new("b.nc", vec!["G0 X2".into()])).unwrap();
+    let c = sched.submit(write_job(&td, "c.nc", "G0 X3\n"));
+    assert_eq!(s.queued_jobs().into_iter().map(|j| j.id).collect::<Vec<_>>(), vec![b.clone(), c.clone()]);
+
+    s.resume().unwrap();
+    assert!(wait_until(|| finished_ids(&sched).len() == 3));
+    assert_eq!(finished_ids(&sched), vec![a, b, c]);
+    assert!(sched.finished().iter().all(|j| matches!(j.status, JobStatus::Completed)));
+
+    // After shutdown the session owns the queue again, halted until run_queue
+    sched.shutdown();
+    let d = s.enqueue(QueuedJob::new("d.nc", vec!["G0 X4".into()])).unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(s.queued_jobs().len(), 1);
+    s.run_queue().unwrap();
+    assert!(wait_until(|| {
+        let p = s.progress();
+        p.job_id.as_deref() == Some(d.as_str()) && p.status == StreamStatus::Completed
+    }));
+}
+
+#[test]
+fn test_failed_or_cancelled_job_halts_queue() {
+    let td = tempfile::tempdir().unwrap();
+    let dm = DeviceManager::new();
+    let s = session(&dm, "mill");
+    let sched = JobScheduler::start(s.clone(), options());
+
+    let missing = sched.submit(td.path().join("missing.nc"));
+    let next = sched.submit(write_job(&td, "ok.nc", "G0 X1\n"));
+    assert!(wait_until(|| matches!(sched.status(), SchedulerStatus::Halted(_))));
+    assert!(matches!(sched.finished()[0].status, JobStatus::Failed(_)));
+    assert_eq!(sched.finished()[0].id, missing);
+    assert_eq!(sched.queued().len(), 1);
+
+    sched.resume();
+    assert!(wait_until(|| finished_ids(&sched).contains(&next)));
+
+    // Cancelling the running job stops the stream and halts again
+    s.pause().unwrap();
+    let long = sched.submit(write_job(&td, "long.nc", "G0 X1\nG0 X2\nG0 X3\n"));
+    assert!(wait_until(|| sched.current().is_some_and(|j| j.id == long)));
+    assert!(sched.cancel(&long).unwrap());
+    assert!(wait_until(|| sched.status() == SchedulerStatus::Halted("cancelled".into())));
+    assert!(matches!(sched.finished().last().unwrap().status, JobStatus::Cancelled));
+}
+
+#[test]
+fn test_confirmation_between_jobs() {
+    let td = tempfile::tempdir().unwrap();
+    let dm = DeviceManager::new();
+    let mut events = dm.subscribe();
+    let opts = SchedulerOptions {
+        confirm_between_jobs: true,
+        ..options()
+    };
+    let sched = JobScheduler::start(session(&dm, "plasma"), opts);
+    assert!(!sched.confirm(), "nothing to confirm yet");
+
+    let first = sched.submit(write_job(&td, "1.nc", "G0 X1\n"));
+    let second = sched.submit(write_job(&td, "2.nc", "G0 X2\n"));
+    assert!(wait_until(|| sched.status() == SchedulerStatus::AwaitingConfirmation(second.clone())));
+    assert_eq!(finished_ids(&sched), vec![first]);
+    assert!(std::iter::from_fn(|| events.try_recv().ok())
+        .any(|e| matches!(e, SessionEvent::ConfirmationRequired { ref job_id, .. } if *job_id == second)));
+
+    assert!(sched.confirm());
+    assert!(wait_until(|| finished_ids(&sched).contains(&second)));
+}
+
+#[test]
+fn test_scheduler_levels_jobs_to_the_height_map() {
+    let td = tempfile::tempdir().unwrap();
+    let dm = DeviceManager::new();
+    let s = session(&dm, "pcb");
+    let mut map = gcodekit_core::gcode::HeightMap::covering([0.0, 0.0], [10.0, 10.0], 10.0);
+    map.set_height(1, 0, 0.2);
+    let opts = SchedulerOptions {
+        height_map: Some(map),
+        ..options()
+    };
+    let sched = JobScheduler::start(s.clone(), opts);
+
+    // The cut is split into 1 mm segments that follow the surface; the feed goes first
+    sched.submit(write_job(&td, "board.nc", "G21 G90\nG0 X0 Y0 Z0\nG1 X10 F500\n"));
+    assert!(wait_until(|| sched.finished().len() == 1));
+    let job = &sched.finished()[0];
+    assert!(matches!(job.status, JobStatus::Completed));
+    assert_eq!((job.lines_total, job.lines_sent), (13, 13));
+    let mpos = s.wait_for_report(WAIT, |_| true).unwrap().mpos;
+    assert!((mpos[2] - 0.2).abs() < 1e-6, "{:?}", mpos);
+}
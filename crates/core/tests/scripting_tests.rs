@@ -0,0 +1,114 @@
+#![cfg(feature = "scripting")]
+
+mod common;
+
+use gcodekit_core::device_manager::DeviceManager;
+use gcodekit_core::macros::{Macro, MacroLanguage, MacroParam};
+use gcodekit_core::scripting::{ScriptOptions, ScriptRunner};
+use gcodekit_core::session::Session;
+use gcodekit_device_adapters::sim::SimOptions;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn open(dm: &DeviceManager) -> Arc<Session> {
+    let sim = SimOptions {
+        solids: vec![([-5.0, -5.0, -60.0], [5.0, 5.0, -10.0])],
+        ..Default::default()
+    };
+    common::open(dm, sim)
+}
+
+#[test]
+fn test_script_probes_branches_and_prompts() {
+    let dm = DeviceManager::new();
+    let session = open(&dm);
+    let asked = Arc::new(Mutex::new(Vec::new()));
+    let log = asked.clone();
+    let runner = ScriptRunner::new(session.clone(), ScriptOptions::default()).with_prompt(move |message, default| {
+        log.lock().unwrap().push((message.to_string(), default.map(str::to_string)));
+        Some("20".into())
+    });
+
+    let script = r#"
+        let depth = prompt("Probe depth?", "15");
+        send("G38.2 Z-" + depth + " F" + feed);
+        let p = probe_result();
+        if p.ok {
+            send(`G53 G0 Z${p.z + lift}`);
+        } else {
+            send("G53 G0 Z0");
+        }
+        wait_idle();
+        if state().mz != p.z + lift { throw "not lifted"; }
+    "#;
+    let vars = BTreeMap::from([("feed".to_string(), "100".to_string()), ("lift".to_string(), "5".to_string())]);
+    runner.run(script, &vars).unwrap();
+    assert_eq!(*asked.lock().unwrap(), [("Probe depth?".to_string(), Some("15".to_string()))]);
+    let state = session.wait_for_report(Duration::from_secs(1), |_| true).unwrap();
+    assert_eq!(state.mpos[2], -5.0);
+}
+
+#[test]
+fn test_errors_end_the_script() {
+    let dm = DeviceManager::new();
+    let session = open(&dm);
+    let runner = ScriptRunner::new(session.clone(), ScriptOptions::default());
+    let err = runner.run(r#"send("G0 X1"); send("M6 T2"); send("G0 X2");"#, &BTreeMap::new()).unwrap_err();
+    assert!(err.to_string().contains("error:20"), "{}", err);
+    assert_eq!(session.wait_for_report(Duration::from_secs(1), |_| true).unwrap().mpos[0], 1.0);
+
+    // Without a prompt handler, prompts need a default
+    assert!(runner.run(r#"let d = prompt("Depth?");"#, &BTreeMap::new()).is_err());
+    assert!(runner.run(r#"let d = prompt("Depth?", "2");"#, &BTreeMap::new()).is_ok());
+    assert!(runner.run("let x = ;", &BTreeMap::new()).unwrap_err().to_string().contains("compile"));
+}
+
+#[test]
+fn test_sandbox_blocks_modules_eval_and_runaway_loops() {
+    let dm = DeviceManager::new();
+    let session = open(&dm);
+    let options = ScriptOptions {
+        max_operations: 10_000,
+        ..Default::default()
+    };
+    let runner = ScriptRunner::new(session, options);
+    let none = BTreeMap::new();
+    assert!(runner.run(r#"import "std/fs" as fs;"#, &none).is_err());
+    assert!(runner.run(r#"eval("send(\"G0 X1\")")"#, &none).is_err());
+    assert!(runner.run("loop { }", &none).is_err());
+    assert!(runner.run(r#"let s = "x"; loop { s += s; }"#, &none).is_err());
+}
+
+#[test]
+fn test_emergency_stop_aborts_script() {
+    let dm = DeviceManager::new();
+    let session = open(&dm);
+    let options = ScriptOptions {
+        max_operations: 0,
+        ..Default::default()
+    };
+    let runner = Arc::new(ScriptRunner::new(session, options));
+    let started = Instant::now();
+    let stopper = runner.clone();
+    let handle = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        stopper.emergency_stop().unwrap();
+    });
+    let err = runner.run("loop { sleep(10); let s = state(); }", &BTreeMap::new()).unwrap_err();
+    handle.join().unwrap();
+    assert!(err.to_string().contains("emergency stop"), "{}", err);
+    assert!(started.elapsed() < Duration::from_secs(1));
+
+    // Script macros get their parameters as variables
+    let mut m = Macro::new("lift", r#"if type_of(height) != "f64" { throw "bad"; } print(height);"#);
+    m.language = MacroLanguage::Rhai;
+    m.params.push(MacroParam {
+        name: "height".into(),
+        prompt: "Height".into(),
+        default: Some("2.5".into()),
+    });
+    m.validate().unwrap();
+    runner.run_macro(&m, &BTreeMap::new()).unwrap();
+}
@@ -0,0 +1,168 @@
+mod common;
+
+use common::{wait_until, WAIT};
+use gcodekit_core::device_manager::DeviceManager;
+use gcodekit_core::endpoint::Endpoint;
+use gcodekit_core::machine_state::MachineStatus;
+use gcodekit_core::profile::StreamingMode;
+use gcodekit_core::protocol::Protocol;
+use gcodekit_core::session::{SessionEvent, SessionOptions, StreamStatus};
+use gcodekit_device_adapters::replay::{ReplayOptions, ReplayTransport};
+use gcodekit_device_adapters::Transport;
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Acknowledges one line per read and records the most bytes ever in flight.
+#[derive(Clone, Default)]
+struct CountingTransport {
+    outstanding: Arc<Mutex<VecDeque<usize>>>,
+    max_in_flight: Arc<Mutex<usize>>,
+}
+
+impl Transport for CountingTransport {
+    fn send_line(&mut self, line: &str) -> io::Result<()> {
+        let mut q = self.outstanding.lock().unwrap();
+        q.push_back(line.len() + 1);
+        let total: usize = q.iter().sum();
+        let mut max = self.max_in_flight.lock().unwrap();
+        *max = (*max).max(total);
+        Ok(())
+    }
+    fn emergency_stop(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+    fn disconnect(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+    fn is_alive(&self) -> io::Result<bool> {
+        Ok(true)
+    }
+    fn read_line(&mut self) -> io::Result<String> {
+        std::thread::sleep(Duration::from_millis(1));
+        match self.outstanding.lock().unwrap().pop_front() {
+            Some(_) => Ok("ok".into()),
+            None => Err(io::Error::new(io::ErrorKind::TimedOut, "idle")),
+        }
+    }
+}
+
+#[test]
+fn test_sim_session_tracks_state_and_streams_job() {
+    let dm = DeviceManager::new();
+    let mut events = dm.subscribe();
+    let ep: Endpoint = "sim:grbl".parse().unwrap();
+    let td = tempfile::tempdir().unwrap();
+    std::env::set_var("XDG_DATA_HOME", td.path());
+    let session = dm.connect_session(&ep).expect("connect sim");
+    assert_eq!(session.id(), "sim:grbl");
+
+    // Status polling fills in the machine state
+    assert!(wait_until(|| session.state().status == MachineStatus::Idle));
+
+    session.stream(["G21 G90", "G0 X10 Y5", "", "G1 Z-1 F100"]).unwrap();
+    let done = session.wait_for_stream(WAIT).expect("job finished");
+    assert_eq!(done.status, StreamStatus::Completed);
+    assert_eq!((done.total, done.sent, done.acked), (3, 3, 3));
+    assert!(wait_until(|| session.state().mpos == [10.0, 5.0, -1.0]));
+
+    // Connecting the same endpoint again returns the live session
+    let again = dm.connect_session(&ep).unwrap();
+    assert!(Arc::ptr_eq(&session, &again));
+    assert_eq!(dm.list_sessions().len(), 1);
+
+    assert!(dm.disconnect("sim:grbl").unwrap());
+    assert!(!session.is_connected());
+    assert!(session.send_line("G0 X0").is_err());
+    assert!(dm.list_sessions().is_empty());
+
+    let mut seen = Vec::new();
+    while let Ok(ev) = events.try_recv() {
+        assert_eq!(ev.device_id(), "sim:grbl");
+        seen.push(ev);
+    }
+    assert!(matches!(seen.first(), Some(SessionEvent::Connected { .. })));
+    assert!(seen.iter().any(|e| matches!(e, SessionEvent::StateChanged { .. })));
+    assert!(seen.iter().any(
+        |e| matches!(e, SessionEvent::Stream { progress, .. } if progress.status == StreamStatus::Completed)
+    ));
+    assert!(matches!(seen.last(), Some(SessionEvent::Disconnected { reason: None, .. })));
+}
+
+#[test]
+fn test_error_reply_fails_job() {
+    let dm = DeviceManager::new();
+    let replay = ReplayTransport::from_session(
+        "> G0 X1\n< ok\n> BAD\n< error:20\n> G0 X3\n< ok\n",
+        ReplayOptions::default(),
+    );
+    let opts = SessionOptions {
+        streaming_mode: StreamingMode::SendResponse,
+        ..Default::default()
+    };
+    let ep: Endpoint = "replay:///tmp/x.log".parse().unwrap();
+    let session = dm.open_session("replay", ep, Box::new(replay), Protocol::Grbl, opts);
+    session.stream(["G0 X1", "BAD", "G0 X3"]).unwrap();
+    let done = session.wait_for_stream(WAIT).expect("job finished");
+    assert_eq!(done.status, StreamStatus::Failed);
+    assert_eq!(done.error.as_deref(), Some("error:20"));
+    assert_eq!((done.sent, done.acked), (2, 2));
+    assert_eq!(session.state().last_error.as_deref(), Some("error:20"));
+
+    // Manual commands still go through after the job failed; the recording
+    // ends after the last reply, which drops the session
+    session.send_line("G0 X3").unwrap();
+    assert!(wait_until(|| !session.is_connected()));
+}
+
+#[test]
+fn test_character_counting_respects_buffer() {
+    let dm = DeviceManager::new();
+    let transport = CountingTransport::default();
+    let opts = SessionOptions {
+        buffer_size: 20,
+        status_interval: None,
+        ..Default::default()
+    };
+    let session = dm.open_session("count", "sim:grbl".parse().unwrap(), Box::new(transport.clone()), Protocol::Grbl, opts);
+    let lines: Vec<String> = (0..50).map(|i| format!("G1 X{:.2}", i as f64 / 10.0)).collect();
+    session.stream(&lines).unwrap();
+    let done = session.wait_for_stream(WAIT).expect("job finished");
+    assert_eq!(done.status, StreamStatus::Completed);
+    assert_eq!(done.acked, 50);
+    let max = *transport.max_in_flight.lock().unwrap();
+    assert!(max <= 20, "in flight {} exceeds buffer", max);
+    assert!(max > 9, "expected more than one line in flight, got {}", max);
+}
+
+#[test]
+fn test_pause_resume_and_cancel() {
+    let dm = DeviceManager::new();
+    let session = dm.open_session(
+        "sim",
+        "sim:grbl".parse().unwrap(),
+        gcodekit_device_adapters::create_sim_transport(Default::default()).unwrap(),
+        Protocol::Grbl,
+        SessionOptions::default(),
+    );
+    session.pause().unwrap();
+    session.stream(["G0 X1", "G0 X2"]).unwrap();
+    assert!(wait_until(|| session.progress().status == StreamStatus::Paused));
+    assert!(wait_until(|| session.state().status == MachineStatus::Hold));
+    assert!(session.stream(["G0 X3"]).is_err(), "second job while paused");
+    assert_eq!(session.progress().sent, 0);
+
+    session.resume().unwrap();
+    assert_eq!(session.wait_for_stream(WAIT).unwrap().status, StreamStatus::Completed);
+
+    session.pause().unwrap();
+    session.stream(["G0 X1"]).unwrap();
+    session.cancel().unwrap();
+    let done = session.wait_for_stream(WAIT).unwrap();
+    assert_eq!(done.status, StreamStatus::Cancelled);
+    assert_eq!(done.sent, 0);
+}
@@ -0,0 +1,168 @@
+mod common;
+
+use gcodekit_core::device_manager::DeviceManager;
+use gcodekit_core::profile::MachineProfile;
+use gcodekit_core::session::{Session, SessionEvent, SessionOptions, StreamProgress, StreamStatus};
+use gcodekit_core::toolchange::{ToolChange, ToolChangeConfig, ToolChanger, ToolSetter};
+use gcodekit_device_adapters::sim::SimOptions;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// A tool setter whose top is at machine Z-50, under X200 Y0.
+const SETTER: ([f64; 3], [f64; 3]) = ([195.0, -5.0, -60.0], [205.0, 5.0, -50.0]);
+
+fn open(dm: &DeviceManager) -> Arc<Session> {
+    let sim = SimOptions {
+        solids: vec![SETTER],
+        ..Default::default()
+    };
+    common::open_with(dm, sim, SessionOptions { tool_changes: true, ..common::options() })
+}
+
+fn config() -> ToolChangeConfig {
+    ToolChangeConfig {
+        position: [100.0, 100.0, 0.0],
+        setter: Some(ToolSetter {
+            position: [200.0, 0.0, -40.0],
+            max_travel: 30.0,
+            feed: 200.0,
+        }),
+        atc_macro: None,
+        spindle_dwell: 0.1,
+    }
+}
+
+/// Wait for the job to be held at line `from` or later.
+fn wait_for_hold(session: &Session, from: usize) -> StreamProgress {
+    for _ in 0..200 {
+        let progress = session.progress();
+        if progress.tool_change.is_some_and(|c| c.line >= from) {
+            return progress;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    panic!("job was not held for a tool change: {:?}", session.progress());
+}
+
+#[test]
+fn test_m6_holds_the_job_until_resumed() {
+    let dm = DeviceManager::new();
+    let mut events = dm.subscribe();
+    let session = open(&dm);
+    session.stream(["T3", "G0 X10", "M6 (new tool)", "G0 X20", "M6 T4 M3 S1000", "G0 X30"]).unwrap();
+
+    let held = wait_for_hold(&session, 0);
+    assert_eq!((held.status, held.sent, held.acked), (StreamStatus::Paused, 2, 2));
+    assert_eq!(held.tool_change, Some(ToolChange { tool: Some(3), line: 2 }));
+    session.resume().unwrap();
+
+    // The controller would reject M6; the rest of the line is still sent
+    let held = wait_for_hold(&session, 3);
+    assert_eq!(held.tool_change, Some(ToolChange { tool: Some(4), line: 4 }));
+    session.resume().unwrap();
+    let done = session.wait_for_stream(Duration::from_secs(2)).unwrap();
+    assert_eq!((done.status, done.acked, done.total), (StreamStatus::Completed, 6, 6));
+
+    let mut requests = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        if let SessionEvent::ToolChange { request, .. } = event {
+            requests.push(request.tool);
+        }
+    }
+    assert_eq!(requests, [Some(3), Some(4)]);
+}
+
+#[test]
+fn test_manual_change_measures_new_tool_and_returns() {
+    let dm = DeviceManager::new();
+    let session = open(&dm);
+    let changer = ToolChanger::new(session.clone(), config());
+    assert!(changer.begin().is_err());
+
+    session.stream(["G0 X10 Y10 Z-5", "M6 T1", "G0 X20"]).unwrap();
+    wait_for_hold(&session, 0);
+    // The job was zeroed with a tool 2 mm shorter than the next one
+    changer.set_reference(Some(-52.0));
+    assert_eq!(changer.begin().unwrap().tool, Some(1));
+    let parked = session.wait_for_report(Duration::from_secs(1), |_| true).unwrap();
+    assert_eq!((parked.mpos, session.progress().status), ([100.0, 100.0, 0.0], StreamStatus::Paused));
+
+    assert_eq!(changer.finish().unwrap(), Some(2.0));
+    assert_eq!(session.wait_for_stream(Duration::from_secs(2)).unwrap().status, StreamStatus::Completed);
+    // The tip is back at work Z-5, with the machine 2 mm higher
+    let state = session.wait_for_report(Duration::from_secs(1), |_| true).unwrap();
+    assert_eq!((state.mpos, state.wpos), ([20.0, 10.0, -3.0], [20.0, 10.0, -5.0]));
+}
+
+#[test]
+fn test_change_stops_and_restores_spindle_coolant_and_units() {
+    let dm = DeviceManager::new();
+    let session = open(&dm);
+    let changer = ToolChanger::new(session.clone(), config());
+    changer.set_reference(Some(-50.0));
+    // Job in inches: work X0.5 Y0.5 Z-0.1 is machine 12.7, 12.7, -2.54
+    session.stream(["G20 G0 X0.5 Y0.5 Z-0.1", "M3 S12000 M8", "M6 T1", "G1 X1 F10"]).unwrap();
+    wait_for_hold(&session, 0);
+    changer.begin().unwrap();
+    session.send_command("$G", Duration::from_secs(1)).unwrap();
+    let modes = session.state().modes;
+    for word in ["M5", "M9", "G21"] {
+        assert!(modes.iter().any(|m| m == word), "{:?}", modes);
+    }
+    // Parked at the change position in mm despite the job's G20
+    let parked = session.wait_for_report(Duration::from_secs(1), |_| true).unwrap();
+    assert_eq!(parked.mpos, [100.0, 100.0, 0.0]);
+
+    changer.finish().unwrap();
+    assert_eq!(session.wait_for_stream(Duration::from_secs(2)).unwrap().status, StreamStatus::Completed);
+    session.send_command("$G", Duration::from_secs(1)).unwrap();
+    let modes = session.state().modes;
+    for word in ["M3", "M8", "S12000", "G20"] {
+        assert!(modes.iter().any(|m| m == word), "{:?}", modes);
+    }
+    let mpos = session.wait_for_report(Duration::from_secs(1), |_| true).unwrap().mpos;
+    assert!((mpos[0] - 25.4).abs() < 1e-6 && (mpos[2] + 2.54).abs() < 1e-6, "{:?}", mpos);
+}
+
+#[test]
+fn test_first_change_measures_outgoing_tool_as_reference() {
+    let dm = DeviceManager::new();
+    let session = open(&dm);
+    let changer = ToolChanger::new(session.clone(), config());
+    session.stream(["G0 X10 Y10 Z-5", "M6 T2"]).unwrap();
+    wait_for_hold(&session, 0);
+    changer.begin().unwrap();
+    assert_eq!(changer.reference(), Some(-50.0));
+    assert!(changer.finish().is_ok());
+    assert!(changer.finish().is_err());
+    assert_eq!(session.wait_for_stream(Duration::from_secs(2)).unwrap().status, StreamStatus::Completed);
+}
+
+#[test]
+fn test_atc_macro_changes_without_operator() {
+    let dm = DeviceManager::new();
+    let session = open(&dm);
+    let changer = ToolChanger::new(
+        session.clone(),
+        ToolChangeConfig {
+            setter: None,
+            atc_macro: Some("G10 L2 P2 X{{tool}}\n\nG53 G0 Z0".into()),
+            ..config()
+        },
+    );
+    session.stream(["G0 X10 Y10 Z-5", "M6 T7", "G0 X20"]).unwrap();
+    wait_for_hold(&session, 0);
+    assert_eq!(changer.begin().unwrap().tool, Some(7));
+    assert_eq!(session.wait_for_stream(Duration::from_secs(2)).unwrap().status, StreamStatus::Completed);
+    session.send_command("$#", Duration::from_secs(1)).unwrap();
+    assert_eq!(session.state().offsets["G55"], [7.0, 0.0, 0.0]);
+    let mpos = session.wait_for_report(Duration::from_secs(1), |_| true).unwrap().mpos;
+    assert_eq!(mpos, [20.0, 10.0, -5.0]);
+
+    // Profiles with a tool change configuration hold jobs at M6
+    let mut profile = MachineProfile::new("Mill", "sim:grbl".parse().unwrap());
+    assert!(!SessionOptions::from_profile(&profile).tool_changes);
+    profile.tool_change = Some(changer.config().clone());
+    assert!(SessionOptions::from_profile(&profile).tool_changes);
+}
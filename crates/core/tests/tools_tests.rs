@@ -0,0 +1,147 @@
+mod common;
+
+use gcodekit_core::device_manager::DeviceManager;
+use gcodekit_core::gcode::analyze;
+use gcodekit_core::session::{Session, SessionOptions, StreamStatus};
+use gcodekit_core::toolchange::{ToolChangeConfig, ToolChanger, ToolSetter};
+use gcodekit_core::tools::{delete_tool, load_tools, save_tool, Tool, ToolKind, ToolTable};
+use gcodekit_device_adapters::sim::SimOptions;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+fn lines(text: &str) -> Vec<String> {
+    text.lines().map(str::to_string).collect()
+}
+
+fn open(dm: &DeviceManager) -> Arc<Session> {
+    let sim = SimOptions {
+        solids: vec![([195.0, -5.0, -60.0], [205.0, 5.0, -50.0])],
+        ..Default::default()
+    };
+    common::open_with(dm, sim, SessionOptions { tool_changes: true, ..common::options() })
+}
+
+/// Stream `job`, wait for its `M6` and run the change.
+fn change_tool(session: &Session, changer: &ToolChanger, job: &[&str]) -> Option<f64> {
+    session.stream(job).unwrap();
+    while changer.pending().is_none() {
+        thread::sleep(Duration::from_millis(5));
+    }
+    changer.begin().unwrap();
+    let applied = changer.finish().unwrap();
+    assert_eq!(session.wait_for_stream(Duration::from_secs(2)).unwrap().status, StreamStatus::Completed);
+    applied
+}
+
+#[test]
+fn test_library_crud_and_profile_tables() {
+    // Isolated data dir; the only test in this file that touches storage
+    let td = tempfile::tempdir().expect("tempdir");
+    std::env::set_var("XDG_DATA_HOME", td.path());
+
+    let mut shared = Tool::new(1, ToolKind::FlatEndMill, 6.0);
+    shared.description = "6 mm 2-flute".into();
+    shared.flutes = 2;
+    let mut router = Tool::new(1, ToolKind::FlatEndMill, 3.175);
+    router.profile_id = Some("router".into());
+    let mut vbit = Tool::new(2, ToolKind::VBit { angle: 60.0 }, 6.0);
+    vbit.spindle_speed = Some(18000);
+    for tool in [shared.clone(), router.clone(), vbit.clone()] {
+        save_tool(tool).unwrap();
+    }
+    assert!(save_tool(Tool::new(3, ToolKind::Drill, 0.0)).is_err());
+
+    // Saving again replaces the entry with the same profile and number
+    shared.feed = Some(800.0);
+    save_tool(shared.clone()).unwrap();
+    assert_eq!(load_tools().unwrap().len(), 3);
+
+    let table = ToolTable::for_profile("router").unwrap();
+    assert_eq!(table.get(1), Some(&router));
+    assert_eq!(table.get(2), Some(&vbit));
+    assert_eq!(ToolTable::for_profile("laser").unwrap().get(1), Some(&shared));
+
+    assert!(delete_tool(Some("router"), 1).unwrap());
+    assert!(!delete_tool(Some("router"), 1).unwrap());
+    assert_eq!(ToolTable::for_profile("router").unwrap().get(1), Some(&shared));
+
+    // A changer on the library saves measured offsets to the profile
+    let dm = DeviceManager::new();
+    let session = open(&dm);
+    let config = ToolChangeConfig {
+        position: [100.0, 100.0, 0.0],
+        setter: Some(ToolSetter {
+            position: [200.0, 0.0, -40.0],
+            max_travel: 30.0,
+            feed: 200.0,
+        }),
+        atc_macro: None,
+        spindle_dwell: 0.0,
+    };
+    let changer = ToolChanger::new(session.clone(), config).with_library("router").unwrap();
+    changer.set_reference(Some(-51.5));
+    assert_eq!(change_tool(&session, &changer, &["M6 T2"]), Some(1.5));
+    let saved = ToolTable::for_profile("router").unwrap();
+    assert_eq!(saved.get(2).unwrap().length_offset, Some(1.5));
+    assert_eq!(ToolTable::for_profile("laser").unwrap().get(2).unwrap().length_offset, None);
+}
+
+#[test]
+fn test_kerf_defaults_and_validation() {
+    let vbit = Tool::new(1, ToolKind::VBit { angle: 90.0 }, 6.0);
+    assert!((vbit.kerf(1.0) - 2.0).abs() < 1e-9);
+    assert_eq!(vbit.kerf(10.0), 6.0);
+    let ball = Tool::new(2, ToolKind::BallEndMill, 4.0);
+    assert!((ball.kerf(1.0) - 2.0 * 3f64.sqrt()).abs() < 1e-9);
+    assert_eq!((ball.kerf(5.0), Tool::new(3, ToolKind::Drill, 3.0).kerf(0.0)), (4.0, 3.0));
+    assert!(Tool::new(4, ToolKind::VBit { angle: 180.0 }, 6.0).validate().is_err());
+
+    let tool: Tool = serde_json::from_str(r#"{"number": 5, "diameter": 1.5}"#).unwrap();
+    assert_eq!(tool, Tool::new(5, ToolKind::FlatEndMill, 1.5));
+}
+
+#[test]
+fn test_usage_lists_tools_used_and_missing() {
+    let table = ToolTable::new([Tool::new(1, ToolKind::FlatEndMill, 6.0), Tool::new(3, ToolKind::Drill, 3.0)]);
+    let analysis = analyze(&lines("T0\nT1 M6\nG0 X1\nT2 M6\nT3 M6"), None);
+    let usage = table.usage(&analysis);
+    let found: Vec<u32> = usage.found.iter().map(|t| t.number).collect();
+    assert_eq!((found, usage.missing), (vec![1, 3], vec![2]));
+}
+
+#[test]
+fn test_tool_changes_use_and_record_length_offsets() {
+    let dm = DeviceManager::new();
+    let session = open(&dm);
+    let mut stored = Tool::new(4, ToolKind::FlatEndMill, 6.0);
+    stored.length_offset = Some(1.5);
+    let table = ToolTable::new([stored, Tool::new(5, ToolKind::BallEndMill, 3.0)]);
+    let config = ToolChangeConfig {
+        position: [100.0, 100.0, 0.0],
+        setter: None,
+        atc_macro: None,
+        spindle_dwell: 0.0,
+    };
+
+    // Without a setter the stored offset is applied; unknown lengths are not
+    let changer = ToolChanger::new(session.clone(), config.clone()).with_tools(table.clone());
+    assert_eq!(change_tool(&session, &changer, &["G0 X10 Z-5", "M6 T4"]), Some(1.5));
+    let state = session.wait_for_report(Duration::from_secs(1), |_| true).unwrap();
+    assert_eq!((state.mpos[2], state.wpos[2]), (-3.5, -5.0));
+    assert_eq!(change_tool(&session, &changer, &["M6 T5"]), None);
+
+    // Measured offsets are recorded in the table
+    let config = ToolChangeConfig {
+        setter: Some(ToolSetter {
+            position: [200.0, 0.0, -40.0],
+            max_travel: 30.0,
+            feed: 200.0,
+        }),
+        ..config
+    };
+    let changer = ToolChanger::new(session.clone(), config).with_tools(table);
+    changer.set_reference(Some(-51.0));
+    assert_eq!(change_tool(&session, &changer, &["M6 T5"]), Some(1.0));
+    assert_eq!(changer.tools().get(5).unwrap().length_offset, Some(1.0));
+}
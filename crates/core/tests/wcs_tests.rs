@@ -0,0 +1,89 @@
+mod common;
+
+use gcodekit_core::device_manager::DeviceManager;
+use gcodekit_core::machine_state::{Axis, MachineState};
+use gcodekit_core::session::Session;
+use gcodekit_core::wcs::{ReturnPosition, WcsManager};
+use gcodekit_device_adapters::sim::SimOptions;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn open(dm: &DeviceManager) -> Arc<Session> {
+    common::open(dm, SimOptions::default())
+}
+
+fn settle(session: &Session) -> MachineState {
+    session.wait_for_report(Duration::from_secs(1), |_| true).unwrap();
+    session.wait_for_report(Duration::from_secs(1), |_| true).unwrap()
+}
+
+#[test]
+fn test_zeroing_axes_sets_offsets_in_active_or_chosen_wcs() {
+    let dm = DeviceManager::new();
+    let session = open(&dm);
+    let wcs = WcsManager::new(session.clone());
+    session.send_command("G0 X10 Y20 Z-5", Duration::from_secs(1)).unwrap();
+    assert_eq!(wcs.refresh().unwrap()["G54"], [0.0; 3]);
+    assert_eq!(wcs.active(), "G54");
+
+    let change = wcs.zero(None, &[Axis::X, Axis::Y]).unwrap();
+    assert_eq!((change.offset.as_str(), change.before, change.after), ("G54", [0.0; 3], [10.0, 20.0, 0.0]));
+    let state = settle(&session);
+    assert_eq!((state.wco, state.wpos), ([10.0, 20.0, 0.0], [0.0, 0.0, -5.0]));
+
+    // Zeroing another system leaves the active one alone
+    let change = wcs.set_position(Some("g55"), &[(Axis::Z, 2.0)]).unwrap();
+    assert_eq!((change.offset.as_str(), change.after), ("G55", [0.0, 0.0, -7.0]));
+    wcs.select("G55").unwrap();
+    assert_eq!(wcs.active(), "G55");
+    assert_eq!(settle(&session).wpos, [10.0, 20.0, 2.0]);
+
+    assert!(wcs.zero(Some("G60"), &[Axis::X]).is_err());
+    assert!(wcs.zero(None, &[]).is_err());
+}
+
+#[test]
+fn test_numeric_offsets_g92_and_return_positions() {
+    let dm = DeviceManager::new();
+    let session = open(&dm);
+    let wcs = WcsManager::new(session.clone());
+    wcs.set_offset(Some("G54"), &[(Axis::X, 100.0), (Axis::Y, 50.0)]).unwrap();
+    session.send_command("G0 X5 Y5", Duration::from_secs(1)).unwrap();
+    assert_eq!(settle(&session).mpos, [105.0, 55.0, 0.0]);
+
+    session.send_command("G92 X0", Duration::from_secs(1)).unwrap();
+    assert_eq!(wcs.refresh().unwrap()["G92"], [5.0, 0.0, 0.0]);
+    let change = wcs.clear_g92().unwrap();
+    assert_eq!((change.before, change.after), ([5.0, 0.0, 0.0], [0.0; 3]));
+
+    let stored = wcs.store_position(ReturnPosition::G30).unwrap();
+    assert_eq!(stored.after, [105.0, 55.0, 0.0]);
+    session.send_command("G53 G0 X0 Y0", Duration::from_secs(1)).unwrap();
+    wcs.go_to_position(ReturnPosition::G30).unwrap();
+    assert_eq!(settle(&session).mpos, [105.0, 55.0, 0.0]);
+    assert!(wcs.restore(&stored).is_err());
+}
+
+#[test]
+fn test_history_per_job_restores_mistaken_zero() {
+    let dm = DeviceManager::new();
+    let session = open(&dm);
+    let wcs = WcsManager::new(session.clone());
+    session.send_command("G0 X10 Y10 Z-3", Duration::from_secs(1)).unwrap();
+    wcs.set_job(Some("job-1"));
+    wcs.zero(None, &[Axis::X, Axis::Y]).unwrap();
+    wcs.set_job(Some("job-2"));
+    let mistake = wcs.zero(None, &[Axis::Z]).unwrap();
+    session.send_command("G92 X1", Duration::from_secs(1)).unwrap();
+    let g92 = wcs.clear_g92().unwrap();
+
+    assert_eq!(wcs.history().len(), 3);
+    assert_eq!(wcs.history_for("job-1").len(), 1);
+    assert_eq!(wcs.history_for("job-2"), [mistake.clone(), g92.clone()]);
+
+    let restored = wcs.restore(&mistake).unwrap();
+    assert_eq!(restored.after, [10.0, 10.0, 0.0]);
+    let restored = wcs.restore(&g92).unwrap();
+    assert_eq!(restored.after, g92.before);
+    assert_eq!(settle(&session).wpos, [1.0, 0.0, -3.0]);
+}
@@ -14,13 +14,8 @@ fn test_streamer_over_websocket() {
         let (stream, _) = listener.accept().expect("accept");
         let mut ws = tungstenite::accept(stream).expect("accept ws");
         // Read messages in a loop and reply with "ok" for each one.
-        loop {
-            match ws.read() {
-                Ok(_msg) => {
-                    let _ = ws.send(tungstenite::Message::Text("ok".to_string()));
-                }
-                Err(_) => break,
-            }
+        while let Ok(_msg) = ws.read() {
+            let _ = ws.send(tungstenite::Message::Text("ok\n".to_string()));
         }
     });
 
@@ -34,5 +29,7 @@ fn test_streamer_over_websocket() {
     let res = streamer.stream(lines);
     assert!(res.is_ok());
 
+    // Close the client so the server loop ends
+    drop(streamer);
     let _ = server.join();
 }
@@ -0,0 +1,145 @@
+use std::io;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{lookup_host, TcpStream, ToSocketAddrs};
+use tokio::time::{timeout, Instant};
+use tracing::{debug, info};
+
+use crate::framing::LineAssembler;
+use crate::telnet::{TelnetCodec, TelnetOptions, IAC, NOP};
+
+/// Async telnet transport using tokio::net::TcpStream and the shared
+/// `TelnetCodec` for option negotiation.
+pub struct AsyncTelnetTransport {
+    stream: TcpStream,
+    codec: TelnetCodec,
+    lines: LineAssembler,
+    read_timeout: Duration,
+    keepalive: Option<Duration>,
+    last_tx: Instant,
+}
+
+impl AsyncTelnetTransport {
+    /// Connect to `addr` (`"host:port"`, a `SocketAddr`, ...) with default
+    /// options, trying each resolved address in turn like the sync transport.
+    pub async fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        AsyncTelnetTransport::connect_with_options(addr, TelnetOptions::default()).await
+    }
+
+    pub async fn connect_with_options<A: ToSocketAddrs>(addr: A, opts: TelnetOptions) -> io::Result<Self> {
+        info!(timeout = ?opts.timeout, "async_telnet::connect: attempting");
+        let mut last_err = None;
+        for sock in lookup_host(addr).await? {
+            let stream = match timeout(opts.timeout, TcpStream::connect(sock)).await {
+                Ok(Ok(stream)) => stream,
+                Ok(Err(e)) => {
+                    last_err = Some(e);
+                    continue;
+                }
+                Err(_) => {
+                    last_err = Some(io::Error::new(io::ErrorKind::TimedOut, "connect timeout"));
+                    continue;
+                }
+            };
+            stream.set_nodelay(true)?;
+            let mut t = AsyncTelnetTransport {
+                stream,
+                codec: TelnetCodec::new(),
+                lines: LineAssembler::new(),
+                read_timeout: opts.timeout,
+                keepalive: opts.keepalive,
+                last_tx: Instant::now(),
+            };
+            let hello = t.codec.initial_negotiation(opts.binary);
+            t.write_raw(&hello).await?;
+            debug!(peer = %sock, "async_telnet::connect: connected");
+            return Ok(t);
+        }
+        Err(last_err.unwrap_or_else(|| io::Error::other("connect failed")))
+    }
+
+    async fn write_raw(&mut self, bytes: &[u8]) -> io::Result<()> {
+        timeout(self.read_timeout, self.stream.write_all(bytes))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "write timeout"))??;
+        self.last_tx = Instant::now();
+        Ok(())
+    }
+
+    pub async fn send_line(&mut self, line: &str) -> io::Result<()> {
+        let mut payload = self.codec.encode(line.as_bytes());
+        payload.push(b'\n');
+        debug!(len = payload.len(), "async_telnet::send_line: sending bytes");
+        self.write_raw(&payload).await
+    }
+
+    pub async fn emergency_stop(&mut self) -> io::Result<()> {
+        debug!("async_telnet::emergency_stop: sending stop sequence");
+        self.write_raw(b"!").await
+    }
+
+    pub async fn send_keepalive(&mut self) -> io::Result<()> {
+        self.write_raw(&[IAC, NOP]).await
+    }
+
+    pub async fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush().await
+    }
+
+    pub async fn disconnect(&mut self) -> io::Result<()> {
+        let _ = self.stream.shutdown().await;
+        Ok(())
+    }
+
+    pub async fn is_alive(&self) -> io::Result<bool> {
+        Ok(self.stream.peer_addr().is_ok())
+    }
+
+    pub async fn read_line(&mut self) -> io::Result<String> {
+        let deadline = Instant::now() + self.read_timeout;
+        let mut buf = [0u8; 512];
+        loop {
+            if let Some(line) = self.lines.next_line() {
+                debug!(len = line.len(), "async_telnet::read_line: read line bytes");
+                return Ok(line);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "read timeout"));
+            }
+            let mut wait = deadline - now;
+            if let Some(k) = self.keepalive {
+                wait = wait.min(k);
+            }
+
+            match timeout(wait, self.stream.read(&mut buf)).await {
+                Ok(Ok(0)) => {
+                    return match self.lines.take_partial() {
+                        Some(line) => Ok(line),
+                        None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "telnet closed")),
+                    };
+                }
+                Ok(Ok(n)) => {
+                    let mut data = Vec::with_capacity(n);
+                    let mut reply = Vec::new();
+                    self.codec.decode(&buf[..n], &mut data, &mut reply);
+                    if !reply.is_empty() {
+                        self.write_raw(&reply).await?;
+                    }
+                    self.lines.push(&data);
+                }
+                Ok(Err(e)) => return Err(e),
+                Err(_) => {
+                    if let Some(k) = self.keepalive {
+                        if self.last_tx.elapsed() >= k {
+                            debug!("async_telnet::read_line: idle, sending keepalive");
+                            self.send_keepalive().await?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,7 +1,7 @@
 #![cfg(all(feature = "async", feature = "websocket"))]
 
 use std::io;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use futures_util::{SinkExt, StreamExt};
 use tokio::net::TcpStream;
@@ -10,18 +10,37 @@ use tokio_tungstenite::{connect_async, tungstenite::protocol::Message, WebSocket
 use tokio_tungstenite::tungstenite::client::IntoClientRequest;
 use tokio_tungstenite::MaybeTlsStream as TokioMaybeTlsStream;
 
+use crate::framing::WsFrameDecoder;
+use crate::tls::TlsOptions;
+use crate::websocket_sync::DEFAULT_KEEPALIVE;
+use tracing::debug;
+
 /// Async WebSocket transport using tokio-tungstenite.
+///
+/// Frames are decoded like the sync transport: binary frames are reassembled
+/// into lines and ESP3D session messages are consumed.
 pub struct AsyncWebSocketTransport {
     ws: WebSocketStream<TokioMaybeTlsStream<TcpStream>>,
+    decoder: WsFrameDecoder,
     read_timeout: Duration,
+    keepalive: Option<Duration>,
+    last_tx: Instant,
 }
 
 impl AsyncWebSocketTransport {
     /// Connect to a ws:// or wss:// URL. Caller must provide a valid URL.
+    /// wss:// uses the platform trust store and requires the `websocket-tls`
+    /// feature; see `connect_with_tls` for custom CAs and client certificates.
     pub async fn connect(url: &str) -> io::Result<Self> {
+        AsyncWebSocketTransport::connect_with_tls(url, &TlsOptions::default()).await
+    }
+
+    /// Connect using explicit TLS settings (ignored for plain ws:// URLs).
+    pub async fn connect_with_tls(url: &str, tls: &TlsOptions) -> io::Result<Self> {
         let req = url
             .into_client_request()
             .map_err(io::Error::other)?;
+        let secure = req.uri().scheme_str() == Some("wss");
 
     // Hard-coded 30s connect timeout to avoid indefinite hangs
     let connect_timeout = Duration::from_secs(30);
@@ -30,32 +49,93 @@ impl AsyncWebSocketTransport {
     // Note: async connect uses tokio::time::timeout which yields a
     // `Elapsed` error if the connect doesn't complete within the specified
     // duration. We use a 30s default here to match the synchronous adapter.
-        let (ws_stream, _resp) = timeout(connect_timeout, connect_async(req))
-            .await
-            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "ws connect timeout"))?
-            .map_err(io::Error::other)?;
+        let ws_stream = if secure {
+            Self::connect_secure(req, tls, connect_timeout).await?
+        } else {
+            let (ws_stream, _resp) = timeout(connect_timeout, connect_async(req))
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "ws connect timeout"))?
+                .map_err(io::Error::other)?;
+            ws_stream
+        };
 
     let read_timeout = Duration::from_secs(30);
-        Ok(AsyncWebSocketTransport { ws: ws_stream, read_timeout })
+        Ok(AsyncWebSocketTransport {
+            ws: ws_stream,
+            decoder: WsFrameDecoder::new(),
+            read_timeout,
+            keepalive: Some(DEFAULT_KEEPALIVE),
+            last_tx: Instant::now(),
+        })
     }
 
-    pub async fn send_line(&mut self, line: &str) -> io::Result<()> {
-    let text = line.trim_end_matches(&['\n', '\r'][..]).to_string();
-        // Keep semantics consistent: send as a text frame without trailing newline
-        timeout(self.read_timeout, self.ws.send(Message::Text(text.into())))
+    /// Change the idle keepalive interval; `None` disables keepalives.
+    pub fn set_keepalive(&mut self, keepalive: Option<Duration>) {
+        self.keepalive = keepalive;
+    }
+
+    /// Session id assigned by an ESP3D/FluidNC board, if any.
+    pub fn session_id(&self) -> Option<&str> {
+        self.decoder.session_id()
+    }
+
+    async fn send_message(&mut self, msg: Message) -> io::Result<()> {
+        timeout(self.read_timeout, self.ws.send(msg))
             .await
             .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "ws send timeout"))?
             .map_err(io::Error::other)?;
+        self.last_tx = Instant::now();
         Ok(())
     }
 
+    /// Send a WebSocket ping, plus the ESP3D `PING:<id>` message once the
+    /// board has assigned a session id.
+    pub async fn send_keepalive(&mut self) -> io::Result<()> {
+        debug!("async_websocket::send_keepalive: sending ping");
+        self.send_message(Message::Ping(Default::default())).await?;
+        if let Some(ping) = self.decoder.ping_message() {
+            self.send_message(Message::Text(ping.into())).await?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "websocket-tls")]
+    async fn connect_secure(
+        req: tokio_tungstenite::tungstenite::handshake::client::Request,
+        tls: &TlsOptions,
+        connect_timeout: Duration,
+    ) -> io::Result<WebSocketStream<TokioMaybeTlsStream<TcpStream>>> {
+        let connector = tokio_tungstenite::Connector::Rustls(crate::tls::client_config(tls)?);
+        let (ws_stream, _resp) = timeout(
+            connect_timeout,
+            tokio_tungstenite::connect_async_tls_with_config(req, None, false, Some(connector)),
+        )
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "wss connect timeout"))?
+        .map_err(io::Error::other)?;
+        Ok(ws_stream)
+    }
+
+    #[cfg(not(feature = "websocket-tls"))]
+    async fn connect_secure(
+        _req: tokio_tungstenite::tungstenite::handshake::client::Request,
+        _tls: &TlsOptions,
+        _connect_timeout: Duration,
+    ) -> io::Result<WebSocketStream<TokioMaybeTlsStream<TcpStream>>> {
+        Err(io::Error::other(
+            "wss:// requested but device-adapters built without 'websocket-tls' feature",
+        ))
+    }
+
+    pub async fn send_line(&mut self, line: &str) -> io::Result<()> {
+    let text = line.trim_end_matches(&['\n', '\r'][..]).to_string();
+        // Keep semantics consistent: send as a text frame without trailing newline
+        self.send_message(Message::Text(text.into())).await
+    }
+
     pub async fn emergency_stop(&mut self) -> io::Result<()> {
         // Try sending a '!' as text frame
-        timeout(self.read_timeout, self.ws.send(Message::Text("!".into())))
-            .await
-            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "es send timeout"))?
-            .map_err(io::Error::other)?;
-        Ok(())
+        self.send_message(Message::Text("!".into())).await
     }
 
     pub async fn flush(&mut self) -> io::Result<()> {
@@ -74,18 +154,50 @@ impl AsyncWebSocketTransport {
         Ok(true)
     }
 
+    /// Read the next line, reassembling lines across frames and sending
+    /// keepalives while idle. Pings are answered by tokio-tungstenite.
     pub async fn read_line(&mut self) -> io::Result<String> {
-        // Read next text message
-        let msg = timeout(self.read_timeout, self.ws.next())
-            .await
-            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "ws read timeout"))?
-            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "ws closed"))?;
-
-        match msg {
-            Ok(Message::Text(t)) => Ok(t.to_string()),
-            Ok(Message::Binary(b)) => Ok(String::from_utf8_lossy(&b).into_owned()),
-            Ok(_) => Err(io::Error::other("unsupported ws frame")),
-            Err(e) => Err(io::Error::other(e)),
+        let deadline = Instant::now() + self.read_timeout;
+        loop {
+            if let Some(line) = self.decoder.next_line() {
+                return Ok(line);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "ws read timeout"));
+            }
+            let mut wait = deadline - now;
+            if let Some(k) = self.keepalive {
+                wait = wait.min(k);
+            }
+
+            let msg = match timeout(wait, self.ws.next()).await {
+                Ok(Some(msg)) => msg.map_err(io::Error::other)?,
+                Ok(None) => Message::Close(None),
+                Err(_) => {
+                    if let Some(k) = self.keepalive {
+                        if self.last_tx.elapsed() >= k {
+                            self.send_keepalive().await?;
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            match msg {
+                Message::Binary(b) => self.decoder.push_binary(&b),
+                Message::Text(t) => {
+                    self.decoder.push_text(t.as_str());
+                }
+                Message::Close(_) => {
+                    return match self.decoder.take_partial() {
+                        Some(line) => Ok(line),
+                        None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "ws closed")),
+                    };
+                }
+                _ => {}
+            }
         }
     }
 }
@@ -123,7 +235,6 @@ mod tests {
     use super::*;
     use futures_util::{SinkExt, StreamExt};
     use tokio::net::TcpListener;
-    use tokio_tungstenite::tungstenite::Message;
 
     #[tokio::test]
     async fn test_ws_connect_send_receive() {
@@ -135,9 +246,9 @@ mod tests {
             let (stream, _) = listener.accept().await.unwrap();
             let ws = tokio_tungstenite::accept_async(stream).await.unwrap();
             let (mut sink, mut stream) = ws.split();
-            // Echo loop: send back first text frame
+            // Echo the first text frame back newline-terminated
             if let Some(Ok(msg)) = stream.next().await {
-                let _ = sink.send(msg).await;
+                let _ = sink.send(Message::Text(format!("{}\n", msg.to_text().unwrap()).into())).await;
             }
         });
 
@@ -0,0 +1,246 @@
+//! Network discovery of FluidNC, ESP3D and grblHAL controllers.
+//!
+//! Combines an mDNS browse with an optional TCP port scan of a subnet (for
+//! boards with mDNS disabled). Each peer found carries a transport hint and
+//! an endpoint string suitable for `DeviceManager::connect_endpoint`.
+
+use crate::mdns::{self, MdnsQuery, MdnsService};
+use crate::network::discover_tcp_peer;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Ports probed by the subnet scan when none are configured: telnet and the
+/// HTTP/WebSocket ports used by the ESP32 web UIs.
+pub const DEFAULT_SCAN_PORTS: &[u16] = &[23, 80, 81];
+
+/// Largest subnet the scan will walk (a /22, 1022 hosts).
+pub const MIN_SCAN_PREFIX: u8 = 22;
+
+/// How a discovered peer was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerSource {
+    Mdns,
+    PortScan,
+}
+
+/// A controller found on the network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkPeer {
+    /// Advertised instance name, or the address for scanned peers.
+    pub name: String,
+    pub addr: SocketAddr,
+    /// Transport hint: `telnet`, `websocket` or `tcp`.
+    pub transport: String,
+    pub source: PeerSource,
+    /// mDNS TXT entries, empty for scanned peers.
+    pub txt: Vec<String>,
+}
+
+impl NetworkPeer {
+    /// Endpoint string for connecting to this peer, e.g. `telnet://10.0.0.5:23`.
+    pub fn endpoint(&self) -> String {
+        let scheme = match self.transport.as_str() {
+            "telnet" => "telnet",
+            "websocket" => "ws",
+            _ => "tcp",
+        };
+        format!("{}://{}", scheme, self.addr)
+    }
+}
+
+/// How long the default mDNS browse collects answers.
+pub const DEFAULT_MDNS_TIMEOUT: Duration = Duration::from_millis(400);
+
+/// Options for `discover`. mDNS is on by default with a short browse; the
+/// subnet scan is off, since it probes every host on the subnet.
+#[derive(Debug, Clone)]
+pub struct DiscoveryOptions {
+    /// mDNS browse parameters; `None` disables mDNS.
+    pub mdns: Option<MdnsQuery>,
+    /// Subnet to scan as (network, prefix length); `None` disables scanning.
+    pub scan_subnet: Option<(Ipv4Addr, u8)>,
+    pub scan_ports: Vec<u16>,
+    /// Per-connection timeout used by the scan.
+    pub connect_timeout: Duration,
+    /// Number of concurrent connection attempts during a scan.
+    pub parallelism: usize,
+}
+
+impl Default for DiscoveryOptions {
+    fn default() -> Self {
+        DiscoveryOptions {
+            mdns: Some(MdnsQuery {
+                timeout: DEFAULT_MDNS_TIMEOUT,
+                ..Default::default()
+            }),
+            scan_subnet: None,
+            scan_ports: DEFAULT_SCAN_PORTS.to_vec(),
+            connect_timeout: Duration::from_millis(200),
+            parallelism: 64,
+        }
+    }
+}
+
+/// Transport hint for a TCP port found open by the scan.
+pub fn transport_hint_for_port(port: u16) -> &'static str {
+    match port {
+        23 => "telnet",
+        80 | 81 | 8080 => "websocket",
+        _ => "tcp",
+    }
+}
+
+/// Names that mark an mDNS service as a CNC controller, matched against
+/// TXT values and instance and host names.
+const CONTROLLER_MARKERS: &[&str] = &["fluidnc", "esp3d", "grblhal", "grbl"];
+
+/// Whether an `_http._tcp` service is a controller's web UI rather than a
+/// printer, NAS or router: a TXT entry (e.g. `firmware=ESP3D`) or the
+/// instance or host name names FluidNC, ESP3D or grblHAL.
+pub fn is_controller_http(service: &MdnsService) -> bool {
+    let names = [service.instance.as_str(), service.host.as_str()];
+    names
+        .into_iter()
+        .chain(service.txt.iter().map(String::as_str))
+        .map(str::to_ascii_lowercase)
+        .any(|text| CONTROLLER_MARKERS.iter().any(|m| text.contains(m)))
+}
+
+/// Transport hint and connect port for an mDNS service, or `None` when it
+/// is not a controller. ESP3D and FluidNC serve the WebUI socket on the
+/// HTTP port + 1.
+pub fn transport_for_service(service: &MdnsService) -> Option<(&'static str, u16)> {
+    let kind = service.service.split('.').next().unwrap_or_default();
+    match kind {
+        "_telnet" | "_fluidnc" => Some(("telnet", service.port)),
+        "_http" if is_controller_http(service) => Some(("websocket", service.port.saturating_add(1))),
+        "_http" => None,
+        _ => Some(("tcp", service.port)),
+    }
+}
+
+/// Guess the local IPv4 address used for LAN traffic. No packets are sent.
+pub fn local_ipv4() -> io::Result<Ipv4Addr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.connect(mdns::MDNS_ADDR)?;
+    match socket.local_addr()?.ip() {
+        IpAddr::V4(ip) if !ip.is_unspecified() => Ok(ip),
+        _ => Err(io::Error::new(io::ErrorKind::NotFound, "no local ipv4 address")),
+    }
+}
+
+/// Parse a subnet in CIDR form (`192.168.1.0/24`). `auto` selects the /24
+/// containing `local_ipv4()`.
+pub fn parse_subnet(s: &str) -> io::Result<(Ipv4Addr, u8)> {
+    if s.eq_ignore_ascii_case("auto") {
+        let ip = local_ipv4()?;
+        return Ok((ip, 24));
+    }
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("invalid subnet: {}", s));
+    let (ip, prefix) = s.split_once('/').ok_or_else(invalid)?;
+    let ip: Ipv4Addr = ip.parse().map_err(|_| invalid())?;
+    let prefix: u8 = prefix.parse().map_err(|_| invalid())?;
+    if prefix > 32 {
+        return Err(invalid());
+    }
+    Ok((ip, prefix))
+}
+
+/// Host addresses in a subnet, excluding the network and broadcast
+/// addresses. Subnets larger than `MIN_SCAN_PREFIX` are rejected.
+pub fn subnet_hosts(net: Ipv4Addr, prefix: u8) -> io::Result<Vec<Ipv4Addr>> {
+    if !(MIN_SCAN_PREFIX..=32).contains(&prefix) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("subnet /{} too large to scan (minimum /{})", prefix, MIN_SCAN_PREFIX),
+        ));
+    }
+    if prefix >= 31 {
+        return Ok(vec![net]);
+    }
+    let mask = u32::MAX << (32 - prefix);
+    let base = u32::from(net) & mask;
+    let size = 1u32 << (32 - prefix);
+    Ok((1..size - 1).map(|i| Ipv4Addr::from(base + i)).collect())
+}
+
+/// Probe every host/port pair with `discover_tcp_peer`, running up to
+/// `parallelism` attempts at once. Returns the addresses that accepted.
+pub fn scan_tcp(hosts: &[Ipv4Addr], ports: &[u16], timeout: Duration, parallelism: usize) -> Vec<SocketAddr> {
+    let targets: Vec<SocketAddr> = hosts
+        .iter()
+        .flat_map(|h| ports.iter().map(move |p| SocketAddr::new(IpAddr::V4(*h), *p)))
+        .collect();
+    let mut found = Vec::new();
+    for chunk in targets.chunks(parallelism.max(1)) {
+        let handles: Vec<_> = chunk
+            .iter()
+            .map(|addr| {
+                let addr = *addr;
+                std::thread::spawn(move || discover_tcp_peer(&addr.to_string(), timeout).ok().map(|_| addr))
+            })
+            .collect();
+        found.extend(handles.into_iter().filter_map(|h| h.join().ok().flatten()));
+    }
+    debug!(probed = targets.len(), open = found.len(), "discovery::scan_tcp: completed");
+    found
+}
+
+/// Run mDNS and/or the subnet scan as configured. Failures of either method
+/// are logged and yield no peers rather than an error, so discovery works
+/// on hosts without multicast or with firewalled interfaces.
+pub fn discover(opts: &DiscoveryOptions) -> Vec<NetworkPeer> {
+    let mut peers: Vec<NetworkPeer> = Vec::new();
+
+    if let Some(query) = &opts.mdns {
+        match mdns::browse(query) {
+            Ok(services) => {
+                for s in services {
+                    let Some((transport, port)) = transport_for_service(&s) else {
+                        debug!(instance = %s.instance, "discovery::discover: skipping non-controller service");
+                        continue;
+                    };
+                    for ip in &s.addrs {
+                        let addr = SocketAddr::new(*ip, port);
+                        if peers.iter().any(|p| p.addr == addr) {
+                            continue;
+                        }
+                        peers.push(NetworkPeer {
+                            name: s.instance.clone(),
+                            addr,
+                            transport: transport.to_string(),
+                            source: PeerSource::Mdns,
+                            txt: s.txt.clone(),
+                        });
+                    }
+                }
+            }
+            Err(e) => warn!(error = %e, "discovery::discover: mdns browse failed"),
+        }
+    }
+
+    if let Some((net, prefix)) = opts.scan_subnet {
+        match subnet_hosts(net, prefix) {
+            Ok(hosts) => {
+                info!(subnet = %format!("{}/{}", net, prefix), "discovery::discover: scanning subnet");
+                for addr in scan_tcp(&hosts, &opts.scan_ports, opts.connect_timeout, opts.parallelism) {
+                    if peers.iter().any(|p| p.addr == addr) {
+                        continue;
+                    }
+                    peers.push(NetworkPeer {
+                        name: addr.ip().to_string(),
+                        addr,
+                        transport: transport_hint_for_port(addr.port()).to_string(),
+                        source: PeerSource::PortScan,
+                        txt: Vec::new(),
+                    });
+                }
+            }
+            Err(e) => warn!(error = %e, "discovery::discover: subnet scan skipped"),
+        }
+    }
+
+    peers
+}
@@ -0,0 +1,162 @@
+//! Helpers for turning arbitrary byte chunks into protocol lines.
+
+use std::collections::VecDeque;
+use tracing::{debug, warn};
+
+/// Reassembles newline-terminated lines from byte chunks that may split or
+/// join lines arbitrarily (TCP segments, telnet payloads, WebSocket frames).
+///
+/// Trailing `\r` characters are stripped from completed lines so callers see
+/// the same text regardless of whether the device uses `\n` or `\r\n`.
+#[derive(Debug, Default, Clone)]
+pub struct LineAssembler {
+    pending: Vec<u8>,
+}
+
+impl LineAssembler {
+    pub fn new() -> Self {
+        LineAssembler { pending: Vec::new() }
+    }
+
+    /// Append raw bytes to the internal buffer.
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.pending.extend_from_slice(bytes);
+    }
+
+    /// Pop the next complete line, if one is buffered.
+    pub fn next_line(&mut self) -> Option<String> {
+        let idx = self.pending.iter().position(|b| *b == b'\n')?;
+        let mut raw: Vec<u8> = self.pending.drain(..=idx).collect();
+        raw.pop();
+        while raw.last() == Some(&b'\r') {
+            raw.pop();
+        }
+        Some(String::from_utf8_lossy(&raw).into_owned())
+    }
+
+    /// Take whatever partial line is buffered, e.g. when the peer closes the
+    /// connection without a final newline. Returns `None` when empty.
+    pub fn take_partial(&mut self) -> Option<String> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let raw = std::mem::take(&mut self.pending);
+        let s = String::from_utf8_lossy(&raw);
+        Some(s.trim_end_matches('\r').to_string())
+    }
+
+    /// Number of bytes buffered that do not yet form a complete line.
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Discard any buffered data.
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+}
+
+/// Control messages ESP3D and FluidNC web servers exchange over text frames.
+/// They manage the browser session and never carry controller output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Esp3dControl {
+    /// `currentID:<id>` sent right after connecting; identifies this client.
+    CurrentId(String),
+    /// `activeID:<id>` broadcast when a client becomes the active one.
+    ActiveId(String),
+    /// `PING:<...>` session keepalive.
+    Ping(String),
+}
+
+impl Esp3dControl {
+    /// Parse a text frame as an ESP3D control message. Both the ESP3D
+    /// (`currentID`) and FluidNC (`CURRENT_ID`) spellings are accepted.
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim_end_matches(['\r', '\n']);
+        let (key, value) = text.split_once(':')?;
+        let value = value.trim().to_string();
+        match key {
+            "currentID" | "CURRENT_ID" => Some(Esp3dControl::CurrentId(value)),
+            "activeID" | "ACTIVE_ID" => Some(Esp3dControl::ActiveId(value)),
+            "PING" => Some(Esp3dControl::Ping(value)),
+            _ => None,
+        }
+    }
+}
+
+/// Turns WebSocket frames from FluidNC/ESP3D boards into protocol lines.
+///
+/// Binary and text frames both carry the serial stream and may split or
+/// join lines, so both are fed, in arrival order, through one
+/// `LineAssembler`. A text frame that is an ESP3D control message is
+/// consumed and never surfaced as a line.
+#[derive(Debug, Default, Clone)]
+pub struct WsFrameDecoder {
+    lines: LineAssembler,
+    ready: VecDeque<String>,
+    session_id: Option<String>,
+    active_id: Option<String>,
+}
+
+impl WsFrameDecoder {
+    pub fn new() -> Self {
+        WsFrameDecoder::default()
+    }
+
+    /// Feed the payload of a binary frame.
+    pub fn push_binary(&mut self, bytes: &[u8]) {
+        self.lines.push(bytes);
+        while let Some(line) = self.lines.next_line() {
+            self.ready.push_back(line);
+        }
+    }
+
+    /// Feed the payload of a text frame. Returns the control message if the
+    /// frame was one.
+    pub fn push_text(&mut self, text: &str) -> Option<Esp3dControl> {
+        if let Some(ctrl) = Esp3dControl::parse(text) {
+            match &ctrl {
+                Esp3dControl::CurrentId(id) => {
+                    debug!(id = %id, "framing::push_text: ESP3D session id assigned");
+                    self.session_id = Some(id.clone());
+                }
+                Esp3dControl::ActiveId(id) => {
+                    if self.session_id.as_deref().is_some_and(|own| own != id) {
+                        warn!(id = %id, "framing::push_text: another client became active");
+                    }
+                    self.active_id = Some(id.clone());
+                }
+                Esp3dControl::Ping(_) => {}
+            }
+            return Some(ctrl);
+        }
+        self.push_binary(text.as_bytes());
+        None
+    }
+
+    /// Pop the next complete line.
+    pub fn next_line(&mut self) -> Option<String> {
+        self.ready.pop_front()
+    }
+
+    /// Take a partial line left over when the connection closes.
+    pub fn take_partial(&mut self) -> Option<String> {
+        self.lines.take_partial()
+    }
+
+    /// Session id assigned by the board via `currentID`, if any.
+    pub fn session_id(&self) -> Option<&str> {
+        self.session_id.as_deref()
+    }
+
+    /// Whether another client has taken over the board's active session.
+    pub fn superseded(&self) -> bool {
+        matches!((&self.session_id, &self.active_id), (Some(own), Some(active)) if own != active)
+    }
+
+    /// The `PING:<id>` keepalive ESP3D expects from a connected client, once
+    /// a session id has been assigned.
+    pub fn ping_message(&self) -> Option<String> {
+        self.session_id.as_ref().map(|id| format!("PING:{}", id))
+    }
+}
@@ -1,8 +1,16 @@
 //! Device adapters for GCodeKit6 (GRBL, TinyG, Smoothieware)
 pub mod async_network;
 pub mod async_serial;
+pub mod async_telnet;
+pub mod discovery;
+pub mod framing;
+pub mod mdns;
 pub mod network;
+pub mod replay;
 pub mod serial;
+pub mod sim;
+pub mod telnet;
+pub mod tls;
 #[cfg(all(feature = "async", feature = "websocket"))]
 pub mod async_websocket;
 #[cfg(feature = "websocket")]
@@ -33,6 +41,21 @@ pub trait Transport: Send + Sync {
     /// Read a single line (terminated by newline) from the transport. Returns
     /// the line without the trailing newline.
     fn read_line(&mut self) -> std::io::Result<String>;
+
+    /// Send a single realtime command byte (e.g. `?`, `!`, `~`, `0x18`,
+    /// `0x85`) without a line terminator, bypassing the line protocol.
+    fn send_realtime(&mut self, _byte: u8) -> std::io::Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "transport does not support realtime commands",
+        ))
+    }
+
+    /// Bound how long `read_line` blocks before returning `TimedOut` or
+    /// `WouldBlock`. Transports that never block may ignore this.
+    fn set_read_timeout(&mut self, _timeout: std::time::Duration) -> std::io::Result<()> {
+        Ok(())
+    }
 }
 
 /// AsyncTransport: an async counterpart to `Transport` that uses async I/O.
@@ -103,6 +126,44 @@ impl AsyncTransport for async_serial::AsyncSerialTransport {
     }
 }
 
+#[cfg(feature = "async")]
+#[async_trait::async_trait]
+impl AsyncTransport for async_telnet::AsyncTelnetTransport {
+    async fn send_line(&mut self, line: &str) -> std::io::Result<()> {
+        self.send_line(line).await
+    }
+
+    async fn emergency_stop(&mut self) -> std::io::Result<()> {
+        self.emergency_stop().await
+    }
+
+    async fn flush(&mut self) -> std::io::Result<()> {
+        self.flush().await
+    }
+
+    async fn disconnect(&mut self) -> std::io::Result<()> {
+        self.disconnect().await
+    }
+
+    async fn is_alive(&self) -> std::io::Result<bool> {
+        self.is_alive().await
+    }
+
+    async fn read_line(&mut self) -> std::io::Result<String> {
+        self.read_line().await
+    }
+}
+
+/// Create an async telnet transport (boxed dyn AsyncTransport). `addr` may
+/// be a `host:port` string; hostnames are resolved.
+#[cfg(feature = "async")]
+pub async fn create_async_telnet_transport(
+    addr: impl tokio::net::ToSocketAddrs,
+) -> std::io::Result<Box<dyn AsyncTransport>> {
+    let conn = async_telnet::AsyncTelnetTransport::connect(addr).await?;
+    Ok(Box::new(conn))
+}
+
 /// Factory to create an async serial transport from path and options. This wraps the blocking serial port.
 #[cfg(feature = "async")]
 pub fn create_serial_async_transport_with_options(
@@ -138,6 +199,14 @@ impl Transport for network::NetworkConnection {
     fn read_line(&mut self) -> std::io::Result<String> {
         network::NetworkConnection::read_line(self)
     }
+
+    fn send_realtime(&mut self, byte: u8) -> std::io::Result<()> {
+        network::NetworkConnection::send_realtime(self, byte)
+    }
+
+    fn set_read_timeout(&mut self, timeout: std::time::Duration) -> std::io::Result<()> {
+        network::NetworkConnection::set_read_timeout(self, timeout)
+    }
 }
 
 /// Create a TCP transport boxed as a `Transport` trait object.
@@ -146,6 +215,137 @@ pub fn create_tcp_transport(addr: std::net::SocketAddr) -> std::io::Result<Box<d
     Ok(Box::new(conn))
 }
 
+impl Transport for telnet::TelnetConnection {
+    fn send_line(&mut self, line: &str) -> std::io::Result<()> {
+        telnet::TelnetConnection::send_line(self, line)
+    }
+
+    fn emergency_stop(&mut self) -> std::io::Result<()> {
+        telnet::TelnetConnection::emergency_stop(self)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        telnet::TelnetConnection::flush(self)
+    }
+
+    fn disconnect(&mut self) -> std::io::Result<()> {
+        telnet::TelnetConnection::disconnect(self)
+    }
+
+    fn is_alive(&self) -> std::io::Result<bool> {
+        telnet::TelnetConnection::is_alive(self)
+    }
+
+    fn read_line(&mut self) -> std::io::Result<String> {
+        telnet::TelnetConnection::read_line(self)
+    }
+
+    fn send_realtime(&mut self, byte: u8) -> std::io::Result<()> {
+        telnet::TelnetConnection::send_realtime(self, byte)
+    }
+
+    fn set_read_timeout(&mut self, timeout: std::time::Duration) -> std::io::Result<()> {
+        telnet::TelnetConnection::set_read_timeout(self, timeout)
+    }
+}
+
+/// Create a telnet transport boxed as a `Transport` trait object. Accepts
+/// anything resolvable to socket addresses, so `fluidnc.local:23` works.
+pub fn create_telnet_transport<A: std::net::ToSocketAddrs>(
+    addr: A,
+) -> std::io::Result<Box<dyn Transport>> {
+    let conn = telnet::TelnetConnection::connect(addr)?;
+    Ok(Box::new(conn))
+}
+
+/// Create a UDP transport bound to `bind` and connected to `peer`.
+pub fn create_udp_transport(bind: &str, peer: &str) -> std::io::Result<Box<dyn Transport>> {
+    let conn = network::NetworkConnection::connect_udp(bind, peer)?;
+    Ok(Box::new(conn))
+}
+
+impl Transport for sim::SimTransport {
+    fn send_line(&mut self, line: &str) -> std::io::Result<()> {
+        sim::SimTransport::send_line(self, line)
+    }
+
+    fn emergency_stop(&mut self) -> std::io::Result<()> {
+        sim::SimTransport::emergency_stop(self)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        sim::SimTransport::flush(self)
+    }
+
+    fn disconnect(&mut self) -> std::io::Result<()> {
+        sim::SimTransport::disconnect(self)
+    }
+
+    fn is_alive(&self) -> std::io::Result<bool> {
+        sim::SimTransport::is_alive(self)
+    }
+
+    fn read_line(&mut self) -> std::io::Result<String> {
+        sim::SimTransport::read_line(self)
+    }
+
+    fn send_realtime(&mut self, byte: u8) -> std::io::Result<()> {
+        sim::SimTransport::send_realtime(self, byte)
+    }
+}
+
+/// Create a simulated controller transport.
+pub fn create_sim_transport(opts: sim::SimOptions) -> std::io::Result<Box<dyn Transport>> {
+    Ok(Box::new(sim::SimTransport::new(opts)))
+}
+
+impl Transport for replay::ReplayTransport {
+    fn send_line(&mut self, line: &str) -> std::io::Result<()> {
+        replay::ReplayTransport::send_line(self, line)
+    }
+
+    fn emergency_stop(&mut self) -> std::io::Result<()> {
+        replay::ReplayTransport::emergency_stop(self)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        replay::ReplayTransport::flush(self)
+    }
+
+    fn disconnect(&mut self) -> std::io::Result<()> {
+        replay::ReplayTransport::disconnect(self)
+    }
+
+    fn is_alive(&self) -> std::io::Result<bool> {
+        replay::ReplayTransport::is_alive(self)
+    }
+
+    fn read_line(&mut self) -> std::io::Result<String> {
+        replay::ReplayTransport::read_line(self)
+    }
+
+    fn send_realtime(&mut self, byte: u8) -> std::io::Result<()> {
+        replay::ReplayTransport::send_realtime(self, byte)
+    }
+}
+
+/// Create a transport replaying the recorded session at `path`.
+pub fn create_replay_transport(
+    path: &std::path::Path,
+    opts: replay::ReplayOptions,
+) -> std::io::Result<Box<dyn Transport>> {
+    Ok(Box::new(replay::ReplayTransport::open(path, opts)?))
+}
+
+/// Create a telnet transport with explicit negotiation/keepalive options.
+pub fn create_telnet_transport_with_options<A: std::net::ToSocketAddrs>(
+    addr: A,
+    opts: telnet::TelnetOptions,
+) -> std::io::Result<Box<dyn Transport>> {
+    let conn = telnet::TelnetConnection::connect_with_options(addr, opts)?;
+    Ok(Box::new(conn))
+}
+
 /// Create an async websocket transport (boxed dyn AsyncTransport) when feature is enabled.
 #[cfg(all(feature = "async", feature = "websocket"))]
 pub async fn create_async_websocket_transport(url: &str) -> std::io::Result<Box<dyn AsyncTransport>> {
@@ -154,6 +354,16 @@ pub async fn create_async_websocket_transport(url: &str) -> std::io::Result<Box<
     Ok(Box::new(conn))
 }
 
+/// Create an async websocket transport using explicit TLS settings for wss:// URLs.
+#[cfg(all(feature = "async", feature = "websocket"))]
+pub async fn create_async_websocket_transport_with_tls(
+    url: &str,
+    tls: &tls::TlsOptions,
+) -> std::io::Result<Box<dyn AsyncTransport>> {
+    let conn = async_websocket::AsyncWebSocketTransport::connect_with_tls(url, tls).await?;
+    Ok(Box::new(conn))
+}
+
 /// Create a serial transport from a device path and baud rate.
 pub fn create_serial_transport(
     path: &str,
@@ -185,15 +395,25 @@ pub fn create_serial_transport_with_options(
 /// If the crate was built without the `websocket` feature this returns an error
 /// explaining the missing feature.
 pub fn create_websocket_transport(url: &str) -> std::io::Result<Box<dyn Transport>> {
+    create_websocket_transport_with_tls(url, &tls::TlsOptions::default())
+}
+
+/// Create a synchronous websocket transport, using `tls` for wss:// URLs
+/// (custom CA bundle, client certificate). wss:// additionally requires the
+/// `websocket-tls` feature.
+pub fn create_websocket_transport_with_tls(
+    url: &str,
+    tls: &tls::TlsOptions,
+) -> std::io::Result<Box<dyn Transport>> {
         #[cfg(feature = "websocket")]
     {
-        let conn = websocket_sync::WebSocketTransport::connect(url)?;
+        let conn = websocket_sync::WebSocketTransport::connect_with_tls(url, tls)?;
         Ok(Box::new(conn))
     }
 
     #[cfg(not(feature = "websocket"))]
     {
-        let _ = url; // silence unused variable when websocket not enabled
+        let _ = (url, tls); // silence unused variables when websocket not enabled
         Err(std::io::Error::other(
             "device-adapters crate built without 'websocket' feature",
         ))
@@ -224,6 +444,14 @@ impl Transport for serial::SerialConnection {
     fn read_line(&mut self) -> std::io::Result<String> {
         serial::SerialConnection::read_line(self)
     }
+
+    fn send_realtime(&mut self, byte: u8) -> std::io::Result<()> {
+        serial::SerialConnection::send_realtime(self, byte)
+    }
+
+    fn set_read_timeout(&mut self, timeout: std::time::Duration) -> std::io::Result<()> {
+        serial::SerialConnection::set_read_timeout(self, timeout)
+    }
 }
 
 pub fn hello_adapters() -> &'static str {
@@ -0,0 +1,366 @@
+//! Minimal mDNS (RFC 6762) service browser for finding networked controllers.
+//!
+//! Only what discovery needs is implemented: PTR queries for a set of
+//! service types and decoding of the PTR/SRV/TXT/A/AAAA records returned.
+//! Queries are sent from an ephemeral port, so responders answer by unicast
+//! ("legacy unicast") and no socket needs to join the multicast group.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// Standard mDNS multicast group and port.
+pub const MDNS_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(224, 0, 0, 251)), 5353);
+
+/// Service types advertised by FluidNC, ESP3D and grblHAL boards.
+pub const DEFAULT_SERVICES: &[&str] = &["_http._tcp.local", "_telnet._tcp.local", "_fluidnc._tcp.local"];
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+
+/// A service instance resolved from mDNS answers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MdnsService {
+    /// Instance label, e.g. `fluidnc` for `fluidnc._telnet._tcp.local`.
+    pub instance: String,
+    /// Service type, e.g. `_telnet._tcp.local`.
+    pub service: String,
+    /// Target host from the SRV record, e.g. `fluidnc.local`.
+    pub host: String,
+    pub port: u16,
+    pub addrs: Vec<IpAddr>,
+    /// TXT record strings (`key=value`).
+    pub txt: Vec<String>,
+}
+
+impl MdnsService {
+    /// Value of a `key=value` TXT entry.
+    pub fn txt_value(&self, key: &str) -> Option<&str> {
+        self.txt
+            .iter()
+            .filter_map(|t| t.split_once('='))
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v)
+    }
+}
+
+/// Parameters for a single browse.
+#[derive(Debug, Clone)]
+pub struct MdnsQuery {
+    /// Where to send the query; the multicast group unless testing against
+    /// a local responder.
+    pub target: SocketAddr,
+    pub services: Vec<String>,
+    /// How long to collect answers.
+    pub timeout: Duration,
+}
+
+impl Default for MdnsQuery {
+    fn default() -> Self {
+        MdnsQuery {
+            target: MDNS_ADDR,
+            services: DEFAULT_SERVICES.iter().map(|s| s.to_string()).collect(),
+            timeout: Duration::from_millis(750),
+        }
+    }
+}
+
+/// A decoded resource record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Record {
+    Ptr { name: String, target: String },
+    Srv { name: String, port: u16, target: String },
+    Txt { name: String, entries: Vec<String> },
+    Addr { name: String, addr: IpAddr },
+}
+
+fn encode_name(out: &mut Vec<u8>, name: &str) {
+    for label in name.trim_end_matches('.').split('.').filter(|l| !l.is_empty()) {
+        out.push(label.len().min(63) as u8);
+        out.extend_from_slice(&label.as_bytes()[..label.len().min(63)]);
+    }
+    out.push(0);
+}
+
+fn header(out: &mut Vec<u8>, flags: u16, questions: u16, answers: u16) {
+    out.extend_from_slice(&0u16.to_be_bytes());
+    out.extend_from_slice(&flags.to_be_bytes());
+    out.extend_from_slice(&questions.to_be_bytes());
+    out.extend_from_slice(&answers.to_be_bytes());
+    out.extend_from_slice(&[0, 0, 0, 0]);
+}
+
+/// Build a PTR query for the given service types.
+pub fn build_query(services: &[String]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(12 + services.len() * 24);
+    header(&mut out, 0, services.len() as u16, 0);
+    for s in services {
+        encode_name(&mut out, s);
+        out.extend_from_slice(&TYPE_PTR.to_be_bytes());
+        out.extend_from_slice(&CLASS_IN.to_be_bytes());
+    }
+    out
+}
+
+fn push_record(out: &mut Vec<u8>, name: &str, rtype: u16, rdata: &[u8]) {
+    encode_name(out, name);
+    out.extend_from_slice(&rtype.to_be_bytes());
+    out.extend_from_slice(&CLASS_IN.to_be_bytes());
+    out.extend_from_slice(&120u32.to_be_bytes());
+    out.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+    out.extend_from_slice(rdata);
+}
+
+/// Build an authoritative response advertising `services`. Used by device
+/// simulators and tests standing in for a real responder.
+pub fn build_response(services: &[MdnsService]) -> Vec<u8> {
+    let mut body = Vec::new();
+    let mut count = 0u16;
+    for s in services {
+        let full = format!("{}.{}", s.instance, s.service);
+        let mut rdata = Vec::new();
+        encode_name(&mut rdata, &full);
+        push_record(&mut body, &s.service, TYPE_PTR, &rdata);
+
+        let mut rdata = vec![0, 0, 0, 0];
+        rdata.extend_from_slice(&s.port.to_be_bytes());
+        encode_name(&mut rdata, &s.host);
+        push_record(&mut body, &full, TYPE_SRV, &rdata);
+
+        let mut rdata = Vec::new();
+        for t in &s.txt {
+            rdata.push(t.len().min(255) as u8);
+            rdata.extend_from_slice(&t.as_bytes()[..t.len().min(255)]);
+        }
+        if rdata.is_empty() {
+            rdata.push(0);
+        }
+        push_record(&mut body, &full, TYPE_TXT, &rdata);
+        count += 3;
+
+        for addr in &s.addrs {
+            match addr {
+                IpAddr::V4(v4) => push_record(&mut body, &s.host, TYPE_A, &v4.octets()),
+                IpAddr::V6(v6) => push_record(&mut body, &s.host, TYPE_AAAA, &v6.octets()),
+            }
+            count += 1;
+        }
+    }
+    let mut out = Vec::with_capacity(12 + body.len());
+    header(&mut out, 0x8400, 0, count);
+    out.extend_from_slice(&body);
+    out
+}
+
+fn malformed(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("malformed mdns packet: {}", what))
+}
+
+fn read_u16(buf: &[u8], pos: usize) -> io::Result<u16> {
+    buf.get(pos..pos + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .ok_or_else(|| malformed("truncated"))
+}
+
+/// Read a possibly compressed name at `pos`, returning it and the offset
+/// just past it in the original position.
+fn read_name(buf: &[u8], mut pos: usize) -> io::Result<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut end = None;
+    let mut jumps = 0;
+    loop {
+        let len = *buf.get(pos).ok_or_else(|| malformed("name"))? as usize;
+        if len == 0 {
+            end.get_or_insert(pos + 1);
+            break;
+        }
+        if len & 0xC0 == 0xC0 {
+            let ptr = (read_u16(buf, pos)? & 0x3FFF) as usize;
+            end.get_or_insert(pos + 2);
+            jumps += 1;
+            if jumps > 16 {
+                return Err(malformed("compression loop"));
+            }
+            pos = ptr;
+            continue;
+        }
+        let label = buf.get(pos + 1..pos + 1 + len).ok_or_else(|| malformed("label"))?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        pos += 1 + len;
+    }
+    Ok((labels.join("."), end.unwrap_or(pos)))
+}
+
+/// Decode every answer, authority and additional record in a response.
+/// Record types discovery does not use are skipped.
+pub fn parse_response(buf: &[u8]) -> io::Result<Vec<Record>> {
+    if buf.len() < 12 {
+        return Err(malformed("short header"));
+    }
+    let flags = read_u16(buf, 2)?;
+    if flags & 0x8000 == 0 {
+        // A query from another browser on the network
+        return Ok(Vec::new());
+    }
+    let qd = read_u16(buf, 4)? as usize;
+    let rr = read_u16(buf, 6)? as usize + read_u16(buf, 8)? as usize + read_u16(buf, 10)? as usize;
+
+    let mut pos = 12;
+    for _ in 0..qd {
+        let (_, next) = read_name(buf, pos)?;
+        pos = next + 4;
+    }
+
+    let mut out = Vec::new();
+    for _ in 0..rr {
+        let (name, next) = read_name(buf, pos)?;
+        let rtype = read_u16(buf, next)?;
+        let rdlen = read_u16(buf, next + 8)? as usize;
+        let start = next + 10;
+        let rdata = buf.get(start..start + rdlen).ok_or_else(|| malformed("rdata"))?;
+        match rtype {
+            TYPE_PTR => {
+                let (target, _) = read_name(buf, start)?;
+                out.push(Record::Ptr { name, target });
+            }
+            TYPE_SRV if rdlen >= 7 => {
+                let port = read_u16(buf, start + 4)?;
+                let (target, _) = read_name(buf, start + 6)?;
+                out.push(Record::Srv { name, port, target });
+            }
+            TYPE_TXT => {
+                let mut entries = Vec::new();
+                let mut i = 0;
+                while i < rdata.len() {
+                    let len = rdata[i] as usize;
+                    if let Some(s) = rdata.get(i + 1..i + 1 + len) {
+                        if !s.is_empty() {
+                            entries.push(String::from_utf8_lossy(s).into_owned());
+                        }
+                    }
+                    i += 1 + len;
+                }
+                out.push(Record::Txt { name, entries });
+            }
+            TYPE_A if rdlen == 4 => {
+                let addr = IpAddr::V4(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3]));
+                out.push(Record::Addr { name, addr });
+            }
+            TYPE_AAAA if rdlen == 16 => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(rdata);
+                out.push(Record::Addr { name, addr: IpAddr::V6(Ipv6Addr::from(octets)) });
+            }
+            _ => {}
+        }
+        pos = start + rdlen;
+    }
+    Ok(out)
+}
+
+/// Resolve service instances for `services` from a set of records. `source`
+/// maps an SRV owner name to the address that sent it, used when the
+/// responder omitted address records.
+fn resolve(records: &[Record], services: &[String], source: &HashMap<String, IpAddr>) -> Vec<MdnsService> {
+    let mut out: Vec<MdnsService> = Vec::new();
+    for r in records {
+        let Record::Ptr { name, target } = r else { continue };
+        let Some(service) = services.iter().find(|s| s.eq_ignore_ascii_case(name)) else {
+            continue;
+        };
+        if out.iter().any(|s| s.service == *service && format!("{}.{}", s.instance, s.service) == *target) {
+            continue;
+        }
+        let Some((port, host)) = records.iter().find_map(|r| match r {
+            Record::Srv { name, port, target: host } if name.eq_ignore_ascii_case(target) => Some((*port, host.clone())),
+            _ => None,
+        }) else {
+            continue;
+        };
+        let txt = records
+            .iter()
+            .find_map(|r| match r {
+                Record::Txt { name, entries } if name.eq_ignore_ascii_case(target) => Some(entries.clone()),
+                _ => None,
+            })
+            .unwrap_or_default();
+        let mut addrs: Vec<IpAddr> = records
+            .iter()
+            .filter_map(|r| match r {
+                Record::Addr { name, addr } if name.eq_ignore_ascii_case(&host) => Some(*addr),
+                _ => None,
+            })
+            .collect();
+        addrs.dedup();
+        if addrs.is_empty() {
+            if let Some(ip) = source.get(target) {
+                addrs.push(*ip);
+            }
+        }
+        let instance = target
+            .strip_suffix(service.as_str())
+            .map(|s| s.trim_end_matches('.'))
+            .unwrap_or(target)
+            .to_string();
+        out.push(MdnsService {
+            instance,
+            service: service.clone(),
+            host,
+            port,
+            addrs,
+            txt,
+        });
+    }
+    out
+}
+
+/// Send a query and collect the services that answer before the timeout.
+pub fn browse(query: &MdnsQuery) -> io::Result<Vec<MdnsService>> {
+    let bind: SocketAddr = match query.target {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(bind)?;
+    if query.target.ip().is_multicast() && query.target.is_ipv4() {
+        socket.set_multicast_ttl_v4(255)?;
+    }
+    debug!(target = %query.target, services = ?query.services, "mdns::browse: sending query");
+    socket.send_to(&build_query(&query.services), query.target)?;
+
+    let deadline = Instant::now() + query.timeout;
+    let mut records = Vec::new();
+    let mut source = HashMap::new();
+    let mut buf = [0u8; 9000];
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        socket.set_read_timeout(Some(deadline - now))?;
+        match socket.recv_from(&mut buf) {
+            Ok((n, from)) => match parse_response(&buf[..n]) {
+                Ok(recs) => {
+                    for r in &recs {
+                        if let Record::Srv { name, .. } = r {
+                            source.insert(name.clone(), from.ip());
+                        }
+                    }
+                    records.extend(recs);
+                }
+                Err(e) => warn!(from = %from, error = %e, "mdns::browse: ignoring packet"),
+            },
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => break,
+            Err(e) => return Err(e),
+        }
+    }
+
+    let services = resolve(&records, &query.services, &source);
+    debug!(found = services.len(), "mdns::browse: completed");
+    Ok(services)
+}
@@ -1,14 +1,14 @@
+use crate::framing::LineAssembler;
 use gcodekit_utils::settings::network_timeout;
 use tracing::{debug, info};
-use std::io::BufRead;
-use std::io::BufReader;
-use std::io::{self, Write};
+use std::io::{self, Read, Write};
 use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
 use std::time::Duration;
 
 /// Simple network transport connection enum for tests and stubbing
 pub enum NetworkConnection {
-    Tcp(TcpStream),
+    /// Stream plus the bytes received after the last complete line.
+    Tcp(TcpStream, LineAssembler),
     Udp(UdpSocket, String), // socket + peer addr
 }
 
@@ -26,7 +26,7 @@ impl NetworkConnection {
                     stream.set_read_timeout(Some(timeout))?;
                     stream.set_write_timeout(Some(timeout))?;
                     debug!(peer = ?sock, "network::connect_tcp: connected");
-                    return Ok(NetworkConnection::Tcp(stream));
+                    return Ok(NetworkConnection::Tcp(stream, LineAssembler::new()));
                 }
                 Err(e) => last_err = Some(e),
             }
@@ -48,7 +48,7 @@ impl NetworkConnection {
 
     pub fn send_line(&mut self, line: &str) -> io::Result<()> {
         match self {
-            NetworkConnection::Tcp(s) => {
+            NetworkConnection::Tcp(s, _) => {
                 // write_all will block but respect the socket write timeout set at connect time
                 debug!(len = line.len(), "network::send_line: tcp sending bytes");
                 s.write_all(line.as_bytes())?;
@@ -70,7 +70,7 @@ impl NetworkConnection {
         // For stubs: send a standard ascii kill sequence or a single 0x18 (CAN)
         let stop = b"!"; // placeholder
         match self {
-            NetworkConnection::Tcp(s) => {
+            NetworkConnection::Tcp(s, _) => {
                 // Respect write timeout
                 debug!("network::emergency_stop: tcp sending stop");
                 s.write_all(stop)?;
@@ -91,7 +91,7 @@ impl NetworkConnection {
     pub fn flush(&mut self) -> io::Result<()> {
         use std::io::Write;
         match self {
-            NetworkConnection::Tcp(s) => s.flush(),
+            NetworkConnection::Tcp(s, _) => s.flush(),
             NetworkConnection::Udp(_, _) => Ok(()),
         }
     }
@@ -101,7 +101,7 @@ impl NetworkConnection {
     pub fn disconnect(&mut self) -> io::Result<()> {
         use std::net::Shutdown;
         match self {
-            NetworkConnection::Tcp(s) => s.shutdown(Shutdown::Both),
+            NetworkConnection::Tcp(s, _) => s.shutdown(Shutdown::Both),
             NetworkConnection::Udp(_, _) => Ok(()),
         }
     }
@@ -110,7 +110,7 @@ impl NetworkConnection {
     /// error; for UDP we assume the socket is alive if it exists.
     pub fn is_alive(&self) -> io::Result<bool> {
         match self {
-            NetworkConnection::Tcp(s) => {
+            NetworkConnection::Tcp(s, _) => {
                 // take_error returns any pending socket error; None means no
                 // error observed.
                 match s.take_error() {
@@ -138,22 +138,45 @@ impl NetworkConnection {
         }
     }
 
+    /// Send a single realtime command byte without a line terminator.
+    pub fn send_realtime(&mut self, byte: u8) -> io::Result<()> {
+        match self {
+            NetworkConnection::Tcp(s, _) => s.write_all(&[byte]),
+            NetworkConnection::Udp(s, _) => s.send(&[byte]).map(|_| ()),
+        }
+    }
+
+    /// Set the socket read timeout used by `read_line`.
+    pub fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        let timeout = Some(timeout.max(Duration::from_millis(1)));
+        match self {
+            NetworkConnection::Tcp(s, _) => s.set_read_timeout(timeout),
+            NetworkConnection::Udp(s, _) => s.set_read_timeout(timeout),
+        }
+    }
+
     /// Read a line (up to and including a newline) from the transport and
-    /// return it without the trailing newline.
+    /// return it without the trailing newline. Bytes received after the
+    /// newline are kept for the next call.
     pub fn read_line(&mut self) -> io::Result<String> {
         match self {
-            NetworkConnection::Tcp(s) => {
-                // Create a temporary BufReader borrowing the stream. To avoid
-                // taking ownership we duplicate the stream handle using try_clone.
-                let mut reader = BufReader::new(s.try_clone()?);
-                let mut line = String::new();
+            NetworkConnection::Tcp(s, lines) => {
+                let mut buf = [0u8; 1024];
                 debug!("network::read_line: tcp waiting for line");
-                reader.read_line(&mut line)?;
-                if line.ends_with('\n') {
-                    line.truncate(line.len() - 1);
+                loop {
+                    if let Some(line) = lines.next_line() {
+                        debug!(len = line.len(), "network::read_line: tcp read line bytes");
+                        return Ok(line);
+                    }
+                    match s.read(&mut buf)? {
+                        0 => {
+                            return lines.take_partial().ok_or_else(|| {
+                                io::Error::new(io::ErrorKind::UnexpectedEof, "tcp closed")
+                            });
+                        }
+                        n => lines.push(&buf[..n]),
+                    }
                 }
-                debug!(len = line.len(), "network::read_line: tcp read line bytes");
-                Ok(line)
             }
             NetworkConnection::Udp(s, _) => {
                 let mut buf = [0u8; 1500];
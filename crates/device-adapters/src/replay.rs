@@ -0,0 +1,134 @@
+//! Replays a recorded controller session for `replay:` endpoints.
+//!
+//! A session file is plain text, one entry per line:
+//!
+//! ```text
+//! # comment
+//! < Grbl 1.1h ['$' for help]
+//! > G0 X10
+//! < ok
+//! ```
+//!
+//! `<` lines (or lines without a prefix) are controller output; `>` lines
+//! are what the host sent. Output recorded after a host line is released
+//! only once the host has sent a line, so streaming against a replay keeps
+//! the original request/response pacing.
+
+use std::collections::VecDeque;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Entry {
+    Host(String),
+    Device(String),
+}
+
+/// Options for `ReplayTransport`.
+#[derive(Debug, Clone, Default)]
+pub struct ReplayOptions {
+    /// Delay applied before each output line is returned.
+    pub delay: Duration,
+}
+
+/// Transport that plays back a recorded session.
+pub struct ReplayTransport {
+    entries: VecDeque<Entry>,
+    delay: Duration,
+    connected: bool,
+}
+
+impl ReplayTransport {
+    pub fn open<P: AsRef<Path>>(path: P, opts: ReplayOptions) -> io::Result<Self> {
+        let contents = std::fs::read_to_string(path.as_ref())?;
+        debug!(path = %path.as_ref().display(), "replay::open: loaded session");
+        Ok(ReplayTransport::from_session(&contents, opts))
+    }
+
+    /// Build a replay from session text instead of a file.
+    pub fn from_session(contents: &str, opts: ReplayOptions) -> Self {
+        let entries = contents
+            .lines()
+            .filter(|l| !l.trim().is_empty() && !l.starts_with('#'))
+            .map(|l| {
+                if let Some(sent) = l.strip_prefix('>') {
+                    Entry::Host(sent.trim().to_string())
+                } else {
+                    let out = l.strip_prefix('<').unwrap_or(l);
+                    Entry::Device(out.strip_prefix(' ').unwrap_or(out).trim_end().to_string())
+                }
+            })
+            .collect();
+        ReplayTransport {
+            entries,
+            delay: opts.delay,
+            connected: true,
+        }
+    }
+
+    /// Number of recorded entries not yet consumed.
+    pub fn remaining(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn send_line(&mut self, line: &str) -> io::Result<()> {
+        if !self.connected {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "replay disconnected"));
+        }
+        // Skip output the host never read so pacing stays aligned
+        while let Some(Entry::Device(_)) = self.entries.front() {
+            self.entries.pop_front();
+        }
+        match self.entries.pop_front() {
+            Some(Entry::Host(expected)) if expected != line.trim() => {
+                warn!(expected = %expected, sent = %line, "replay::send_line: diverged from recording");
+            }
+            Some(_) => {}
+            None => debug!("replay::send_line: recording exhausted"),
+        }
+        Ok(())
+    }
+
+    /// Realtime bytes are not recorded, so they are accepted and ignored.
+    pub fn send_realtime(&mut self, _byte: u8) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn emergency_stop(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn disconnect(&mut self) -> io::Result<()> {
+        self.connected = false;
+        Ok(())
+    }
+
+    pub fn is_alive(&self) -> io::Result<bool> {
+        Ok(self.connected)
+    }
+
+    /// Return the next recorded output line. Returns `TimedOut` while the
+    /// recording is waiting for the host to send, and `UnexpectedEof` once
+    /// the recording is exhausted.
+    pub fn read_line(&mut self) -> io::Result<String> {
+        match self.entries.pop_front() {
+            Some(Entry::Device(line)) => {
+                if !self.delay.is_zero() {
+                    std::thread::sleep(self.delay);
+                }
+                Ok(line)
+            }
+            Some(host) => {
+                self.entries.push_front(host);
+                Err(io::Error::new(io::ErrorKind::TimedOut, "replay: waiting for host"))
+            }
+            None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "replay finished")),
+        }
+    }
+}
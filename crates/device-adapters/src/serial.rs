@@ -1,7 +1,8 @@
 use serialport::available_ports;
 use serialport::SerialPort;
+use crate::framing::LineAssembler;
 use std::io;
-use std::io::{BufRead, Write};
+use std::io::{Read, Write};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
@@ -21,6 +22,7 @@ pub struct SerialPortInfo {
 /// satisfy `Sync` for the Transport trait object.
 pub struct SerialConnection {
     port: Arc<Mutex<Box<dyn SerialPort>>>,
+    lines: LineAssembler,
 }
 
 impl SerialConnection {
@@ -46,6 +48,7 @@ impl SerialConnection {
         {
             Ok(p) => Ok(SerialConnection {
                 port: Arc::new(Mutex::new(p)),
+                lines: LineAssembler::new(),
             }),
             Err(e) => Err(io::Error::other(format!("serial open: {}", e))),
         }
@@ -98,22 +101,44 @@ impl SerialConnection {
         }
     }
 
-    /// Read a line from the serial port (blocking until newline) and return
-    /// it without the trailing newline.
+    /// Send a single realtime command byte without a line terminator.
+    pub fn send_realtime(&mut self, byte: u8) -> io::Result<()> {
+        let mut guard = self
+            .port
+            .lock()
+            .map_err(|_| io::Error::other("mutex poisoned"))?;
+        guard.write_all(&[byte])
+    }
+
+    /// Set the port timeout used by `read_line`.
+    pub fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        let mut guard = self
+            .port
+            .lock()
+            .map_err(|_| io::Error::other("mutex poisoned"))?;
+        guard
+            .set_timeout(timeout)
+            .map_err(|e| io::Error::other(format!("set_timeout: {}", e)))
+    }
+
+    /// Read a line from the serial port (blocking until newline or the port
+    /// timeout) and return it without the trailing newline. Bytes received
+    /// after the newline are kept for the next call.
     pub fn read_line(&mut self) -> io::Result<String> {
         let mut guard = self
             .port
             .lock()
                 .map_err(|_| io::Error::other("mutex poisoned"))?;
-        let mut reader = std::io::BufReader::new(guard.as_mut());
-        let mut line = String::new();
-        reader
-            .read_line(&mut line)
-            .map_err(|e| io::Error::other(format!("read_line: {}", e)))?;
-        if line.ends_with('\n') {
-            line.truncate(line.len() - 1);
+        let mut buf = [0u8; 256];
+        loop {
+            if let Some(line) = self.lines.next_line() {
+                return Ok(line);
+            }
+            match guard.read(&mut buf)? {
+                0 => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "serial port closed")),
+                n => self.lines.push(&buf[..n]),
+            }
         }
-        Ok(line)
     }
 }
 
@@ -131,15 +156,12 @@ pub fn list_serial_ports() -> io::Result<Vec<SerialPortInfo>> {
                     vid: None,
                     pid: None,
                 };
-                match p.port_type {
-                    serialport::SerialPortType::UsbPort(ref usb) => {
-                        info.product = usb.product.clone();
-                        info.manufacturer = usb.manufacturer.clone();
-                        // usb.vid and usb.pid are u16
-                        info.vid = Some(usb.vid);
-                        info.pid = Some(usb.pid);
-                    }
-                    _ => {}
+                if let serialport::SerialPortType::UsbPort(ref usb) = p.port_type {
+                    info.product = usb.product.clone();
+                    info.manufacturer = usb.manufacturer.clone();
+                    // usb.vid and usb.pid are u16
+                    info.vid = Some(usb.vid);
+                    info.pid = Some(usb.pid);
                 }
                 out.push(info);
             }
@@ -0,0 +1,560 @@
+//! In-process controller simulator used by `sim:` endpoints.
+//!
+//! The simulator answers like a GRBL or Marlin board without any hardware:
+//! every command is acknowledged, linear moves update the machine position
+//! and status queries report it. It is meant for demos, UI work and tests,
+//! not for validating G-code.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::time::Duration;
+use tracing::debug;
+
+/// Firmware dialect emulated by the simulator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimFirmware {
+    Grbl,
+    Marlin,
+}
+
+impl std::str::FromStr for SimFirmware {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "" | "grbl" => Ok(SimFirmware::Grbl),
+            "marlin" => Ok(SimFirmware::Marlin),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown simulated firmware: {}", other),
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for SimFirmware {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SimFirmware::Grbl => "grbl",
+            SimFirmware::Marlin => "marlin",
+        })
+    }
+}
+
+/// Options for `SimTransport`.
+#[derive(Debug, Clone)]
+pub struct SimOptions {
+    pub firmware: SimFirmware,
+    /// Delay applied before each response line is returned.
+    pub latency: Duration,
+    /// Start, and come back from resets, in the Alarm state until `$H` or
+    /// `$X`, like GRBL with homing enabled.
+    pub homing_required: bool,
+    /// Axis-aligned boxes (min and max machine corners) the probe touches;
+    /// `G38.x` cycles stop where their path enters or leaves one.
+    pub solids: Vec<([f64; 3], [f64; 3])>,
+    /// `$N` settings reported by `$$`.
+    pub settings: BTreeMap<u32, f64>,
+}
+
+impl Default for SimOptions {
+    fn default() -> Self {
+        SimOptions {
+            firmware: SimFirmware::Grbl,
+            latency: Duration::ZERO,
+            homing_required: false,
+            solids: Vec::new(),
+            // GRBL 1.1 defaults for junction deviation, arc tolerance, rates,
+            // accelerations and travel
+            settings: BTreeMap::from([
+                (11, 0.010),
+                (12, 0.002),
+                (110, 500.0),
+                (111, 500.0),
+                (112, 500.0),
+                (120, 10.0),
+                (121, 10.0),
+                (122, 10.0),
+                (130, 200.0),
+                (131, 200.0),
+                (132, 200.0),
+            ]),
+        }
+    }
+}
+
+/// Simulated controller implementing the `Transport` contract.
+pub struct SimTransport {
+    opts: SimOptions,
+    output: VecDeque<String>,
+    position: [f64; 3],
+    absolute: bool,
+    /// `G20` is active; positions are still kept and reported in mm.
+    inches: bool,
+    /// `G54`-`G59` offsets and the index of the active one.
+    offsets: [[f64; 3]; 6],
+    wcs: usize,
+    g92: [f64; 3],
+    g28: [f64; 3],
+    g30: [f64; 3],
+    /// Tool length offset (`G43.1`), added to the reported Z offset.
+    tlo: f64,
+    /// Spindle state (`M3`-`M5`) and speed, and mist and flood coolant.
+    spindle: u32,
+    speed: f64,
+    coolant: [bool; 2],
+    /// Offset in the last status report; reports carry `WCO:` when it changes.
+    reported_wco: [f64; 3],
+    /// Feed, rapid and spindle overrides, and those last reported (`Ov:`).
+    overrides: [u32; 3],
+    reported_overrides: [u32; 3],
+    state: &'static str,
+    connected: bool,
+}
+
+impl SimTransport {
+    pub fn new(opts: SimOptions) -> Self {
+        debug!(firmware = %opts.firmware, "sim::new: simulator created");
+        let state = Self::reset_state(&opts);
+        SimTransport {
+            opts,
+            output: VecDeque::new(),
+            position: [0.0; 3],
+            absolute: true,
+            inches: false,
+            offsets: [[0.0; 3]; 6],
+            wcs: 0,
+            g92: [0.0; 3],
+            g28: [0.0; 3],
+            g30: [0.0; 3],
+            tlo: 0.0,
+            spindle: 5,
+            speed: 0.0,
+            coolant: [false; 2],
+            reported_wco: [0.0; 3],
+            overrides: [100; 3],
+            reported_overrides: [100; 3],
+            state,
+            connected: true,
+        }
+    }
+
+    fn reset_state(opts: &SimOptions) -> &'static str {
+        if opts.homing_required && opts.firmware == SimFirmware::Grbl {
+            "Alarm"
+        } else {
+            "Idle"
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state = Self::reset_state(&self.opts);
+        self.inches = false;
+        self.spindle = 5;
+        self.coolant = [false; 2];
+        self.output.clear();
+        self.output.push_back("Grbl 1.1h ['$' for help]".to_string());
+    }
+
+    /// Current simulated machine position (X, Y, Z).
+    pub fn position(&self) -> [f64; 3] {
+        self.position
+    }
+
+    /// Active work offset: the selected `G54`-`G59` offset plus `G92`, and
+    /// the tool length offset on Z.
+    fn wco(&self) -> [f64; 3] {
+        let tlo = [0.0, 0.0, self.tlo];
+        [0, 1, 2].map(|i| self.offsets[self.wcs][i] + self.g92[i] + tlo[i])
+    }
+
+    fn status_line(&mut self) -> String {
+        let [x, y, z] = self.position;
+        let mut line = format!("<{}|MPos:{:.3},{:.3},{:.3}|FS:0,0", self.state, x, y, z);
+        let wco = self.wco();
+        if wco != self.reported_wco {
+            line.push_str(&format!("|WCO:{:.3},{:.3},{:.3}", wco[0], wco[1], wco[2]));
+            self.reported_wco = wco;
+        }
+        if self.overrides != self.reported_overrides {
+            let [feed, rapid, spindle] = self.overrides;
+            line.push_str(&format!("|Ov:{},{},{}", feed, rapid, spindle));
+            self.reported_overrides = self.overrides;
+        }
+        line.push('>');
+        line
+    }
+
+    fn touching(&self, p: [f64; 3]) -> bool {
+        const EPS: f64 = 1e-9;
+        self.opts
+            .solids
+            .iter()
+            .any(|(min, max)| (0..3).all(|i| p[i] >= min[i] - EPS && p[i] <= max[i] + EPS))
+    }
+
+    /// Fraction of the move `from`-`to` at which it enters (`entering`) or
+    /// leaves the first solid it crosses.
+    fn contact(&self, from: [f64; 3], to: [f64; 3], entering: bool) -> Option<f64> {
+        let mut hits = self.opts.solids.iter().filter_map(|(min, max)| {
+            let (mut t0, mut t1) = (f64::NEG_INFINITY, f64::INFINITY);
+            for i in 0..3 {
+                let d = to[i] - from[i];
+                if d == 0.0 {
+                    if from[i] < min[i] || from[i] > max[i] {
+                        return None;
+                    }
+                    continue;
+                }
+                let (a, b) = ((min[i] - from[i]) / d, (max[i] - from[i]) / d);
+                t0 = t0.max(a.min(b));
+                t1 = t1.min(a.max(b));
+            }
+            let t = if entering { t0 } else { t1 };
+            (t0 <= t1 && (0.0..=1.0).contains(&t)).then_some(t)
+        });
+        let first = hits.next()?;
+        Some(hits.fold(first, f64::min))
+    }
+
+    /// Run a `G38.2`-`G38.5` line: stop at contact (or loss of contact) and
+    /// report `[PRB:...]`, or alarm like GRBL when the probe cannot be used.
+    fn probe(&mut self, line: &str) {
+        let words = Self::words(line);
+        let Some(cycle) = words.iter().find(|(c, v)| *c == 'G' && (38.0..39.0).contains(v)).map(|(_, v)| *v) else {
+            return;
+        };
+        let toward = cycle < 38.35;
+        let alarm_on_miss = (cycle - 38.2).abs() < 1e-6 || (cycle - 38.4).abs() < 1e-6;
+        let from = self.position;
+        self.apply_motion(line);
+        let to = std::mem::replace(&mut self.position, from);
+        if self.touching(from) == toward {
+            // Probe not in its expected initial state
+            self.state = "Alarm";
+            self.output.push_back("ALARM:4".to_string());
+            return;
+        }
+        let hit = self.contact(from, to, toward);
+        self.position = match hit {
+            Some(t) => [0, 1, 2].map(|i| from[i] + t * (to[i] - from[i])),
+            None => to,
+        };
+        if hit.is_none() && alarm_on_miss {
+            self.state = "Alarm";
+            self.output.push_back("ALARM:5".to_string());
+            return;
+        }
+        let [x, y, z] = self.position;
+        self.output
+            .push_back(format!("[PRB:{:.3},{:.3},{:.3}:{}]", x, y, z, u8::from(hit.is_some())));
+        self.output.push_back("ok".to_string());
+    }
+
+    /// Letter/value words of a line, e.g. `G10 L20 P1 X0` as `(G, 10)`,
+    /// `(L, 20)`, `(P, 1)`, `(X, 0)`.
+    fn words(line: &str) -> Vec<(char, f64)> {
+        let upper = line.to_ascii_uppercase();
+        let mut words = Vec::new();
+        let mut chars = upper.chars().peekable();
+        while let Some(c) = chars.next() {
+            let mut num = String::new();
+            while let Some(&d) = chars.peek() {
+                if d.is_ascii_digit() || d == '.' || d == '-' || d == '+' {
+                    num.push(d);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            if let Ok(v) = num.parse::<f64>() {
+                words.push((c, v));
+            }
+        }
+        words
+    }
+
+    fn apply_motion(&mut self, line: &str) {
+        let words = Self::words(line);
+        let g = |n: f64| words.iter().any(|&(c, v)| c == 'G' && (v - n).abs() < 1e-6);
+        let value = |letter: char| words.iter().rev().find(|(c, _)| *c == letter).map(|(_, v)| *v);
+        let axes = ['X', 'Y', 'Z'].map(value);
+        for &(c, v) in &words {
+            match c {
+                'G' if v == 90.0 => self.absolute = true,
+                'G' if v == 91.0 => self.absolute = false,
+                'G' if v == 20.0 => self.inches = true,
+                'G' if v == 21.0 => self.inches = false,
+                'G' if (54.0..=59.0).contains(&v) && v.fract() == 0.0 => self.wcs = v as usize - 54,
+                'M' if (3.0..=5.0).contains(&v) => self.spindle = v as u32,
+                'M' if v == 7.0 => self.coolant[0] = true,
+                'M' if v == 8.0 => self.coolant[1] = true,
+                'M' if v == 9.0 => self.coolant = [false; 2],
+                'S' => self.speed = v,
+                _ => {}
+            }
+        }
+        let scale = if self.inches { 25.4 } else { 1.0 };
+        let axes = axes.map(|a| a.map(|v| v * scale));
+        // Position of the tool tip, which `G10 L20` and `G92` set
+        let mut tip = self.position;
+        tip[2] -= self.tlo;
+        let set = |target: &mut [f64; 3], f: &dyn Fn(usize, f64) -> f64| {
+            for (i, a) in axes.iter().enumerate() {
+                if let Some(v) = a {
+                    target[i] = f(i, *v);
+                }
+            }
+        };
+        if g(10.0) {
+            let p = value('P').unwrap_or(0.0) as usize;
+            let index = if p == 0 { self.wcs } else { p - 1 };
+            let g92 = self.g92;
+            if let Some(offset) = self.offsets.get_mut(index) {
+                if value('L') == Some(20.0) {
+                    set(offset, &|i, v| tip[i] - g92[i] - v);
+                } else {
+                    set(offset, &|_, v| v);
+                }
+            }
+            return;
+        }
+        if g(43.1) {
+            self.tlo = axes[2].unwrap_or(0.0);
+            return;
+        }
+        if g(49.0) {
+            self.tlo = 0.0;
+            return;
+        }
+        if g(92.1) {
+            self.g92 = [0.0; 3];
+            return;
+        }
+        if g(92.0) {
+            let offset = self.offsets[self.wcs];
+            set(&mut self.g92, &|i, v| tip[i] - offset[i] - v);
+            return;
+        }
+        for (n, stored) in [(28.0, self.g28), (30.0, self.g30)] {
+            if g(n) {
+                self.position = stored;
+                return;
+            }
+        }
+        if g(28.1) {
+            self.g28 = self.position;
+            return;
+        }
+        if g(30.1) {
+            self.g30 = self.position;
+            return;
+        }
+        // Distance mode on the same line applies to its axis words; G53
+        // moves are always absolute
+        let machine = g(53.0);
+        let origin = if machine { [0.0; 3] } else { self.wco() };
+        for (i, w) in axes.into_iter().enumerate() {
+            match (w, self.absolute || machine) {
+                (Some(v), true) => self.position[i] = origin[i] + v,
+                (Some(v), false) => self.position[i] += v,
+                (None, _) => {}
+            }
+        }
+    }
+
+    pub fn send_line(&mut self, line: &str) -> io::Result<()> {
+        if !self.connected {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "sim disconnected"));
+        }
+        let line = line.trim();
+        debug!(line = %line, "sim::send_line: received");
+        match (self.opts.firmware, line) {
+            (SimFirmware::Grbl, "?") => {
+                let status = self.status_line();
+                self.output.push_back(status);
+            }
+            (SimFirmware::Grbl, "!") => self.state = "Hold:0",
+            (SimFirmware::Grbl, "~") => {
+                if self.state.starts_with("Hold") {
+                    self.state = "Idle";
+                }
+            }
+            (SimFirmware::Grbl, "\u{18}") => self.reset(),
+            (SimFirmware::Grbl, l) if l.starts_with("$H") => {
+                self.position = [0.0; 3];
+                self.state = "Idle";
+                self.output.push_back("ok".to_string());
+            }
+            (SimFirmware::Grbl, "$X") => {
+                if self.state == "Alarm" {
+                    self.output.push_back("[MSG:Caution: Unlocked]".to_string());
+                    self.state = "Idle";
+                }
+                self.output.push_back("ok".to_string());
+            }
+            (SimFirmware::Grbl, "$C") => {
+                if self.state == "Check" {
+                    // Leaving check mode resets the controller
+                    self.output.push_back("[MSG:Disabled]".to_string());
+                    self.reset();
+                } else {
+                    self.state = "Check";
+                    self.output.push_back("[MSG:Enabled]".to_string());
+                    self.output.push_back("ok".to_string());
+                }
+            }
+            (SimFirmware::Grbl, "$SLP") => {
+                self.state = "Sleep";
+                self.output.push_back("ok".to_string());
+                self.output.push_back("[MSG:Sleeping]".to_string());
+            }
+            // Locked by an alarm: motion is refused until homing or unlock
+            (SimFirmware::Grbl, l) if self.state == "Alarm" && (!l.starts_with('$') || l.starts_with("$J=")) => {
+                self.output.push_back("error:9".to_string());
+            }
+            (SimFirmware::Grbl, _) if self.state == "Check" => self.output.push_back("ok".to_string()),
+            (SimFirmware::Grbl, l) if l.to_ascii_uppercase().contains("G38.") => self.probe(l),
+            // GRBL has no tool changer
+            (SimFirmware::Grbl, l) if Self::words(l).contains(&('M', 6.0)) => self.output.push_back("error:20".to_string()),
+            (SimFirmware::Grbl, "$#") => {
+                let names = ["G54", "G55", "G56", "G57", "G58", "G59", "G28", "G30", "G92"];
+                let offsets = self.offsets.iter().chain([&self.g28, &self.g30, &self.g92]);
+                for (name, [x, y, z]) in names.iter().zip(offsets) {
+                    self.output.push_back(format!("[{}:{:.3},{:.3},{:.3}]", name, x, y, z));
+                }
+                self.output.push_back(format!("[TLO:{:.3}]", self.tlo));
+                self.output.push_back("ok".to_string());
+            }
+            (SimFirmware::Grbl, "$$") => {
+                for (n, v) in &self.opts.settings {
+                    self.output.push_back(format!("${}={:.3}", n, v));
+                }
+                self.output.push_back("ok".to_string());
+            }
+            (SimFirmware::Grbl, "$G") => {
+                let distance = if self.absolute { "G90" } else { "G91" };
+                let units = if self.inches { "G20" } else { "G21" };
+                let coolant = match self.coolant {
+                    [false, false] => "M9",
+                    [true, false] => "M7",
+                    [false, true] => "M8",
+                    [true, true] => "M7 M8",
+                };
+                self.output.push_back(format!(
+                    "[GC:G0 G{} G17 {} {} G94 M{} {} T0 F0 S{}]",
+                    54 + self.wcs,
+                    units,
+                    distance,
+                    self.spindle,
+                    coolant,
+                    self.speed
+                ));
+                self.output.push_back("ok".to_string());
+            }
+            (SimFirmware::Grbl, "$I") => {
+                self.output.push_back("[VER:1.1h.20190825:SIM]".to_string());
+                self.output.push_back("[OPT:V,15,128]".to_string());
+                self.output.push_back("ok".to_string());
+            }
+            (SimFirmware::Marlin, l) if l.eq_ignore_ascii_case("M114") => {
+                let [x, y, z] = self.position;
+                self.output
+                    .push_back(format!("X:{:.2} Y:{:.2} Z:{:.2} E:0.00 Count X:0 Y:0 Z:0", x, y, z));
+                self.output.push_back("ok".to_string());
+            }
+            (SimFirmware::Marlin, l) if l.to_ascii_uppercase().starts_with("G28") => {
+                let axes = l[3..].to_ascii_uppercase();
+                for (p, axis) in self.position.iter_mut().zip(['X', 'Y', 'Z']) {
+                    if !axes.contains(['X', 'Y', 'Z']) || axes.contains(axis) {
+                        *p = 0.0;
+                    }
+                }
+                self.output.push_back("ok".to_string());
+            }
+            (SimFirmware::Marlin, l) if l.eq_ignore_ascii_case("M115") => {
+                self.output.push_back("FIRMWARE_NAME:Marlin SIM (gcodekit) PROTOCOL_VERSION:1.0".to_string());
+                self.output.push_back("ok".to_string());
+            }
+            (SimFirmware::Grbl, l) if l.starts_with("$J=") => {
+                // Jogs run in their own distance mode and units without
+                // changing the modal ones
+                let (absolute, inches) = (self.absolute, self.inches);
+                self.apply_motion(&l[3..]);
+                (self.absolute, self.inches) = (absolute, inches);
+                self.output.push_back("ok".to_string());
+            }
+            (_, "") => {}
+            (_, l) => {
+                self.apply_motion(l);
+                self.output.push_back("ok".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply a feed (`0x90`-`0x94`), rapid (`0x95`-`0x97`) or spindle
+    /// (`0x99`-`0x9D`) override byte, clamped to 10-200%.
+    fn override_step(&mut self, byte: u8) {
+        let [feed, rapid, spindle] = &mut self.overrides;
+        let step = |v: &mut u32, d: i32| *v = (*v as i32 + d).clamp(10, 200) as u32;
+        match byte {
+            0x90 => *feed = 100,
+            0x91 => step(feed, 10),
+            0x92 => step(feed, -10),
+            0x93 => step(feed, 1),
+            0x94 => step(feed, -1),
+            0x95 => *rapid = 100,
+            0x96 => *rapid = 50,
+            0x97 => *rapid = 25,
+            0x99 => *spindle = 100,
+            0x9A => step(spindle, 10),
+            0x9B => step(spindle, -10),
+            0x9C => step(spindle, 1),
+            0x9D => step(spindle, -1),
+            _ => {}
+        }
+    }
+
+    /// Realtime bytes are handled like the matching single-character line.
+    pub fn send_realtime(&mut self, byte: u8) -> io::Result<()> {
+        match byte {
+            b'?' | b'!' | b'~' | 0x18 => self.send_line(&(byte as char).to_string()),
+            0x90..=0x9D => {
+                self.override_step(byte);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn emergency_stop(&mut self) -> io::Result<()> {
+        debug!("sim::emergency_stop: entering hold");
+        self.state = "Hold:0";
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn disconnect(&mut self) -> io::Result<()> {
+        self.connected = false;
+        Ok(())
+    }
+
+    pub fn is_alive(&self) -> io::Result<bool> {
+        Ok(self.connected)
+    }
+
+    /// Return the next pending response, or `TimedOut` when the simulator has
+    /// nothing to say (like a real board that stays silent).
+    pub fn read_line(&mut self) -> io::Result<String> {
+        if !self.opts.latency.is_zero() {
+            std::thread::sleep(self.opts.latency);
+        }
+        self.output
+            .pop_front()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut, "sim: no pending output"))
+    }
+}
@@ -0,0 +1,357 @@
+//! Telnet transport for ESP32-based controllers (FluidNC, grblHAL WiFi).
+//!
+//! These boards expose the controller's serial protocol on a telnet port.
+//! Unlike a raw TCP socket, the server may interleave IAC option negotiation
+//! with the response stream, so incoming bytes are passed through a
+//! `TelnetCodec` that strips negotiation, answers it, and un-escapes data.
+
+use crate::framing::LineAssembler;
+use gcodekit_utils::settings::network_timeout;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+/// Interpret As Command: introduces every telnet command sequence.
+pub const IAC: u8 = 255;
+pub const DONT: u8 = 254;
+pub const DO: u8 = 253;
+pub const WONT: u8 = 252;
+pub const WILL: u8 = 251;
+/// Subnegotiation begin.
+pub const SB: u8 = 250;
+/// Subnegotiation end.
+pub const SE: u8 = 240;
+/// No operation; used as an application-level keepalive.
+pub const NOP: u8 = 241;
+
+/// Option codes negotiated by the transport.
+pub const OPT_BINARY: u8 = 0;
+pub const OPT_ECHO: u8 = 1;
+pub const OPT_SGA: u8 = 3;
+
+/// Default telnet port used when an endpoint omits one.
+pub const DEFAULT_PORT: u16 = 23;
+
+/// Options controlling telnet negotiation and keepalive behavior.
+#[derive(Debug, Clone)]
+pub struct TelnetOptions {
+    /// Request TRANSMIT-BINARY in both directions when connecting.
+    pub binary: bool,
+    /// Send `IAC NOP` when the connection has been idle for this long while
+    /// waiting for a response. `None` disables keepalives.
+    pub keepalive: Option<Duration>,
+    /// Connect and read timeout; defaults to the configured network timeout.
+    pub timeout: Duration,
+}
+
+impl Default for TelnetOptions {
+    fn default() -> Self {
+        TelnetOptions {
+            binary: true,
+            keepalive: Some(Duration::from_secs(10)),
+            timeout: network_timeout(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DecodeState {
+    Data,
+    Iac,
+    Negotiate(u8),
+    Sub,
+    SubIac,
+}
+
+/// Stateful telnet decoder/encoder.
+///
+/// `decode` splits incoming bytes into application data and the negotiation
+/// replies that must be written back to the peer. Only BINARY and
+/// SUPPRESS-GO-AHEAD are accepted; ECHO and every other option are refused
+/// so command echoes never end up in the response stream.
+#[derive(Debug, Clone)]
+pub struct TelnetCodec {
+    state: DecodeState,
+    local: [bool; 256],
+    remote: [bool; 256],
+}
+
+impl Default for TelnetCodec {
+    fn default() -> Self {
+        TelnetCodec::new()
+    }
+}
+
+impl TelnetCodec {
+    pub fn new() -> Self {
+        TelnetCodec {
+            state: DecodeState::Data,
+            local: [false; 256],
+            remote: [false; 256],
+        }
+    }
+
+    /// Bytes to send right after connecting to request binary mode and
+    /// suppress go-ahead. Marks the options as requested so the peer's
+    /// acknowledgement does not trigger another reply.
+    pub fn initial_negotiation(&mut self, binary: bool) -> Vec<u8> {
+        let mut out = Vec::new();
+        if binary {
+            out.extend_from_slice(&[IAC, DO, OPT_BINARY, IAC, WILL, OPT_BINARY]);
+            self.remote[OPT_BINARY as usize] = true;
+            self.local[OPT_BINARY as usize] = true;
+        }
+        out.extend_from_slice(&[IAC, DO, OPT_SGA]);
+        self.remote[OPT_SGA as usize] = true;
+        out
+    }
+
+    /// Whether the peer agreed to send binary data to us.
+    pub fn remote_binary(&self) -> bool {
+        self.remote[OPT_BINARY as usize]
+    }
+
+    /// Whether we agreed to send binary data to the peer.
+    pub fn local_binary(&self) -> bool {
+        self.local[OPT_BINARY as usize]
+    }
+
+    fn accepts(option: u8) -> bool {
+        matches!(option, OPT_BINARY | OPT_SGA)
+    }
+
+    /// Decode `input`, appending payload bytes to `data` and any negotiation
+    /// replies to `reply`. State is kept across calls so sequences split
+    /// between reads are handled.
+    pub fn decode(&mut self, input: &[u8], data: &mut Vec<u8>, reply: &mut Vec<u8>) {
+        for &b in input {
+            self.state = match self.state {
+                DecodeState::Data => {
+                    if b == IAC {
+                        DecodeState::Iac
+                    } else {
+                        // NVT mode sends a bare CR as "CR NUL"; drop the NUL.
+                        if b != 0 || self.remote_binary() {
+                            data.push(b);
+                        }
+                        DecodeState::Data
+                    }
+                }
+                DecodeState::Iac => match b {
+                    IAC => {
+                        data.push(IAC);
+                        DecodeState::Data
+                    }
+                    WILL | WONT | DO | DONT => DecodeState::Negotiate(b),
+                    SB => DecodeState::Sub,
+                    // NOP, GA, AYT and friends carry no data for us.
+                    _ => DecodeState::Data,
+                },
+                DecodeState::Negotiate(cmd) => {
+                    self.negotiate(cmd, b, reply);
+                    DecodeState::Data
+                }
+                DecodeState::Sub => {
+                    if b == IAC {
+                        DecodeState::SubIac
+                    } else {
+                        DecodeState::Sub
+                    }
+                }
+                DecodeState::SubIac => {
+                    if b == SE {
+                        DecodeState::Data
+                    } else {
+                        DecodeState::Sub
+                    }
+                }
+            };
+        }
+    }
+
+    fn negotiate(&mut self, cmd: u8, option: u8, reply: &mut Vec<u8>) {
+        let idx = option as usize;
+        let accepted = Self::accepts(option);
+        match cmd {
+            WILL if !accepted => reply.extend_from_slice(&[IAC, DONT, option]),
+            WILL if !self.remote[idx] => {
+                self.remote[idx] = true;
+                reply.extend_from_slice(&[IAC, DO, option]);
+            }
+            DO if !accepted => reply.extend_from_slice(&[IAC, WONT, option]),
+            DO if !self.local[idx] => {
+                self.local[idx] = true;
+                reply.extend_from_slice(&[IAC, WILL, option]);
+            }
+            WONT if self.remote[idx] => {
+                self.remote[idx] = false;
+                reply.extend_from_slice(&[IAC, DONT, option]);
+            }
+            DONT if self.local[idx] => {
+                self.local[idx] = false;
+                reply.extend_from_slice(&[IAC, WONT, option]);
+            }
+            // Already in the requested state: acknowledging again would loop.
+            _ => {}
+        }
+        debug!(cmd, option, "telnet::negotiate: handled option");
+    }
+
+    /// Escape outgoing payload bytes (IAC is doubled).
+    pub fn encode(&self, payload: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(payload.len() + 1);
+        for &b in payload {
+            out.push(b);
+            if b == IAC {
+                out.push(IAC);
+            }
+        }
+        out
+    }
+}
+
+/// Blocking telnet connection implementing the `Transport` contract.
+pub struct TelnetConnection {
+    stream: TcpStream,
+    codec: TelnetCodec,
+    lines: LineAssembler,
+    timeout: Duration,
+    keepalive: Option<Duration>,
+    last_tx: Instant,
+}
+
+impl TelnetConnection {
+    /// Connect with default options (binary mode, 10s keepalive).
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        TelnetConnection::connect_with_options(addr, TelnetOptions::default())
+    }
+
+    pub fn connect_with_options<A: ToSocketAddrs>(addr: A, opts: TelnetOptions) -> io::Result<Self> {
+        info!(timeout = ?opts.timeout, "telnet::connect: attempting to connect to addr");
+        let mut last_err = None;
+        for sock in addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&sock, opts.timeout) {
+                Ok(stream) => {
+                    stream.set_nodelay(true)?;
+                    stream.set_write_timeout(Some(opts.timeout))?;
+                    let mut conn = TelnetConnection {
+                        stream,
+                        codec: TelnetCodec::new(),
+                        lines: LineAssembler::new(),
+                        timeout: opts.timeout,
+                        keepalive: opts.keepalive,
+                        last_tx: Instant::now(),
+                    };
+                    let hello = conn.codec.initial_negotiation(opts.binary);
+                    conn.write_raw(&hello)?;
+                    debug!(peer = ?sock, "telnet::connect: connected");
+                    return Ok(conn);
+                }
+                Err(e) => last_err = Some(e),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| io::Error::other("connect failed")))
+    }
+
+    fn write_raw(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.stream.write_all(bytes)?;
+        self.last_tx = Instant::now();
+        Ok(())
+    }
+
+    pub fn send_line(&mut self, line: &str) -> io::Result<()> {
+        let mut payload = self.codec.encode(line.as_bytes());
+        payload.push(b'\n');
+        debug!(len = line.len(), "telnet::send_line: sending bytes");
+        self.write_raw(&payload)
+    }
+
+    pub fn emergency_stop(&mut self) -> io::Result<()> {
+        debug!("telnet::emergency_stop: sending stop");
+        self.write_raw(b"!")
+    }
+
+    /// Send a realtime command byte without a line terminator.
+    pub fn send_realtime(&mut self, byte: u8) -> io::Result<()> {
+        let payload = self.codec.encode(&[byte]);
+        self.write_raw(&payload)
+    }
+
+    /// Set how long `read_line` waits for a complete line.
+    pub fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    /// Send an `IAC NOP` so idle connections are not dropped by the board.
+    pub fn send_keepalive(&mut self) -> io::Result<()> {
+        debug!("telnet::send_keepalive: sending IAC NOP");
+        self.write_raw(&[IAC, NOP])
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+
+    pub fn disconnect(&mut self) -> io::Result<()> {
+        self.stream.shutdown(Shutdown::Both)
+    }
+
+    pub fn is_alive(&self) -> io::Result<bool> {
+        match self.stream.take_error() {
+            Ok(None) => Ok(true),
+            Ok(Some(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Read the next line of application data, answering any negotiation
+    /// received along the way and sending keepalives while idle.
+    pub fn read_line(&mut self) -> io::Result<String> {
+        let deadline = Instant::now() + self.timeout;
+        let mut buf = [0u8; 512];
+        loop {
+            if let Some(line) = self.lines.next_line() {
+                debug!(len = line.len(), "telnet::read_line: read line bytes");
+                return Ok(line);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "read timeout"));
+            }
+            let mut wait = deadline - now;
+            if let Some(k) = self.keepalive {
+                wait = wait.min(k);
+            }
+            self.stream.set_read_timeout(Some(wait.max(Duration::from_millis(1))))?;
+
+            match self.stream.read(&mut buf) {
+                Ok(0) => {
+                    return match self.lines.take_partial() {
+                        Some(line) => Ok(line),
+                        None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "telnet closed")),
+                    };
+                }
+                Ok(n) => {
+                    let mut data = Vec::with_capacity(n);
+                    let mut reply = Vec::new();
+                    self.codec.decode(&buf[..n], &mut data, &mut reply);
+                    if !reply.is_empty() {
+                        self.write_raw(&reply)?;
+                    }
+                    self.lines.push(&data);
+                }
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                    if let Some(k) = self.keepalive {
+                        if self.last_tx.elapsed() >= k {
+                            self.send_keepalive()?;
+                        }
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! TLS client configuration for secure (`wss://`) transports.
+//!
+//! `TlsOptions` is always available so callers can carry TLS settings
+//! regardless of features; building a rustls config requires the
+//! `websocket-tls` feature.
+
+use std::path::PathBuf;
+
+/// TLS settings for secure transports, typically loaded from the app config.
+#[derive(Debug, Clone, Default)]
+pub struct TlsOptions {
+    /// PEM file with additional CA certificates to trust (e.g. the
+    /// self-signed certificate of a controller on the shop network).
+    pub ca_bundle: Option<PathBuf>,
+    /// Trust only `ca_bundle` and ignore the platform certificate store.
+    pub ca_bundle_only: bool,
+    /// PEM certificate chain presented to servers requiring client auth.
+    pub client_cert: Option<PathBuf>,
+    /// PEM private key matching `client_cert`.
+    pub client_key: Option<PathBuf>,
+}
+
+#[cfg(feature = "websocket-tls")]
+mod imp {
+    use super::TlsOptions;
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+    use rustls::{ClientConfig, RootCertStore};
+    use std::io;
+    use std::sync::Arc;
+    use tracing::{debug, warn};
+
+    fn pem_error(what: &str, e: impl std::fmt::Display) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", what, e))
+    }
+
+    /// Build a rustls client configuration from `opts`.
+    pub fn client_config(opts: &TlsOptions) -> io::Result<Arc<ClientConfig>> {
+        let mut roots = RootCertStore::empty();
+
+        if !opts.ca_bundle_only {
+            let native = rustls_native_certs::load_native_certs();
+            if !native.errors.is_empty() {
+                warn!(errors = ?native.errors, "tls::client_config: some platform certificates failed to load");
+            }
+            let (added, ignored) = roots.add_parsable_certificates(native.certs);
+            debug!(added, ignored, "tls::client_config: loaded platform roots");
+        }
+
+        if let Some(path) = &opts.ca_bundle {
+            let certs = CertificateDer::pem_file_iter(path)
+                .map_err(|e| pem_error("ca bundle", e))?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| pem_error("ca bundle", e))?;
+            if certs.is_empty() {
+                return Err(pem_error("ca bundle", format!("no certificates in {}", path.display())));
+            }
+            for cert in certs {
+                roots.add(cert).map_err(|e| pem_error("ca bundle", e))?;
+            }
+            debug!(path = %path.display(), "tls::client_config: added custom CA bundle");
+        }
+
+        if roots.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no trusted root certificates available"));
+        }
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(io::Error::other)?
+            .with_root_certificates(roots);
+
+        let config = match (&opts.client_cert, &opts.client_key) {
+            (Some(cert), Some(key)) => {
+                let chain = CertificateDer::pem_file_iter(cert)
+                    .map_err(|e| pem_error("client cert", e))?
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| pem_error("client cert", e))?;
+                let key = PrivateKeyDer::from_pem_file(key).map_err(|e| pem_error("client key", e))?;
+                debug!(cert = %cert.display(), "tls::client_config: using client certificate");
+                builder.with_client_auth_cert(chain, key).map_err(io::Error::other)?
+            }
+            (None, None) => builder.with_no_client_auth(),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "client_cert and client_key must be configured together",
+                ))
+            }
+        };
+
+        Ok(Arc::new(config))
+    }
+}
+
+#[cfg(feature = "websocket-tls")]
+pub use imp::client_config;
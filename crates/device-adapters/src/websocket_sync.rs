@@ -3,21 +3,37 @@ use std::io;
 #[cfg(feature = "websocket")]
 use std::net::TcpStream;
 #[cfg(feature = "websocket")]
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 #[cfg(feature = "websocket")]
-use tungstenite::{client, protocol::Message};
+use tungstenite::{client, protocol::Message, stream::MaybeTlsStream};
 #[cfg(feature = "websocket")]
 use url::Url;
 #[cfg(feature = "websocket")]
+use crate::framing::{Esp3dControl, WsFrameDecoder};
+#[cfg(feature = "websocket")]
+use crate::tls::TlsOptions;
+#[cfg(feature = "websocket")]
+use tracing::debug;
+#[cfg(feature = "websocket")]
 use crate::Transport;
 
+/// Default idle interval after which a WebSocket keepalive is sent.
+#[cfg(feature = "websocket")]
+pub const DEFAULT_KEEPALIVE: Duration = Duration::from_secs(10);
+
 /// Synchronous WebSocket transport using blocking tungstenite client.
+///
+/// Incoming frames go through a `WsFrameDecoder`, so lines split across
+/// binary frames are reassembled and ESP3D session messages are handled
+/// rather than returned as responses.
 #[cfg(feature = "websocket")]
 pub struct WebSocketTransport {
-    ws: tungstenite::protocol::WebSocket<std::net::TcpStream>,
-    #[allow(dead_code)]
+    ws: tungstenite::protocol::WebSocket<MaybeTlsStream<TcpStream>>,
+    decoder: WsFrameDecoder,
     timeout: Duration,
+    keepalive: Option<Duration>,
+    last_tx: Instant,
 }
 
 #[cfg(feature = "websocket")]
@@ -45,13 +61,40 @@ impl Transport for WebSocketTransport {
     fn read_line(&mut self) -> std::io::Result<String> {
         WebSocketTransport::read_line(self)
     }
+
+    fn send_realtime(&mut self, byte: u8) -> std::io::Result<()> {
+        WebSocketTransport::send_realtime(self, byte)
+    }
+
+    fn set_read_timeout(&mut self, timeout: std::time::Duration) -> std::io::Result<()> {
+        WebSocketTransport::set_read_timeout(self, timeout);
+        Ok(())
+    }
 }
 
 #[cfg(feature = "websocket")]
 impl WebSocketTransport {
-    /// Connect to a ws:// URL. Only ws (non-TLS) is supported in this sync implementation.
+    /// Connect to a ws:// or wss:// URL. wss:// uses the platform trust store
+    /// and requires the `websocket-tls` feature; see `connect_with_tls` to
+    /// supply a custom CA bundle or client certificate.
     pub fn connect(url: &str) -> io::Result<Self> {
+        WebSocketTransport::connect_with_tls(url, &TlsOptions::default())
+    }
+
+    /// Connect to a ws:// or wss:// URL using the given TLS settings. The
+    /// settings are ignored for plain ws:// URLs.
+    pub fn connect_with_tls(url: &str, tls: &TlsOptions) -> io::Result<Self> {
     let url = Url::parse(url).map_err(io::Error::other)?;
+    let secure = match url.scheme() {
+        "ws" => false,
+        "wss" => true,
+        other => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported websocket scheme: {}", other),
+            ))
+        }
+    };
 
         // Resolve addresses from the URL
     let addrs = url.socket_addrs(|| None).map_err(io::Error::other)?;
@@ -69,6 +112,8 @@ impl WebSocketTransport {
         stream.set_read_timeout(Some(timeout)).ok();
         stream.set_write_timeout(Some(timeout)).ok();
 
+    let connector = if secure { Some(Self::tls_connector(tls)?) } else { None };
+
     // Perform the tungstenite client handshake in a separate thread and enforce
     // an overall timeout so this function cannot block indefinitely. The
     // underlying TcpStream has read/write timeouts set, but some platforms
@@ -78,7 +123,16 @@ impl WebSocketTransport {
     let (tx, rx) = std::sync::mpsc::channel();
     let url_string = url.to_string();
     std::thread::spawn(move || {
-        let res = client(url_string.as_str(), stream);
+        let res = match connector {
+            #[cfg(feature = "websocket-tls")]
+            Some(connector) => {
+                tungstenite::client_tls_with_config(url_string.as_str(), stream, None, Some(connector))
+                    .map_err(|e| e.to_string())
+            }
+            #[cfg(not(feature = "websocket-tls"))]
+            Some(never) => match never {},
+            None => client(url_string.as_str(), MaybeTlsStream::Plain(stream)).map_err(|e| e.to_string()),
+        };
         // Ignore send errors (receiver may have timed out)
         let _ = tx.send(res);
     });
@@ -88,19 +142,80 @@ impl WebSocketTransport {
         .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "ws handshake timeout"))?
         .map_err(io::Error::other)?;
 
-    Ok(WebSocketTransport { ws, timeout })
+    Ok(WebSocketTransport {
+        ws,
+        decoder: WsFrameDecoder::new(),
+        timeout,
+        keepalive: Some(DEFAULT_KEEPALIVE),
+        last_tx: Instant::now(),
+    })
+    }
+
+    /// Change the idle keepalive interval; `None` disables keepalives.
+    pub fn set_keepalive(&mut self, keepalive: Option<Duration>) {
+        self.keepalive = keepalive;
+    }
+
+    /// Session id assigned by an ESP3D/FluidNC board, if any.
+    pub fn session_id(&self) -> Option<&str> {
+        self.decoder.session_id()
+    }
+
+    fn tcp_stream(&self) -> Option<&TcpStream> {
+        match self.ws.get_ref() {
+            MaybeTlsStream::Plain(s) => Some(s),
+            #[cfg(feature = "websocket-tls")]
+            MaybeTlsStream::Rustls(s) => Some(s.get_ref()),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+
+    fn send_message(&mut self, msg: Message) -> io::Result<()> {
+        self.ws.send(msg).map_err(io::Error::other)?;
+        self.last_tx = Instant::now();
+        Ok(())
+    }
+
+    /// Send a WebSocket ping, plus the ESP3D `PING:<id>` message once the
+    /// board has assigned a session id.
+    pub fn send_keepalive(&mut self) -> io::Result<()> {
+        debug!("websocket_sync::send_keepalive: sending ping");
+        self.send_message(Message::Ping(Default::default()))?;
+        if let Some(ping) = self.decoder.ping_message() {
+            self.send_message(Message::Text(ping.into()))?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "websocket-tls")]
+    fn tls_connector(tls: &TlsOptions) -> io::Result<tungstenite::Connector> {
+        Ok(tungstenite::Connector::Rustls(crate::tls::client_config(tls)?))
+    }
+
+    #[cfg(not(feature = "websocket-tls"))]
+    fn tls_connector(_tls: &TlsOptions) -> io::Result<std::convert::Infallible> {
+        Err(io::Error::other(
+            "wss:// requested but device-adapters built without 'websocket-tls' feature",
+        ))
     }
 
     pub fn send_line(&mut self, line: &str) -> io::Result<()> {
-        self.ws
-            .send(Message::Text(line.trim_end_matches(&['\n', '\r'][..]).to_string().into()))
-            .map_err(io::Error::other)
+        self.send_message(Message::Text(line.trim_end_matches(&['\n', '\r'][..]).to_string().into()))
     }
 
     pub fn emergency_stop(&mut self) -> io::Result<()> {
-        self.ws
-            .send(Message::Text("!".to_string().into()))
-            .map_err(io::Error::other)
+        self.send_message(Message::Text("!".to_string().into()))
+    }
+
+    /// Send a realtime command byte as a single-byte binary frame.
+    pub fn send_realtime(&mut self, byte: u8) -> io::Result<()> {
+        self.send_message(Message::Binary(vec![byte].into()))
+    }
+
+    /// Set how long `read_line` waits for a complete line.
+    pub fn set_read_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
     }
 
     pub fn flush(&mut self) -> io::Result<()> {
@@ -118,12 +233,53 @@ impl WebSocketTransport {
         Ok(true)
     }
 
+    /// Read the next line, reassembling lines across frames. Pings are
+    /// answered by tungstenite; keepalives are sent while waiting.
     pub fn read_line(&mut self) -> io::Result<String> {
-    let msg = self.ws.read().map_err(io::Error::other)?;
-        match msg {
-            Message::Text(t) => Ok(t.to_string()),
-            Message::Binary(b) => Ok(String::from_utf8_lossy(&b).into_owned()),
-            _ => Err(io::Error::other("unsupported ws frame")),
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if let Some(line) = self.decoder.next_line() {
+                return Ok(line);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "ws read timeout"));
+            }
+            let mut wait = deadline - now;
+            if let Some(k) = self.keepalive {
+                wait = wait.min(k);
+            }
+            if let Some(tcp) = self.tcp_stream() {
+                tcp.set_read_timeout(Some(wait.max(Duration::from_millis(1))))?;
+            }
+
+            match self.ws.read() {
+                Ok(Message::Binary(b)) => self.decoder.push_binary(&b),
+                Ok(Message::Text(t)) => {
+                    if let Some(Esp3dControl::CurrentId(id)) = self.decoder.push_text(t.as_str()) {
+                        debug!(id = %id, "websocket_sync::read_line: session established");
+                    }
+                }
+                Ok(Message::Close(_)) => {
+                    return match self.decoder.take_partial() {
+                        Some(line) => Ok(line),
+                        None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "ws closed")),
+                    };
+                }
+                // Ping replies are queued by tungstenite and flushed on the next read/write
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(e))
+                    if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) =>
+                {
+                    if let Some(k) = self.keepalive {
+                        if self.last_tx.elapsed() >= k {
+                            self.send_keepalive()?;
+                        }
+                    }
+                }
+                Err(e) => return Err(io::Error::other(e)),
+            }
         }
     }
 }
@@ -142,8 +298,9 @@ mod tests {
         let server = std::thread::spawn(move || {
             let (stream, _) = listener.accept().unwrap();
             let mut ws = accept(stream).unwrap();
+            // Echo the line back newline-terminated, as a controller would
             if let Ok(msg) = ws.read() {
-                let _ = ws.send(msg);
+                let _ = ws.send(Message::Text(format!("{}\n", msg.to_text().unwrap()).into()));
             }
         });
 
@@ -1,3 +1,6 @@
+#![cfg(feature = "websocket")]
+
+use gcodekit_device_adapters::websocket_sync::WebSocketTransport;
 use std::net::TcpListener;
 use std::time::Duration;
 
@@ -28,7 +31,7 @@ fn test_handshake_times_out_after_30s() {
     let url = format!("ws://127.0.0.1:{}", addr.port());
 
     // Use the public API to attempt a connect; expecting an error due to timeout
-    match crate::WebSocketTransport::connect(&url) {
+    match WebSocketTransport::connect(&url) {
         Err(e) => {
             // Expect a timeout error kind
             assert!(matches!(e.kind(), std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock));
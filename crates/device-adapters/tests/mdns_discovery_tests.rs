@@ -0,0 +1,169 @@
+use gcodekit_device_adapters::discovery::{self, DiscoveryOptions, PeerSource};
+use gcodekit_device_adapters::mdns::{self, MdnsQuery, MdnsService, Record};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, UdpSocket};
+use std::time::Duration;
+
+fn fluidnc_telnet() -> MdnsService {
+    MdnsService {
+        instance: "fluidnc".into(),
+        service: "_telnet._tcp.local".into(),
+        host: "fluidnc.local".into(),
+        port: 23,
+        addrs: vec![IpAddr::V4(Ipv4Addr::new(192, 168, 1, 40))],
+        txt: vec!["model=FluidNC".into()],
+    }
+}
+
+/// Stand-in for an mDNS responder: answers the first query it receives
+/// with `services` and reports the question count it saw.
+fn spawn_responder(services: Vec<MdnsService>) -> (SocketAddr, std::thread::JoinHandle<u16>) {
+    let socket = UdpSocket::bind("127.0.0.1:0").expect("bind");
+    let addr = socket.local_addr().unwrap();
+    let handle = std::thread::spawn(move || {
+        socket.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let mut buf = [0u8; 1500];
+        let Ok((n, from)) = socket.recv_from(&mut buf) else {
+            return 0;
+        };
+        let questions = u16::from_be_bytes([buf[4], buf[5]]);
+        assert!(n > 12);
+        socket.send_to(&mdns::build_response(&services), from).unwrap();
+        questions
+    });
+    (addr, handle)
+}
+
+#[test]
+fn test_parse_response_with_compressed_names() {
+    // PTR _telnet._tcp.local -> grbl._telnet._tcp.local, using a pointer
+    // back to the owner name for the target suffix.
+    let mut pkt = vec![0, 0, 0x84, 0, 0, 0, 0, 1, 0, 0, 0, 0];
+    let owner = pkt.len();
+    for label in ["_telnet", "_tcp", "local"] {
+        pkt.push(label.len() as u8);
+        pkt.extend_from_slice(label.as_bytes());
+    }
+    pkt.push(0);
+    pkt.extend_from_slice(&[0, 12, 0, 1, 0, 0, 0, 120, 0, 7]);
+    pkt.extend_from_slice(&[4, b'g', b'r', b'b', b'l', 0xC0, owner as u8]);
+
+    let records = mdns::parse_response(&pkt).unwrap();
+    assert_eq!(
+        records,
+        vec![Record::Ptr {
+            name: "_telnet._tcp.local".into(),
+            target: "grbl._telnet._tcp.local".into()
+        }]
+    );
+}
+
+#[test]
+fn test_parse_rejects_truncated_packets() {
+    let mut pkt = mdns::build_response(&[fluidnc_telnet()]);
+    pkt.truncate(pkt.len() - 3);
+    assert!(mdns::parse_response(&pkt).is_err());
+    // Queries from other browsers are ignored rather than parsed
+    let query = mdns::build_query(&["_http._tcp.local".to_string()]);
+    assert!(mdns::parse_response(&query).unwrap().is_empty());
+}
+
+#[test]
+fn test_browse_against_local_responder() {
+    let (addr, responder) = spawn_responder(vec![fluidnc_telnet()]);
+    let query = MdnsQuery {
+        target: addr,
+        timeout: Duration::from_millis(300),
+        ..Default::default()
+    };
+    let found = mdns::browse(&query).expect("browse");
+    assert_eq!(responder.join().unwrap(), mdns::DEFAULT_SERVICES.len() as u16);
+    assert_eq!(found, vec![fluidnc_telnet()]);
+    assert_eq!(found[0].txt_value("MODEL"), Some("FluidNC"));
+}
+
+#[test]
+fn test_discover_maps_services_to_transport_hints() {
+    let http = MdnsService {
+        instance: "esp3d".into(),
+        service: "_http._tcp.local".into(),
+        host: "esp3d.local".into(),
+        port: 80,
+        addrs: vec![IpAddr::V4(Ipv4Addr::new(192, 168, 1, 41))],
+        txt: vec![],
+    };
+    // Other web servers on the LAN are not controllers
+    let printer = MdnsService {
+        instance: "Brother HL-L2350DW".into(),
+        host: "brn3c2af4.local".into(),
+        addrs: vec![IpAddr::V4(Ipv4Addr::new(192, 168, 1, 42))],
+        txt: vec!["ty=Brother HL-L2350DW".into()],
+        ..http.clone()
+    };
+    let board = MdnsService {
+        instance: "shop-mill".into(),
+        host: "shop-mill.local".into(),
+        addrs: vec![IpAddr::V4(Ipv4Addr::new(192, 168, 1, 43))],
+        txt: vec!["firmware=ESP3D".into()],
+        ..http.clone()
+    };
+    assert!(discovery::transport_for_service(&printer).is_none());
+    let (addr, _responder) = spawn_responder(vec![fluidnc_telnet(), http, printer, board]);
+    let opts = DiscoveryOptions {
+        mdns: Some(MdnsQuery {
+            target: addr,
+            timeout: Duration::from_millis(300),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let peers = discovery::discover(&opts);
+    let endpoints: Vec<String> = peers.iter().map(|p| p.endpoint()).collect();
+    assert_eq!(endpoints, vec!["telnet://192.168.1.40:23", "ws://192.168.1.41:81", "ws://192.168.1.43:81"]);
+    assert!(peers.iter().all(|p| p.source == PeerSource::Mdns));
+}
+
+#[test]
+fn test_default_options_browse_mdns() {
+    let (addr, responder) = spawn_responder(vec![fluidnc_telnet()]);
+    let mut opts = DiscoveryOptions::default();
+    assert!(opts.scan_subnet.is_none());
+    let query = opts.mdns.as_mut().expect("mDNS is on by default");
+    assert_eq!(query.timeout, discovery::DEFAULT_MDNS_TIMEOUT);
+    // Only the target moves from the multicast group to the stand-in
+    query.target = addr;
+    let peers = discovery::discover(&opts);
+    assert_eq!(responder.join().unwrap(), mdns::DEFAULT_SERVICES.len() as u16);
+    let endpoints: Vec<String> = peers.iter().map(|p| p.endpoint()).collect();
+    assert_eq!(endpoints, vec!["telnet://192.168.1.40:23"]);
+}
+
+#[test]
+fn test_subnet_scan_finds_local_listener() {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+    let port = listener.local_addr().unwrap().port();
+    std::thread::spawn(move || while listener.accept().is_ok() {});
+
+    let opts = DiscoveryOptions {
+        mdns: None,
+        scan_subnet: Some((Ipv4Addr::LOCALHOST, 32)),
+        scan_ports: vec![port],
+        ..Default::default()
+    };
+    let peers = discovery::discover(&opts);
+    assert_eq!(peers.len(), 1);
+    assert_eq!(peers[0].source, PeerSource::PortScan);
+    assert_eq!(peers[0].endpoint(), format!("tcp://127.0.0.1:{}", port));
+}
+
+#[test]
+fn test_subnet_helpers() {
+    let hosts = discovery::subnet_hosts(Ipv4Addr::new(10, 0, 0, 77), 30).unwrap();
+    assert_eq!(hosts, vec![Ipv4Addr::new(10, 0, 0, 77 & !3 | 1), Ipv4Addr::new(10, 0, 0, 77 & !3 | 2)]);
+    assert_eq!(discovery::subnet_hosts(Ipv4Addr::new(10, 0, 0, 0), 24).unwrap().len(), 254);
+    assert!(discovery::subnet_hosts(Ipv4Addr::new(10, 0, 0, 0), 16).is_err());
+    assert_eq!(
+        discovery::parse_subnet("192.168.4.0/24").unwrap(),
+        (Ipv4Addr::new(192, 168, 4, 0), 24)
+    );
+    assert!(discovery::parse_subnet("192.168.4.0").is_err());
+}
@@ -60,3 +60,31 @@ fn test_udp_connect_and_send() {
     let mut conn = NetworkConnection::connect_udp(client_bind, &peer).expect("connect_udp");
     conn.send_line("G0 X1 Y1").expect("send");
 }
+
+#[test]
+fn test_tcp_read_line_keeps_bytes_after_newline() {
+    use std::io::Write;
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        if let Ok((mut s, _)) = listener.accept() {
+            // Several replies in one segment, the last split across writes
+            let _ = s.write_all(b"ok\r\nerror:20\nALA");
+            thread::sleep(Duration::from_millis(20));
+            let _ = s.write_all(b"RM:1\n");
+            let mut buf = [0u8; 16];
+            let _ = s.read(&mut buf);
+        }
+    });
+
+    let mut conn = NetworkConnection::connect_tcp(addr).expect("connect");
+    assert_eq!(conn.read_line().unwrap(), "ok");
+    assert_eq!(conn.read_line().unwrap(), "error:20");
+    assert_eq!(conn.read_line().unwrap(), "ALARM:1");
+
+    // A short read timeout surfaces as an error without losing data
+    conn.set_read_timeout(Duration::from_millis(20)).unwrap();
+    let err = conn.read_line().unwrap_err();
+    assert!(matches!(err.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut));
+    conn.send_realtime(b'?').expect("realtime");
+}
@@ -0,0 +1,61 @@
+use gcodekit_device_adapters::replay::{ReplayOptions, ReplayTransport};
+use gcodekit_device_adapters::sim::{SimFirmware, SimOptions, SimTransport};
+use std::io::ErrorKind;
+
+#[test]
+fn test_sim_grbl_tracks_position_and_status() {
+    let mut sim = SimTransport::new(SimOptions::default());
+    sim.send_line("G21 G90 G0 X10 Y5").unwrap();
+    sim.send_line("G91 G1 X-2.5 Z1 F300").unwrap();
+    assert_eq!(sim.read_line().unwrap(), "ok");
+    assert_eq!(sim.read_line().unwrap(), "ok");
+    assert_eq!(sim.position(), [7.5, 5.0, 1.0]);
+
+    sim.send_line("?").unwrap();
+    assert_eq!(sim.read_line().unwrap(), "<Idle|MPos:7.500,5.000,1.000|FS:0,0>");
+    sim.send_line("!").unwrap();
+    sim.send_line("?").unwrap();
+    assert!(sim.read_line().unwrap().starts_with("<Hold:0|"));
+    assert_eq!(sim.read_line().unwrap_err().kind(), ErrorKind::TimedOut);
+}
+
+#[test]
+fn test_sim_marlin_reports_position() {
+    let mut sim = SimTransport::new(SimOptions {
+        firmware: SimFirmware::Marlin,
+        ..Default::default()
+    });
+    sim.send_line("G1 X3 Y4").unwrap();
+    sim.send_line("M114").unwrap();
+    assert_eq!(sim.read_line().unwrap(), "ok");
+    assert!(sim.read_line().unwrap().starts_with("X:3.00 Y:4.00 Z:0.00"));
+    assert_eq!(sim.read_line().unwrap(), "ok");
+}
+
+#[test]
+fn test_replay_paces_output_on_host_sends() {
+    let session = "# recorded on a GRBL 1.1h\n\
+                   < Grbl 1.1h ['$' for help]\n\
+                   > G0 X1\n\
+                   < ok\n\
+                   > G0 X2\n\
+                   <error:20\n";
+    let mut t = ReplayTransport::from_session(session, ReplayOptions::default());
+    assert_eq!(t.read_line().unwrap(), "Grbl 1.1h ['$' for help]");
+    assert_eq!(t.read_line().unwrap_err().kind(), ErrorKind::TimedOut);
+    t.send_line("G0 X1").unwrap();
+    assert_eq!(t.read_line().unwrap(), "ok");
+    t.send_line("G0 X2").unwrap();
+    assert_eq!(t.read_line().unwrap(), "error:20");
+    assert_eq!(t.read_line().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn test_replay_opens_session_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("session.log");
+    std::fs::write(&path, "ok\n").unwrap();
+    let mut t = ReplayTransport::open(&path, ReplayOptions::default()).unwrap();
+    assert_eq!(t.remaining(), 1);
+    assert_eq!(t.read_line().unwrap(), "ok");
+}
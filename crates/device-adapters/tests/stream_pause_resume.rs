@@ -10,6 +10,10 @@ use std::thread;
 fn integration_stream_pause_resume_udp() {
     let server = UdpSocket::bind("127.0.0.1:0").expect("bind");
     let server_addr = server.local_addr().unwrap();
+    // Bounded reads so the loop notices `running` going false
+    server
+        .set_read_timeout(Some(std::time::Duration::from_millis(50)))
+        .unwrap();
 
     let running = Arc::new(AtomicBool::new(true));
     let r = running.clone();
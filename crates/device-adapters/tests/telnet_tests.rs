@@ -0,0 +1,147 @@
+use gcodekit_device_adapters::telnet::{
+    TelnetCodec, TelnetConnection, TelnetOptions, DO, DONT, IAC, NOP, OPT_BINARY, OPT_ECHO, OPT_SGA, WILL, WONT,
+};
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::time::Duration;
+
+#[test]
+fn test_codec_strips_negotiation_and_replies() {
+    let mut codec = TelnetCodec::new();
+    let mut data = Vec::new();
+    let mut reply = Vec::new();
+    // Server offers ECHO (refused) and BINARY (accepted) around payload bytes
+    let input = [b'o', IAC, WILL, OPT_ECHO, b'k', IAC, WILL, OPT_BINARY, b'\r', b'\n'];
+    codec.decode(&input, &mut data, &mut reply);
+    assert_eq!(data, b"ok\r\n");
+    assert_eq!(reply, vec![IAC, DONT, OPT_ECHO, IAC, DO, OPT_BINARY]);
+    assert!(codec.remote_binary());
+}
+
+#[test]
+fn test_codec_handles_sequences_split_across_reads() {
+    let mut codec = TelnetCodec::new();
+    let mut data = Vec::new();
+    let mut reply = Vec::new();
+    codec.decode(&[b'a', IAC], &mut data, &mut reply);
+    codec.decode(&[DO], &mut data, &mut reply);
+    codec.decode(&[OPT_SGA, IAC, IAC, b'b'], &mut data, &mut reply);
+    assert_eq!(data, vec![b'a', IAC, b'b']);
+    assert_eq!(reply, vec![IAC, WILL, OPT_SGA]);
+}
+
+#[test]
+fn test_codec_does_not_loop_on_acknowledgements() {
+    let mut codec = TelnetCodec::new();
+    let hello = codec.initial_negotiation(true);
+    assert_eq!(&hello[..6], &[IAC, DO, OPT_BINARY, IAC, WILL, OPT_BINARY]);
+    let mut data = Vec::new();
+    let mut reply = Vec::new();
+    // Peer acknowledges what we asked for: no further replies expected
+    codec.decode(&[IAC, WILL, OPT_BINARY, IAC, DO, OPT_BINARY], &mut data, &mut reply);
+    assert!(reply.is_empty());
+    // Peer later withdraws binary: we acknowledge once
+    codec.decode(&[IAC, WONT, OPT_BINARY], &mut data, &mut reply);
+    assert_eq!(reply, vec![IAC, DONT, OPT_BINARY]);
+    assert!(data.is_empty());
+}
+
+#[test]
+fn test_codec_skips_subnegotiation_and_escapes_output() {
+    let mut codec = TelnetCodec::new();
+    let mut data = Vec::new();
+    let mut reply = Vec::new();
+    codec.decode(&[IAC, 250, 24, 1, IAC, 240, b'x', IAC, NOP], &mut data, &mut reply);
+    assert_eq!(data, b"x");
+    assert_eq!(codec.encode(&[b'a', IAC]), vec![b'a', IAC, IAC]);
+}
+
+#[test]
+fn test_telnet_connection_reads_clean_lines() {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+    let addr = listener.local_addr().unwrap();
+
+    let server = std::thread::spawn(move || {
+        let (mut s, _) = listener.accept().expect("accept");
+        s.write_all(&[IAC, DO, OPT_ECHO]).unwrap();
+        s.write_all(b"Grbl 3.7 [FluidNC v3.7.8 ").unwrap();
+        s.write_all(b"'$' for help]\r\nok\r\n").unwrap();
+        // Collect what the client sends so we can check negotiation replies
+        let mut received = Vec::new();
+        let mut buf = [0u8; 256];
+        s.set_read_timeout(Some(Duration::from_millis(300))).unwrap();
+        while let Ok(n) = s.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            received.extend_from_slice(&buf[..n]);
+            if received.ends_with(b"$I\n") {
+                break;
+            }
+        }
+        received
+    });
+
+    let mut conn = TelnetConnection::connect(addr).expect("connect");
+    assert_eq!(conn.read_line().unwrap(), "Grbl 3.7 [FluidNC v3.7.8 '$' for help]");
+    assert_eq!(conn.read_line().unwrap(), "ok");
+    conn.send_line("$I").unwrap();
+
+    let received = server.join().unwrap();
+    assert!(received.windows(3).any(|w| w == [IAC, WONT, OPT_ECHO]));
+    assert!(received.ends_with(b"$I\n"));
+}
+
+#[test]
+fn test_telnet_keepalive_sent_while_idle() {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+    let addr = listener.local_addr().unwrap();
+
+    let server = std::thread::spawn(move || {
+        let (mut s, _) = listener.accept().expect("accept");
+        let mut received = Vec::new();
+        let mut buf = [0u8; 64];
+        s.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+        let start = std::time::Instant::now();
+        while start.elapsed() < Duration::from_millis(400) {
+            if let Ok(n) = s.read(&mut buf) {
+                received.extend_from_slice(&buf[..n]);
+            }
+            if received.windows(2).any(|w| w == [IAC, NOP]) {
+                break;
+            }
+        }
+        let _ = s.write_all(b"ok\n");
+        received
+    });
+
+    let opts = TelnetOptions {
+        binary: false,
+        keepalive: Some(Duration::from_millis(50)),
+        timeout: Duration::from_secs(2),
+    };
+    let mut conn = TelnetConnection::connect_with_options(addr, opts).expect("connect");
+    assert_eq!(conn.read_line().unwrap(), "ok");
+    let received = server.join().unwrap();
+    assert!(received.windows(2).any(|w| w == [IAC, NOP]));
+}
+
+#[tokio::test]
+async fn test_async_telnet_reads_clean_lines() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        use tokio::io::AsyncWriteExt;
+        let (mut s, _) = listener.accept().await.unwrap();
+        let _ = s.write_all(&[IAC, WILL, OPT_SGA, b'o', b'k', b'\r', b'\n']).await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    });
+
+    // Hostnames are resolved like the sync transport does
+    let host = format!("localhost:{}", addr.port());
+    let mut t = gcodekit_device_adapters::async_telnet::AsyncTelnetTransport::connect(host)
+        .await
+        .expect("connect");
+    assert_eq!(t.read_line().await.unwrap(), "ok");
+}
@@ -0,0 +1,132 @@
+use gcodekit_device_adapters::framing::{Esp3dControl, WsFrameDecoder};
+
+#[test]
+fn test_decoder_reassembles_binary_frames() {
+    let mut d = WsFrameDecoder::new();
+    d.push_binary(b"<Idle|MPos:0.000,0.000,0.000|FS:0,0>\r\nok\r");
+    assert_eq!(d.next_line().as_deref(), Some("<Idle|MPos:0.000,0.000,0.000|FS:0,0>"));
+    assert_eq!(d.next_line(), None);
+    d.push_binary(b"\nerr");
+    d.push_binary(b"or:20\n");
+    assert_eq!(d.next_line().as_deref(), Some("ok"));
+    assert_eq!(d.next_line().as_deref(), Some("error:20"));
+    assert_eq!(d.next_line(), None);
+}
+
+#[test]
+fn test_decoder_text_frames_and_esp3d_control() {
+    let mut d = WsFrameDecoder::new();
+    assert_eq!(d.push_text("currentID:3"), Some(Esp3dControl::CurrentId("3".into())));
+    assert_eq!(d.push_text("ACTIVE_ID:3"), Some(Esp3dControl::ActiveId("3".into())));
+    assert!(!d.superseded());
+    assert_eq!(d.push_text("PING:60000:60000"), Some(Esp3dControl::Ping("60000:60000".into())));
+    assert_eq!(d.push_text("ok\nok"), None);
+    assert_eq!(d.session_id(), Some("3"));
+    assert_eq!(d.ping_message().as_deref(), Some("PING:3"));
+    assert_eq!(d.next_line().as_deref(), Some("ok"));
+    assert_eq!(d.next_line(), None);
+
+    // Text frames can end mid-line and share the stream with binary frames
+    d.push_text("\r\n<Idle|MPos:0.000,");
+    d.push_binary(b"0.000,0.000>\r\n[MSG:");
+    d.push_text("INFO: ready]\n");
+    assert_eq!(d.next_line().as_deref(), Some("ok"));
+    assert_eq!(d.next_line().as_deref(), Some("<Idle|MPos:0.000,0.000,0.000>"));
+    assert_eq!(d.next_line().as_deref(), Some("[MSG:INFO: ready]"));
+    assert_eq!(d.next_line(), None);
+
+    d.push_text("activeID:4");
+    assert!(d.superseded());
+}
+
+#[cfg(feature = "websocket")]
+mod transport {
+    use gcodekit_device_adapters::websocket_sync::WebSocketTransport;
+    use std::net::TcpListener;
+    use std::time::Duration;
+    use tungstenite::Message;
+
+    #[test]
+    fn test_sync_ws_lines_split_across_binary_frames() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut ws = tungstenite::accept(stream).unwrap();
+            ws.send(Message::Text("currentID:0".into())).unwrap();
+            ws.send(Message::Ping(Default::default())).unwrap();
+            ws.send(Message::Binary(b"Grbl 3.7 [FluidNC".to_vec().into())).unwrap();
+            ws.send(Message::Binary(b" v3.7.8]\r\nok\r\n[MSG:".to_vec().into())).unwrap();
+            ws.send(Message::Binary(b"INFO: ready]\n".to_vec().into())).unwrap();
+            // Keep reading so the client's pong is consumed
+            while let Ok(msg) = ws.read() {
+                if msg.is_close() {
+                    break;
+                }
+            }
+        });
+
+        let mut client = WebSocketTransport::connect(&format!("ws://{}", addr)).unwrap();
+        assert_eq!(client.read_line().unwrap(), "Grbl 3.7 [FluidNC v3.7.8]");
+        assert_eq!(client.read_line().unwrap(), "ok");
+        assert_eq!(client.read_line().unwrap(), "[MSG:INFO: ready]");
+        assert_eq!(client.session_id(), Some("0"));
+        client.disconnect().unwrap();
+        let _ = server.join();
+    }
+
+    #[test]
+    fn test_sync_ws_keepalive_sends_ping_and_esp3d_ping() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut ws = tungstenite::accept(stream).unwrap();
+            ws.send(Message::Text("currentID:7".into())).unwrap();
+            let mut got_ping = false;
+            let mut got_esp3d = false;
+            while !(got_ping && got_esp3d) {
+                match ws.read() {
+                    Ok(Message::Ping(_)) => got_ping = true,
+                    Ok(Message::Text(t)) if t.as_str() == "PING:7" => got_esp3d = true,
+                    Ok(_) => {}
+                    Err(_) => break,
+                }
+            }
+            let _ = ws.send(Message::Text("ok\n".into()));
+            (got_ping, got_esp3d)
+        });
+
+        let mut client = WebSocketTransport::connect(&format!("ws://{}", addr)).unwrap();
+        client.set_keepalive(Some(Duration::from_millis(50)));
+        assert_eq!(client.read_line().unwrap(), "ok");
+        assert_eq!(server.join().unwrap(), (true, true));
+    }
+}
+
+#[cfg(all(feature = "async", feature = "websocket-full"))]
+#[tokio::test]
+async fn test_async_ws_reassembles_binary_frames() {
+    use futures_util::{SinkExt, StreamExt};
+    use gcodekit_device_adapters::async_websocket::AsyncWebSocketTransport;
+    use tokio_tungstenite::tungstenite::Message;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+        let _ = ws.send(Message::Text("CURRENT_ID:1".into())).await;
+        let _ = ws.send(Message::Binary(b"o".to_vec().into())).await;
+        let _ = ws.send(Message::Binary(b"k\nok\n".to_vec().into())).await;
+        while let Some(Ok(_)) = ws.next().await {}
+    });
+
+    let mut client = AsyncWebSocketTransport::connect(&format!("ws://{}", addr)).await.unwrap();
+    assert_eq!(client.read_line().await.unwrap(), "ok");
+    assert_eq!(client.read_line().await.unwrap(), "ok");
+    assert_eq!(client.session_id(), Some("1"));
+}
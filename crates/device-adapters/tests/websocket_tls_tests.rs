@@ -0,0 +1,199 @@
+#![cfg(feature = "websocket-tls")]
+
+use gcodekit_device_adapters::tls::TlsOptions;
+use gcodekit_device_adapters::websocket_sync::WebSocketTransport;
+use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, KeyPair};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig, ServerConnection, StreamOwned};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// A throwaway CA plus a server and client certificate signed by it.
+struct TestPki {
+    _dir: tempfile::TempDir,
+    ca_pem: PathBuf,
+    client_cert_pem: PathBuf,
+    client_key_pem: PathBuf,
+    ca_der: CertificateDer<'static>,
+    server_chain: Vec<CertificateDer<'static>>,
+    server_key: PrivateKeyDer<'static>,
+}
+
+fn test_pki() -> TestPki {
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params.distinguished_name.push(DnType::CommonName, "gcodekit test ca");
+    let ca = ca_params.self_signed(&ca_key).unwrap();
+
+    let server_key = KeyPair::generate().unwrap();
+    let server = CertificateParams::new(vec!["127.0.0.1".to_string()])
+        .unwrap()
+        .signed_by(&server_key, &ca, &ca_key)
+        .unwrap();
+
+    let client_key = KeyPair::generate().unwrap();
+    let client = CertificateParams::new(vec!["gcodekit-client".to_string()])
+        .unwrap()
+        .signed_by(&client_key, &ca, &ca_key)
+        .unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let ca_pem = dir.path().join("ca.pem");
+    let client_cert_pem = dir.path().join("client.pem");
+    let client_key_pem = dir.path().join("client.key");
+    std::fs::write(&ca_pem, ca.pem()).unwrap();
+    std::fs::write(&client_cert_pem, client.pem()).unwrap();
+    std::fs::write(&client_key_pem, client_key.serialize_pem()).unwrap();
+
+    TestPki {
+        _dir: dir,
+        ca_pem,
+        client_cert_pem,
+        client_key_pem,
+        ca_der: ca.der().clone(),
+        server_chain: vec![server.der().clone()],
+        server_key: PrivatePkcs8KeyDer::from(server_key.serialize_der()).into(),
+    }
+}
+
+fn server_config(pki: &TestPki, require_client_cert: bool) -> Arc<ServerConfig> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .unwrap();
+    let builder = if require_client_cert {
+        let mut roots = RootCertStore::empty();
+        roots.add(pki.ca_der.clone()).unwrap();
+        let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+            .build()
+            .unwrap();
+        builder.with_client_cert_verifier(verifier)
+    } else {
+        builder.with_no_client_auth()
+    };
+    Arc::new(
+        builder
+            .with_single_cert(pki.server_chain.clone(), pki.server_key.clone_key())
+            .unwrap(),
+    )
+}
+
+/// Accept one TLS WebSocket client and answer every frame with "ok".
+fn spawn_echo_server(config: Arc<ServerConfig>) -> (u16, std::thread::JoinHandle<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+    let port = listener.local_addr().unwrap().port();
+    let handle = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().expect("accept");
+        let conn = ServerConnection::new(config).unwrap();
+        let tls = StreamOwned::new(conn, stream);
+        let Ok(mut ws) = tungstenite::accept(tls) else {
+            return;
+        };
+        while let Ok(msg) = ws.read() {
+            if msg.is_text() {
+                let _ = ws.send(tungstenite::Message::Text(format!("ok {}\n", msg).into()));
+            }
+        }
+    });
+    (port, handle)
+}
+
+#[test]
+fn test_wss_with_custom_ca_bundle() {
+    let pki = test_pki();
+    let (port, server) = spawn_echo_server(server_config(&pki, false));
+
+    let tls = TlsOptions {
+        ca_bundle: Some(pki.ca_pem.clone()),
+        ca_bundle_only: true,
+        ..Default::default()
+    };
+    let url = format!("wss://127.0.0.1:{}", port);
+    let mut client = WebSocketTransport::connect_with_tls(&url, &tls).expect("wss connect");
+    client.send_line("G0 X1").unwrap();
+    assert_eq!(client.read_line().unwrap(), "ok G0 X1");
+    client.disconnect().unwrap();
+    let _ = server.join();
+}
+
+#[test]
+fn test_wss_rejects_untrusted_certificate() {
+    let pki = test_pki();
+    let other = test_pki();
+    let (port, _server) = spawn_echo_server(server_config(&pki, false));
+
+    let tls = TlsOptions {
+        ca_bundle: Some(other.ca_pem.clone()),
+        ca_bundle_only: true,
+        ..Default::default()
+    };
+    let url = format!("wss://127.0.0.1:{}", port);
+    assert!(WebSocketTransport::connect_with_tls(&url, &tls).is_err());
+}
+
+#[test]
+fn test_wss_with_client_certificate() {
+    let pki = test_pki();
+    let (port, server) = spawn_echo_server(server_config(&pki, true));
+    let url = format!("wss://127.0.0.1:{}", port);
+
+    let tls = TlsOptions {
+        ca_bundle: Some(pki.ca_pem.clone()),
+        ca_bundle_only: true,
+        client_cert: Some(pki.client_cert_pem.clone()),
+        client_key: Some(pki.client_key_pem.clone()),
+    };
+    let mut client = WebSocketTransport::connect_with_tls(&url, &tls).expect("mTLS connect");
+    client.send_line("$I").unwrap();
+    assert_eq!(client.read_line().unwrap(), "ok $I");
+    client.disconnect().unwrap();
+    let _ = server.join();
+}
+
+#[test]
+fn test_client_cert_without_key_is_rejected() {
+    let pki = test_pki();
+    let tls = TlsOptions {
+        ca_bundle: Some(pki.ca_pem.clone()),
+        ca_bundle_only: true,
+        client_cert: Some(pki.client_cert_pem.clone()),
+        client_key: None,
+    };
+    let err = gcodekit_device_adapters::tls::client_config(&tls).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[cfg(all(feature = "async", feature = "websocket-full"))]
+#[tokio::test]
+async fn test_async_wss_with_custom_ca_bundle() {
+    use futures_util::{SinkExt, StreamExt};
+    use gcodekit_device_adapters::async_websocket::AsyncWebSocketTransport;
+
+    let pki = test_pki();
+    let acceptor = tokio_rustls::TlsAcceptor::from(server_config(&pki, false));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let tls = acceptor.accept(stream).await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(tls).await.unwrap();
+        if let Some(Ok(msg)) = ws.next().await {
+            let reply = format!("{}\n", msg.to_text().unwrap());
+            let _ = ws.send(tokio_tungstenite::tungstenite::Message::Text(reply.into())).await;
+        }
+    });
+
+    let tls = TlsOptions {
+        ca_bundle: Some(pki.ca_pem.clone()),
+        ca_bundle_only: true,
+        ..Default::default()
+    };
+    let url = format!("wss://127.0.0.1:{}", port);
+    let mut client = AsyncWebSocketTransport::connect_with_tls(&url, &tls).await.expect("wss connect");
+    client.send_line("hello").await.unwrap();
+    assert_eq!(client.read_line().await.unwrap(), "hello");
+}
@@ -9,27 +9,15 @@ pub mod typed {
 
     pub fn ui_connect_endpoint(id: &str) -> Result<(), String> {
         tracing::info!(id = id, "ui_connect_endpoint: attempting connect");
-        if id.starts_with("serial:") {
-            let path = &id["serial:".len()..];
-            match gcodekit_core::device_manager::DeviceManager::connect_endpoint(path) {
-                Ok(_t) => Ok(()),
-                Err(e) => Err(e.to_string()),
-            }
-        } else if id.starts_with("tcp:") {
-            let addr = &id["tcp:".len()..];
-            match addr.parse::<std::net::SocketAddr>() {
-                Ok(sock) => match gcodekit_core::device_manager::DeviceManager::connect_network(sock) {
-                    Ok(_t) => Ok(()),
-                    Err(e) => Err(e.to_string()),
-                },
-                Err(e) => Err(e.to_string()),
-            }
-        } else {
-            match gcodekit_core::device_manager::DeviceManager::connect_endpoint(id) {
-                Ok(_t) => Ok(()),
-                Err(e) => Err(e.to_string()),
-            }
-        }
+        // Device ids are endpoint URLs (older persisted `serial:`/`tcp:` ids parse too)
+        let endpoint = id
+            .parse::<gcodekit_core::endpoint::Endpoint>()
+            .map_err(|e| e.to_string())?;
+        // The global manager keeps the session (and its transport) alive
+        gcodekit_core::device_manager::DeviceManager::global()
+            .connect_session(&endpoint)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
     }
 }
 
@@ -8,6 +8,7 @@ use serde::Serialize;
 use std::fs;
 use std::io;
 use std::path::PathBuf;
+use std::sync::Mutex;
 use tracing::debug;
 
 /// Return the platform-appropriate persistent data directory for gcodekit6.
@@ -62,6 +63,34 @@ pub fn read_json<T: DeserializeOwned>(name: &str) -> io::Result<T> {
     Ok(v)
 }
 
+/// Serializes read-modify-write cycles so concurrent `update_json` callers
+/// in this process do not lose each other's changes.
+static UPDATE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Read a JSON file, let `f` modify the value and write it back atomically.
+///
+/// A missing file starts from `T::default()`. Use this instead of
+/// `write_json` for lists that several callers append to or edit, so a save
+/// merges into the current contents rather than replacing them.
+pub fn update_json<T, R, F>(name: &str, f: F) -> io::Result<R>
+where
+    T: Serialize + DeserializeOwned + Default,
+    F: FnOnce(&mut T) -> R,
+{
+    let _guard = UPDATE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut value = match read_json::<T>(name) {
+        Ok(v) => v,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            debug!(name = %name, "storage::update_json: file not found, starting from default");
+            T::default()
+        }
+        Err(e) => return Err(e),
+    };
+    let out = f(&mut value);
+    write_json(name, &value)?;
+    Ok(out)
+}
+
 /// Return the full path for a named file inside the data dir (without creating it).
 pub fn file_path(name: &str) -> Option<PathBuf> {
     data_dir().map(|d| d.join(name))
@@ -35,3 +35,41 @@ fn test_write_and_read_json() {
         let _ = fs::remove_file(p);
     }
 }
+
+#[test]
+fn test_update_json_merges_into_existing_contents() {
+    let now = chrono::Utc::now();
+    let fname = format!(
+        "test-update-{}{:09}.json",
+        now.timestamp(),
+        now.timestamp_subsec_nanos()
+    );
+
+    // Missing file starts from the default value
+    let len = storage::update_json::<Vec<u32>, _, _>(&fname, |v| {
+        v.push(1);
+        v.len()
+    })
+    .expect("first update failed");
+    assert_eq!(len, 1);
+
+    let handles: Vec<_> = (2..10u32)
+        .map(|n| {
+            let fname = fname.clone();
+            std::thread::spawn(move || {
+                storage::update_json::<Vec<u32>, _, _>(&fname, |v| v.push(n)).expect("update failed")
+            })
+        })
+        .collect();
+    for h in handles {
+        h.join().unwrap();
+    }
+
+    let mut read: Vec<u32> = storage::read_json(&fname).expect("read_json failed");
+    read.sort();
+    assert_eq!(read, (1..10).collect::<Vec<_>>());
+
+    if let Some(p) = storage::file_path(&fname) {
+        let _ = fs::remove_file(p);
+    }
+}